@@ -0,0 +1,475 @@
+// src/config.rs
+
+use std::env;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+use vb::chunk::{ChunkBoundary, ChunkConfig};
+use vb::embed::{self, EmbedConfig};
+use vb::extract::ControlChars;
+use vb::fuzzy::FuzzyConfig;
+use vb::layers::LayerSelection;
+use vb::qdrant::{self, Metric, Precision};
+use vb::resources::ResourceBudget;
+
+use crate::pdf::{LineTolerance, TableFormat};
+
+/// Knobs for turning search hits into on-page highlight rects.
+#[derive(Debug, Clone)]
+pub struct HighlightConfig {
+    pub line_tolerance: LineTolerance,
+    pub fuzzy: FuzzyConfig,
+    /// Only the best-scoring matches on each page are highlighted.
+    pub max_highlights_per_page: usize,
+    /// Most spans a single search result may contribute, best-scoring first.
+    pub max_matches_per_result: usize,
+    /// When set, overrides `fuzzy.threshold` based on the needle's length.
+    pub adaptive_threshold: Option<AdaptiveThreshold>,
+    /// Extra sentences each highlight is widened by on either side.
+    pub context_sentences: usize,
+    /// When set, highlights whose rects meet on a line (at most this many
+    /// points apart) are drawn as one, so a phrase split across two matches
+    /// shows no gap.
+    pub merge_gap: Option<f32>,
+    /// Cleanup applied to page text before matching; kept in step with
+    /// `ChunkConfig::control_chars` so needle and page agree.
+    pub control_chars: ControlChars,
+    /// When nothing on the page reaches the fuzzy threshold, search once more
+    /// at this much below it and flag what turns up as low confidence.
+    pub relaxed_retry: Option<f32>,
+}
+
+impl HighlightConfig {
+    /// Fuzzy settings for a needle of `needle_len` chars.
+    pub fn fuzzy_for(&self, needle_len: usize) -> FuzzyConfig {
+        match &self.adaptive_threshold {
+            Some(adaptive) => FuzzyConfig { threshold: adaptive.threshold_for(needle_len), ..self.fuzzy.clone() },
+            None => self.fuzzy.clone(),
+        }
+    }
+}
+
+/// Fuzzy threshold that falls linearly from `short` to `long` as the needle
+/// grows. Short needles need a strict threshold to avoid spurious hits; long
+/// ones need a looser one to tolerate extraction drift.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AdaptiveThreshold {
+    /// (needle chars, threshold) at and below which the strict end applies.
+    pub short: (usize, f32),
+    /// (needle chars, threshold) at and above which the loose end applies.
+    pub long: (usize, f32),
+}
+
+impl Default for AdaptiveThreshold {
+    fn default() -> Self {
+        AdaptiveThreshold { short: (20, 0.92), long: (200, 0.80) }
+    }
+}
+
+impl AdaptiveThreshold {
+    pub fn threshold_for(&self, needle_len: usize) -> f32 {
+        let ((short_len, short_t), (long_len, long_t)) = (self.short, self.long);
+        if needle_len <= short_len || long_len <= short_len {
+            return short_t;
+        }
+        if needle_len >= long_len {
+            return long_t;
+        }
+        let t = (needle_len - short_len) as f32 / (long_len - short_len) as f32;
+        short_t + (long_t - short_t) * t
+    }
+
+    /// Parse `<chars>:<threshold>,<chars>:<threshold>`, e.g. `20:0.92,200:0.80`.
+    /// `on` uses the defaults.
+    pub fn parse(value: &str) -> Option<Self> {
+        let value = value.trim();
+        if value.eq_ignore_ascii_case("on") {
+            return Some(AdaptiveThreshold::default());
+        }
+        let point = |p: &str| -> Option<(usize, f32)> {
+            let (chars, threshold) = p.split_once(':')?;
+            Some((chars.trim().parse().ok()?, threshold.trim().parse().ok()?))
+        };
+        let (short, long) = value.split_once(',')?;
+        Some(AdaptiveThreshold { short: point(short)?, long: point(long)? })
+    }
+}
+
+impl Default for HighlightConfig {
+    fn default() -> Self {
+        HighlightConfig {
+            line_tolerance: LineTolerance::default(),
+            // A pathological page shouldn't hold a request for seconds
+            fuzzy: FuzzyConfig { time_budget: Some(Duration::from_millis(250)), ..FuzzyConfig::default() },
+            max_highlights_per_page: 10,
+            max_matches_per_result: 3,
+            adaptive_threshold: None,
+            context_sentences: 0,
+            merge_gap: None,
+            control_chars: ControlChars::default(),
+            relaxed_retry: Some(0.1),
+        }
+    }
+}
+
+/// Delimiters wrapped around the query's match in a result's `html_snippet`.
+#[derive(Debug, Clone)]
+pub struct SnippetMarkers {
+    pub open: String,
+    pub close: String,
+}
+
+impl Default for SnippetMarkers {
+    fn default() -> Self {
+        SnippetMarkers { open: "<mark>".to_string(), close: "</mark>".to_string() }
+    }
+}
+
+/// Payload fields every search fetches: the hit itself, plus what heading
+/// boost (`kind`), context (`parent_text`) and `neighbors` (`seq`) read.
+pub const SEARCH_PAYLOAD_FIELDS: [&str; 5] = ["text", "page", "kind", "parent_text", "seq"];
+
+/// Knobs applied to Qdrant hits before they are highlighted.
+#[derive(Debug, Clone)]
+pub struct SearchConfig {
+    /// Score multiplier for chunks flagged as headings; 1.0 turns boosting off.
+    pub heading_boost: f32,
+    /// Vector distance for the collection; scores are converted so higher is always better.
+    pub metric: Metric,
+    /// How the collection stores vectors; uploads are rounded to match.
+    pub precision: Precision,
+    /// Refuse to start, rather than warn, when embeddings don't suit `metric`.
+    pub strict_metric_check: bool,
+    pub snippet_markers: SnippetMarkers,
+    /// Shorter queries (after trimming, in chars) are rejected; a lone letter embeds as noise.
+    pub min_query_chars: usize,
+    /// Payload fields fetched with each hit. [`SEARCH_PAYLOAD_FIELDS`] are always included.
+    pub payload_fields: Vec<String>,
+    /// Most responses kept for repeated identical searches; 0 disables the cache.
+    pub cache_size: usize,
+    /// How long a cached response is served before the search runs again.
+    pub cache_ttl: Duration,
+    /// Searches taking longer are logged with their stage timings; `None` logs none.
+    pub slow_search: Option<Duration>,
+    /// Score multiplier for the `boost_id` document in a search across all documents.
+    pub document_boost: f32,
+}
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        SearchConfig {
+            heading_boost: 1.5,
+            metric: Metric::default(),
+            precision: Precision::default(),
+            strict_metric_check: false,
+            snippet_markers: SnippetMarkers::default(),
+            min_query_chars: 2,
+            payload_fields: SEARCH_PAYLOAD_FIELDS.map(String::from).to_vec(),
+            cache_size: 256,
+            cache_ttl: Duration::from_secs(300),
+            slow_search: Some(Duration::from_secs(2)),
+            document_boost: 1.2,
+        }
+    }
+}
+
+/// Limits on accepting and indexing uploaded documents.
+#[derive(Debug, Clone)]
+pub struct UploadConfig {
+    /// Documents with more pages are rejected before anything is embedded.
+    pub max_pages: usize,
+    /// How long shutdown waits for in-flight indexing before marking it failed.
+    pub shutdown_grace: Duration,
+    /// How often documents uploaded with a TTL are checked for expiry.
+    pub sweep_interval: Duration,
+    /// How tables detected from text positions are chunked.
+    pub tables: TableFormat,
+    /// Largest request body, and largest file a resumable upload may add up to.
+    pub max_bytes: usize,
+    /// Resumable upload parts are numbered below this.
+    pub max_parts: u32,
+}
+
+impl Default for UploadConfig {
+    fn default() -> Self {
+        UploadConfig {
+            max_pages: 2000,
+            shutdown_grace: Duration::from_secs(30),
+            sweep_interval: Duration::from_secs(60),
+            tables: TableFormat::default(),
+            max_bytes: 100 * 1024 * 1024,
+            max_parts: 10_000,
+        }
+    }
+}
+
+/// Who may call `POST /internal/embed`, and how much one call may embed.
+#[derive(Debug, Clone)]
+pub struct EmbedEndpointConfig {
+    /// Shared token callers send as `Authorization: Bearer <token>`. The
+    /// endpoint isn't routed without one.
+    pub token: Option<String>,
+    /// Most texts in one request.
+    pub max_texts: usize,
+    /// Most characters across one request's texts.
+    pub max_chars: usize,
+}
+
+impl Default for EmbedEndpointConfig {
+    fn default() -> Self {
+        EmbedEndpointConfig { token: None, max_texts: 256, max_chars: 256 * 2000 }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct AppConfig {
+    pub highlight: HighlightConfig,
+    pub search: SearchConfig,
+    /// Base chunking for uploads; reindex may override the chunk size.
+    pub chunking: ChunkConfig,
+    pub embed: EmbedConfig,
+    pub upload: UploadConfig,
+    /// Threads for the runtime and rayon, and concurrent embedding batches.
+    pub resources: ResourceBudget,
+    /// Route `POST /internal/embed`, so the CLI can use this server's model.
+    pub serve_embeddings: bool,
+    pub embed_endpoint: EmbedEndpointConfig,
+}
+
+impl AppConfig {
+    /// Qdrant collection for documents embedded with `lang`'s model (the
+    /// default model for `None`), named after the model's files.
+    pub fn collection(&self, lang: Option<&str>) -> anyhow::Result<String> {
+        let dir = embed::model_dir(&self.embed, lang)?;
+        let fingerprint = embed::model_fingerprint(&dir)?;
+        Ok(qdrant::model_collection(&fingerprint, embed::get_dim(), lang))
+    }
+
+    /// Read overrides from the environment; anything unset or unparsable keeps its default.
+    pub fn from_env() -> Self {
+        let mut config = AppConfig::default();
+
+        if let Some(ratio) = env_parse::<f32>("HIGHLIGHT_LINE_TOLERANCE_RATIO") {
+            config.highlight.line_tolerance = LineTolerance::HeightRatio(ratio);
+        } else if let Some(points) = env_parse::<f32>("HIGHLIGHT_LINE_TOLERANCE") {
+            config.highlight.line_tolerance = LineTolerance::Fixed(points);
+        }
+
+        if let Some(max) = env_parse::<usize>("HIGHLIGHT_MAX_PER_PAGE") {
+            config.highlight.max_highlights_per_page = max;
+        }
+
+        if let Some(max) = env_parse::<usize>("HIGHLIGHT_MAX_PER_RESULT") {
+            config.highlight.max_matches_per_result = max;
+        }
+
+        if let Some(context) = env_parse::<usize>("HIGHLIGHT_CONTEXT_SENTENCES") {
+            config.highlight.context_sentences = context;
+        }
+
+        if let Some(gap) = env_parse::<f32>("HIGHLIGHT_MERGE_GAP") {
+            config.highlight.merge_gap = Some(gap);
+        }
+
+        if let Some(ms) = env_parse::<u64>("HIGHLIGHT_FUZZY_BUDGET_MS") {
+            config.highlight.fuzzy.time_budget = (ms > 0).then(|| Duration::from_millis(ms));
+        }
+
+        if let Some(margin) = env_parse::<f32>("HIGHLIGHT_RELAXED_RETRY") {
+            config.highlight.relaxed_retry = (margin > 0.0).then_some(margin);
+        }
+
+        if let Some(fold) = env_parse::<bool>("HIGHLIGHT_FOLD_DIACRITICS") {
+            config.highlight.fuzzy.fold_diacritics = fold;
+        }
+
+        if let Some(collapse) = env_parse::<bool>("HIGHLIGHT_COLLAPSE_WHITESPACE") {
+            config.highlight.fuzzy.collapse_whitespace = collapse;
+        }
+
+        if let Some(adaptive) = env::var("HIGHLIGHT_ADAPTIVE_THRESHOLD").ok().and_then(|v| AdaptiveThreshold::parse(&v)) {
+            config.highlight.adaptive_threshold = Some(adaptive);
+        }
+
+        if let Some(boost) = env_parse::<f32>("HEADING_BOOST") {
+            config.search.heading_boost = boost;
+        }
+
+        if let Some(metric) = env::var("VECTOR_METRIC").ok().and_then(|m| Metric::parse(&m)) {
+            config.search.metric = metric;
+        }
+
+        if let Some(precision) = env::var("VECTOR_PRECISION").ok().and_then(|p| Precision::parse(&p)) {
+            config.search.precision = precision;
+        }
+
+        if let Some(strict) = env_parse::<bool>("VECTOR_METRIC_STRICT") {
+            config.search.strict_metric_check = strict;
+        }
+
+        if let Some(min) = env_parse::<usize>("SEARCH_MIN_QUERY_CHARS") {
+            config.search.min_query_chars = min;
+        }
+
+        if let Ok(fields) = env::var("SEARCH_PAYLOAD_FIELDS") {
+            config.search.payload_fields = payload_fields(&fields);
+        }
+
+        if let Some(size) = env_parse::<usize>("SEARCH_CACHE_SIZE") {
+            config.search.cache_size = size;
+        }
+
+        if let Some(secs) = env_parse::<u64>("SEARCH_CACHE_TTL_SECS") {
+            config.search.cache_ttl = Duration::from_secs(secs);
+        }
+
+        if let Some(ms) = env_parse::<u64>("SLOW_SEARCH_MS") {
+            config.search.slow_search = (ms > 0).then(|| Duration::from_millis(ms));
+        }
+
+        if let Some(boost) = env_parse::<f32>("SEARCH_DOCUMENT_BOOST") {
+            config.search.document_boost = boost;
+        }
+
+        if let Ok(open) = env::var("SNIPPET_MARK_OPEN") {
+            config.search.snippet_markers.open = open;
+        }
+
+        if let Ok(close) = env::var("SNIPPET_MARK_CLOSE") {
+            config.search.snippet_markers.close = close;
+        }
+
+        if let Some(max) = env_parse::<usize>("UPLOAD_MAX_PAGES") {
+            config.upload.max_pages = max;
+        }
+
+        if let Some(max) = env_parse::<usize>("UPLOAD_MAX_BYTES") {
+            config.upload.max_bytes = max;
+        }
+
+        if let Some(max) = env_parse::<u32>("UPLOAD_MAX_PARTS") {
+            config.upload.max_parts = max;
+        }
+
+        if let Some(secs) = env_parse::<u64>("SHUTDOWN_DRAIN_SECS") {
+            config.upload.shutdown_grace = Duration::from_secs(secs);
+        }
+
+        if let Some(secs) = env_parse::<u64>("DOCUMENT_SWEEP_SECS").filter(|&secs| secs > 0) {
+            config.upload.sweep_interval = Duration::from_secs(secs);
+        }
+
+        if let Some(serve) = env_parse::<bool>("SERVE_EMBEDDINGS") {
+            config.serve_embeddings = serve;
+        }
+        config.embed_endpoint.token = env::var("EMBED_TOKEN").ok().filter(|token| !token.is_empty());
+        if let Some(texts) = env_parse::<usize>("EMBED_MAX_TEXTS").filter(|&n| n > 0) {
+            config.embed_endpoint.max_texts = texts;
+        }
+        if let Some(chars) = env_parse::<usize>("EMBED_MAX_CHARS").filter(|&n| n > 0) {
+            config.embed_endpoint.max_chars = chars;
+        }
+
+        if let Some(cpus) = env_parse::<usize>("CPU_BUDGET").filter(|&cpus| cpus > 0) {
+            config.resources = ResourceBudget::from_cpus(cpus);
+        }
+        if let Some(threads) = env_parse::<usize>("RUNTIME_THREADS").filter(|&n| n > 0) {
+            config.resources.runtime_threads = threads;
+        }
+        if let Some(threads) = env_parse::<usize>("RAYON_THREADS").filter(|&n| n > 0) {
+            config.resources.rayon_threads = threads;
+        }
+        if let Some(jobs) = env_parse::<usize>("EMBED_JOBS").filter(|&n| n > 0) {
+            config.resources.embed_jobs = jobs;
+        }
+        config.embed.intra_threads = Some(config.resources.embed_jobs);
+
+        if let Some(tables) = env::var("EXTRACT_TABLES").ok().and_then(|t| TableFormat::parse(&t)) {
+            config.upload.tables = tables;
+        }
+
+        if let Some(chars) = env_parse::<usize>("CHUNK_PARENT_CHARS") {
+            config.chunking.parent_chars = Some(chars);
+        }
+
+        if let Some(mode) = env::var("EXTRACT_CONTROL_CHARS").ok().and_then(|m| ControlChars::parse(&m)) {
+            config.chunking.control_chars = mode;
+            config.highlight.control_chars = mode;
+        }
+
+        if let Some(boundary) = env::var("CHUNK_BOUNDARY").ok().and_then(|b| ChunkBoundary::parse(&b)) {
+            config.chunking.boundary = boundary;
+        }
+
+        if let Some(layers) = env::var("EXTRACT_LAYERS").ok().and_then(|l| LayerSelection::parse(&l)) {
+            config.chunking.layers = layers;
+        }
+
+        if let Ok(dir) = env::var("EMBED_MODEL_DIR") {
+            config.embed.model_dir = PathBuf::from(dir.trim());
+        }
+
+        if let Ok(models) = env::var("EMBED_LANGUAGE_MODELS") {
+            config.embed.language_models = embed::parse_language_models(&models);
+        }
+
+        if let Some(mode) = env::var("EMBED_QUANTIZATION").ok().and_then(|m| embed::parse_quantization(&m)) {
+            config.embed.quantization = Some(mode);
+        }
+
+        if let Some(dir) = env::var_os("EMBED_SESSION_CACHE").filter(|dir| !dir.is_empty()) {
+            config.embed.session_cache = Some(dir.into());
+        }
+
+        // Not trimmed: the trailing space is usually part of the prefix
+        if let Ok(prefix) = env::var("EMBED_QUERY_PREFIX") {
+            config.embed.query_prefix = prefix;
+        }
+        if let Ok(prefix) = env::var("EMBED_DOCUMENT_PREFIX") {
+            config.embed.document_prefix = prefix;
+        }
+
+        if let Ok(words) = env::var("QUERY_STOP_WORDS") {
+            config.embed.query_stop_words = match words.trim() {
+                "default" => embed::DEFAULT_STOP_WORDS.iter().map(|w| w.to_string()).collect(),
+                list => list.split(',').map(str::trim).filter(|w| !w.is_empty()).map(String::from).collect(),
+            };
+        }
+
+        if let Some(entries) = env_parse::<usize>("EMBED_CACHE_MAX_ENTRIES") {
+            config.embed.cache_entries = entries;
+        }
+
+        config
+    }
+}
+
+// Comma-separated field names, plus the ones search itself reads
+fn payload_fields(value: &str) -> Vec<String> {
+    let mut fields: Vec<String> = SEARCH_PAYLOAD_FIELDS.map(String::from).to_vec();
+    for field in value.split(',').map(str::trim).filter(|f| !f.is_empty()) {
+        if !fields.iter().any(|f| f == field) {
+            fields.push(field.to_string());
+        }
+    }
+    fields
+}
+
+fn env_parse<T: FromStr>(key: &str) -> Option<T> {
+    env::var(key).ok()?.trim().parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn payload_override_keeps_the_fields_search_reads() {
+        let fields = payload_fields("filename, uploaded_at,text");
+        for field in SEARCH_PAYLOAD_FIELDS {
+            assert!(fields.iter().any(|f| f == field), "{} missing from {:?}", field, fields);
+        }
+        assert_eq!(&fields[5..], ["filename", "uploaded_at"]);
+    }
+}
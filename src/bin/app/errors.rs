@@ -1,18 +1,105 @@
 // src/errors.rs
 
-use axum::{http::StatusCode, response::IntoResponse};
+use axum::{http::StatusCode, response::IntoResponse, Json};
+use serde::Serialize;
+use serde_json::json;
 
-pub struct AppError(anyhow::Error);
+/// Stable, machine-readable error codes returned to the frontend.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    BadRequest,
+    NotFound,
+    /// The document exists but is still indexing, or indexing failed.
+    NotReady,
+    /// The endpoint needs a token the request didn't carry.
+    Unauthorized,
+    /// The upload is over a configured size limit.
+    PayloadTooLarge,
+    /// A dependency such as pdfium isn't loaded; retrying later may help.
+    Unavailable,
+    Internal,
+}
+
+impl ErrorCode {
+    pub fn status(self) -> StatusCode {
+        match self {
+            ErrorCode::BadRequest => StatusCode::BAD_REQUEST,
+            ErrorCode::NotFound => StatusCode::NOT_FOUND,
+            ErrorCode::NotReady => StatusCode::CONFLICT,
+            ErrorCode::Unauthorized => StatusCode::UNAUTHORIZED,
+            ErrorCode::PayloadTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            ErrorCode::Unavailable => StatusCode::SERVICE_UNAVAILABLE,
+            ErrorCode::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+pub struct AppError {
+    code: ErrorCode,
+    error: anyhow::Error,
+}
+
+impl AppError {
+    pub fn new(code: ErrorCode, error: impl Into<anyhow::Error>) -> Self {
+        AppError { code, error: error.into() }
+    }
+
+    pub fn bad_request(message: impl Into<String>) -> Self {
+        AppError::new(ErrorCode::BadRequest, anyhow::anyhow!(message.into()))
+    }
+
+    pub fn not_found(message: impl Into<String>) -> Self {
+        AppError::new(ErrorCode::NotFound, anyhow::anyhow!(message.into()))
+    }
+
+    /// The `{"error": {"code", "message"}}` body, also sent over WebSockets.
+    pub fn to_json(&self) -> serde_json::Value {
+        json!({
+            "error": {
+                "code": self.code,
+                "message": self.error.to_string(),
+            }
+        })
+    }
+}
 
 impl IntoResponse for AppError {
     fn into_response(self) -> axum::response::Response {
-        (StatusCode::INTERNAL_SERVER_ERROR, self.0.to_string()).into_response()
+        (self.code.status(), Json(self.to_json())).into_response()
     }
 }
 
 // This lets you use ? on anything that converts into anyhow::Error
 impl<E: Into<anyhow::Error>> From<E> for AppError {
     fn from(e: E) -> Self {
-        AppError(e.into())
+        AppError::new(ErrorCode::Internal, e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn error_body_has_code_and_message() {
+        let resp = AppError::not_found("No file found for id: abc").into_response();
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+        let value: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(value["error"]["code"], "not_found");
+        assert_eq!(value["error"]["message"], "No file found for id: abc");
+    }
+
+    #[tokio::test]
+    async fn question_mark_errors_are_internal() {
+        let err: AppError = anyhow::anyhow!("boom").into();
+        let resp = err.into_response();
+        assert_eq!(resp.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+        let value: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(value["error"]["code"], "internal");
     }
-}
\ No newline at end of file
+}
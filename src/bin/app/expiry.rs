@@ -0,0 +1,131 @@
+// src/expiry.rs
+
+use std::future::Future;
+
+use tokio::task::JoinHandle;
+use vb::qdrant;
+
+use crate::errors::AppError;
+use crate::handlers::ready::document_status;
+use crate::handlers::search::{document_collection, forget_searches};
+use crate::metrics::metrics;
+use crate::types::AppState;
+
+/// Delete documents uploaded with `?ttl=` once they expire, checking every
+/// `config.upload.sweep_interval` for the life of the server.
+pub fn spawn_sweeper(state: AppState) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticks = tokio::time::interval(state.config.upload.sweep_interval);
+        loop {
+            ticks.tick().await;
+            let removed = sweep_expired(&state, qdrant::unix_now(), |id, file_name| {
+                let state = state.clone();
+                async move {
+                    let collection = document_collection(&state, &id).await?;
+                    let client = &state.qdrant;
+                    let _timer = metrics().qdrant_seconds.with_label_values(&["delete"]).start_timer();
+                    qdrant::delete_by_filename(client, &collection, &file_name)
+                        .await
+                        .map_err(|e| AppError::from(anyhow::anyhow!("Qdrant delete failed for '{}': {:?}", file_name, e)))
+                }
+            })
+            .await;
+            if !removed.is_empty() {
+                println!("Expired documents removed: {}", removed.join(", "));
+            }
+        }
+    })
+}
+
+/// Remove every document whose expiry is at or before `now` (Unix seconds),
+/// calling `delete` with its id and Qdrant filename first. Returns the removed ids.
+///
+/// Documents still processing, or whose vectors failed to delete, stay until
+/// a later sweep so nothing is left orphaned in Qdrant.
+pub async fn sweep_expired<F, Fut>(state: &AppState, now: i64, delete: F) -> Vec<String>
+where
+    F: Fn(String, String) -> Fut,
+    Fut: Future<Output = Result<(), AppError>>,
+{
+    let due: Vec<String> = state
+        .expiry_map
+        .read()
+        .await
+        .iter()
+        .filter(|&(_, &expires)| expires <= now)
+        .map(|(id, _)| id.clone())
+        .collect();
+
+    let mut removed = Vec::new();
+    for id in due {
+        let entry = state.id_map.read().await.get(&id).cloned();
+        match document_status(entry.as_deref()) {
+            "processing" => continue,
+            "indexed" => {
+                let file_name = entry.expect("indexed documents have a filename");
+                if let Err(e) = delete(id.clone(), file_name).await {
+                    eprintln!("Keeping expired document {} for the next sweep: {}", id, e.to_json());
+                    continue;
+                }
+            }
+            _ => {}
+        }
+        forget(state, &id).await;
+        removed.push(id);
+    }
+    removed
+}
+
+// Drop every trace of `id` from the in-memory maps
+pub(crate) async fn forget(state: &AppState, id: &str) {
+    state.expiry_map.write().await.remove(id);
+    state.id_map.write().await.remove(id);
+    state.name_map.write().await.remove(id);
+    state.bytes_map.write().await.remove(id);
+    state.ready_set.write().await.remove(id);
+    state.progress_map.write().await.remove(id);
+    state.uploaded_map.write().await.remove(id);
+    state.label_map.write().await.remove(id);
+    state.lang_map.write().await.remove(id);
+    state.terms_map.write().await.remove(id);
+    state.page_label_map.write().await.remove(id);
+    forget_searches(state, id).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    #[tokio::test]
+    async fn document_is_gone_once_its_ttl_passes() {
+        let state = AppState::for_tests();
+        let uploaded = 1_700_000_000;
+        for (id, entry) in [("short", "notes.pdf_1"), ("busy", "processing")] {
+            state.id_map.write().await.insert(id.to_string(), entry.to_string());
+            state.bytes_map.write().await.insert(id.to_string(), b"%PDF".to_vec());
+            state.expiry_map.write().await.insert(id.to_string(), uploaded + 5);
+        }
+
+        let deleted = Arc::new(Mutex::new(Vec::new()));
+        let delete = |_id: String, file_name: String| {
+            let deleted = deleted.clone();
+            async move {
+                deleted.lock().unwrap().push(file_name);
+                Ok(())
+            }
+        };
+
+        assert!(sweep_expired(&state, uploaded + 4, &delete).await.is_empty());
+        assert!(state.id_map.read().await.contains_key("short"));
+
+        let removed = sweep_expired(&state, uploaded + 5, &delete).await;
+        assert_eq!(removed, ["short"]);
+        assert_eq!(*deleted.lock().unwrap(), ["notes.pdf_1"]);
+        assert!(!state.id_map.read().await.contains_key("short"));
+        assert!(!state.bytes_map.read().await.contains_key("short"));
+        // Still indexing, so its vectors would outlive a deletion now
+        assert!(state.id_map.read().await.contains_key("busy"));
+        assert!(state.expiry_map.read().await.contains_key("busy"));
+    }
+}
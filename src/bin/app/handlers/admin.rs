@@ -0,0 +1,10 @@
+// src/handlers/admin.rs
+
+use axum::Json;
+use vb::cache::CacheStats;
+use vb::embed;
+
+/// `GET /admin/cache-stats` — embedding cache hits, misses and current size.
+pub async fn cache_stats() -> Json<CacheStats> {
+    Json(embed::cache_stats())
+}
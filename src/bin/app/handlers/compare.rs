@@ -0,0 +1,142 @@
+// src/handlers/compare.rs
+
+use std::collections::HashSet;
+use axum::{extract::State, Json};
+use vb::qdrant::{self, Metric, StoredChunk};
+
+use crate::errors::AppError;
+use crate::handlers::search::document_collection;
+use crate::metrics::metrics;
+use crate::types::{AppState, CompareRequest, Passage, PassagePair};
+
+// Cosine similarity a pair must reach when the request doesn't set one
+const DEFAULT_THRESHOLD: f32 = 0.85;
+
+/// `POST /api/compare` — passages of document `a` and document `b` that say
+/// the same thing, best match first.
+///
+/// Both documents' stored vectors are compared directly, so nothing is
+/// re-embedded.
+pub async fn compare_documents(
+    State(state): State<AppState>,
+    Json(req): Json<CompareRequest>,
+) -> Result<Json<Vec<PassagePair>>, AppError> {
+    let a = stored_chunks(&state, &req.a).await?;
+    let b = stored_chunks(&state, &req.b).await?;
+    let threshold = req.threshold.unwrap_or(DEFAULT_THRESHOLD);
+    let metric = state.config.search.metric;
+    // Every chunk is compared with every chunk of the other document, too long to hold the executor
+    let pairs = tokio::task::spawn_blocking(move || align_passages(&a, &b, threshold, metric))
+        .await
+        .map_err(|e| AppError::from(anyhow::anyhow!(e)))?;
+    Ok(Json(pairs))
+}
+
+async fn stored_chunks(state: &AppState, id: &str) -> Result<Vec<StoredChunk>, AppError> {
+    let file_name = state
+        .id_map
+        .read()
+        .await
+        .get(id)
+        .cloned()
+        .ok_or_else(|| AppError::not_found(format!("No file found for id: {}", id)))?;
+
+    let _timer = metrics().qdrant_seconds.with_label_values(&["scroll"]).start_timer();
+    qdrant::document_chunks(&state.qdrant, &document_collection(state, id).await?, &file_name)
+        .await
+        .map_err(|e| AppError::from(anyhow::anyhow!("Qdrant scroll failed for '{}': {:?}", file_name, e)))
+}
+
+/// Pair each chunk with its closest chunk in the other document, in both
+/// directions, keeping pairs at least as close as a cosine similarity of
+/// `threshold`. A pair found from both sides is returned once.
+fn align_passages(a: &[StoredChunk], b: &[StoredChunk], threshold: f32, metric: Metric) -> Vec<PassagePair> {
+    let threshold = metric.threshold(threshold);
+    let mut matched: Vec<(usize, usize, f32)> = Vec::new();
+    let mut seen: HashSet<(usize, usize)> = HashSet::new();
+
+    let a_to_b = a.iter().enumerate().filter_map(|(i, chunk)| {
+        closest(chunk, b, metric).map(|(j, score)| (i, j, score))
+    });
+    let b_to_a = b.iter().enumerate().filter_map(|(j, chunk)| {
+        closest(chunk, a, metric).map(|(i, score)| (i, j, score))
+    });
+    for (i, j, score) in a_to_b.chain(b_to_a) {
+        if score >= threshold && seen.insert((i, j)) {
+            matched.push((i, j, score));
+        }
+    }
+
+    matched.sort_by(|x, y| y.2.total_cmp(&x.2));
+    matched
+        .into_iter()
+        .map(|(i, j, score)| PassagePair { score, a: passage(&a[i]), b: passage(&b[j]) })
+        .collect()
+}
+
+// Index and score of the chunk in `others` nearest to `chunk`
+fn closest(chunk: &StoredChunk, others: &[StoredChunk], metric: Metric) -> Option<(usize, f32)> {
+    others
+        .iter()
+        .map(|other| metric.compare(&chunk.vector, &other.vector))
+        .enumerate()
+        .max_by(|x, y| x.1.total_cmp(&y.1))
+}
+
+fn passage(chunk: &StoredChunk) -> Passage {
+    Passage { page: chunk.page, text: chunk.text.clone() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use vb::PageNumber;
+
+    fn stored(page: u16, text: &str, vector: [f32; 3]) -> StoredChunk {
+        let norm = vector.iter().map(|v| v * v).sum::<f32>().sqrt();
+        StoredChunk {
+            page: PageNumber::new(page),
+            text: text.to_string(),
+            vector: vector.iter().map(|v| v / norm).collect(),
+        }
+    }
+
+    #[test]
+    fn shared_paragraph_is_paired_once_with_high_score() {
+        let shared = "Dropout randomly zeroes activations during training to reduce overfitting.";
+        let a = vec![
+            stored(1, "Convolutional layers share weights across the image.", [1.0, 0.0, 0.0]),
+            stored(2, shared, [0.0, 1.0, 0.1]),
+        ];
+        let b = vec![
+            stored(1, "Quarterly revenue grew by twelve percent.", [0.0, 0.0, 1.0]),
+            stored(3, shared, [0.0, 1.0, 0.1]),
+            stored(4, "Weight decay penalizes large parameters.", [0.6, 0.3, 0.7]),
+        ];
+
+        let pairs = align_passages(&a, &b, DEFAULT_THRESHOLD, Metric::Dot);
+
+        assert_eq!(pairs.len(), 1, "found from both sides but returned once");
+        assert_eq!(pairs[0].a.text, shared);
+        assert_eq!(pairs[0].a.page, PageNumber::new(2));
+        assert_eq!(pairs[0].b.page, PageNumber::new(3));
+        assert!(pairs[0].score > 0.99, "{}", pairs[0].score);
+    }
+
+    #[test]
+    fn default_threshold_keeps_the_same_pairs_under_euclid() {
+        // Cosine 0.9 apart: close enough at the default, as it is under Dot
+        let a = vec![stored(1, "Dropout zeroes activations.", [1.0, 0.0, 0.0])];
+        let b = vec![
+            stored(2, "Dropout zeroes random activations.", [0.9, 0.436, 0.0]),
+            stored(3, "Revenue grew.", [0.0, 0.0, 1.0]),
+        ];
+
+        for metric in [Metric::Dot, Metric::Euclid] {
+            let pairs = align_passages(&a, &b, DEFAULT_THRESHOLD, metric);
+            assert_eq!(pairs.len(), 1, "{:?}", metric);
+            assert_eq!(pairs[0].b.page, PageNumber::new(2));
+            assert!(align_passages(&a, &b, 0.95, metric).is_empty(), "{:?}", metric);
+        }
+    }
+}
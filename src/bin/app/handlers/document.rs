@@ -0,0 +1,254 @@
+// src/handlers/document.rs
+
+use axum::{
+    extract::{Path, State},
+    http::header,
+    response::{IntoResponse, Response},
+    Json,
+};
+
+use std::future::Future;
+use vb::chunk::{self, ChunkConfig, PdfSource};
+use vb::{layers, qdrant};
+
+use crate::errors::AppError;
+use crate::handlers::ready::document_status;
+use crate::handlers::search::document_collection;
+use crate::metrics::metrics;
+use crate::types::{AppState, DocumentResponse, DocumentStats, DocumentText, RenameRequest};
+
+pub async fn serve_pdf(State(state): State<AppState>, Path(id): Path<String>) -> Response {
+    let Some(bytes) = state.bytes_map.read().await.get(&id).cloned() else {
+        return AppError::not_found(format!("No bytes found for id: {}", id)).into_response();
+    };
+
+    let label = state.label_map.read().await.get(&id).cloned();
+    let filename = match label {
+        Some(label) => label,
+        None => state
+            .name_map
+            .read()
+            .await
+            .get(&id)
+            .cloned()
+            .unwrap_or_else(|| "document.pdf".to_string()),
+    };
+
+    pdf_response(bytes, &filename)
+}
+
+/// `PATCH /api/document/:id` — change a document's display label.
+///
+/// Only the label changes; the id and the Qdrant `filename` key stay as
+/// uploaded, so nothing is reindexed.
+pub async fn rename_document(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Json(req): Json<RenameRequest>,
+) -> Result<Json<DocumentResponse>, AppError> {
+    let label = req.label.trim();
+    if label.is_empty() {
+        return Err(AppError::bad_request("Label must not be empty"));
+    }
+    let filename = state
+        .name_map
+        .read()
+        .await
+        .get(&id)
+        .cloned()
+        .ok_or_else(|| AppError::not_found(format!("No document found for id: {}", id)))?;
+
+    state.label_map.write().await.insert(id.clone(), label.to_string());
+    Ok(Json(DocumentResponse { id, label: label.to_string(), filename }))
+}
+
+/// `GET /api/document/:id/stats` — how many chunks a document was indexed as.
+pub async fn document_stats(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<DocumentStats>, AppError> {
+    let entry = state
+        .id_map
+        .read()
+        .await
+        .get(&id)
+        .cloned()
+        .ok_or_else(|| AppError::not_found(format!("No document found for id: {}", id)))?;
+
+    let client = state.qdrant.clone();
+    let collection = document_collection(&state, &id).await?;
+    let stats = stats_for(id, &entry, |file_name| async move {
+        let _timer = metrics().qdrant_seconds.with_label_values(&["count"]).start_timer();
+        qdrant::count_points(&client, &collection, &file_name)
+            .await
+            .map_err(|e| AppError::from(anyhow::anyhow!("Qdrant count failed for '{}': {:?}", file_name, e)))
+    })
+    .await?;
+    Ok(Json(stats))
+}
+
+/// `GET /api/document/:id/text` — the text extraction produced for each page.
+///
+/// Extraction runs again on the stored PDF with the configured layers and
+/// control-character cleanup, so it matches what was chunked at index time.
+pub async fn document_text(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<DocumentText>, AppError> {
+    let bytes = state
+        .bytes_map
+        .read()
+        .await
+        .get(&id)
+        .cloned()
+        .ok_or_else(|| AppError::not_found(format!("No bytes found for id: {}", id)))?;
+
+    let config = state.config.chunking.clone();
+    let pages = tokio::task::spawn_blocking(move || extracted_pages(bytes, &config))
+        .await
+        .map_err(|e| AppError::from(anyhow::anyhow!(e)))??;
+    Ok(Json(DocumentText { id, pages }))
+}
+
+// Every page's text, prepared the way process_file prepares it for chunking
+fn extracted_pages(bytes: Vec<u8>, config: &ChunkConfig) -> anyhow::Result<Vec<String>> {
+    let mut doc = chunk::load_document(PdfSource::Bytes(bytes))?;
+    layers::strip_excluded_layers(&mut doc, &config.layers)?;
+    chunk::page_numbers(&doc)
+        .into_iter()
+        .map(|page| chunk::page_text(&doc, page, config))
+        .collect()
+}
+
+// Only an indexed entry is a Qdrant filename worth counting
+async fn stats_for<F, Fut>(id: String, entry: &str, count: F) -> Result<DocumentStats, AppError>
+where
+    F: FnOnce(String) -> Fut,
+    Fut: Future<Output = Result<u64, AppError>>,
+{
+    let status = document_status(Some(entry));
+    let chunks = match status {
+        "indexed" => count(entry.to_string()).await?,
+        _ => 0,
+    };
+    Ok(DocumentStats { id, status, chunks })
+}
+
+fn pdf_response(bytes: Vec<u8>, filename: &str) -> Response {
+    // Header values must be visible ASCII; quotes and backslashes would break the quoted-string
+    let safe_name: String = filename
+        .chars()
+        .map(|c| match c {
+            '"' | '\\' => '_',
+            c if c.is_ascii_graphic() || c == ' ' => c,
+            _ => '_',
+        })
+        .collect();
+
+    (
+        [
+            (header::CONTENT_TYPE, "application/pdf".to_string()),
+            (header::CONTENT_DISPOSITION, format!("inline; filename=\"{}\"", safe_name)),
+            // Bytes for an id never change, so the browser can hold onto them
+            (header::CACHE_CONTROL, "private, max-age=3600".to_string()),
+        ],
+        bytes,
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::StatusCode;
+
+    #[tokio::test]
+    async fn pdf_response_sets_headers_and_body() {
+        let bytes = b"%PDF-1.4 fake body".to_vec();
+        let resp = pdf_response(bytes.clone(), "r\u{e9}sum\u{e9} \"final\".pdf");
+
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers()[header::CONTENT_TYPE], "application/pdf");
+        assert_eq!(
+            resp.headers()[header::CONTENT_DISPOSITION],
+            "inline; filename=\"r_sum_ _final_.pdf\""
+        );
+        assert!(resp.headers().contains_key(header::CACHE_CONTROL));
+
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(body.len(), bytes.len());
+    }
+
+    #[tokio::test]
+    async fn stats_report_the_stored_chunk_count() {
+        let stats = stats_for("doc-1".to_string(), "lecture3.pdf_6f1c", |file_name| async move {
+            assert_eq!(file_name, "lecture3.pdf_6f1c");
+            Ok(17)
+        })
+        .await
+        .ok()
+        .unwrap();
+        assert_eq!((stats.status, stats.chunks), ("indexed", 17));
+
+        // A document still indexing is reported without asking Qdrant
+        let state = AppState::for_tests();
+        state.id_map.write().await.insert("doc-2".to_string(), "processing".to_string());
+        let Json(stats) = document_stats(State(state.clone()), Path("doc-2".to_string())).await.ok().unwrap();
+        assert_eq!((stats.status, stats.chunks), ("processing", 0));
+
+        let err = document_stats(State(state), Path("nope".to_string())).await.err().unwrap();
+        assert_eq!(err.into_response().status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn text_endpoint_returns_each_page_as_extracted() {
+        let state = AppState::for_tests();
+        let bytes = crate::pdf::tests::a4_pdf_pages(&[
+            &[(72, 700, "Backpropagation applies the chain rule.")],
+            &[(72, 700, "Exercises for chapter three.")],
+        ]);
+        state.bytes_map.write().await.insert("doc-1".to_string(), bytes);
+
+        let Json(text) = document_text(State(state.clone()), Path("doc-1".to_string())).await.ok().unwrap();
+        assert_eq!(text.pages.len(), 2);
+        assert!(text.pages[0].contains("Backpropagation applies the chain rule."));
+        assert!(!text.pages[0].contains("Exercises"));
+
+        let err = document_text(State(state), Path("nope".to_string())).await.err().unwrap();
+        assert_eq!(err.into_response().status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn rename_changes_label_but_not_search_key() {
+        let state = AppState::for_tests();
+        let id = "doc-1".to_string();
+        let unique = "lecture3.pdf_6f1c".to_string();
+        state.id_map.write().await.insert(id.clone(), unique.clone());
+        state.name_map.write().await.insert(id.clone(), "lecture3.pdf".to_string());
+        state.bytes_map.write().await.insert(id.clone(), b"%PDF-1.4 fake body".to_vec());
+
+        let req = RenameRequest { label: "  Week 3 - Backprop.pdf ".to_string() };
+        let Json(renamed) = rename_document(State(state.clone()), Path(id.clone()), Json(req))
+            .await
+            .ok()
+            .unwrap();
+        assert_eq!(renamed.label, "Week 3 - Backprop.pdf");
+        assert_eq!(renamed.filename, "lecture3.pdf");
+
+        // Searches resolve the id through id_map, which still points at the indexed name
+        assert_eq!(state.id_map.read().await.get(&id), Some(&unique));
+
+        let resp = serve_pdf(State(state.clone()), Path(id.clone())).await;
+        assert_eq!(
+            resp.headers()[header::CONTENT_DISPOSITION],
+            "inline; filename=\"Week 3 - Backprop.pdf\""
+        );
+
+        let blank = RenameRequest { label: " ".to_string() };
+        let err = rename_document(State(state.clone()), Path(id), Json(blank)).await.err().unwrap();
+        assert_eq!(err.into_response().status(), StatusCode::BAD_REQUEST);
+        let missing = RenameRequest { label: "x".to_string() };
+        let err = rename_document(State(state), Path("nope".to_string()), Json(missing)).await.err().unwrap();
+        assert_eq!(err.into_response().status(), StatusCode::NOT_FOUND);
+    }
+}
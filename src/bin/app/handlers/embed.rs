@@ -0,0 +1,170 @@
+// src/handlers/embed.rs
+
+use axum::{
+    extract::State,
+    http::{header::AUTHORIZATION, HeaderMap},
+    Json,
+};
+use vb::embed::{self, EmbedRequest, EmbedResponse};
+
+use crate::config::EmbedEndpointConfig;
+use crate::errors::{AppError, ErrorCode};
+use crate::types::AppState;
+
+/// `POST /internal/embed` — embed texts with the server's loaded model, so a
+/// CLI on the same host doesn't load its own. Only routed with `SERVE_EMBEDDINGS`
+/// and `EMBED_TOKEN`, which callers send as a bearer token.
+pub async fn embed_texts(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(request): Json<EmbedRequest>,
+) -> Result<Json<EmbedResponse>, AppError> {
+    let limits = &state.config.embed_endpoint;
+    check_token(&headers, limits.token.as_deref())?;
+    check_limits(&request, limits)?;
+    if let Some(lang) = &request.lang {
+        embed::model_dir(&state.config.embed, Some(lang)).map_err(|e| AppError::bad_request(e.to_string()))?;
+    }
+    if request.texts.is_empty() {
+        return Ok(Json(EmbedResponse { vectors: Vec::new() }));
+    }
+
+    // Shares the indexing budget, so callers can't starve uploads of the model
+    let vectors = with_embed_permit(&state, move || embed::embed_request(&request))
+        .await
+        .map_err(|e| AppError::new(ErrorCode::Unavailable, e))?;
+    Ok(Json(EmbedResponse { vectors }))
+}
+
+// Without a configured token nobody gets in
+fn check_token(headers: &HeaderMap, token: Option<&str>) -> Result<(), AppError> {
+    let sent = headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    match (token, sent) {
+        (Some(token), Some(sent)) if same_secret(token, sent) => Ok(()),
+        _ => Err(AppError::new(ErrorCode::Unauthorized, anyhow::anyhow!("Missing or wrong embedding token"))),
+    }
+}
+
+// Compares every byte, so the time taken doesn't tell how much of a guess was right
+fn same_secret(a: &str, b: &str) -> bool {
+    a.len() == b.len() && a.bytes().zip(b.bytes()).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+// One request may hold an embedding permit only so long
+fn check_limits(request: &EmbedRequest, limits: &EmbedEndpointConfig) -> Result<(), AppError> {
+    let chars: usize = request.texts.iter().map(|text| text.chars().count()).sum();
+    if request.texts.len() > limits.max_texts || chars > limits.max_chars {
+        return Err(AppError::new(
+            ErrorCode::PayloadTooLarge,
+            anyhow::anyhow!(
+                "{} texts of {} chars in total; at most {} texts and {} chars per request",
+                request.texts.len(),
+                chars,
+                limits.max_texts,
+                limits.max_chars
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Run `embed` on the blocking pool once one of the
+/// `config.resources.embed_jobs` permits is free. Anything that runs the
+/// model goes through here, so uploads and searches share one budget.
+pub(crate) async fn with_embed_permit<T, F>(state: &AppState, embed: F) -> anyhow::Result<T>
+where
+    F: FnOnce() -> anyhow::Result<T> + Send + 'static,
+    T: Send + 'static,
+{
+    let _permit = state.embed_permits.acquire().await?;
+    tokio::task::spawn_blocking(embed).await?
+}
+
+/// Embed a search query with `lang`'s model, within the embedding budget.
+pub(crate) async fn embed_query(state: &AppState, query: &str, lang: Option<&str>) -> anyhow::Result<Vec<f32>> {
+    let (query, lang) = (query.to_string(), lang.map(str::to_string));
+    with_embed_permit(state, move || embed::embed_query_in(&query, lang.as_deref())).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A server with the endpoint's token set, and the headers that carry it
+    fn with_token(token: &str) -> (AppState, HeaderMap) {
+        let mut config = crate::config::AppConfig::default();
+        config.embed_endpoint.token = Some(token.to_string());
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, format!("Bearer {}", token).parse().unwrap());
+        (AppState { config: std::sync::Arc::new(config), ..AppState::for_tests() }, headers)
+    }
+
+    async fn status(state: &AppState, headers: HeaderMap, request: EmbedRequest) -> Option<axum::http::StatusCode> {
+        embed_texts(State(state.clone()), headers, Json(request))
+            .await
+            .err()
+            .map(|e| axum::response::IntoResponse::into_response(e).status())
+    }
+
+    #[tokio::test]
+    async fn unknown_language_is_rejected_before_embedding() {
+        let (state, headers) = with_token("s3cret");
+        let request = EmbedRequest { texts: vec!["dropout".to_string()], query: true, lang: Some("xx".to_string()) };
+        assert_eq!(status(&state, headers.clone(), request).await, Some(axum::http::StatusCode::BAD_REQUEST));
+
+        let empty = embed_texts(State(state), headers, Json(EmbedRequest::default())).await.ok().unwrap();
+        assert!(empty.vectors.is_empty());
+    }
+
+    #[tokio::test]
+    async fn callers_need_the_token() {
+        let (state, headers) = with_token("s3cret");
+        let (_, wrong) = with_token("s3creT");
+        assert_eq!(status(&state, HeaderMap::new(), EmbedRequest::default()).await, Some(axum::http::StatusCode::UNAUTHORIZED));
+        assert_eq!(status(&state, wrong, EmbedRequest::default()).await, Some(axum::http::StatusCode::UNAUTHORIZED));
+        assert_eq!(status(&state, headers.clone(), EmbedRequest::default()).await, None);
+
+        // No configured token lets nobody in, even with an empty bearer
+        let mut empty = HeaderMap::new();
+        empty.insert(AUTHORIZATION, "Bearer ".parse().unwrap());
+        assert_eq!(status(&AppState::for_tests(), empty, EmbedRequest::default()).await, Some(axum::http::StatusCode::UNAUTHORIZED));
+    }
+
+    #[tokio::test]
+    async fn oversized_batches_are_rejected_before_embedding() {
+        let (state, headers) = with_token("s3cret");
+        let limits = state.config.embed_endpoint.clone();
+        let too_many = EmbedRequest { texts: vec!["a".to_string(); limits.max_texts + 1], ..EmbedRequest::default() };
+        assert_eq!(status(&state, headers.clone(), too_many).await, Some(axum::http::StatusCode::PAYLOAD_TOO_LARGE));
+
+        let too_long = EmbedRequest { texts: vec!["é".repeat(limits.max_chars / 2 + 1); 2], ..EmbedRequest::default() };
+        assert_eq!(status(&state, headers.clone(), too_long).await, Some(axum::http::StatusCode::PAYLOAD_TOO_LARGE));
+
+        // Within both limits it gets as far as checking the language
+        let lang = Some("xx".to_string());
+        let fits = EmbedRequest { texts: vec!["é".repeat(limits.max_chars / 2); 2], lang, ..EmbedRequest::default() };
+        assert_eq!(status(&state, headers, fits).await, Some(axum::http::StatusCode::BAD_REQUEST));
+    }
+
+    #[tokio::test]
+    async fn query_embedding_waits_for_an_embed_permit() {
+        let state = AppState::for_tests();
+        assert_eq!(state.embed_permits.available_permits(), 1);
+        let upload = state.embed_permits.clone().acquire_owned().await.unwrap();
+
+        // An upload's batch holds the only permit, so the query can't start embedding
+        let waiting = tokio::spawn({
+            let state = state.clone();
+            async move { embed_query(&state, "dropout", None).await }
+        });
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        assert!(!waiting.is_finished());
+
+        drop(upload);
+        tokio::time::timeout(std::time::Duration::from_secs(60), waiting).await.unwrap().unwrap().ok();
+        assert_eq!(state.embed_permits.available_permits(), 1);
+    }
+}
@@ -0,0 +1,138 @@
+// src/handlers/export.rs
+
+use std::future::Future;
+use std::io;
+
+use axum::{
+    body::{Body, Bytes},
+    extract::{Path, State},
+    http::header,
+    response::{IntoResponse, Response},
+};
+use futures_util::stream::{self, Stream};
+use qdrant_client::qdrant::PointId;
+use vb::qdrant::{self, ExportedPoint};
+
+use crate::errors::AppError;
+use crate::handlers::search::{document_collection, resolve_file_name};
+use crate::types::AppState;
+
+/// `GET /api/export/:id` — every chunk stored for a document as JSON lines of
+/// `{id, text, page, vector}`, plus `kind`, `field`, `parent_id`,
+/// `parent_text`, `seq` and `lang` where the chunk has them.
+///
+/// Points are streamed one Qdrant scroll page at a time, so a large document
+/// never sits in memory whole. A scroll failing part-way cuts the body short.
+/// Points missing a field are left out, and how many is logged.
+pub async fn export_document(State(state): State<AppState>, Path(id): Path<String>) -> Result<Response, AppError> {
+    let file_name = resolve_file_name(&state, &id).await?;
+    let client = state.qdrant.clone();
+    let collection = document_collection(&state, &id).await?;
+    let lang = state.lang_map.read().await.get(&id).cloned();
+    let lines = export_lines(move |offset| {
+        let client = client.clone();
+        let collection = collection.clone();
+        let file_name = file_name.clone();
+        let lang = lang.clone();
+        async move {
+            let mut page = qdrant::export_page(&client, &collection, &file_name, offset)
+                .await
+                .map_err(|e| anyhow::anyhow!("Qdrant scroll failed for '{}': {:?}", file_name, e))?;
+            if page.skipped > 0 {
+                eprintln!("Export of '{}' left out {} points missing their id, text, page or vector", file_name, page.skipped);
+            }
+            for point in &mut page.points {
+                point.lang = lang.clone();
+            }
+            Ok((page.points, page.next))
+        }
+    });
+    Ok(([(header::CONTENT_TYPE, "application/x-ndjson")], Body::from_stream(lines)).into_response())
+}
+
+/// One chunk of JSON lines per page `fetch` returns, until it reports no next offset.
+fn export_lines<F, Fut>(fetch: F) -> impl Stream<Item = io::Result<Bytes>>
+where
+    F: FnMut(Option<PointId>) -> Fut,
+    Fut: Future<Output = anyhow::Result<(Vec<ExportedPoint>, Option<PointId>)>>,
+{
+    // `Some(offset)` is the next page to fetch (`Some(None)` the first); `None` means done
+    stream::unfold((fetch, Some(None)), |(mut fetch, next)| async move {
+        let offset = next?;
+        match fetch(offset).await {
+            Ok((points, next_offset)) => {
+                let mut lines = Vec::new();
+                for point in &points {
+                    serde_json::to_writer(&mut lines, point).expect("exported points always serialize");
+                    lines.push(b'\n');
+                }
+                Some((Ok(Bytes::from(lines)), (fetch, next_offset.map(Some))))
+            }
+            Err(e) => {
+                eprintln!("Export stopped early: {:?}", e);
+                Some((Err(io::Error::other(e.to_string())), (fetch, None)))
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::StreamExt;
+    use vb::PageNumber;
+
+    fn stored(n: u64) -> ExportedPoint {
+        ExportedPoint::new(n.to_string(), format!("Chunk {} of the lecture notes", n), PageNumber::new(1 + n as u16 / 2), vec![n as f32, 0.5])
+    }
+
+    #[tokio::test]
+    async fn export_walks_every_scroll_page() {
+        // Three scroll pages of two points, chained by the id the next page starts at
+        let fetch = |offset: Option<PointId>| async move {
+            let start = match offset.as_ref().and_then(qdrant::point_id_string) {
+                Some(id) => id.parse::<u64>().unwrap(),
+                None => 0,
+            };
+            let points = vec![stored(start), stored(start + 1)];
+            let next = (start + 2 < 6).then(|| PointId::from(start + 2));
+            Ok((points, next))
+        };
+
+        let chunks: Vec<io::Result<Bytes>> = export_lines(fetch).collect().await;
+        assert_eq!(chunks.len(), 3);
+        let body: Vec<u8> = chunks.into_iter().flat_map(|chunk| chunk.unwrap().to_vec()).collect();
+        let lines: Vec<serde_json::Value> = String::from_utf8(body)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+
+        let ids: Vec<&str> = lines.iter().map(|line| line["id"].as_str().unwrap()).collect();
+        assert_eq!(ids, ["0", "1", "2", "3", "4", "5"]);
+        assert_eq!(lines[5]["text"], "Chunk 5 of the lecture notes");
+        assert_eq!(lines[5]["page"], 3);
+        assert_eq!(lines[5]["vector"], serde_json::json!([5.0, 0.5]));
+    }
+
+    #[tokio::test]
+    async fn failed_scroll_ends_the_export_with_an_error() {
+        let mut calls = 0;
+        let fetch = move |_offset: Option<PointId>| {
+            calls += 1;
+            let first = calls == 1;
+            async move {
+                if first {
+                    Ok((vec![stored(0)], Some(PointId::from(1))))
+                } else {
+                    Err(anyhow::anyhow!("connection reset"))
+                }
+            }
+        };
+
+        let chunks: Vec<io::Result<Bytes>> = export_lines(fetch).collect().await;
+        assert_eq!(chunks.len(), 2);
+        assert!(chunks[0].is_ok());
+        assert!(chunks[1].is_err());
+    }
+}
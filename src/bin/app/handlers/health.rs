@@ -0,0 +1,91 @@
+// src/handlers/health.rs
+
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Json, Response},
+};
+use serde_json::{json, Value};
+use vb::embed;
+
+use crate::types::{AppState, HealthQuery};
+
+/// `GET /health` — Qdrant reachability; with `deep=true` also checks that the
+/// embedding model loads and can embed. 503 if anything checked is down.
+pub async fn health(
+    State(state): State<AppState>,
+    Query(params): Query<HealthQuery>,
+) -> Response {
+    let qdrant = state
+        .qdrant
+        .health_check()
+        .await
+        .map(|_| ())
+        .map_err(|e| e.to_string());
+
+    let model = if params.deep {
+        // First call loads the model, so keep it off the async workers
+        let warmed = tokio::task::spawn_blocking(embed::warmup)
+            .await
+            .map_err(|e| e.to_string())
+            .and_then(|r| r.map_err(|e| e.to_string()));
+        Some(warmed)
+    } else {
+        None
+    };
+
+    health_response(qdrant, model)
+}
+
+fn component(check: &Result<(), String>) -> Value {
+    match check {
+        Ok(()) => json!({ "ok": true }),
+        Err(e) => json!({ "ok": false, "error": e }),
+    }
+}
+
+// `model` is only present for deep checks
+fn health_response(qdrant: Result<(), String>, model: Option<Result<(), String>>) -> Response {
+    let healthy = qdrant.is_ok() && model.as_ref().is_none_or(Result::is_ok);
+    let mut body = json!({
+        "status": if healthy { "ok" } else { "unhealthy" },
+        "qdrant": component(&qdrant),
+    });
+    if let Some(model) = &model {
+        body["model"] = component(model);
+    }
+
+    let status = if healthy { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+    (status, Json(body)).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn body(resp: Response) -> Value {
+        let bytes = axum::body::to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+        serde_json::from_slice(&bytes).unwrap()
+    }
+
+    #[tokio::test]
+    async fn deep_check_reports_model_status() {
+        let resp = health_response(Ok(()), Some(Err("model/tokenizer.json not found".to_string())));
+        assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let value = body(resp).await;
+        assert_eq!(value["status"], "unhealthy");
+        assert_eq!(value["qdrant"]["ok"], true);
+        assert_eq!(value["model"]["ok"], false);
+
+        let resp = health_response(Ok(()), Some(Ok(())));
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(body(resp).await["model"]["ok"], true);
+    }
+
+    #[tokio::test]
+    async fn shallow_check_leaves_model_out() {
+        let value = body(health_response(Ok(()), None)).await;
+        assert_eq!(value["status"], "ok");
+        assert!(value.get("model").is_none());
+    }
+}
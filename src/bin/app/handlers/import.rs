@@ -0,0 +1,255 @@
+// src/handlers/import.rs
+
+use axum::{
+    extract::{Multipart, State},
+    Json,
+};
+use uuid::Uuid;
+use vb::{chunk, embed, page, PdfSource};
+use vb::qdrant::{self, ExportedPoint};
+
+use crate::errors::AppError;
+use crate::handlers::upload::multipart_error;
+use crate::metrics::metrics;
+use crate::types::{AppState, ImportResponse};
+
+/// `POST /api/import` — load a document from `GET /api/export/:id` output
+/// without re-embedding it.
+///
+/// Multipart fields: `chunks` holds the JSON lines; the optional `pdf` is the
+/// original file, needed to highlight hits. Points get fresh ids, so importing
+/// into the instance the export came from leaves the original document alone.
+pub async fn import_document(
+    State(state): State<AppState>,
+    mut multipart: Multipart,
+) -> Result<Json<ImportResponse>, AppError> {
+    let mut chunks = None;
+    let mut pdf = None;
+    while let Some(field) = multipart.next_field().await.map_err(multipart_error)? {
+        match field.name() {
+            Some("chunks") => chunks = Some(field.text().await.map_err(multipart_error)?),
+            Some("pdf") => {
+                let filename = field.file_name().unwrap_or("imported.pdf").to_string();
+                pdf = Some((filename, field.bytes().await.map_err(multipart_error)?.to_vec()));
+            }
+            _ => continue,
+        }
+    }
+    let chunks = chunks.ok_or_else(|| AppError::bad_request("No chunks field found in multipart body"))?;
+    let points = parse_export(&chunks, embed::get_dim())?;
+    let lang = export_lang(&points)?;
+    embed::model_dir(&state.config.embed, lang.as_deref()).map_err(|e| AppError::bad_request(e.to_string()))?;
+
+    let filename = pdf.as_ref().map_or("imported.pdf", |(name, _)| name.as_str()).to_string();
+    let unique_filename = qdrant::unique_filename(&filename);
+    let uploaded_at = qdrant::unix_now();
+    let imported = points.len();
+    {
+        let _timer = metrics().qdrant_seconds.with_label_values(&["upsert"]).start_timer();
+        let search = &state.config.search;
+        let collection = state.config.collection(lang.as_deref())?;
+        qdrant::ensure_collection(&state.qdrant, &collection, search.metric, search.precision)
+            .await
+            .map_err(|e| AppError::from(anyhow::anyhow!("Qdrant collection setup failed: {:?}", e)))?;
+        qdrant::import_points(&state.qdrant, &collection, &unique_filename, uploaded_at, points, search.precision)
+            .await
+            .map_err(|e| AppError::from(anyhow::anyhow!("Qdrant import failed for '{}': {:?}", unique_filename, e)))?;
+    }
+
+    let document = ImportedDocument { unique_filename, filename, uploaded_at, lang };
+    let id = register_import(&state, document, pdf.map(|(_, bytes)| bytes)).await;
+    Ok(Json(ImportResponse { id, imported }))
+}
+
+/// What [`register_import`] records about an imported document.
+struct ImportedDocument {
+    unique_filename: String,
+    filename: String,
+    uploaded_at: i64,
+    lang: Option<String>,
+}
+
+// Record an imported document under a new id, with what an upload records once
+// indexing finishes; page labels come from the PDF, when there is one
+async fn register_import(state: &AppState, document: ImportedDocument, pdf: Option<Vec<u8>>) -> String {
+    let id = Uuid::new_v4().to_string();
+    state.id_map.write().await.insert(id.clone(), document.unique_filename);
+    state.name_map.write().await.insert(id.clone(), document.filename);
+    state.uploaded_map.write().await.insert(id.clone(), document.uploaded_at);
+    if let Some(lang) = document.lang {
+        state.lang_map.write().await.insert(id.clone(), lang);
+    }
+    state.ready_set.write().await.insert(id.clone());
+    if let Some(bytes) = pdf {
+        let labels = chunk::load_document(PdfSource::Bytes(bytes.clone())).ok().and_then(|doc| page::page_labels(&doc));
+        if let Some(labels) = labels {
+            state.page_label_map.write().await.insert(id.clone(), labels);
+        }
+        state.bytes_map.write().await.insert(id.clone(), bytes);
+    }
+    id
+}
+
+/// The language every point was exported with, lowercased. One export is one
+/// document, so points that disagree are rejected.
+fn export_lang(points: &[ExportedPoint]) -> Result<Option<String>, AppError> {
+    let lang = points.first().and_then(|point| point.lang.as_deref()).map(str::to_ascii_lowercase);
+    if let Some(other) = points.iter().find(|point| point.lang.as_deref().map(str::to_ascii_lowercase) != lang) {
+        return Err(AppError::bad_request(format!(
+            "Chunks mix languages {:?} and {:?}; import one document at a time",
+            lang, other.lang
+        )));
+    }
+    Ok(lang)
+}
+
+/// Parse export JSON lines, rejecting the whole import on the first bad line
+/// or a vector that doesn't fit the collection's `dim`.
+fn parse_export(body: &str, dim: usize) -> Result<Vec<ExportedPoint>, AppError> {
+    let mut points = Vec::new();
+    for (i, line) in body.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+        let point: ExportedPoint = serde_json::from_str(line)
+            .map_err(|e| AppError::bad_request(format!("Line {}: {}", i + 1, e)))?;
+        if point.vector.len() != dim {
+            return Err(AppError::bad_request(format!(
+                "Line {}: vector has {} dimensions, the collection expects {}",
+                i + 1,
+                point.vector.len(),
+                dim
+            )));
+        }
+        points.push(point);
+    }
+    if points.is_empty() {
+        return Err(AppError::bad_request("No chunks to import"));
+    }
+    Ok(points)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use vb::qdrant::Metric;
+    use vb::PageNumber;
+
+    fn stored(id: &str, text: &str, page: u16, vector: [f32; 3]) -> ExportedPoint {
+        ExportedPoint::new(id.to_string(), text.to_string(), PageNumber::new(page), vector.to_vec())
+    }
+
+    fn document(unique_filename: &str, filename: &str, lang: Option<&str>) -> ImportedDocument {
+        ImportedDocument {
+            unique_filename: unique_filename.to_string(),
+            filename: filename.to_string(),
+            uploaded_at: 0,
+            lang: lang.map(str::to_string),
+        }
+    }
+
+    // Texts best first for `query`, the way Qdrant ranks a Dot collection
+    fn ranked(points: &[ExportedPoint], query: &[f32]) -> Vec<String> {
+        let mut scored: Vec<(f32, &ExportedPoint)> =
+            points.iter().map(|p| (Metric::Dot.compare(query, &p.vector), p)).collect();
+        scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+        scored.into_iter().map(|(_, p)| p.text.clone()).collect()
+    }
+
+    #[test]
+    fn exported_lines_import_to_the_same_points() {
+        let original = vec![
+            stored("7", "Dropout zeroes random activations.", 1, [0.8, 0.6, 0.0]),
+            stored("8", "Momentum keeps a running average of gradients.", 2, [0.0, 0.6, 0.8]),
+            stored("9", "Batch norm rescales layer inputs.", 2, [0.6, 0.0, 0.8]),
+        ];
+        // What GET /api/export/:id writes, blank trailing line included
+        let body: String = original.iter().map(|p| serde_json::to_string(p).unwrap() + "\n").collect::<String>() + "\n";
+
+        let imported = parse_export(&body, 3).ok().unwrap();
+        assert_eq!(imported, original);
+        let query = [0.7, 0.7, 0.1];
+        assert_eq!(ranked(&imported, &query), ranked(&original, &query));
+    }
+
+    #[tokio::test]
+    async fn import_stores_what_the_export_wrote_under_new_ids() {
+        let exported = [
+            ExportedPoint {
+                kind: Some("heading".to_string()),
+                lang: Some("de".to_string()),
+                ..stored("7", "Optimierung", 1, [0.6, 0.8, 0.0])
+            },
+            ExportedPoint {
+                parent_id: Some("1-0".to_string()),
+                parent_text: Some("Momentum glättet Schritte. Es dämpft Schwingungen.".to_string()),
+                seq: Some(0),
+                lang: Some("de".to_string()),
+                ..stored("8", "Momentum glättet Schritte.", 1, [0.1, 0.2, 0.3])
+            },
+        ];
+        let body: String = exported.iter().map(|p| serde_json::to_string(p).unwrap() + "\n").collect();
+
+        // The handler's steps, up to the upsert
+        let points = parse_export(&body, 3).ok().unwrap();
+        let lang = export_lang(&points).ok().unwrap();
+        assert_eq!(lang.as_deref(), Some("de"));
+        let structs = qdrant::import_structs("skript.pdf_3", 0, points, vb::qdrant::Precision::F16);
+
+        let ids: Vec<String> = structs.iter().filter_map(|p| p.id.as_ref().and_then(qdrant::point_id_string)).collect();
+        assert_eq!(ids.len(), 2);
+        assert!(ids.iter().all(|id| id != "7" && id != "8"), "{:?}", ids);
+        assert_eq!(structs[0].payload["kind"].as_str().map(String::as_str), Some("heading"));
+        let body_chunk = &structs[1].payload;
+        assert_eq!(body_chunk["kind"].as_str().map(String::as_str), Some("body"));
+        assert_eq!(body_chunk["seq"].as_integer(), Some(0));
+        assert_eq!(body_chunk["parent_text"].as_str().map(String::as_str), exported[1].parent_text.as_deref());
+        // Stored at the collection's precision, not as exported
+        let vector = match structs[1].vectors.as_ref().and_then(|v| v.vectors_options.clone()) {
+            Some(qdrant_client::qdrant::vectors::VectorsOptions::Vector(vector)) => vector.try_into_dense().unwrap(),
+            other => panic!("unexpected vectors {:?}", other),
+        };
+        assert_eq!(vector, vb::qdrant::Precision::F16.apply(vec![0.1, 0.2, 0.3]));
+        assert_ne!(vector, vec![0.1, 0.2, 0.3]);
+
+        let state = AppState::for_tests();
+        let id = register_import(&state, document("skript.pdf_3", "skript.pdf", lang.as_deref()), None).await;
+        assert_eq!(state.lang_map.read().await.get(&id).map(String::as_str), Some("de"));
+
+        let mixed = [exported[0].clone(), stored("9", "No language.", 2, [1.0, 0.0, 0.0])];
+        let status = export_lang(&mixed).err().map(|e| axum::response::IntoResponse::into_response(e).status());
+        assert_eq!(status, Some(axum::http::StatusCode::BAD_REQUEST));
+    }
+
+    #[tokio::test]
+    async fn imported_pdf_keeps_its_page_labels() {
+        use crate::test_pdf::{to_bytes, TestPdf};
+        use lopdf::dictionary;
+
+        let mut pdf = TestPdf::new();
+        for _ in 0..3 {
+            pdf.text_page(&[]);
+        }
+        let labels = dictionary! { "Nums" => vec![0.into(), dictionary! { "S" => "r" }.into(), 2.into(), dictionary! { "S" => "D" }.into()] };
+        let bytes = to_bytes(pdf.finish(dictionary! { "PageLabels" => labels }));
+
+        let state = AppState::for_tests();
+        let id = register_import(&state, document("book.pdf_1", "book.pdf", None), Some(bytes)).await;
+        assert_eq!(state.page_label_map.read().await.get(&id).unwrap(), &["i", "ii", "1"]);
+        assert_eq!(state.id_map.read().await.get(&id).unwrap(), "book.pdf_1");
+
+        // Without the PDF there is nothing to read labels from
+        let id = register_import(&state, document("notes.pdf_2", "notes.pdf", None), None).await;
+        assert!(!state.page_label_map.read().await.contains_key(&id));
+    }
+
+    #[test]
+    fn wrong_vector_size_is_rejected_with_its_line() {
+        let body = [
+            serde_json::to_string(&stored("1", "Fits.", 1, [1.0, 0.0, 0.0])).unwrap(),
+            r#"{"id":"2","text":"Too short.","page":1,"vector":[1.0,0.0]}"#.to_string(),
+        ]
+        .join("\n");
+
+        let err = parse_export(&body, 3).err().unwrap();
+        assert_eq!(err.to_json()["error"]["message"], "Line 2: vector has 2 dimensions, the collection expects 3");
+        assert!(parse_export("\n\n", 3).is_err());
+    }
+}
@@ -0,0 +1,178 @@
+// src/handlers/library.rs
+
+use std::collections::{BTreeSet, HashMap};
+use axum::{
+    extract::{Query, State},
+    Json,
+};
+use qdrant_client::qdrant::ScoredPoint;
+use vb::qdrant::{QueryOptions, RESULT_LIMIT};
+use vb::qdrant;
+
+use crate::errors::AppError;
+use crate::handlers::embed::embed_query;
+use crate::handlers::search::page_from_payload;
+use crate::metrics::metrics;
+use crate::types::{AppState, LibraryHit, LibrarySearchQuery, LibrarySearchResponse};
+
+// Hits fetched per returned hit when a boost may reorder them
+const BOOST_POOL_FACTOR: usize = 4;
+
+/// `GET /api/search-all?q=&boost_id=` — search every indexed document at once,
+/// in each model's collection the documents were indexed into.
+/// Hits from `boost_id` have their score multiplied by the configured
+/// `document_boost` and are reordered with the rest; nothing is filtered out.
+pub async fn search_library(
+    State(state): State<AppState>,
+    Query(params): Query<LibrarySearchQuery>,
+) -> Result<Json<LibrarySearchResponse>, AppError> {
+    let query = params.q.trim();
+    let min_chars = state.config.search.min_query_chars;
+    if query.chars().count() < min_chars {
+        return Err(AppError::bad_request(format!(
+            "Query {:?} is too short; use at least {} characters",
+            query, min_chars
+        )));
+    }
+    // Hits are reported by id, so only indexed documents can be told apart
+    let names: HashMap<String, String> = state
+        .id_map
+        .read()
+        .await
+        .iter()
+        .filter(|(_, name)| !matches!(name.as_str(), "processing" | "failed" | "empty"))
+        .map(|(id, name)| (name.clone(), id.clone()))
+        .collect();
+    if let Some(boost_id) = &params.boost_id
+        && !names.values().any(|id| id == boost_id)
+    {
+        return Err(AppError::not_found(format!("No document found for id: {}", boost_id)));
+    }
+
+    metrics().searches.inc();
+    let search = &state.config.search;
+    // A boost can only promote hits it sees, so fetch past the usual cut
+    let limit = if params.boost_id.is_some() { RESULT_LIMIT * BOOST_POOL_FACTOR } else { RESULT_LIMIT };
+    let options = QueryOptions {
+        payload_fields: search.payload_fields.clone(),
+        metric: search.metric,
+        limit,
+        ..QueryOptions::default()
+    };
+    let langs = document_langs(names.values(), &*state.lang_map.read().await);
+    let mut points = Vec::new();
+    for lang in langs {
+        let vector = embed_query(&state, query, lang.as_deref()).await.map_err(AppError::from)?;
+        let collection = state.config.collection(lang.as_deref())?;
+        let resp = qdrant::run_library_query(&state.qdrant, &collection, vector, &options)
+            .await
+            .map_err(|e| AppError::from(anyhow::anyhow!("Qdrant query failed: {:?}", e)))?;
+        points.extend(resp.result);
+    }
+
+    let boost = params.boost_id.as_deref().map(|id| (id, search.document_boost));
+    let mut results = library_hits(points, &names, boost);
+    results.truncate(RESULT_LIMIT);
+    Ok(Json(LibrarySearchResponse { results }))
+}
+
+/// The upload languages of `ids`, one per collection to search; `None` is the
+/// default model's.
+fn document_langs<'a>(ids: impl Iterator<Item = &'a String>, lang_map: &HashMap<String, String>) -> BTreeSet<Option<String>> {
+    ids.map(|id| lang_map.get(id).cloned()).collect()
+}
+
+/// Hits from every document, best first. `names` maps indexed file names to
+/// ids; points of documents it doesn't know are dropped. `boost` multiplies
+/// one document's scores before sorting.
+fn library_hits(points: Vec<ScoredPoint>, names: &HashMap<String, String>, boost: Option<(&str, f32)>) -> Vec<LibraryHit> {
+    let mut hits: Vec<LibraryHit> = points
+        .into_iter()
+        .filter_map(|point| {
+            let id = names.get(point.payload.get("filename")?.as_str()?)?;
+            let text = point.payload.get("text")?.as_str()?.to_string();
+            let page = page_from_payload(&point.payload.get("page")?.kind)?;
+            let score = match boost {
+                Some((boosted, factor)) if boosted == id => point.score * factor,
+                _ => point.score,
+            };
+            Some(LibraryHit { id: id.clone(), page, text, score })
+        })
+        .collect();
+    hits.sort_by(|a, b| {
+        b.score
+            .total_cmp(&a.score)
+            .then_with(|| a.id.cmp(&b.id))
+            .then(a.page.cmp(&b.page))
+    });
+    hits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use qdrant_client::qdrant::Value;
+
+    fn point(filename: &str, page: i64, text: &str, score: f32) -> ScoredPoint {
+        ScoredPoint {
+            payload: HashMap::from([
+                ("filename".to_string(), Value::from(filename.to_string())),
+                ("page".to_string(), Value::from(page)),
+                ("text".to_string(), Value::from(text.to_string())),
+            ]),
+            score,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn boosting_a_document_promotes_it_above_a_slightly_better_one() {
+        let names = HashMap::from([
+            ("paper.pdf_1".to_string(), "paper".to_string()),
+            ("notes.pdf_2".to_string(), "notes".to_string()),
+        ]);
+        let points = || {
+            vec![
+                point("paper.pdf_1", 3, "Gradient descent converges.", 0.82),
+                point("notes.pdf_2", 1, "Gradient descent, step by step.", 0.78),
+                point("gone.pdf_3", 1, "Expired document.", 0.95),
+            ]
+        };
+        let order = |hits: Vec<LibraryHit>| hits.into_iter().map(|hit| (hit.id, hit.page.get())).collect::<Vec<_>>();
+
+        assert_eq!(order(library_hits(points(), &names, None)), [("paper".to_string(), 3), ("notes".to_string(), 1)]);
+
+        let boosted = library_hits(points(), &names, Some(("notes", 1.2)));
+        assert!((boosted[0].score - 0.78 * 1.2).abs() < 1e-6);
+        assert_eq!(order(boosted), [("notes".to_string(), 1), ("paper".to_string(), 3)]);
+    }
+
+    #[test]
+    fn boost_promotes_a_document_from_outside_the_top_hits() {
+        let mut names = HashMap::from([("notes.pdf_9".to_string(), "notes".to_string())]);
+        let mut points: Vec<ScoredPoint> = (0..RESULT_LIMIT)
+            .map(|i| {
+                let filename = format!("paper{}.pdf_{}", i, i);
+                names.insert(filename.clone(), format!("paper{}", i));
+                point(&filename, 1, "Gradient descent converges.", 0.9 - i as f32 * 0.01)
+            })
+            .collect();
+        // Sixth best: cut by a plain search, but fetched when boosting
+        points.push(point("notes.pdf_9", 2, "Gradient descent, step by step.", 0.84));
+
+        let mut hits = library_hits(points, &names, Some(("notes", 1.2)));
+        hits.truncate(RESULT_LIMIT);
+        assert_eq!(hits.len(), RESULT_LIMIT);
+        assert_eq!((hits[0].id.as_str(), hits[0].page.get()), ("notes", 2));
+    }
+
+    #[test]
+    fn every_upload_language_gets_its_collection_searched() {
+        let ids = ["en".to_string(), "de1".to_string(), "de2".to_string()];
+        let lang_map = HashMap::from([("de1".to_string(), "de".to_string()), ("de2".to_string(), "de".to_string())]);
+
+        let langs: Vec<Option<String>> = document_langs(ids.iter(), &lang_map).into_iter().collect();
+        assert_eq!(langs, [None, Some("de".to_string())]);
+        assert!(document_langs([].iter(), &lang_map).is_empty());
+    }
+}
@@ -0,0 +1,162 @@
+// src/handlers/live_search.rs
+
+use std::future::Future;
+use std::time::Duration;
+
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        Query, State,
+    },
+    response::Response,
+};
+use serde_json::json;
+use tokio::sync::mpsc;
+
+use crate::handlers::search::{highlights_for_queries, SearchOptions};
+use crate::types::{AppState, LiveSearchQuery};
+
+// Quiet period before a query is searched; keystrokes inside it are coalesced
+const DEBOUNCE: Duration = Duration::from_millis(150);
+
+/// `GET /ws/search?id=` — each text message is a query for that document.
+///
+/// Replies are `{"query", "highlights"}` or the usual `{"error"}` body. A
+/// query that arrives while an older one is still running cancels it, so only
+/// the latest query gets a reply.
+pub async fn live_search(
+    ws: WebSocketUpgrade,
+    State(state): State<AppState>,
+    Query(params): Query<LiveSearchQuery>,
+) -> Response {
+    ws.on_upgrade(move |socket| handle_socket(socket, state, params.id))
+}
+
+async fn handle_socket(mut socket: WebSocket, state: AppState, id: String) {
+    let (query_tx, query_rx) = mpsc::channel::<String>(16);
+    let (reply_tx, mut reply_rx) = mpsc::channel::<String>(16);
+
+    let session = tokio::spawn(run_session(query_rx, reply_tx, DEBOUNCE, move |query| {
+        let state = state.clone();
+        let id = id.clone();
+        async move {
+            let body = match highlights_for_queries(&state, &id, &[query.as_str()], &SearchOptions::default()).await {
+                Ok(found) => json!({ "query": query, "highlights": found.highlights, "warnings": found.warnings }),
+                Err(e) => e.to_json(),
+            };
+            body.to_string()
+        }
+    }));
+
+    loop {
+        tokio::select! {
+            msg = socket.recv() => match msg {
+                Some(Ok(Message::Text(query))) => {
+                    if query_tx.send(query.trim().to_string()).await.is_err() {
+                        break;
+                    }
+                }
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => {}
+            },
+            Some(reply) = reply_rx.recv() => {
+                if socket.send(Message::Text(reply)).await.is_err() {
+                    break;
+                }
+            }
+        }
+    }
+
+    session.abort();
+}
+
+/// Feed queries to `search` one at a time, always preferring the newest.
+///
+/// Queries are debounced, and an in-flight search is dropped as soon as a
+/// newer query arrives. Ends when either channel closes.
+async fn run_session<S, Fut, T>(
+    mut queries: mpsc::Receiver<String>,
+    replies: mpsc::Sender<T>,
+    debounce: Duration,
+    search: S,
+) where
+    S: Fn(String) -> Fut,
+    Fut: Future<Output = T>,
+{
+    let mut next_query: Option<String> = None;
+
+    loop {
+        let mut query = match next_query.take() {
+            Some(query) => query,
+            None => match queries.recv().await {
+                Some(query) => query,
+                None => return,
+            },
+        };
+
+        // Keep taking newer queries until the input goes quiet
+        loop {
+            tokio::select! {
+                newer = queries.recv() => match newer {
+                    Some(newer) => query = newer,
+                    None => return,
+                },
+                _ = tokio::time::sleep(debounce) => break,
+            }
+        }
+
+        if query.is_empty() {
+            continue;
+        }
+
+        tokio::select! {
+            reply = search(query) => {
+                if replies.send(reply).await.is_err() {
+                    return;
+                }
+            }
+            newer = queries.recv() => match newer {
+                // Dropping the search future cancels it
+                Some(newer) => next_query = Some(newer),
+                None => return,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn newer_query_cancels_in_flight_search() {
+        let (query_tx, query_rx) = mpsc::channel(16);
+        let (reply_tx, mut reply_rx) = mpsc::channel(16);
+
+        let session = tokio::spawn(run_session(
+            query_rx,
+            reply_tx,
+            Duration::from_millis(10),
+            |query: String| async move {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                query
+            },
+        ));
+
+        query_tx.send("neural".to_string()).await.unwrap();
+        // Past the debounce, so the first search is already running
+        tokio::time::sleep(Duration::from_millis(40)).await;
+        query_tx.send("neural networks".to_string()).await.unwrap();
+
+        let reply = tokio::time::timeout(Duration::from_secs(1), reply_rx.recv())
+            .await
+            .unwrap();
+        assert_eq!(reply.as_deref(), Some("neural networks"));
+
+        // Nothing else is coming for the superseded query
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert!(reply_rx.try_recv().is_err());
+
+        session.abort();
+    }
+}
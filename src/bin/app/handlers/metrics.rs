@@ -0,0 +1,65 @@
+// src/handlers/metrics.rs
+
+use axum::{http::header, response::IntoResponse};
+
+use crate::metrics::metrics;
+
+pub async fn serve_metrics() -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, prometheus::TEXT_FORMAT)],
+        metrics().render(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::extract::State;
+    use axum_extra::extract::Query;
+
+    use crate::handlers::search::search_with_bboxes;
+    use crate::types::{AppState, SearchWithBboxQuery};
+
+    async fn scrape() -> String {
+        let resp = serve_metrics().await.into_response();
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+        String::from_utf8(body.to_vec()).unwrap()
+    }
+
+    fn counter(text: &str, name: &str) -> u64 {
+        text.lines()
+            .find_map(|line| line.strip_prefix(name)?.trim().parse().ok())
+            .unwrap_or(0)
+    }
+
+    #[tokio::test]
+    async fn search_increments_search_counter() {
+        // The unknown id fails before any Qdrant call
+        let state = AppState::for_tests();
+
+        let before = counter(&scrape().await, "pdf_searches_total");
+
+        let params = SearchWithBboxQuery {
+            id: "missing".to_string(),
+            q: vec!["gradient".to_string()],
+            max_text_len: None,
+            uploaded_after: None,
+            uploaded_before: None,
+            neighbors: false,
+            origin: Default::default(),
+            units: Default::default(),
+            padding: None,
+            within_ids: vec![],
+            lang: None,
+            diversity: None,
+            case_sensitive: false,
+            retrieve_score: None,
+            highlight_score: None,
+        };
+        let _ = search_with_bboxes(State(state), Query(params)).await;
+
+        let after = scrape().await;
+        assert!(counter(&after, "pdf_searches_total") > before);
+        assert!(after.contains("pdf_request_errors_total{endpoint=\"search\"}"));
+    }
+}
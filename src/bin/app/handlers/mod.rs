@@ -1,5 +1,19 @@
 // src/handlers/mod.rs
 
 pub mod upload;
+pub mod resumable;
 pub mod search;
-pub mod ready;
\ No newline at end of file
+pub mod ready;
+pub mod reindex;
+pub mod document;
+pub mod live_search;
+pub mod metrics;
+pub mod health;
+pub mod selection;
+pub mod compare;
+pub mod export;
+pub mod import;
+pub mod admin;
+pub mod suggest;
+pub mod embed;
+pub mod library;
@@ -1,12 +1,10 @@
 use axum::{
     extract::{Query, State},
 };
-use axum::{
-    response::{IntoResponse, Json, Response},
-    http::StatusCode,
-};
+use axum::response::{IntoResponse, Json, Response};
 use std::collections::HashMap;
 use serde_json::json;
+use crate::errors::AppError;
 use crate::types::{AppState};
 
 // handlers/ready.rs
@@ -16,8 +14,22 @@ pub async fn is_ready(
 ) -> Response {
     if let Some(id) = params.get("id") {
         let ready = state.ready_set.read().await.contains(id);
-        Json(json!({ "ready": ready })).into_response()
+        let indexed_through = state.progress_map.read().await.get(id).copied();
+        let status = document_status(state.id_map.read().await.get(id).map(String::as_str));
+        Json(json!({ "ready": ready, "status": status, "indexed_through": indexed_through }))
+            .into_response()
     } else {
-        (StatusCode::BAD_REQUEST, "Missing id").into_response()
+        AppError::bad_request("Missing id").into_response()
+    }
+}
+
+// id_map holds either a marker string or the Qdrant filename once indexed
+pub(crate) fn document_status(entry: Option<&str>) -> &'static str {
+    match entry {
+        None => "unknown",
+        Some("processing") => "processing",
+        Some("failed") => "failed",
+        Some("empty") => "empty",
+        Some(_) => "indexed",
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,170 @@
+// src/handlers/reindex.rs
+
+use axum::{
+    extract::{Path, Query, State},
+    Json,
+};
+use vb::qdrant;
+
+use crate::errors::AppError;
+use crate::metrics::metrics;
+use crate::handlers::search::{document_collection, forget_searches};
+use crate::handlers::upload::{check_chunk_size, process_file, ProcessOutcome};
+use crate::types::{AppState, ReindexQuery, ReindexResponse};
+
+pub async fn reindex_document(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(params): Query<ReindexQuery>,
+) -> Result<Json<ReindexResponse>, AppError> {
+    check_chunk_size(params.chunk_size)?;
+    let bytes = state
+        .bytes_map
+        .read()
+        .await
+        .get(&id)
+        .cloned()
+        .ok_or_else(|| AppError::not_found(format!("No bytes found for id: {}", id)))?;
+
+    let filename = state
+        .name_map
+        .read()
+        .await
+        .get(&id)
+        .cloned()
+        .ok_or_else(|| AppError::not_found(format!("No filename found for id: {}", id)))?;
+
+    let old_unique_filename = state
+        .id_map
+        .read()
+        .await
+        .get(&id)
+        .cloned()
+        .filter(|name| !matches!(name.as_str(), "processing" | "failed" | "empty"));
+    let indexed_through = state.progress_map.read().await.get(&id).copied();
+
+    let mut config = state.config.chunking.clone();
+    if let Some(chunk_size) = params.chunk_size {
+        config.max_chars = chunk_size;
+    }
+
+    let collection = document_collection(&state, &id).await?;
+    // Resuming keeps the pages already in Qdrant and continues after the last one.
+    // Starting over indexes under a new name and keeps the old points searchable
+    // until the new ones are all written, so a failed reindex loses nothing.
+    let (unique_filename, resume_after, replaced) = match (params.resume, old_unique_filename, indexed_through) {
+        (true, Some(old), Some(last)) => {
+            // Searches against this id would hit a half-empty index until we are done
+            state.ready_set.write().await.remove(&id);
+            (old, Some(last), None)
+        }
+        (_, old, _) => (qdrant::unique_filename(&filename), None, old),
+    };
+    let old_terms = match resume_after {
+        Some(_) => None,
+        None => {
+            state.progress_map.write().await.remove(&id);
+            state.terms_map.write().await.remove(&id)
+        }
+    };
+    forget_searches(&state, &id).await;
+
+    let result = process_file(
+        &state,
+        &id,
+        &unique_filename,
+        bytes.into(),
+        &config,
+        resume_after,
+    )
+    .await;
+
+    let outcome = match result {
+        Ok(outcome) => outcome,
+        Err(e) => {
+            match replaced {
+                Some(old) => {
+                    eprintln!("Reindexing {} failed, keeping its previous index {}: {:?}", id, old, e);
+                    // Pages written under the new name before the failure are unreachable
+                    if state.progress_map.read().await.contains_key(&id)
+                        && let Err(e) = qdrant::delete_by_filename(&state.qdrant, &collection, &unique_filename).await
+                    {
+                        eprintln!("Couldn't delete the partial reindex of {} under {}: {:?}", id, unique_filename, e);
+                    }
+                    match indexed_through {
+                        Some(last) => state.progress_map.write().await.insert(id.clone(), last),
+                        None => state.progress_map.write().await.remove(&id),
+                    };
+                    if let Some(terms) = old_terms {
+                        state.terms_map.write().await.insert(id.clone(), terms);
+                    }
+                    state.ready_set.write().await.insert(id.clone());
+                }
+                // With nothing to fall back to, pages committed before the failure stay searchable
+                None if state.progress_map.read().await.contains_key(&id) => {
+                    state.id_map.write().await.insert(id.clone(), unique_filename.clone());
+                    state.ready_set.write().await.insert(id.clone());
+                }
+                None => {}
+            }
+            forget_searches(&state, &id).await;
+            return Err(e.into());
+        }
+    };
+
+    let empty = matches!(outcome, ProcessOutcome::Empty);
+    if empty {
+        state.id_map.write().await.insert(id.clone(), "empty".to_string());
+    } else {
+        state.id_map.write().await.insert(id.clone(), unique_filename.clone());
+        state.ready_set.write().await.insert(id.clone());
+    }
+    // The old points are only dropped once nothing points at them
+    if let Some(old) = replaced {
+        let _timer = metrics().qdrant_seconds.with_label_values(&["delete"]).start_timer();
+        if let Err(e) = qdrant::delete_by_filename(&state.qdrant, &collection, &old).await {
+            eprintln!("Reindexed {} but couldn't delete its old points under {}: {:?}", id, old, e);
+        }
+    }
+    // A search that raced the reindex may have cached the old index's hits
+    forget_searches(&state, &id).await;
+    if empty {
+        return Err(AppError::bad_request("Document has no extractable text"));
+    }
+
+    println!("Reindexed {} as {}", id, unique_filename);
+
+    Ok(Json(ReindexResponse { id, unique_filename }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use crate::test_pdf::{to_bytes, TestPdf};
+
+    #[tokio::test]
+    async fn failed_reindex_keeps_the_previous_index() {
+        let mut config = crate::config::AppConfig::default();
+        // Fails the reindex before anything new is embedded
+        config.upload.max_pages = 0;
+        let state = AppState { config: std::sync::Arc::new(config), ..AppState::for_tests() };
+        let mut pdf = TestPdf::new();
+        pdf.text_page(&[(72, 700, "Dropout zeroes random activations.")]);
+        state.bytes_map.write().await.insert("doc".to_string(), to_bytes(pdf.finish(lopdf::Dictionary::new())));
+        state.name_map.write().await.insert("doc".to_string(), "notes.pdf".to_string());
+        state.id_map.write().await.insert("doc".to_string(), "notes.pdf_old".to_string());
+        state.ready_set.write().await.insert("doc".to_string());
+        state.progress_map.write().await.insert("doc".to_string(), 1);
+        state.terms_map.write().await.insert("doc".to_string(), HashMap::from([("dropout".to_string(), 1)]));
+
+        let resp = reindex_document(State(state.clone()), Path("doc".to_string()), Query(ReindexQuery { chunk_size: None, resume: false })).await;
+        assert!(resp.is_err());
+
+        // Still searchable under the old points, with its old progress and vocabulary
+        assert_eq!(state.id_map.read().await.get("doc").map(String::as_str), Some("notes.pdf_old"));
+        assert!(state.ready_set.read().await.contains("doc"));
+        assert_eq!(state.progress_map.read().await.get("doc"), Some(&1));
+        assert!(state.terms_map.read().await.get("doc").is_some_and(|terms| terms.contains_key("dropout")));
+    }
+}
@@ -0,0 +1,225 @@
+// src/handlers/resumable.rs
+
+use axum::{
+    body::Bytes,
+    extract::{Path, Query, State},
+    Json,
+};
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+use vb::embed;
+
+use crate::errors::{AppError, ErrorCode};
+use crate::handlers::upload::{check_chunk_size, start_indexing};
+use crate::types::{
+    AppState, InitUploadRequest, PartialUpload, UploadPartsResponse, UploadQuery, UploadResponse,
+};
+
+// Parts of an upload nobody completed are dropped after this long
+const PARTIAL_UPLOAD_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// `POST /upload/init` — start a resumable upload; the returned id is also the
+/// document id once the upload completes.
+///
+/// Send the file as `PUT /upload/:id/part/:n` (parts numbered from 0, in any
+/// order, re-sending a part replaces it), then `POST /upload/:id/complete`.
+pub async fn init_upload(
+    State(state): State<AppState>,
+    Query(params): Query<UploadQuery>,
+    Json(req): Json<InitUploadRequest>,
+) -> Result<Json<UploadResponse>, AppError> {
+    let filename = req.filename.trim();
+    if filename.is_empty() {
+        return Err(AppError::bad_request("Missing filename"));
+    }
+    check_chunk_size(params.chunk_size)?;
+    if let Some(lang) = &params.lang {
+        embed::model_dir(&state.config.embed, Some(lang)).map_err(|e| AppError::bad_request(e.to_string()))?;
+    }
+
+    let id = Uuid::new_v4().to_string();
+    let mut uploads = state.partial_uploads.write().await;
+    drop_stale(&mut uploads, Instant::now());
+    uploads.insert(
+        id.clone(),
+        PartialUpload {
+            filename: filename.to_string(),
+            lang: params.lang,
+            ttl: params.ttl,
+            chunk_size: params.chunk_size,
+            parts: BTreeMap::new(),
+            started: Instant::now(),
+        },
+    );
+    Ok(Json(UploadResponse { id }))
+}
+
+/// `PUT /upload/:id/part/:n` — store part `n`; replies with every part received so far.
+///
+/// Part numbers stop below `UPLOAD_MAX_PARTS`, and the parts together may not
+/// exceed `UPLOAD_MAX_BYTES` (413).
+pub async fn upload_part(
+    State(state): State<AppState>,
+    Path((id, n)): Path<(String, u32)>,
+    body: Bytes,
+) -> Result<Json<UploadPartsResponse>, AppError> {
+    let limits = &state.config.upload;
+    if n >= limits.max_parts {
+        return Err(AppError::bad_request(format!(
+            "Part {} is out of range; parts are numbered below {}",
+            n, limits.max_parts
+        )));
+    }
+    let mut uploads = state.partial_uploads.write().await;
+    let upload = uploads
+        .get_mut(&id)
+        .ok_or_else(|| AppError::not_found(format!("No upload in progress for id: {}", id)))?;
+    // A re-sent part replaces the old one, so only the other parts count
+    let others: usize = upload.parts.iter().filter(|&(&k, _)| k != n).map(|(_, part)| part.len()).sum();
+    if others + body.len() > limits.max_bytes {
+        return Err(AppError::new(
+            ErrorCode::PayloadTooLarge,
+            anyhow::anyhow!("Upload {} would be over the limit of {} bytes", id, limits.max_bytes),
+        ));
+    }
+    upload.parts.insert(n, body.to_vec());
+    let received = upload.parts.keys().copied().collect();
+    Ok(Json(UploadPartsResponse { id, received }))
+}
+
+/// `POST /upload/:id/complete` — join the parts in order and index the file.
+pub async fn complete_upload(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<UploadResponse>, AppError> {
+    let upload = {
+        let mut uploads = state.partial_uploads.write().await;
+        let upload = uploads
+            .get(&id)
+            .ok_or_else(|| AppError::not_found(format!("No upload in progress for id: {}", id)))?;
+        let missing = missing_parts(&upload.parts);
+        if !missing.is_empty() {
+            return Err(AppError::bad_request(format!("Upload {} is missing parts {:?}", id, missing)));
+        }
+        uploads.remove(&id).expect("upload checked above")
+    };
+
+    let data: Vec<u8> = upload.parts.into_values().flatten().collect();
+    let options = UploadQuery { lang: upload.lang, ttl: upload.ttl, chunk_size: upload.chunk_size };
+    start_indexing(&state, id.clone(), upload.filename, data, &options).await;
+    Ok(Json(UploadResponse { id }))
+}
+
+// Gaps below the highest part received; an upload with no parts misses part 0
+fn missing_parts(parts: &BTreeMap<u32, Vec<u8>>) -> Vec<u32> {
+    let Some(&last) = parts.keys().next_back() else {
+        return vec![0];
+    };
+    (0..last).filter(|n| !parts.contains_key(n)).collect()
+}
+
+fn drop_stale(uploads: &mut HashMap<String, PartialUpload>, now: Instant) {
+    uploads.retain(|_, upload| now.duration_since(upload.started) < PARTIAL_UPLOAD_TTL);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handlers::upload::tests::blank_pdf;
+
+    #[tokio::test]
+    async fn parts_sent_out_of_order_are_reassembled_and_indexed() {
+        let state = AppState::for_tests();
+        let pdf = blank_pdf();
+
+        let init = InitUploadRequest { filename: "blank.pdf".to_string() };
+        let id = init_upload(State(state.clone()), Query(UploadQuery::default()), Json(init))
+            .await
+            .ok()
+            .unwrap()
+            .0
+            .id;
+
+        let third = pdf.len() / 3;
+        let parts = [&pdf[..third], &pdf[third..2 * third], &pdf[2 * third..]];
+        for n in [2u32, 0, 1] {
+            let part = Bytes::copy_from_slice(parts[n as usize]);
+            assert!(upload_part(State(state.clone()), Path((id.clone(), n)), part).await.is_ok());
+        }
+
+        assert!(complete_upload(State(state.clone()), Path(id.clone())).await.is_ok());
+        assert_eq!(state.bytes_map.read().await.get(&id), Some(&pdf));
+        assert!(state.partial_uploads.read().await.is_empty());
+
+        // Indexing runs in the background; a blank page ends up "empty"
+        for _ in 0..100 {
+            if state.id_map.read().await.get(&id).map(String::as_str) != Some("processing") {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert_eq!(state.id_map.read().await.get(&id).map(String::as_str), Some("empty"));
+    }
+
+    #[tokio::test]
+    async fn completing_with_a_gap_is_rejected() {
+        let state = AppState::for_tests();
+        let init = InitUploadRequest { filename: "notes.pdf".to_string() };
+        let id = init_upload(State(state.clone()), Query(UploadQuery::default()), Json(init))
+            .await
+            .ok()
+            .unwrap()
+            .0
+            .id;
+        for n in [0u32, 2] {
+            let part = Bytes::from_static(b"%PDF");
+            assert!(upload_part(State(state.clone()), Path((id.clone(), n)), part).await.is_ok());
+        }
+
+        let status = complete_upload(State(state.clone()), Path(id.clone()))
+            .await
+            .err()
+            .map(|e| axum::response::IntoResponse::into_response(e).status());
+        assert_eq!(status, Some(axum::http::StatusCode::BAD_REQUEST));
+        // Still open, so the client can send part 1 and try again
+        assert!(state.partial_uploads.read().await.contains_key(&id));
+    }
+
+    #[tokio::test]
+    async fn parts_over_the_limits_are_rejected() {
+        let mut config = crate::config::AppConfig::default();
+        config.upload.max_bytes = 10;
+        config.upload.max_parts = 4;
+        let state = AppState { config: std::sync::Arc::new(config), ..AppState::for_tests() };
+        let init = InitUploadRequest { filename: "notes.pdf".to_string() };
+        let id = init_upload(State(state.clone()), Query(UploadQuery::default()), Json(init))
+            .await
+            .ok()
+            .unwrap()
+            .0
+            .id;
+        let send = |n: u32, part: &'static [u8]| {
+            let state = state.clone();
+            let id = id.clone();
+            async move {
+                upload_part(State(state), Path((id, n)), Bytes::from_static(part))
+                    .await
+                    .err()
+                    .map(|e| axum::response::IntoResponse::into_response(e).status())
+            }
+        };
+
+        // A huge part number never reaches the missing-parts scan
+        assert_eq!(send(u32::MAX, b"%PDF").await, Some(axum::http::StatusCode::BAD_REQUEST));
+        assert_eq!(send(4, b"%PDF").await, Some(axum::http::StatusCode::BAD_REQUEST));
+
+        assert_eq!(send(0, b"%PDF-1.5").await, None);
+        assert_eq!(send(1, b"abc").await, Some(axum::http::StatusCode::PAYLOAD_TOO_LARGE));
+        // Re-sending a part counts only its new size
+        assert_eq!(send(0, b"%PDF").await, None);
+        assert_eq!(send(1, b"abcdef").await, None);
+        let parts = &state.partial_uploads.read().await[&id].parts;
+        assert_eq!(parts.values().map(Vec::len).sum::<usize>(), 10);
+    }
+}
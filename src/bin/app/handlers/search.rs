@@ -1,184 +1,2243 @@
 // src/handlers/search.rs
 
 use anyhow::Result;
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::ops::Range;
+use std::time::{Duration, Instant};
 use axum::{
-    extract::{Query, State},
+    extract::State,
     Json,
 };
+use axum_extra::extract::Query;
+use pdfium_render::prelude::{PdfDocument, PdfPageText};
 use qdrant_client::Qdrant;
 use qdrant_client::qdrant::value::Kind;
-use vb::{fuzzy, qdrant};
+use qdrant_client::qdrant::ScoredPoint;
+use vb::extract::clean_control_chars;
+use vb::fuzzy::FuzzyConfig;
+use vb::{embed, fuzzy, qdrant, ChunkKind, PageNumber};
+use vb::qdrant::{QueryOptions, UploadedRange};
 
-use crate::errors::AppError;
-use crate::pdf::{expand_ligatures, extract_char_bboxes, get_pdfium, snap_to_sentence_boundaries};
-use crate::types::{AppState, PageHighlight, SearchResult, SearchWithBboxQuery};
+use crate::config::{AppConfig, HighlightConfig, SnippetMarkers};
+use crate::errors::{AppError, ErrorCode};
+use crate::handlers::embed::embed_query;
+use crate::metrics::metrics;
+use crate::pdf::{
+    expand_ligatures, extract_char_bboxes, CoordinateOrigin, CoordinateUnits, get_pdfium, join_break_artifacts, merge_char_boxes, pad_rects,
+    snap_to_sentence_boundaries, table_cell_text, PdfiumUnavailable, TextSpans,
+};
+use crate::types::{AppState, CharBbox, HighlightResponse, PageHighlight, PageWarning, SearchResult, SearchWithBboxQuery};
+
+// How much of the next page, in needle lengths, a match may run onto
+const NEXT_PAGE_HEAD_FACTOR: usize = 2;
 
+/// `GET /api/search` — highlights for the document's hits, as
+/// `{"highlights": [...], "warnings": [{"page", "message"}]}`.
+///
+/// A page that can't be highlighted adds a warning instead of failing the
+/// search. Before warnings were added the body was the bare highlight array.
 pub async fn search_with_bboxes(
     State(state): State<AppState>,
     Query(params): Query<SearchWithBboxQuery>,
-) -> Result<Json<Vec<PageHighlight>>, AppError> {
-    if params.q.is_empty() {
-        return Ok(Json(vec![]));
+) -> Result<Json<HighlightResponse>, AppError> {
+    let queries: Vec<&str> = params
+        .q
+        .iter()
+        .map(|q| q.trim())
+        .filter(|q| !q.is_empty())
+        .collect();
+    if let Some(lambda) = params.diversity
+        && !(0.0..=1.0).contains(&lambda)
+    {
+        return Err(AppError::bad_request(format!("diversity must be between 0 and 1, got {}", lambda)));
+    }
+    if let Some(floor) = params.highlight_score
+        && !(0.0..=1.0).contains(&floor)
+    {
+        return Err(AppError::bad_request(format!("highlight_score must be between 0 and 1, got {}", floor)));
     }
+    if let Some(padding) = params.padding
+        && !(padding.is_finite() && padding >= 0.0)
+    {
+        return Err(AppError::bad_request(format!("padding must be a non-negative number of points, got {}", padding)));
+    }
+    let options = SearchOptions {
+        uploaded: UploadedRange { after: params.uploaded_after, before: params.uploaded_before },
+        neighbors: params.neighbors,
+        origin: params.origin,
+        units: params.units,
+        padding: params.padding.unwrap_or(0.0),
+        within_ids: params.within_ids,
+        lang: params.lang,
+        diversity: params.diversity,
+        case_sensitive: params.case_sensitive,
+        retrieve_score: params.retrieve_score,
+        highlight_score: params.highlight_score,
+    };
+    let mut response = highlights_for_queries(&state, &params.id, &queries, &options).await?;
+    if let Some(max_len) = params.max_text_len {
+        truncate_highlight_text(&mut response.highlights, max_len, &state.config.search.snippet_markers);
+    }
+    Ok(Json(response))
+}
 
-    // --- Resolve file name ---
-    let file_name = match resolve_file_name(&state, &params.id).await {
-        Ok(name) => name,
-        Err(e) => {
-            eprintln!("Error resolving file name for id {}: {:?}", params.id, e);
-            return Err(AppError::from(anyhow::anyhow!("Error resolving file name for id {}: {:?}", params.id, e)))
+// Runs after highlighting so the fuzzy match still sees the whole chunk
+fn truncate_highlight_text(highlights: &mut [PageHighlight], max_len: usize, markers: &SnippetMarkers) {
+    for highlight in highlights {
+        let texts = [&mut highlight.context, &mut highlight.before, &mut highlight.after];
+        for text in texts.into_iter().flatten() {
+            *text = truncate_at_word(text, max_len);
         }
-    };
+        if let Some(snippet) = &mut highlight.html_snippet {
+            *snippet = truncate_snippet(snippet, max_len, markers);
+        }
+    }
+}
 
-    // --- Run search API ---
-    let search_results = match run_search_api(&state.qdrant, &file_name, &params.q).await {
-        Ok(results) => results,
-        Err(e) => {
-            eprintln!("Error querying Qdrant for file '{}', query '{}': {:?}", file_name, params.q, e);
-            return Err(AppError::from(anyhow::anyhow!("Error querying Qdrant for file '{}', query '{}': {:?}", file_name, params.q, e)))
+/// Shorten `text` to at most `max_len` characters plus an ellipsis, cutting
+/// at the last whitespace so no word is split. A first word longer than
+/// `max_len` is cut mid-word instead. Short text comes back as is.
+fn truncate_at_word(text: &str, max_len: usize) -> String {
+    if text.chars().count() <= max_len {
+        return text.to_string();
+    }
+    let cut = text.char_indices().nth(max_len).map_or(text.len(), |(i, _)| i);
+    let head = &text[..cut];
+    // Only back up if the cut landed mid-word
+    let head = if text[cut..].starts_with(char::is_whitespace) {
+        head
+    } else {
+        match head.rfind(char::is_whitespace) {
+            // Backing up to leading whitespace would leave nothing
+            Some(i) if !head[..i].trim().is_empty() => &head[..i],
+            _ => head,
         }
     };
+    format!("{}…", head.trim_end())
+}
 
-    if search_results.is_empty() {
-        return Ok(Json(vec![]));
+/// `truncate_at_word` for an `html_snippet`: keeps the marked match in view,
+/// never splits a marker or an HTML entity, and closes a marker the cut left
+/// open. `max_len` counts the characters a reader sees.
+fn truncate_snippet(snippet: &str, max_len: usize, markers: &SnippetMarkers) -> String {
+    // (piece of the snippet, whether it is a visible char rather than a marker)
+    let mut units: Vec<(&str, bool)> = Vec::new();
+    let mut rest = snippet;
+    while let Some(c) = rest.chars().next() {
+        let len = if !markers.open.is_empty() && rest.starts_with(markers.open.as_str()) {
+            markers.open.len()
+        } else if !markers.close.is_empty() && rest.starts_with(markers.close.as_str()) {
+            markers.close.len()
+        } else if c == '&' {
+            rest.find(';').filter(|&end| end <= 8).map_or(1, |end| end + 1)
+        } else {
+            c.len_utf8()
+        };
+        let is_marker = rest[..len] == *markers.open || rest[..len] == *markers.close;
+        units.push((&rest[..len], !is_marker));
+        rest = &rest[len..];
+    }
+
+    let visible: Vec<usize> = (0..units.len()).filter(|&i| units[i].1).collect();
+    if visible.len() <= max_len {
+        return snippet.to_string();
+    }
+    // Visible position of each unit, so markers know where they sit
+    let position = |unit: usize| visible.partition_point(|&i| i < unit);
+    let mark = units.iter().position(|u| u.0 == markers.open).map(position).unwrap_or(0);
+    let mark_end = units.iter().rposition(|u| u.0 == markers.close).map(position).unwrap_or(mark);
+    let is_space = |at: usize| units[visible[at]].0.starts_with(char::is_whitespace);
+
+    // Lead into a late match with a little of the text before it
+    let mut start = if mark_end <= max_len { 0 } else { mark.saturating_sub(max_len / 4) };
+    if start > 0 {
+        start = (start..mark).find(|&at| is_space(at)).map_or(start, |at| at + 1);
+    }
+    let mut end = (start + max_len).min(visible.len());
+    if end < visible.len() && !is_space(end) {
+        end = (start + 1..end).rev().find(|&at| is_space(at)).filter(|&at| at > start).unwrap_or(end);
+    }
+
+    let mut cut = String::new();
+    let mut open = false;
+    for (i, &(piece, is_char)) in units.iter().enumerate() {
+        let at = position(i);
+        // A close right after the last kept char still belongs to the cut
+        let closing = !is_char && open && piece == markers.close;
+        let inside = if closing { (start..=end).contains(&at) } else { (start..end).contains(&at) };
+        if !inside {
+            continue;
+        }
+        if !is_char {
+            if closing {
+                open = false;
+            } else if piece == markers.open {
+                open = true;
+            } else {
+                // A close whose open fell before the cut has nothing to close
+                continue;
+            }
+        }
+        cut.push_str(piece);
+    }
+    let mut cut = cut.trim().to_string();
+    if open {
+        cut.push_str(&markers.close);
+    }
+    if start > 0 {
+        cut.insert(0, '…');
+    }
+    if end < visible.len() {
+        cut.push('…');
     }
+    cut
+}
+
+/// Per-request knobs shared by the HTTP, WebSocket and selection searches.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct SearchOptions {
+    pub uploaded: UploadedRange,
+    /// Attach the body chunks stored just before and after each hit on its page.
+    pub neighbors: bool,
+    pub origin: CoordinateOrigin,
+    /// Applied last, once merging and padding have worked in points.
+    pub units: CoordinateUnits,
+    /// Points added around each rect, after line merging.
+    pub padding: f32,
+    /// Only rank these Qdrant point ids (from an earlier response); empty searches everything.
+    pub within_ids: Vec<String>,
+    /// Language model for the query; `None` uses the document's upload language.
+    pub lang: Option<String>,
+    /// MMR lambda; `None` keeps plain relevance order.
+    pub diversity: Option<f32>,
+    /// Highlight and snippet matching keep case instead of lowercasing.
+    pub case_sensitive: bool,
+    /// Drop hits whose similarity is below this, before highlighting.
+    pub retrieve_score: Option<f32>,
+    /// Fuzzy threshold a span needs to be highlighted; overrides the configured one.
+    pub highlight_score: Option<f32>,
+}
 
-    // --- Get PDF bytes ---
-    let bytes = match get_pdf_bytes(&state, &params.id).await {
-        Ok(b) => b,
+/// Search `id` for every query and turn the hits into page highlights.
+pub(crate) async fn highlights_for_queries(
+    state: &AppState,
+    id: &str,
+    queries: &[&str],
+    options: &SearchOptions,
+) -> Result<HighlightResponse, AppError> {
+    metrics().searches.inc();
+    let result = collect_highlights(state, id, queries, options).await;
+    if result.is_err() {
+        metrics().errors.with_label_values(&["search"]).inc();
+    }
+    result
+}
+
+async fn collect_highlights(
+    state: &AppState,
+    id: &str,
+    queries: &[&str],
+    options: &SearchOptions,
+) -> Result<HighlightResponse, AppError> {
+    let queries: Vec<&str> = queries.iter().map(|q| q.trim()).filter(|q| !q.is_empty()).collect();
+    if queries.is_empty() {
+        return Ok(HighlightResponse { units: options.units, ..Default::default() });
+    }
+    // Here rather than in each handler, so the socket and selection searches are held to it too
+    let min_chars = state.config.search.min_query_chars;
+    if let Some(short) = queries.iter().find(|q| q.chars().count() < min_chars) {
+        return Err(AppError::bad_request(format!(
+            "Query {:?} is too short; use at least {} characters",
+            short, min_chars
+        )));
+    }
+    if let Some(bare) = queries.iter().find(|q| classify_query(q) == QueryKind::Punctuation) {
+        return Err(AppError::bad_request(format!(
+            "Query {:?} has no letters or digits to search for",
+            bare
+        )));
+    }
+
+    // --- Resolve file name and bytes ---
+    let document = match snapshot_document(state, id).await {
+        Ok(document) => document,
         Err(e) => {
-            eprintln!("Error getting PDF bytes for id {}: {:?}", params.id, e);
-            return Err(AppError::from(anyhow::anyhow!("Error getting PDF bytes for id {}: {:?}", params.id, e)))
+            eprintln!("Error resolving file name for id {}: {}", id, e.to_json());
+            return Err(e)
         }
     };
 
+    cached_highlights(state, id, &queries, options, || search_and_highlight(state, id, &document, &queries, options)).await
+}
+
+/// The cached response for this exact search of `id`, or `search`'s, which is
+/// cached if it succeeds. Queries match after trimming and collapsing whitespace.
+async fn cached_highlights<F, Fut>(
+    state: &AppState,
+    id: &str,
+    queries: &[&str],
+    options: &SearchOptions,
+    search: F,
+) -> Result<HighlightResponse, AppError>
+where
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = Result<HighlightResponse, AppError>>,
+{
+    let key = search_cache_key(id, queries, options);
+    if let Some(cached) = state.search_cache.write().await.get(&key, Instant::now()) {
+        return Ok(cached);
+    }
+    let response = search().await?;
+    // Checked under the cache lock: a document deleted mid-search has either
+    // gone already or will clear this entry when it goes
+    let mut cache = state.search_cache.write().await;
+    if state.id_map.read().await.contains_key(id) {
+        cache.insert(key, response.clone(), Instant::now());
+    }
+    Ok(response)
+}
+
+// Every option that changes the response is part of the key
+fn search_cache_key(id: &str, queries: &[&str], options: &SearchOptions) -> (String, String) {
+    let queries: Vec<String> = queries
+        .iter()
+        .map(|q| q.split_whitespace().collect::<Vec<_>>().join(" "))
+        .collect();
+    (id.to_string(), format!("{:?} {:?}", queries, options))
+}
+
+/// Drop every cached search of `id`, once its index or bytes change.
+pub(crate) async fn forget_searches(state: &AppState, id: &str) {
+    state.search_cache.write().await.retain(|(cached, _)| cached != id);
+}
+
+// Timed as a whole and by stage. A slow search is logged whether or not it
+// succeeded, since a stage that stalled before failing is worth knowing about
+async fn search_and_highlight(
+    state: &AppState,
+    id: &str,
+    document: &DocumentSnapshot,
+    queries: &[&str],
+    options: &SearchOptions,
+) -> Result<HighlightResponse, AppError> {
+    let started = Instant::now();
+    let mut timings = SearchTimings::default();
+    let result = search_stages(state, id, document, queries, options, &mut timings).await;
+
+    timings.total = started.elapsed();
+    if let Some(mut warning) = slow_search_warning(state.config.search.slow_search, id, &document.file_name, queries, &timings) {
+        warning["failed"] = serde_json::json!(result.is_err());
+        metrics().slow_searches.inc();
+        eprintln!("{}", warning);
+    }
+    result
+}
+
+async fn search_stages(
+    state: &AppState,
+    id: &str,
+    document: &DocumentSnapshot,
+    queries: &[&str],
+    options: &SearchOptions,
+    timings: &mut SearchTimings,
+) -> Result<HighlightResponse, AppError> {
+    let file_name = document.file_name.as_str();
+    let lang = query_lang(state, id, options.lang.as_deref()).await?;
+    let collection = document_collection(state, id).await?;
+
+    // --- Run search API ---
+    let mut per_query_results = Vec::with_capacity(queries.len());
+    for query in queries {
+        match run_search_api(state, &collection, file_name, query, options, lang.as_deref(), timings).await {
+            Ok(results) => per_query_results.push(results),
+            Err(e) => {
+                eprintln!("Error querying Qdrant for file '{}', query '{}': {:?}", file_name, query, e);
+                return Err(AppError::from(anyhow::anyhow!("Error querying Qdrant for file '{}', query '{}': {:?}", file_name, query, e)))
+            }
+        }
+    }
+    let mut search_results = merge_search_results(per_query_results);
+
+    if search_results.is_empty() {
+        return Ok(HighlightResponse { units: options.units, ..Default::default() });
+    }
+
+    if options.neighbors {
+        attach_neighbors(&state.qdrant, &collection, file_name, &mut search_results).await;
+    }
+
     // --- Compute highlights ---
-    let highlights = match compute_highlights(&bytes, &search_results) {
+    let computed = timed(&mut timings.highlight, || {
+        compute_highlights(&document.bytes, &search_results, &state.config.highlight, options)
+    });
+    let mut highlights = match computed {
         Ok(h) => h,
         Err(e) => {
             eprintln!("Error computing highlights for file '{}': {:?}", file_name, e);
-            return Err(AppError::from(anyhow::anyhow!("Error computing highlights for file '{}': {:?}", file_name, e)))
+            return Err(highlight_error(e, file_name))
         }
     };
+    if let Some(labels) = state.page_label_map.read().await.get(id) {
+        label_pages(&mut highlights.highlights, labels);
+    }
+    Ok(highlights)
+}
 
-    Ok(Json(highlights))
+/// Where one search spent its time. Query stages add up over the queries.
+#[derive(Debug, Default)]
+struct SearchTimings {
+    embed: Duration,
+    /// Qdrant searches, keyword fallbacks included.
+    query: Duration,
+    highlight: Duration,
+    /// The whole search, including what falls between the stages.
+    total: Duration,
 }
 
-async fn resolve_file_name(state: &AppState, id: &str) -> Result<String> {
-    state
-        .id_map
-        .read()
-        .await
-        .get(id)
-        .cloned()
-        .ok_or_else(|| anyhow::anyhow!("No file found for id: {}", id))
+// Run `stage`, adding the time it took to `spent`
+fn timed<T>(spent: &mut Duration, stage: impl FnOnce() -> T) -> T {
+    let started = Instant::now();
+    let result = stage();
+    *spent += started.elapsed();
+    result
+}
+
+/// A `slow_search` warning, as one JSON object for log search, when the
+/// search took longer than `threshold`.
+fn slow_search_warning(
+    threshold: Option<Duration>,
+    id: &str,
+    file_name: &str,
+    queries: &[&str],
+    timings: &SearchTimings,
+) -> Option<serde_json::Value> {
+    let threshold = threshold.filter(|&threshold| timings.total > threshold)?;
+    let ms = |duration: Duration| duration.as_millis() as u64;
+    Some(serde_json::json!({
+        "level": "warn",
+        "event": "slow_search",
+        "id": id,
+        "file": file_name,
+        "queries": queries,
+        "total_ms": ms(timings.total),
+        "threshold_ms": ms(threshold),
+        "stages_ms": {
+            "embed_query": ms(timings.embed),
+            "run_query": ms(timings.query),
+            "compute_highlights": ms(timings.highlight),
+        },
+    }))
+}
+
+// Give each highlight the label printed on its page, as listed by page index
+fn label_pages(highlights: &mut [PageHighlight], labels: &[String]) {
+    for highlight in highlights {
+        highlight.page_label = labels.get(highlight.page.index() as usize).cloned();
+    }
+}
+
+// A missing pdfium library is a 503, anything else while highlighting is a 500
+fn highlight_error(e: anyhow::Error, file_name: &str) -> AppError {
+    if e.downcast_ref::<PdfiumUnavailable>().is_some() {
+        return AppError::new(ErrorCode::Unavailable, e);
+    }
+    AppError::from(anyhow::anyhow!("Error computing highlights for file '{}': {:?}", file_name, e))
+}
+
+/// The language whose model embeds the query: the one the document was
+/// uploaded with. A requested language must be that one, since a query
+/// embedded by another model can't be compared with the document's vectors.
+async fn query_lang(state: &AppState, id: &str, requested: Option<&str>) -> Result<Option<String>, AppError> {
+    let document = state.lang_map.read().await.get(id).cloned();
+    let Some(lang) = requested else {
+        return Ok(document);
+    };
+    embed::model_dir(&state.config.embed, Some(lang)).map_err(|e| AppError::bad_request(e.to_string()))?;
+    let lang = lang.to_ascii_lowercase();
+    if document.as_deref() != Some(lang.as_str()) {
+        return Err(AppError::bad_request(format!(
+            "Document {} was indexed with the {} model, not '{}'",
+            id,
+            document.map_or("default".to_string(), |lang| format!("'{}'", lang)),
+            lang
+        )));
+    }
+    Ok(Some(lang))
+}
+
+/// The collection holding `id`'s vectors: that of the model for the language
+/// it was uploaded with.
+pub(crate) async fn document_collection(state: &AppState, id: &str) -> Result<String, AppError> {
+    let lang = state.lang_map.read().await.get(id).cloned();
+    state.config.collection(lang.as_deref()).map_err(AppError::from)
+}
+
+/// A document's indexed file name and PDF bytes, read together.
+struct DocumentSnapshot {
+    file_name: String,
+    bytes: Vec<u8>,
 }
 
-async fn get_pdf_bytes(state: &AppState, id: &str) -> Result<Vec<u8>> {
-    state
+// Both maps are read with the id_map lock held. Deleting a document takes
+// id_map's write lock before bytes_map's, so the search sees it whole or not at all
+async fn snapshot_document(state: &AppState, id: &str) -> Result<DocumentSnapshot, AppError> {
+    let id_map = state.id_map.read().await;
+    let file_name = indexed_file_name(id, id_map.get(id).map(String::as_str))?;
+    let bytes = state
         .bytes_map
         .read()
         .await
         .get(id)
         .cloned()
-        .ok_or_else(|| anyhow::anyhow!("No bytes found for id: {}", id))
+        .ok_or_else(|| AppError::not_found(format!("No bytes found for id: {}", id)))?;
+    Ok(DocumentSnapshot { file_name, bytes })
+}
+
+// The indexed file name; the id_map sentinels mean there's nothing to search yet
+pub(crate) async fn resolve_file_name(state: &AppState, id: &str) -> Result<String, AppError> {
+    let name = state.id_map.read().await.get(id).cloned();
+    indexed_file_name(id, name.as_deref())
 }
 
-async fn run_search_api(client: &Qdrant, file_name: &str, query: &str) -> Result<Vec<SearchResult>> {
+fn indexed_file_name(id: &str, entry: Option<&str>) -> Result<String, AppError> {
+    match entry {
+        None => Err(AppError::not_found(format!("No file found for id: {}", id))),
+        Some("processing") => Err(AppError::new(
+            ErrorCode::NotReady,
+            anyhow::anyhow!("Document {} is not ready: still processing, retry shortly", id),
+        )),
+        Some("failed") => Err(AppError::new(
+            ErrorCode::NotReady,
+            anyhow::anyhow!("Document {} is not ready: indexing failed, upload it again", id),
+        )),
+        Some(name) => Ok(name.to_string()),
+    }
+}
+
+async fn run_search_api(
+    state: &AppState,
+    collection: &str,
+    file_name: &str,
+    query: &str,
+    options: &SearchOptions,
+    lang: Option<&str>,
+    timings: &mut SearchTimings,
+) -> Result<Vec<SearchResult>> {
     let query = query.trim();
     if query.is_empty() {
         return Ok(vec![]);
     }
+    let client = &state.qdrant;
 
-    let resp = qdrant::run_query(client, "embedded_pdfs", file_name, query)
-        .await
-        .map_err(|e| anyhow::anyhow!("Qdrant query failed: {:?}", e))?;
+    let _timer = metrics().qdrant_seconds.with_label_values(&["query"]).start_timer();
+    let query_options = query_options(&state.config, options, lang);
+    let started = Instant::now();
+    let mut embed_time = Duration::ZERO;
+    let resp = match classify_query(query) {
+        QueryKind::Punctuation => return Ok(vec![]),
+        QueryKind::Semantic => {
+            let embed = embed_query(state, query, lang);
+            let (resp, spent) = qdrant::run_query_with(client, collection, file_name, query, &query_options, embed)
+                .await
+                .map_err(|e| anyhow::anyhow!("Qdrant query failed: {:?}", e))?;
+            embed_time = spent;
+            resp
+        }
+        _ => qdrant::run_keyword_query(client, collection, file_name, query, &query_options)
+            .await
+            .map_err(|e| anyhow::anyhow!("Qdrant query failed: {:?}", e))?,
+    };
+    timings.embed += embed_time;
+    timings.query += started.elapsed().saturating_sub(embed_time);
+
+    Ok(search_results(resp.result, query, &state.config, options))
+}
 
-    let results = resp
-        .result
+// What Qdrant is asked for, and how the keyword fallback matches
+fn query_options(config: &AppConfig, options: &SearchOptions, lang: Option<&str>) -> QueryOptions {
+    QueryOptions {
+        uploaded: options.uploaded,
+        within_ids: options.within_ids.clone(),
+        payload_fields: config.search.payload_fields.clone(),
+        metric: config.search.metric,
+        lang: lang.map(str::to_string),
+        diversity: options.diversity,
+        fold_diacritics: config.highlight.fuzzy.fold_diacritics,
+        case_sensitive: options.case_sensitive,
+        ..QueryOptions::default()
+    }
+}
+
+/// The hits kept by the request's `retrieve_score` as results, each with its
+/// snippet marked the way highlights are matched.
+fn search_results(mut points: Vec<ScoredPoint>, query: &str, config: &AppConfig, options: &SearchOptions) -> Vec<SearchResult> {
+    retain_above(&mut points, options.retrieve_score);
+    let matching = FuzzyConfig {
+        case_sensitive: options.case_sensitive,
+        fold_diacritics: config.highlight.fuzzy.fold_diacritics,
+        ..FuzzyConfig::default()
+    };
+    let mut results = results_from_points(points, query, config.search.heading_boost);
+    for result in &mut results {
+        result.html_snippet = mark_snippet(&result.text, query, &config.search.snippet_markers, &matching);
+    }
+    results
+}
+
+/// How a query is searched, from what it's made of.
+#[derive(Debug, PartialEq, Eq)]
+enum QueryKind {
+    /// Has words: embedded and ranked by similarity.
+    Semantic,
+    /// Only digits and punctuation ("2024", "3.14"), which embed to noise, so
+    /// only chunks containing it verbatim are returned.
+    Exact,
+    /// No letters or digits at all; nothing to search for.
+    Punctuation,
+}
+
+fn classify_query(query: &str) -> QueryKind {
+    if query.chars().any(char::is_alphabetic) {
+        QueryKind::Semantic
+    } else if query.chars().any(char::is_numeric) {
+        QueryKind::Exact
+    } else {
+        QueryKind::Punctuation
+    }
+}
+
+/// HTML-escape `text` and wrap the best fuzzy match of `query` in `markers`.
+/// `None` when the query doesn't appear in the text closely enough.
+fn mark_snippet(text: &str, query: &str, markers: &SnippetMarkers, config: &FuzzyConfig) -> Option<String> {
+    let entries: Vec<(usize, char)> = text.chars().enumerate().collect();
+    let needle: Vec<char> = query.trim().chars().collect();
+    let (start, end, _) = fuzzy::fuzzy_search_with_config(&entries, &needle, config)
+        .into_iter()
+        .max_by(|a, b| a.2.total_cmp(&b.2))?;
+
+    // Char offsets to byte offsets, so multibyte text splits on boundaries
+    let byte_at = |i: usize| text.char_indices().nth(i).map_or(text.len(), |(b, _)| b);
+    let (start, end) = (byte_at(start), byte_at(end));
+    Some(format!(
+        "{}{}{}{}{}",
+        escape_html(&text[..start]),
+        markers.open,
+        escape_html(&text[start..end]),
+        markers.close,
+        escape_html(&text[end..]),
+    ))
+}
+
+// Lowercase `text` unless the search keeps case
+fn fold_case(text: &str, case_sensitive: bool) -> String {
+    match case_sensitive {
+        true => text.to_string(),
+        false => text.to_lowercase(),
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Convert scored points to results, best first.
+///
+/// Heading chunks have their score multiplied by `heading_boost`. Qdrant
+/// returns equal scores in no particular order, so ties are broken by page and
+/// then chunk text to keep the output (and the highlights) stable.
+fn results_from_points(points: Vec<ScoredPoint>, query: &str, heading_boost: f32) -> Vec<SearchResult> {
+    let mut results: Vec<SearchResult> = points
         .into_iter()
         .filter_map(|point| {
             let text = point.payload.get("text")?.as_str()?.to_string();
-            let page = match &point.payload.get("page")?.kind {
-                Some(Kind::DoubleValue(d)) => *d as i64,
-                Some(Kind::IntegerValue(i)) => *i,
-                Some(Kind::StringValue(s)) => s.parse().unwrap_or(1),
-                _ => 1,
+            // A hit we can't place would be highlighted on the wrong page, so drop it
+            let Some(page) = point.payload.get("page").and_then(|v| page_from_payload(&v.kind)) else {
+                eprintln!("Skipping search hit with unusable page {:?}: {:?}", point.payload.get("page"), point.id);
+                return None;
             };
-            Some(SearchResult { page, text })
+            let kind = point.payload.get("kind").and_then(|v| v.as_str()).map(|kind| kind.to_string());
+            let is_heading = kind.as_deref() == Some(ChunkKind::Heading.as_str());
+            let score = if is_heading { point.score * heading_boost } else { point.score };
+            let context = point
+                .payload
+                .get("parent_text")
+                .and_then(|v| v.as_str())
+                .map(|text| text.to_string());
+            let seq = point.payload.get("seq").and_then(|v| v.as_integer()).map(|seq| seq as u32);
+            Some(SearchResult {
+                point_id: point.id.as_ref().and_then(qdrant::point_id_string),
+                page,
+                text,
+                query: query.to_string(),
+                score,
+                kind,
+                context,
+                seq,
+                before: None,
+                after: None,
+                html_snippet: None,
+            })
         })
         .collect();
 
-    Ok(results)
+    results.sort_by(|a, b| {
+        b.score
+            .total_cmp(&a.score)
+            .then(a.page.cmp(&b.page))
+            .then_with(|| a.text.cmp(&b.text))
+    });
+    results
 }
 
-fn compute_highlights(bytes: &[u8], search_results: &[SearchResult]) -> Result<Vec<PageHighlight>> {
-    let pdfium = get_pdfium();
+// Scores are similarities by now (higher is better) for every metric and the keyword fallback
+fn retain_above(points: &mut Vec<ScoredPoint>, floor: Option<f32>) {
+    if let Some(floor) = floor {
+        points.retain(|point| point.score >= floor);
+    }
+}
+
+// The configured fuzzy settings with this request's overrides
+fn request_fuzzy_config(config: &HighlightConfig, options: &SearchOptions, needle_len: usize) -> FuzzyConfig {
+    let fuzzy = config.fuzzy_for(needle_len);
+    FuzzyConfig {
+        threshold: options.highlight_score.unwrap_or(fuzzy.threshold),
+        case_sensitive: options.case_sensitive,
+        ..fuzzy
+    }
+}
+
+// Best effort: a failed lookup just leaves that hit without context
+async fn attach_neighbors(client: &Qdrant, collection: &str, file_name: &str, results: &mut [SearchResult]) {
+    for result in results {
+        let Some(seq) = result.seq else { continue };
+        match qdrant::page_neighbors(client, collection, file_name, result.page, seq).await {
+            Ok((before, after)) => {
+                result.before = before;
+                result.after = after;
+            }
+            Err(e) => eprintln!("Failed to fetch neighbors for page {}: {:?}", result.page, e),
+        }
+    }
+}
+
+/// Flatten per-query results, keeping the first query to return a given chunk.
+fn merge_search_results(per_query: Vec<Vec<SearchResult>>) -> Vec<SearchResult> {
+    let mut seen: HashSet<(PageNumber, String)> = HashSet::new();
+    per_query
+        .into_iter()
+        .flatten()
+        .filter(|r| seen.insert((r.page, r.text.clone())))
+        .collect()
+}
+
+fn compute_highlights(
+    bytes: &[u8],
+    search_results: &[SearchResult],
+    config: &HighlightConfig,
+    options: &SearchOptions,
+) -> Result<HighlightResponse> {
+    let pdfium = get_pdfium()?;
     let doc = pdfium.load_pdf_from_byte_slice(bytes, None)
         .map_err(|e| anyhow::anyhow!("PDFium load failed: {:?}", e))?;
-    let mut highlights: Vec<PageHighlight> = Vec::new();
+    // (page, start, end) spans already highlighted, so overlapping hits are only drawn once
+    let mut emitted: Vec<(PageNumber, usize, usize)> = Vec::new();
+    // Only read if pdfium finds no text on a hit's page
+    let mut spans = TextSpans::new(bytes, config.line_tolerance);
+
+    let (highlights, warnings) = highlight_each(search_results, |search_result| {
+        highlight_result(&doc, &mut spans, search_result, config, options, &mut emitted)
+    });
+
+    let mut highlights = cap_highlights_per_page(highlights, config.max_highlights_per_page);
+    if let Some(max_gap) = config.merge_gap {
+        highlights = merge_adjacent_highlights(highlights, max_gap);
+    }
+    for highlight in &mut highlights {
+        let (width, height) = (highlight.page_width, highlight.page_height);
+        options.units.apply(&mut highlight.exact_rects, width, height);
+        options.units.apply(&mut highlight.sentence_rects, width, height);
+    }
+    Ok(HighlightResponse { highlights, warnings, units: options.units })
+}
 
+/// Run `highlight` on every result, keeping what succeeds. A failing result
+/// becomes one warning for its page instead of failing the whole search.
+fn highlight_each<F>(
+    search_results: &[SearchResult],
+    mut highlight: F,
+) -> (Vec<(f32, PageHighlight)>, Vec<PageWarning>)
+where
+    F: FnMut(&SearchResult) -> Result<Vec<(f32, PageHighlight)>>,
+{
+    let mut highlights = Vec::new();
+    let mut warnings: Vec<PageWarning> = Vec::new();
     for search_result in search_results {
-        let page_idx = (search_result.page - 1) as u16;
+        match highlight(search_result) {
+            Ok(found) => highlights.extend(found),
+            Err(e) => {
+                eprintln!("Skipping highlights on page {}: {:?}", search_result.page, e);
+                if !warnings.iter().any(|w| w.page == search_result.page) {
+                    warnings.push(PageWarning { page: search_result.page, message: e.to_string() });
+                }
+            }
+        }
+    }
+    (highlights, warnings)
+}
 
-        let page = match doc.pages().get(page_idx) {
-            Ok(p) => p,
-            Err(_) => {
-                eprintln!("Invalid page index {} for PDF", page_idx);
-                continue;
+// Scored highlights for one search result on its page
+fn highlight_result(
+    doc: &PdfDocument<'_>,
+    spans: &mut TextSpans<'_>,
+    search_result: &SearchResult,
+    config: &HighlightConfig,
+    options: &SearchOptions,
+    emitted: &mut Vec<(PageNumber, usize, usize)>,
+) -> Result<Vec<(f32, PageHighlight)>> {
+    let page = doc
+        .pages()
+        .get(search_result.page.index())
+        .map_err(|_| anyhow::anyhow!("Page {} is not in the PDF", search_result.page))?;
+    let text_page = page
+        .text()
+        .map_err(|e| anyhow::anyhow!("Failed to get text for page {}: {:?}", search_result.page, e))?;
+    let (page_width, page_height) = (page.width().value, page.height().value);
+
+    // Alt text isn't in the page's text, so there is nothing to box; the hit points at the page
+    if search_result.kind.as_deref() == Some(ChunkKind::Caption.as_str()) {
+        return Ok(vec![(1.0, page_highlight(search_result, Vec::new(), Vec::new(), page_width, page_height))]);
+    }
+
+    // Chunk text can carry lopdf's line-break joins; normalize it like the page text
+    // A table chunk's labels and separators aren't on the page, only its cells are
+    let needle_text = match search_result.kind.as_deref() == Some(ChunkKind::Table.as_str()) {
+        true => fold_case(&table_cell_text(&search_result.text), options.case_sensitive),
+        false => fold_case(&search_result.text, options.case_sensitive),
+    };
+    let needle_chars: Vec<char> =
+        join_break_artifacts(needle_text.chars().enumerate())
+            .into_iter()
+            .map(|(_, c)| c)
+            .collect();
+
+    let char_entries = page_char_entries(&text_page, config);
+    let next_number = search_result.page.next();
+    let next_page = next_number.and_then(|next| doc.pages().get(next.index()).ok());
+
+    // pdfium can load a page yet read none of its text, or only U+FFFD where it
+    // couldn't decode the font; place the hit from pdf_oxide's runs instead
+    if !char_entries.iter().any(|&(_, ch)| !ch.is_whitespace() && ch != char::REPLACEMENT_CHARACTER) {
+        let page_size = (page_width, page_height);
+        let next_size = next_page.as_ref().map(|next| (next.width().value, next.height().value));
+        return fallback_highlights(spans, search_result, &needle_chars, config, options, page_size, next_size);
+    }
+
+    let fuzzy_config = request_fuzzy_config(config, options, needle_chars.len());
+    let mut fuzzy_matches = strict_matches(&char_entries, &needle_chars, config, &fuzzy_config);
+
+    // A hit that isn't on its page may be a chunk running on past the page break
+    if fuzzy_matches.is_empty()
+        && let (Some(next_page), Some(next_number)) = (next_page, next_number)
+        && let Ok(next_text) = next_page.text()
+    {
+        let next_entries = page_char_entries(&next_text, config);
+        let next_size = (next_page.width().value, next_page.height().value);
+        let across = matches_across_break(&char_entries, &next_entries, &needle_chars, &fuzzy_config, config.max_matches_per_result);
+        if !across.is_empty() {
+            let mut highlights = Vec::new();
+            for (here, there, score) in across {
+                if already_emitted(emitted, search_result.page, &here) || already_emitted(emitted, next_number, &there) {
+                    continue;
+                }
+                let (here_range, there_range) = (here.clone(), there.clone());
+                let rects = entry_rects(&text_page, &char_entries, here, config, options, (page_width, page_height))
+                    .and_then(|here| Ok((here, entry_rects(&next_text, &next_entries, there, config, options, next_size)?)));
+                let (here_rects, there_rects) = match rects {
+                    Ok(rects) => rects,
+                    Err(e) => {
+                        eprintln!("Skipping a match across pages {} and {}: {:?}", search_result.page, next_number, e);
+                        continue;
+                    }
+                };
+                emitted.push((search_result.page, here_range.start, here_range.end));
+                emitted.push((next_number, there_range.start, there_range.end));
+                highlights.push((score, page_highlight(search_result, here_rects.clone(), here_rects, page_width, page_height)));
+                highlights.push((score, next_page_highlight(search_result, next_number, there_rects, next_size)));
             }
-        };
-        let text_page = match page.text() {
-            Ok(t) => t,
-            Err(_) => {
-                eprintln!("Failed to get text for page {}", page_idx + 1);
+            return Ok(highlights);
+        }
+    }
+
+    let low_confidence = fuzzy_matches.is_empty();
+    if low_confidence {
+        fuzzy_matches = relaxed_matches(&char_entries, &needle_chars, config, options, &fuzzy_config);
+    }
+
+    let mut highlights = Vec::new();
+    for span in snap_matches(&char_entries, fuzzy_matches, config.context_sentences) {
+        if already_emitted(emitted, search_result.page, &span.sentence) {
+            continue;
+        }
+
+        // One span's boxes failing costs only that span, not the result's others
+        let span_rects = |range: Range<usize>| entry_rects(&text_page, &char_entries, range, config, options, (page_width, page_height));
+        let sentence = span.sentence.clone();
+        let (sentence_rects, exact_rects) = match span_rects(span.sentence).and_then(|rects| Ok((rects, span_rects(span.exact)?))) {
+            Ok(rects) => rects,
+            Err(e) => {
+                eprintln!("Skipping a match on page {}: failed to extract its bounding boxes: {:?}", search_result.page, e);
                 continue;
             }
         };
+        emitted.push((search_result.page, sentence.start, sentence.end));
+        if sentence_rects.is_empty() {
+            continue;
+        }
+        highlights.push((
+            span.score,
+            PageHighlight {
+                low_confidence,
+                ..page_highlight(search_result, exact_rects, sentence_rects, page_width, page_height)
+            },
+        ));
+    }
+    Ok(highlights)
+}
 
-        let needle_chars: Vec<char> = search_result.text.to_lowercase().chars().collect();
+// Whether part of `range` on `page` is already highlighted
+fn already_emitted(emitted: &[(PageNumber, usize, usize)], page: PageNumber, range: &Range<usize>) -> bool {
+    emitted.iter().any(|&(emitted_page, start, end)| emitted_page == page && range.start < end && range.end > start)
+}
 
-        let char_entries: Vec<(usize, char)> = text_page
-            .chars()
-            .iter()
-            .enumerate()
-            .flat_map(|(pdf_idx, c)| {
-                c.unicode_char()
-                    .map(|ch| expand_ligatures(pdf_idx, ch))
-                    .unwrap_or_default()
+// The page's chars as (pdfium index, char), cleaned up the way chunk text is
+fn page_char_entries(text_page: &PdfPageText<'_>, config: &HighlightConfig) -> Vec<(usize, char)> {
+    join_break_artifacts(clean_control_chars(
+        text_page.chars().iter().enumerate().flat_map(|(pdf_idx, c)| {
+            c.unicode_char()
+                .map(|ch| expand_ligatures(pdf_idx, ch))
+                .unwrap_or_default()
+        }),
+        config.control_chars,
+    ))
+}
+
+// Rects for `range` of a page's char entries, padded and in the requested origin
+fn entry_rects(
+    text_page: &PdfPageText<'_>,
+    char_entries: &[(usize, char)],
+    range: Range<usize>,
+    config: &HighlightConfig,
+    options: &SearchOptions,
+    (page_width, page_height): (f32, f32),
+) -> Result<Vec<CharBbox>> {
+    let pdf_char_indices: Vec<usize> = char_entries[range].iter().map(|(pdf_idx, _)| *pdf_idx).collect();
+    let mut rects = extract_char_bboxes(text_page, &pdf_char_indices, config.line_tolerance)?;
+    pad_rects(&mut rects, options.padding, page_width, page_height);
+    options.origin.apply(&mut rects, page_height);
+    Ok(rects)
+}
+
+/// Matches of `needle` that start on a page and finish on the next one, each
+/// split at the break into a range of `page` and a range of `next_page`.
+///
+/// Only the start of the next page is searched, and a match has to cross the
+/// break, so text that is entirely on the next page never counts.
+fn matches_across_break(
+    page: &[(usize, char)],
+    next_page: &[(usize, char)],
+    needle: &[char],
+    fuzzy_config: &FuzzyConfig,
+    max_matches: usize,
+) -> Vec<(Range<usize>, Range<usize>, f32)> {
+    let head = &next_page[..next_page.len().min(needle.len() * NEXT_PAGE_HEAD_FACTOR)];
+    let mut joined = page.to_vec();
+    // Where the next page's text starts in `joined`
+    let mut next_start = page.len();
+    if joined.last().is_some_and(|&(_, ch)| !ch.is_whitespace()) {
+        // The break reads as a space; it belongs to neither page
+        joined.push((usize::MAX, ' '));
+        next_start += 1;
+    }
+    joined.extend_from_slice(head);
+
+    best_matches(fuzzy::fuzzy_search_with_config(&joined, needle, fuzzy_config), max_matches)
+        .into_iter()
+        .filter(|&(start, end, _)| start < page.len() && end > next_start)
+        .map(|(start, end, score)| (start..page.len(), 0..end - next_start, score))
+        .collect()
+}
+
+// The part of a cross-page match on the page after the hit's own
+fn next_page_highlight(
+    search_result: &SearchResult,
+    next_page: PageNumber,
+    rects: Vec<CharBbox>,
+    (width, height): (f32, f32),
+) -> PageHighlight {
+    PageHighlight {
+        page: next_page,
+        ..page_highlight(search_result, rects.clone(), rects, width, height)
+    }
+}
+
+/// Approximate highlights from pdf_oxide's text runs: every run a match
+/// touches is boxed whole, so the hit's lines are marked rather than its
+/// exact words. Both rect sets are the same.
+fn fallback_highlights(
+    spans: &mut TextSpans<'_>,
+    search_result: &SearchResult,
+    needle_chars: &[char],
+    config: &HighlightConfig,
+    options: &SearchOptions,
+    page_size: (f32, f32),
+    next_page_size: Option<(f32, f32)>,
+) -> Result<Vec<(f32, PageHighlight)>> {
+    let runs = spans.page(search_result.page)?;
+    let entries = run_entries(&runs, options.case_sensitive);
+
+    let fuzzy_config = request_fuzzy_config(config, options, needle_chars.len());
+    let mut matches = strict_matches(&entries, needle_chars, config, &fuzzy_config);
+
+    let mut highlights = Vec::new();
+    // As with pdfium's text, a hit missing from its page may run onto the next
+    if matches.is_empty()
+        && let (Some(next_page), Some(next_size)) = (search_result.page.next(), next_page_size)
+        && let Ok(next_runs) = spans.page(next_page)
+    {
+        let next_entries = run_entries(&next_runs, options.case_sensitive);
+        for (here, there, score) in matches_across_break(&entries, &next_entries, needle_chars, &fuzzy_config, config.max_matches_per_result) {
+            let here_rects = run_rects(&runs, &entries[here], config, options, page_size);
+            let there_rects = run_rects(&next_runs, &next_entries[there], config, options, next_size);
+            let (width, height) = page_size;
+            highlights.push((score, page_highlight(search_result, here_rects.clone(), here_rects, width, height)));
+            highlights.push((score, next_page_highlight(search_result, next_page, there_rects, next_size)));
+        }
+        if !highlights.is_empty() {
+            return Ok(highlights);
+        }
+    }
+
+    let low_confidence = matches.is_empty();
+    if low_confidence {
+        matches = relaxed_matches(&entries, needle_chars, config, options, &fuzzy_config);
+    }
+    for (start, end, score) in matches {
+        let rects = run_rects(&runs, &entries[start..end], config, options, page_size);
+        if rects.is_empty() {
+            continue;
+        }
+        let (width, height) = page_size;
+        let highlight = page_highlight(search_result, rects.clone(), rects, width, height);
+        highlights.push((score, PageHighlight { low_confidence, ..highlight }));
+    }
+    Ok(highlights)
+}
+
+// The best matches at the request's threshold
+fn strict_matches(
+    entries: &[(usize, char)],
+    needle: &[char],
+    config: &HighlightConfig,
+    fuzzy_config: &FuzzyConfig,
+) -> Vec<(usize, usize, f32)> {
+    best_matches(fuzzy::fuzzy_search_with_config(entries, needle, fuzzy_config), config.max_matches_per_result)
+}
+
+// The best matches at `relaxed_retry` below the request's threshold, for a hit
+// neither the strict pass nor the page break explains; highlights from these are
+// flagged low confidence. An explicit `highlight_score` is a floor the caller
+// chose, so it isn't relaxed.
+fn relaxed_matches(
+    entries: &[(usize, char)],
+    needle: &[char],
+    config: &HighlightConfig,
+    options: &SearchOptions,
+    fuzzy_config: &FuzzyConfig,
+) -> Vec<(usize, usize, f32)> {
+    match config.relaxed_retry {
+        Some(margin) if options.highlight_score.is_none() => {
+            let relaxed = FuzzyConfig { threshold: fuzzy_config.threshold - margin, ..fuzzy_config.clone() };
+            strict_matches(entries, needle, config, &relaxed)
+        }
+        _ => Vec::new(),
+    }
+}
+
+// Page text as (run index, char), with a space between runs
+fn run_entries(spans: &[(String, CharBbox)], case_sensitive: bool) -> Vec<(usize, char)> {
+    spans
+        .iter()
+        .enumerate()
+        .flat_map(|(i, (text, _))| {
+            fold_case(text, case_sensitive).chars().chain([' ']).map(move |c| (i, c)).collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+// Every run `entries` touch, boxed whole; runs on one line join into one rect,
+// keeping the first run's line number
+fn run_rects(
+    spans: &[(String, CharBbox)],
+    entries: &[(usize, char)],
+    config: &HighlightConfig,
+    options: &SearchOptions,
+    (page_width, page_height): (f32, f32),
+) -> Vec<CharBbox> {
+    let mut runs: Vec<usize> = entries.iter().map(|&(i, _)| i).collect();
+    runs.dedup();
+    let mut rects = merge_char_boxes(runs.iter().map(|&i| Some(spans[i].1.clone())), config.line_tolerance);
+    pad_rects(&mut rects, options.padding, page_width, page_height);
+    options.origin.apply(&mut rects, page_height);
+    rects
+}
+
+fn page_highlight(
+    search_result: &SearchResult,
+    exact_rects: Vec<CharBbox>,
+    sentence_rects: Vec<CharBbox>,
+    page_width: f32,
+    page_height: f32,
+) -> PageHighlight {
+    PageHighlight {
+        page: search_result.page,
+        page_label: None,
+        point_id: search_result.point_id.clone(),
+        exact_rects,
+        sentence_rects,
+        page_width,
+        page_height,
+        query: search_result.query.clone(),
+        kind: search_result.kind.clone(),
+        context: search_result.context.clone(),
+        before: search_result.before.clone(),
+        after: search_result.after.clone(),
+        html_snippet: search_result.html_snippet.clone(),
+        low_confidence: false,
+    }
+}
+
+/// A fuzzy match as found, and widened to the sentences around it.
+/// Both ranges index the page's char entries.
+struct MatchSpan {
+    exact: Range<usize>,
+    sentence: Range<usize>,
+    score: f32,
+}
+
+fn snap_matches(
+    char_entries: &[(usize, char)],
+    matches: Vec<(usize, usize, f32)>,
+    context_sentences: usize,
+) -> Vec<MatchSpan> {
+    matches
+        .into_iter()
+        .map(|(start, end, score)| {
+            let (sentence_start, sentence_end) =
+                snap_to_sentence_boundaries(char_entries, start, end, context_sentences);
+            // Snapping never drops part of the hit, even one starting at whitespace after a period
+            let sentence = sentence_start.min(start)..sentence_end.max(end);
+            MatchSpan { exact: start..end, sentence, score }
+        })
+        .collect()
+}
+
+/// A stored 1-indexed page number; `None` for null, list or out-of-range values.
+pub(crate) fn page_from_payload(kind: &Option<Kind>) -> Option<PageNumber> {
+    let page = match kind.as_ref()? {
+        Kind::IntegerValue(i) => *i,
+        Kind::DoubleValue(d) if d.fract() == 0.0 => *d as i64,
+        Kind::StringValue(s) => s.trim().parse().ok()?,
+        _ => return None,
+    };
+    let page = u16::try_from(page).ok().filter(|&p| p >= 1)?;
+    Some(PageNumber::new(page))
+}
+
+/// Keep the `max` best-scoring `(start, end, score)` spans, back in page order.
+fn best_matches(mut matches: Vec<(usize, usize, f32)>, max: usize) -> Vec<(usize, usize, f32)> {
+    matches.sort_by(|a, b| b.2.total_cmp(&a.2));
+    matches.truncate(max);
+    matches.sort_by_key(|&(start, _, _)| start);
+    matches
+}
+
+/// Keep the `max_per_page` best-scoring highlights on each page.
+/// Pages stay in the order they were first hit; within a page, best first.
+fn cap_highlights_per_page(
+    scored: Vec<(f32, PageHighlight)>,
+    max_per_page: usize,
+) -> Vec<PageHighlight> {
+    let mut page_order: Vec<PageNumber> = Vec::new();
+    let mut by_page: HashMap<PageNumber, Vec<(f32, PageHighlight)>> = HashMap::new();
+    for (score, highlight) in scored {
+        if !by_page.contains_key(&highlight.page) {
+            page_order.push(highlight.page);
+        }
+        by_page.entry(highlight.page).or_default().push((score, highlight));
+    }
+
+    page_order
+        .into_iter()
+        .flat_map(|page| {
+            let mut on_page = by_page.remove(&page).unwrap_or_default();
+            on_page.sort_by(|a, b| b.0.total_cmp(&a.0));
+            on_page.into_iter().take(max_per_page).map(|(_, highlight)| highlight)
+        })
+        .collect()
+}
+
+/// Join highlights that continue one another on the page: the first rect of
+/// one starts on the line where the other's last rect ends, within `max_gap`
+/// points of it or overlapping. A joined highlight keeps the details of the part
+/// that comes first on the page, at the list position of its best-ranked part.
+fn merge_adjacent_highlights(highlights: Vec<PageHighlight>, max_gap: f32) -> Vec<PageHighlight> {
+    // Reading order of the highlights' first rects; highlights without rects stay put
+    let start = |h: &PageHighlight| h.sentence_rects.first().map(|r| (r.line, r.x));
+    let mut order: Vec<(usize, PageHighlight)> = highlights.into_iter().enumerate().collect();
+    order.sort_by(|(i, a), (j, b)| {
+        a.page
+            .cmp(&b.page)
+            .then_with(|| match (start(a), start(b)) {
+                (Some((la, xa)), Some((lb, xb))) => la.cmp(&lb).then(xa.total_cmp(&xb)),
+                _ => i.cmp(j),
             })
+    });
+
+    let mut merged: Vec<(usize, PageHighlight)> = Vec::new();
+    for (index, highlight) in order {
+        match merged.last_mut() {
+            Some((first, prev)) if prev.page == highlight.page && continues(prev, &highlight, max_gap) => {
+                *first = (*first).min(index);
+                join_rects(&mut prev.sentence_rects, highlight.sentence_rects, max_gap);
+                join_rects(&mut prev.exact_rects, highlight.exact_rects, max_gap);
+            }
+            _ => merged.push((index, highlight)),
+        }
+    }
+    merged.sort_by_key(|(index, _)| *index);
+    merged.into_iter().map(|(_, highlight)| highlight).collect()
+}
+
+fn continues(prev: &PageHighlight, next: &PageHighlight, max_gap: f32) -> bool {
+    match (prev.sentence_rects.last(), next.sentence_rects.first()) {
+        (Some(a), Some(b)) => touches(a, b, max_gap),
+        _ => false,
+    }
+}
+
+// `b` starts on `a`'s line, no further than `max_gap` past its right edge
+fn touches(a: &CharBbox, b: &CharBbox, max_gap: f32) -> bool {
+    a.line == b.line && b.x >= a.x && b.x <= a.x + a.width + max_gap
+}
+
+// Append `rects`, fusing the first into the last existing rect when they touch
+fn join_rects(rects: &mut Vec<CharBbox>, more: Vec<CharBbox>, max_gap: f32) {
+    let mut more = more.into_iter();
+    if let (Some(last), Some(next)) = (rects.last_mut(), more.as_slice().first())
+        && touches(last, next, max_gap)
+    {
+        let right = (last.x + last.width).max(next.x + next.width);
+        let bottom = (last.y + last.height).max(next.y + next.height);
+        last.y = last.y.min(next.y);
+        last.width = right - last.x;
+        last.height = bottom - last.y;
+        more.next();
+    }
+    rects.extend(more);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{AdaptiveThreshold, SearchConfig};
+
+    fn result(page: u16, text: &str, query: &str) -> SearchResult {
+        SearchResult { point_id: None, page: PageNumber::new(page), text: text.to_string(), query: query.to_string(), score: 1.0, kind: None, context: None, seq: None, before: None, after: None, html_snippet: None }
+    }
+
+    fn point(page: f32, text: &str, score: f32) -> ScoredPoint {
+        let payload = [
+            ("page".to_string(), page.into()),
+            ("text".to_string(), text.into()),
+        ]
+        .into_iter()
+        .collect();
+        ScoredPoint { payload, score, ..Default::default() }
+    }
+
+    #[test]
+    fn results_carry_the_stored_point_id_as_a_string() {
+        let uuid = uuid::Uuid::new_v4().to_string();
+        let stored_as_uuid = ScoredPoint { id: Some(qdrant::point_id_from_string(&uuid)), ..point(2.0, "Dropout zeroes activations.", 0.9) };
+        let stored_as_num = ScoredPoint { id: Some(qdrant::point_id_from_string("42")), ..point(3.0, "Dropout rates near 0.5.", 0.8) };
+
+        let results = results_from_points(vec![stored_as_uuid, stored_as_num], "dropout", 1.0);
+        let ids: Vec<Option<&str>> = results.iter().map(|r| r.point_id.as_deref()).collect();
+        assert_eq!(ids, [Some(uuid.as_str()), Some("42")]);
+
+        // Numeric ids are strings on the wire too, so clients pass either back the same way
+        let highlight = page_highlight(&results[1], Vec::new(), Vec::new(), 612.0, 792.0);
+        assert_eq!(serde_json::to_value(&highlight).unwrap()["point_id"], serde_json::json!("42"));
+    }
+
+    #[test]
+    fn highlights_carry_the_printed_page_label_beside_the_physical_page() {
+        use lopdf::dictionary;
+
+        // Two pages of roman-numbered front matter before the body's page 1
+        let bytes = crate::pdf::tests::a4_pdf_pages(&[
+            &[(72, 700, "Preface.")],
+            &[(72, 700, "Contents.")],
+            &[(72, 700, "Introduction to optimization.")],
+        ]);
+        let mut doc = lopdf::Document::load_mem(&bytes).unwrap();
+        let nums = vec![0.into(), dictionary! { "S" => "r" }.into(), 2.into(), dictionary! { "S" => "D" }.into()];
+        doc.catalog_mut().unwrap().set("PageLabels", dictionary! { "Nums" => nums });
+        let labels = vb::page::page_labels(&doc).unwrap();
+
+        let mut highlights = vec![highlight(2, 72.0), highlight(3, 72.0)];
+        label_pages(&mut highlights, &labels);
+        let pages: Vec<serde_json::Value> = highlights
+            .iter()
+            .map(|h| serde_json::to_value(h).unwrap())
+            .map(|h| serde_json::json!([h["page"], h["page_label"]]))
             .collect();
+        assert_eq!(pages, [serde_json::json!([2, "ii"]), serde_json::json!([3, "1"])]);
+    }
 
-        let fuzzy_matches = fuzzy::fuzzy_search(&char_entries, &needle_chars, 0.85);
+    #[test]
+    fn slow_search_is_logged_with_its_stage_timings() {
+        let mut timings = SearchTimings::default();
+        // An artificially slow highlighting stage
+        timed(&mut timings.highlight, || std::thread::sleep(Duration::from_millis(30)));
+        timed(&mut timings.total, || std::thread::sleep(Duration::from_millis(30)));
+        let queries = ["gradient descent"];
 
-        let snapped_matches: Vec<(usize, usize, f32)> = fuzzy_matches
-            .into_iter()
-            .map(|(start, end, score)| {
-                let (new_start, new_end) = snap_to_sentence_boundaries(&char_entries, start, end);
-                (new_start, new_end, score)
-            })
+        let warning = slow_search_warning(Some(Duration::from_millis(20)), "doc-1", "notes.pdf_6f1c", &queries, &timings).unwrap();
+        assert_eq!(warning["event"], "slow_search");
+        assert_eq!((&warning["id"], &warning["file"]), (&serde_json::json!("doc-1"), &serde_json::json!("notes.pdf_6f1c")));
+        assert_eq!(warning["queries"], serde_json::json!(["gradient descent"]));
+        assert!(warning["stages_ms"]["compute_highlights"].as_u64().unwrap() >= 30);
+        assert_eq!(warning["stages_ms"]["embed_query"], 0);
+
+        // Fast enough, or logging turned off
+        assert!(slow_search_warning(Some(Duration::from_secs(5)), "doc-1", "notes.pdf_6f1c", &queries, &timings).is_none());
+        assert!(slow_search_warning(None, "doc-1", "notes.pdf_6f1c", &queries, &timings).is_none());
+    }
+
+    #[test]
+    fn first_stored_page_resolves_to_pdfium_index_zero() {
+        let results = results_from_points(vec![point(1.0, "Preface.", 0.9)], "preface", 1.0);
+
+        // compute_highlights looks pages up by this index
+        assert_eq!(results[0].page.get(), 1);
+        assert_eq!(results[0].page.index(), 0);
+    }
+
+    #[test]
+    fn hit_without_a_usable_page_is_skipped() {
+        let mut null_page = point(1.0, "Momentum smooths noisy gradients.", 0.9);
+        null_page.payload.insert("page".to_string(), qdrant_client::qdrant::Value { kind: Some(Kind::NullValue(0)) });
+        let mut unset_page = point(1.0, "Nesterov momentum looks ahead.", 0.8);
+        unset_page.payload.remove("page");
+        let good = point(4.0, "Adam adapts per-parameter learning rates.", 0.7);
+
+        let results = results_from_points(vec![null_page, unset_page, good], "momentum", 1.0);
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].page.get(), 4);
+
+        assert_eq!(page_from_payload(&Some(Kind::StringValue("7".to_string()))), Some(PageNumber::new(7)));
+        assert_eq!(page_from_payload(&Some(Kind::IntegerValue(0))), None);
+    }
+
+    #[test]
+    fn missing_pdfium_is_a_503_not_a_panic() {
+        use axum::response::IntoResponse;
+
+        // What compute_highlights hands back when get_pdfium fails
+        let err = anyhow::Error::from(PdfiumUnavailable::for_tests("./libpdfium.so"));
+        let resp = highlight_error(err, "notes.pdf").into_response();
+        assert_eq!(resp.status(), axum::http::StatusCode::SERVICE_UNAVAILABLE);
+
+        let other = highlight_error(anyhow::anyhow!("bad page"), "notes.pdf").into_response();
+        assert_eq!(other.status(), axum::http::StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    #[test]
+    fn repeated_phrase_contributes_one_span_when_capped() {
+        let page = "Gradient descant is one option. Gradient descent is the usual choice. \
+            Gradiant descent also appears in the appendix.";
+        let entries: Vec<(usize, char)> = page.chars().enumerate().collect();
+        // Not an exact substring, so every near-copy is a fuzzy candidate
+        let needle: Vec<char> = "gradient descemt".chars().collect();
+
+        let fuzzy = fuzzy::FuzzyConfig { threshold: 0.75, ..Default::default() };
+        let matches = fuzzy::fuzzy_search_with_config(&entries, &needle, &fuzzy);
+        assert_eq!(matches.len(), 3, "{:?}", matches);
+
+        let capped = best_matches(matches.clone(), 1);
+        assert_eq!(capped.len(), 1);
+        let best = matches.iter().map(|m| m.2).fold(f32::MIN, f32::max);
+        assert_eq!(capped[0].2, best);
+        assert!(matches.contains(&capped[0]));
+    }
+
+    #[test]
+    fn adaptive_threshold_is_strict_for_short_needles_and_loose_for_long() {
+        let config = HighlightConfig {
+            adaptive_threshold: Some(AdaptiveThreshold::default()),
+            ..HighlightConfig::default()
+        };
+        let short = "dropout".chars().count();
+        let long = "Dropout randomly zeroes a fraction of activations during each training step, \
+            which keeps units from co-adapting and acts as a cheap ensemble over thinned networks. \
+            At test time every unit is kept and its output scaled to match."
+            .chars()
+            .count();
+
+        assert_eq!(config.fuzzy_for(short).threshold, 0.92);
+        assert_eq!(config.fuzzy_for(long).threshold, 0.80);
+        // Halfway between the endpoints lands halfway between the thresholds
+        assert!((config.fuzzy_for(110).threshold - 0.86).abs() < 1e-6);
+
+        // Off by default: every needle uses the fixed threshold
+        let fixed = HighlightConfig::default();
+        assert_eq!(fixed.fuzzy_for(short).threshold, fixed.fuzzy.threshold);
+        assert_eq!(fixed.fuzzy_for(long).threshold, fixed.fuzzy.threshold);
+    }
+
+    #[test]
+    fn parses_adaptive_threshold_endpoints() {
+        let parsed = AdaptiveThreshold::parse("10:0.95, 300:0.75").unwrap();
+        assert_eq!(parsed, AdaptiveThreshold { short: (10, 0.95), long: (300, 0.75) });
+        assert_eq!(AdaptiveThreshold::parse("on"), Some(AdaptiveThreshold::default()));
+        assert_eq!(AdaptiveThreshold::parse("0.9"), None);
+    }
+
+    #[test]
+    fn failing_page_becomes_warning_and_others_still_highlight() {
+        let results = vec![
+            result(1, "Backpropagation applies the chain rule.", "chain rule"),
+            result(2, "A page pdfium can't read.", "chain rule"),
+            result(2, "Another hit on the same page.", "chain rule"),
+            result(5, "The chain rule composes derivatives.", "chain rule"),
+        ];
+
+        let (highlights, warnings) = highlight_each(&results, |r| {
+            if r.page.get() == 2 {
+                anyhow::bail!("Failed to get text for page {}", r.page);
+            }
+            Ok(vec![(1.0, highlight(r.page.get(), 0.0))])
+        });
+
+        let pages: Vec<u16> = highlights.iter().map(|(_, h)| h.page.get()).collect();
+        assert_eq!(pages, [1, 5]);
+        assert_eq!(warnings.len(), 1, "one warning per failing page");
+        assert_eq!(warnings[0].page, PageNumber::new(2));
+        assert!(warnings[0].message.contains("page 2"));
+    }
+
+    #[test]
+    fn snippet_marks_exactly_the_matched_words() {
+        let markers = SnippetMarkers::default();
+        let text = "Für große Lernraten <α> divergiert der Gradientenabstieg schnell.";
+
+        let snippet = mark_snippet(text, "Gradientenabstieg", &markers, &FuzzyConfig::default());
+        assert_eq!(
+            snippet.as_deref(),
+            Some("Für große Lernraten &lt;α&gt; divergiert der <mark>Gradientenabstieg</mark> schnell.")
+        );
+
+        let custom = SnippetMarkers { open: "[[".to_string(), close: "]]".to_string() };
+        let snippet = mark_snippet(text, "große", &custom, &FuzzyConfig::default()).unwrap();
+        assert!(snippet.starts_with("Für [[große]] Lernraten"), "{}", snippet);
+        assert_eq!(mark_snippet(text, "backpropagation", &markers, &FuzzyConfig::default()), None);
+    }
+
+    #[test]
+    fn truncated_snippet_keeps_a_late_match_and_its_markers() {
+        let markers = SnippetMarkers::default();
+        let text = "Optimizers differ in how they scale each step, and plain stochastic gradient \
+            descent uses one learning rate for every parameter & every step, while Adam rescales by the variance.";
+        let snippet = mark_snippet(text, "Adam rescales", &markers, &FuzzyConfig::default()).unwrap();
+
+        let mut highlights = vec![highlight(1, 5.0)];
+        highlights[0].html_snippet = Some(snippet.clone());
+        truncate_highlight_text(&mut highlights, 40, &markers);
+        let cut = highlights[0].html_snippet.clone().unwrap();
+        assert!(cut.contains("<mark>Adam rescales</mark>"), "{}", cut);
+        assert!(cut.starts_with('…'), "{}", cut);
+        assert_eq!(cut.matches("<mark>").count(), cut.matches("</mark>").count(), "{}", cut);
+        let seen = cut.replace("<mark>", "").replace("</mark>", "").replace("&amp;", "&");
+        assert!(seen.chars().count() <= 42, "{}", seen);
+
+        // A limit shorter than the match still closes the marker it opened
+        let cut = truncate_snippet(&snippet, 6, &markers);
+        assert_eq!(cut, "…<mark>Adam</mark>…");
+
+        // An entity is kept whole or left out, never split
+        let cut = truncate_snippet("salt &amp; pepper &amp; vinegar", 6, &markers);
+        assert_eq!(cut, "salt &amp;…");
+        assert_eq!(truncate_snippet(&snippet, 1_000, &markers), snippet);
+
+        let same = SnippetMarkers { open: "**".to_string(), close: "**".to_string() };
+        let snippet = mark_snippet(text, "Adam rescales", &same, &FuzzyConfig::default()).unwrap();
+        assert_eq!(truncate_snippet(&snippet, 6, &same), "…**Adam**…");
+    }
+
+    #[test]
+    fn folded_search_marks_accented_words() {
+        let points = || vec![point(1.0, "Attach your Résumé below.", 0.7)];
+        let options = SearchOptions::default();
+        let mut config = crate::config::AppConfig::default();
+
+        let plain = search_results(points(), "resume", &config, &options);
+        assert_eq!(plain[0].html_snippet, None);
+
+        config.highlight.fuzzy.fold_diacritics = true;
+        let folded = search_results(points(), "resume", &config, &options);
+        assert_eq!(folded[0].html_snippet.as_deref(), Some("Attach your <mark>Résumé</mark> below."));
+    }
+
+    #[test]
+    fn case_sensitive_keyword_fallback_keeps_case() {
+        let stored = || qdrant_client::qdrant::RetrievedPoint {
+            payload: point(1.0, "The Acme contract renews in May.", 0.0).payload,
+            ..Default::default()
+        };
+        let config = crate::config::AppConfig::default();
+        let keyword_hit = |options: &SearchOptions| qdrant::keyword_hit(stored(), "ACME", &query_options(&config, options, None));
+
+        assert!(keyword_hit(&SearchOptions::default()).is_some());
+        let sensitive = SearchOptions { case_sensitive: true, ..SearchOptions::default() };
+        assert!(keyword_hit(&sensitive).is_none());
+    }
+
+    fn search_query(q: &str) -> SearchWithBboxQuery {
+        SearchWithBboxQuery {
+            id: "missing".to_string(),
+            q: vec![q.to_string()],
+            max_text_len: None,
+            uploaded_after: None,
+            uploaded_before: None,
+            neighbors: false,
+            origin: Default::default(),
+            units: Default::default(),
+            padding: None,
+            within_ids: vec![],
+            lang: None,
+            diversity: None,
+            case_sensitive: false,
+            retrieve_score: None,
+            highlight_score: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn query_model_is_the_documents_own() {
+        let mut config = crate::config::AppConfig::default();
+        config.embed.language_models = embed::parse_language_models("de=model-de");
+        let state = AppState { config: std::sync::Arc::new(config), ..AppState::for_tests() };
+        state.lang_map.write().await.insert("german-doc".to_string(), "de".to_string());
+
+        // Uploaded in German, so its queries use the German model unless told otherwise
+        assert_eq!(query_lang(&state, "german-doc", None).await.ok(), Some(Some("de".to_string())));
+        assert_eq!(query_lang(&state, "other-doc", None).await.ok(), Some(None));
+        assert_eq!(query_lang(&state, "german-doc", Some("DE")).await.ok(), Some(Some("de".to_string())));
+
+        // Another model's query vector can't be compared with the document's
+        let mismatched = query_lang(&state, "other-doc", Some("de")).await;
+        let status = mismatched.err().map(|e| axum::response::IntoResponse::into_response(e).status());
+        assert_eq!(status, Some(axum::http::StatusCode::BAD_REQUEST));
+
+        let unknown = query_lang(&state, "german-doc", Some("ja")).await;
+        let status = unknown.err().map(|e| axum::response::IntoResponse::into_response(e).status());
+        assert_eq!(status, Some(axum::http::StatusCode::BAD_REQUEST));
+    }
+
+    // A model directory whose files name a collection but hold no real model
+    fn model_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("vb-model-{}-{}", name, uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for file in embed::MODEL_FILES {
+            std::fs::write(dir.join(file), format!("{} {}", file, name)).unwrap();
+        }
+        dir
+    }
+
+    #[tokio::test]
+    async fn slow_stage_inside_a_search_is_logged() {
+        let dir = model_dir("slow");
+        let mut config = crate::config::AppConfig::default();
+        config.embed.model_dir = dir.clone();
+        config.search.slow_search = Some(Duration::from_millis(20));
+        let state = AppState { config: std::sync::Arc::new(config), ..AppState::for_tests() };
+
+        // An upload's embedding holds the only permit for a while, so the query's embedding stalls
+        let upload = state.embed_permits.clone().acquire_owned().await.unwrap();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(60)).await;
+            drop(upload);
+        });
+
+        let before = metrics().slow_searches.get();
+        let document = DocumentSnapshot { file_name: "notes.pdf_6f1c".to_string(), bytes: Vec::new() };
+        let started = Instant::now();
+        let result = search_and_highlight(&state, "doc-1", &document, &["gradient descent"], &SearchOptions::default()).await;
+        std::fs::remove_dir_all(&dir).ok();
+
+        // There's no Qdrant to answer, but the search only failed after the slow stage
+        assert!(result.is_err());
+        assert!(started.elapsed() >= Duration::from_millis(60));
+        assert!(metrics().slow_searches.get() > before);
+    }
+
+    #[tokio::test]
+    async fn each_model_config_routes_to_its_own_collection() {
+        // Two model directories that differ only in their config.json
+        let (small, large, german) = (model_dir("small"), model_dir("large"), model_dir("german"));
+        let state_for = |model_dir: &std::path::Path| {
+            let mut config = crate::config::AppConfig::default();
+            config.embed.model_dir = model_dir.to_path_buf();
+            config.embed.language_models = HashMap::from([("de".to_string(), german.clone())]);
+            AppState { config: std::sync::Arc::new(config), ..AppState::for_tests() }
+        };
+        let (first, second) = (state_for(&small), state_for(&large));
+        for state in [&first, &second] {
+            state.lang_map.write().await.insert("german-doc".to_string(), "de".to_string());
+        }
+
+        let default_first = document_collection(&first, "doc").await.ok().unwrap();
+        let default_second = document_collection(&second, "doc").await.ok().unwrap();
+        let german_first = document_collection(&first, "german-doc").await.ok().unwrap();
+        for dir in [&small, &large, &german] {
+            std::fs::remove_dir_all(dir).ok();
+        }
+
+        // Swapping the default model moves its documents to a new collection
+        assert_ne!(default_first, default_second);
+        // A German document lives with the German model's vectors under either config
+        assert_ne!(german_first, default_first);
+        assert_eq!(german_first, document_collection(&second, "german-doc").await.ok().unwrap());
+        assert!(german_first.ends_with("_de"), "{}", german_first);
+    }
+
+    #[tokio::test]
+    async fn too_short_query_is_rejected() {
+        let resp = search_with_bboxes(State(AppState::for_tests()), Query(search_query(" a "))).await;
+        let status = resp.err().map(|e| axum::response::IntoResponse::into_response(e).status());
+        assert_eq!(status, Some(axum::http::StatusCode::BAD_REQUEST));
+    }
+
+    #[tokio::test]
+    async fn socket_and_selection_queries_are_held_to_the_minimum_length() {
+        // The path /ws/search and /api/search-selection share, before the id is looked up
+        let resp = highlights_for_queries(&AppState::for_tests(), "unknown", &[" a "], &SearchOptions::default()).await;
+        let status = resp.err().map(|e| axum::response::IntoResponse::into_response(e).status());
+        assert_eq!(status, Some(axum::http::StatusCode::BAD_REQUEST));
+
+        // A cleared search box is no query at all
+        let resp = highlights_for_queries(&AppState::for_tests(), "unknown", &["  "], &SearchOptions::default()).await;
+        assert!(resp.is_ok_and(|found| found.highlights.is_empty()));
+    }
+
+    #[test]
+    fn numbers_and_punctuation_skip_the_embedding() {
+        assert_eq!(classify_query("2024"), QueryKind::Exact);
+        assert_eq!(classify_query("3.14 (2019)"), QueryKind::Exact);
+        assert_eq!(classify_query("???"), QueryKind::Punctuation);
+        assert_eq!(classify_query("revenue in 2024"), QueryKind::Semantic);
+        assert_eq!(classify_query("Größe"), QueryKind::Semantic);
+    }
+
+    #[tokio::test]
+    async fn punctuation_only_query_is_rejected() {
+        let resp = search_with_bboxes(State(AppState::for_tests()), Query(search_query("???"))).await;
+        let error = resp.err().unwrap();
+        assert!(error.to_json().to_string().contains("no letters or digits"));
+        let status = axum::response::IntoResponse::into_response(error).status();
+        assert_eq!(status, axum::http::StatusCode::BAD_REQUEST);
+
+        // A year is searched, so validation passes and the unknown id is what fails
+        let resp = search_with_bboxes(State(AppState::for_tests()), Query(search_query("2024"))).await;
+        let status = resp.err().map(|e| axum::response::IntoResponse::into_response(e).status());
+        assert_eq!(status, Some(axum::http::StatusCode::NOT_FOUND));
+    }
+
+    #[tokio::test]
+    async fn repeated_search_is_served_from_cache_until_reindex() {
+        let state = AppState::for_tests();
+        state.id_map.write().await.insert("doc".to_string(), "notes.pdf_6f1c".to_string());
+        let options = SearchOptions::default();
+        // Stands in for run_query, counting how often the search actually runs
+        let runs = std::sync::atomic::AtomicUsize::new(0);
+        let run_query = || async {
+            runs.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            Ok(HighlightResponse { highlights: vec![highlight(2, 72.0)], ..Default::default() })
+        };
+
+        let first = cached_highlights(&state, "doc", &["gradient descent"], &options, run_query).await.ok().unwrap();
+        let second = cached_highlights(&state, "doc", &["  gradient   descent "], &options, run_query).await.ok().unwrap();
+        assert_eq!(runs.load(std::sync::atomic::Ordering::SeqCst), 1);
+        assert_eq!(second.highlights.len(), first.highlights.len());
+
+        // Different options are a different search
+        let normalized = SearchOptions { units: CoordinateUnits::Normalized, ..Default::default() };
+        cached_highlights(&state, "doc", &["gradient descent"], &normalized, run_query).await.ok().unwrap();
+        assert_eq!(runs.load(std::sync::atomic::Ordering::SeqCst), 2);
+
+        forget_searches(&state, "doc").await;
+        cached_highlights(&state, "doc", &["gradient descent"], &options, run_query).await.ok().unwrap();
+        assert_eq!(runs.load(std::sync::atomic::Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn document_deleted_mid_search_still_gets_a_coherent_response() {
+        let state = AppState::for_tests();
+        let line = "Momentum smooths noisy gradients.";
+        state.id_map.write().await.insert("doc".to_string(), "notes.pdf_6f1c".to_string());
+        state.bytes_map.write().await.insert("doc".to_string(), a4_pdf(line));
+        let options = SearchOptions::default();
+
+        let search = || async {
+            let document = snapshot_document(&state, "doc").await?;
+            // The expiry sweeper deletes the document between the query and highlighting
+            crate::expiry::forget(&state, "doc").await;
+            let hit = result(1, line, "momentum");
+            let needle: Vec<char> = line.to_lowercase().chars().collect();
+            let config = HighlightConfig::default();
+            let found = fallback_highlights(&mut page_spans(&document.bytes), &hit, &needle, &config, &options, (595.0, 842.0), None)?;
+            Ok(HighlightResponse { highlights: found.into_iter().map(|(_, h)| h).collect(), ..Default::default() })
+        };
+        // Highlighted from the snapshot taken before the delete
+        let response = cached_highlights(&state, "doc", &["momentum"], &options, search).await.ok().unwrap();
+        assert_eq!(response.highlights.len(), 1);
+        assert_eq!(response.highlights[0].page, PageNumber::new(1));
+
+        // ...but not cached for a document that no longer exists
+        assert!(state.search_cache.read().await.is_empty());
+        let err = collect_highlights(&state, "doc", &["momentum"], &options).await.err().unwrap();
+        assert_eq!(axum::response::IntoResponse::into_response(err).status(), axum::http::StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn negative_or_nan_padding_is_rejected() {
+        for padding in [-2.0, f32::NAN, f32::INFINITY] {
+            let query = SearchWithBboxQuery { padding: Some(padding), ..search_query("dropout") };
+            let resp = search_with_bboxes(State(AppState::for_tests()), Query(query)).await;
+            let status = resp.err().map(|e| axum::response::IntoResponse::into_response(e).status());
+            assert_eq!(status, Some(axum::http::StatusCode::BAD_REQUEST), "{}", padding);
+        }
+    }
+
+    #[tokio::test]
+    async fn two_char_query_is_accepted() {
+        // Gets past validation to the id lookup, which fails for an unknown id
+        let resp = search_with_bboxes(State(AppState::for_tests()), Query(search_query("ml"))).await;
+        let status = resp.err().map(|e| axum::response::IntoResponse::into_response(e).status());
+        assert_eq!(status, Some(axum::http::StatusCode::NOT_FOUND));
+    }
+
+    #[tokio::test]
+    async fn searching_a_processing_document_is_not_ready() {
+        let state = AppState::for_tests();
+        state.id_map.write().await.insert("doc".to_string(), "processing".to_string());
+        let mut query = search_query("dropout");
+        query.id = "doc".to_string();
+
+        let err = search_with_bboxes(State(state), Query(query)).await.err().unwrap();
+        assert_eq!(err.to_json()["error"]["code"], "not_ready");
+        let status = axum::response::IntoResponse::into_response(err).status();
+        assert_eq!(status, axum::http::StatusCode::CONFLICT);
+    }
+
+    #[test]
+    fn exact_span_sits_inside_its_sentence_span() {
+        let page = "Intro text. Dropout zeroes random activations during training. Then more.";
+        let entries: Vec<(usize, char)> = page.chars().enumerate().collect();
+        let hit = page.find("random activations").unwrap();
+        // The second match starts on the space after a period
+        let edge = page.find(" Then").unwrap();
+        let spans = snap_matches(&entries, vec![(hit, hit + 18, 0.95), (edge, edge + 5, 0.9)], 0);
+
+        let text = |range: &Range<usize>| page[range.clone()].to_string();
+        assert_eq!(text(&spans[0].exact), "random activations");
+        assert_eq!(text(&spans[0].sentence), "Dropout zeroes random activations during training.");
+        for span in &spans {
+            assert!(span.sentence.start <= span.exact.start && span.exact.end <= span.sentence.end);
+        }
+    }
+
+    #[test]
+    fn truncation_cuts_at_word_boundary() {
+        let text = "Stochastic gradient descent samples one minibatch per step.";
+        assert_eq!(truncate_at_word(text, 24), "Stochastic gradient…");
+        assert_eq!(truncate_at_word(text, 19), "Stochastic gradient…");
+        assert_eq!(truncate_at_word(text, 200), text);
+    }
+
+    #[test]
+    fn truncation_without_a_word_boundary_cuts_mid_word() {
+        assert_eq!(truncate_at_word("Donaudampfschifffahrtsgesellschaft", 10), "Donaudampf…");
+        assert_eq!(truncate_at_word("  Donaudampfschifffahrt ist lang", 10), "  Donaudam…");
+        assert_eq!(truncate_at_word("https://example.org/a/very/long/path", 8), "https://…");
+    }
+
+    #[test]
+    fn truncation_counts_characters_not_bytes() {
+        // Every char here is multi-byte, so a byte-based cut would split one
+        assert_eq!(truncate_at_word("Ελληνικά κείμενα για δοκιμή", 12), "Ελληνικά…");
+        assert_eq!(truncate_at_word("日本語のテキストです", 4), "日本語の…");
+        assert_eq!(truncate_at_word("Größenänderung über", 14), "Größenänderung…");
+        assert_eq!(truncate_at_word("naïve café", 10), "naïve café");
+    }
+
+    #[test]
+    fn truncation_cuts_every_chunk_text_but_leaves_rects_alone() {
+        let mut highlights = vec![highlight(1, 5.0)];
+        highlights[0].context = Some("Momentum keeps a running average of past gradients.".to_string());
+        highlights[0].before = Some("Plain gradient descent takes one fixed step.".to_string());
+        highlights[0].after = Some("Adam also rescales by the gradient variance.".to_string());
+        highlights[0].html_snippet = Some("Nesterov momentum looks one step ahead.".to_string());
+        let rects_before = format!("{:?} {:?}", highlights[0].exact_rects, highlights[0].sentence_rects);
+
+        truncate_highlight_text(&mut highlights, 20, &SnippetMarkers::default());
+
+        let highlight = &highlights[0];
+        assert_eq!(highlight.context.as_deref(), Some("Momentum keeps a…"));
+        assert_eq!(highlight.before.as_deref(), Some("Plain gradient…"));
+        assert_eq!(highlight.after.as_deref(), Some("Adam also rescales…"));
+        assert_eq!(highlight.html_snippet.as_deref(), Some("Nesterov momentum…"));
+        for text in [&highlight.context, &highlight.before, &highlight.after, &highlight.html_snippet] {
+            assert!(text.as_ref().unwrap().chars().count() <= 21, "{:?}", text);
+        }
+        assert_eq!(format!("{:?} {:?}", highlight.exact_rects, highlight.sentence_rects), rects_before);
+    }
+
+    #[test]
+    fn small_chunk_hit_carries_parent_text() {
+        let parent = "Optimizers differ in how they scale each step. Momentum keeps a running \
+            average of past gradients. Adam also rescales by their variance.";
+        let mut small = point(3.0, "Momentum keeps a running average of past gradients.", 0.8);
+        small.payload.insert("parent_id".to_string(), "3-0".into());
+        small.payload.insert("parent_text".to_string(), parent.into());
+
+        let results = results_from_points(vec![small], "momentum", 1.0);
+
+        // The small chunk is still what gets highlighted; the parent is context
+        assert_eq!(results[0].text, "Momentum keeps a running average of past gradients.");
+        assert_eq!(results[0].context.as_deref(), Some(parent));
+    }
+
+    fn highlight(page: u16, x: f32) -> PageHighlight {
+        PageHighlight {
+            page: PageNumber::new(page),
+            page_label: None,
+            point_id: None,
+            exact_rects: vec![CharBbox { x, y: 0.0, width: 1.0, height: 1.0, line: 0 }],
+            sentence_rects: vec![CharBbox { x, y: 0.0, width: 1.0, height: 1.0, line: 0 }],
+            page_width: 612.0,
+            page_height: 792.0,
+            query: "term".to_string(),
+            kind: None,
+            context: None,
+            before: None,
+            after: None,
+            html_snippet: None,
+            low_confidence: false,
+        }
+    }
+
+    #[test]
+    fn text_pdfium_cannot_read_is_placed_from_pdf_oxide_runs() {
+        // Stands in for a page whose text layer pdfium loads as empty
+        let line = "Gradient descent follows the slope of the loss.";
+        let bytes = a4_pdf(line);
+        let config = HighlightConfig::default();
+        let fallback = |text: &str| {
+            let needle: Vec<char> = text.chars().collect();
+            let options = SearchOptions::default();
+            fallback_highlights(&mut page_spans(&bytes), &result(1, text, "slope"), &needle, &config, &options, (595.0, 842.0), None).unwrap()
+        };
+
+        let found = fallback("follows the slope");
+        assert_eq!(found.len(), 1);
+        let rects = &found[0].1.sentence_rects;
+        // The whole run the match sits in, where the fixture set it
+        assert_eq!(rects.len(), 1);
+        assert_eq!((rects[0].x, rects[0].y, rects[0].line), (72.0, 700.0, 0));
+        assert!(rects[0].width > 200.0);
+        assert_eq!(found[0].1.exact_rects.len(), 1);
+
+        assert!(fallback("batch normalization").is_empty());
+    }
+
+    #[test]
+    fn chunk_running_past_the_page_break_highlights_both_pages() {
+        let first = "Momentum keeps each update moving in";
+        let second = "a consistent direction across steps.";
+        let bytes = crate::pdf::tests::a4_pdf_pages(&[
+            &[(72, 700, "Optimizers differ in how they step."), (72, 60, first)],
+            &[(72, 780, second), (72, 700, "Unrelated closing remarks about regularization.")],
+        ]);
+        let chunk = result(1, &format!("{} {}", first, second), "momentum");
+        let needle: Vec<char> = chunk.text.to_lowercase().chars().collect();
+        let (config, options) = (HighlightConfig::default(), SearchOptions::default());
+        let a4 = (595.0, 842.0);
+
+        let found = fallback_highlights(&mut page_spans(&bytes), &chunk, &needle, &config, &options, a4, Some(a4)).unwrap();
+        let pages: Vec<u16> = found.iter().map(|(_, h)| h.page.get()).collect();
+        assert_eq!(pages, [1, 2]);
+        // Each half is boxed where it sits: the foot of page 1, the top of page 2
+        assert_eq!(found[0].1.exact_rects.iter().map(|r| r.y).collect::<Vec<_>>(), [60.0]);
+        assert_eq!(found[1].1.exact_rects.iter().map(|r| r.y).collect::<Vec<_>>(), [780.0]);
+
+        // On the last page there is nowhere to continue
+        assert!(fallback_highlights(&mut page_spans(&bytes), &chunk, &needle, &config, &options, a4, None).unwrap().is_empty());
+    }
+
+    #[test]
+    fn each_half_of_a_split_chunk_carries_its_own_page_size() {
+        use crate::test_pdf::{to_bytes, TestPdf};
+
+        let first = "Momentum keeps each update moving in";
+        let second = "a consistent direction across steps.";
+        let mut pdf = TestPdf::new();
+        pdf.text_page(&[(72, 60, first)]);
+        let letter = pdf.text_page(&[(72, 700, second)]);
+        let mut doc = pdf.finish(lopdf::Dictionary::new());
+        let media_box = |doc: &lopdf::Document, page| -> (f32, f32) {
+            let corners = doc.get_dictionary(page).unwrap().get(b"MediaBox").unwrap().as_array().unwrap();
+            (corners[2].as_float().unwrap(), corners[3].as_float().unwrap())
+        };
+        doc.get_dictionary_mut(letter).unwrap().set("MediaBox", vec![0.into(), 0.into(), 612.into(), 792.into()]);
+        let sizes: Vec<(f32, f32)> = doc.get_pages().values().map(|&page| media_box(&doc, page)).collect();
+        let bytes = to_bytes(doc);
+
+        let chunk = result(1, &format!("{} {}", first, second), "momentum");
+        let needle: Vec<char> = chunk.text.to_lowercase().chars().collect();
+        let config = HighlightConfig::default();
+        let highlight = |origin: CoordinateOrigin| {
+            let options = SearchOptions { origin, ..SearchOptions::default() };
+            fallback_highlights(&mut page_spans(&bytes), &chunk, &needle, &config, &options, sizes[0], Some(sizes[1])).unwrap()
+        };
+
+        let found = highlight(CoordinateOrigin::BottomLeft);
+        let dims: Vec<(f32, f32)> = found.iter().map(|(_, h)| (h.page_width, h.page_height)).collect();
+        assert_eq!(dims, [(595.0, 842.0), (612.0, 792.0)]);
+
+        // Flipped against the height of the page the half is on
+        let bottom_up = &found[1].1.exact_rects[0];
+        let flipped = &highlight(CoordinateOrigin::TopLeft)[1].1.exact_rects[0];
+        assert_eq!(flipped.y, 792.0 - bottom_up.y - bottom_up.height);
+    }
+
+    #[test]
+    fn page_break_is_tried_before_the_relaxed_retry() {
+        // The opening line is close enough to the chunk for the relaxed pass
+        let lookalike = "Momentum makes every update follow a running average of the past.";
+        let first = "Momentum keeps each update moving in";
+        let second = "a consistent direction across steps.";
+        let bytes = crate::pdf::tests::a4_pdf_pages(&[&[(72, 700, lookalike), (72, 60, first)], &[(72, 780, second)]]);
+        let chunk = result(1, &format!("{} {}", first, second), "momentum");
+        let needle: Vec<char> = chunk.text.to_lowercase().chars().collect();
+        let (config, options) = (HighlightConfig::default(), SearchOptions::default());
+        assert!(config.relaxed_retry.is_some());
+        let a4 = (595.0, 842.0);
+
+        let found = fallback_highlights(&mut page_spans(&bytes), &chunk, &needle, &config, &options, a4, Some(a4)).unwrap();
+        let pages: Vec<(u16, bool)> = found.iter().map(|(_, h)| (h.page.get(), h.low_confidence)).collect();
+        assert_eq!(pages, [(1, false), (2, false)]);
+        assert_eq!(found[0].1.exact_rects.iter().map(|r| r.y).collect::<Vec<_>>(), [60.0]);
+
+        // On the last page, the lookalike is all there is
+        let found = fallback_highlights(&mut page_spans(&bytes), &chunk, &needle, &config, &options, a4, None).unwrap();
+        let pages: Vec<(u16, bool)> = found.iter().map(|(_, h)| (h.page.get(), h.low_confidence)).collect();
+        assert_eq!(pages, [(1, true)]);
+    }
+
+    #[test]
+    fn worn_text_is_found_by_the_relaxed_retry_and_flagged() {
+        // OCR output with misread letters, too far off for the configured threshold
+        let bytes = a4_pdf("Gr4d1ent dcsc3nt fo11ows th3 s1ope 0f th3 1o55.");
+        let chunk = result(1, "Gradient descent follows the slope of the loss.", "descent");
+        let needle: Vec<char> = chunk.text.to_lowercase().chars().collect();
+        let options = SearchOptions::default();
+        let highlight = |config: &HighlightConfig| {
+            fallback_highlights(&mut page_spans(&bytes), &chunk, &needle, config, &options, (595.0, 842.0), None).unwrap()
+        };
+
+        let strict_only = HighlightConfig { relaxed_retry: None, ..HighlightConfig::default() };
+        assert!(highlight(&strict_only).is_empty());
+
+        let found = highlight(&HighlightConfig::default());
+        assert_eq!(found.len(), 1);
+        assert!(found[0].1.low_confidence);
+
+        // A clean page matches on the first pass and isn't flagged
+        let clean = a4_pdf(&chunk.text);
+        let found = fallback_highlights(&mut page_spans(&clean), &chunk, &needle, &HighlightConfig::default(), &options, (595.0, 842.0), None).unwrap();
+        assert!(!found.is_empty() && found.iter().all(|(_, h)| !h.low_confidence));
+    }
+
+    #[test]
+    fn generous_retrieval_with_strict_highlight_floor_highlights_nothing() {
+        let line = "Gradient descent follows the slope of the loss.";
+        let bytes = a4_pdf(line);
+        let mut points = vec![point(1.0, "gradient dscent follows", 0.31), point(1.0, "batch normalization", 0.12)];
+        retain_above(&mut points, Some(0.3));
+        let results = results_from_points(points, "descent", 1.0);
+        assert_eq!(results.len(), 1, "the weak but relevant hit is retrieved");
+
+        let config = HighlightConfig::default();
+        let highlight = |result: &SearchResult, highlight_score: Option<f32>| {
+            let needle: Vec<char> = result.text.chars().collect();
+            let options = SearchOptions { highlight_score, ..SearchOptions::default() };
+            fallback_highlights(&mut page_spans(&bytes), result, &needle, &config, &options, (595.0, 842.0), None).unwrap()
+        };
+        // The configured threshold forgives the typo...
+        assert!(results.iter().all(|result| !highlight(result, None).is_empty()));
+        // ...a strict floor keeps the hits but draws nothing for them
+        assert!(results.iter().all(|result| highlight(result, Some(0.99)).is_empty()));
+    }
+
+    #[test]
+    fn adjacent_matches_merge_into_one_rect() {
+        let rect = |x: f32, width: f32, line: usize| CharBbox { x, y: line as f32 * 14.0, width, height: 12.0, line };
+        let with_rects = |rects: Vec<CharBbox>| PageHighlight { exact_rects: rects.clone(), sentence_rects: rects, ..highlight(3, 0.0) };
+        // Best first: the second half of the phrase, then the first, then a hit two lines down
+        let highlights = vec![
+            with_rects(vec![rect(152.0, 80.0, 0)]),
+            with_rects(vec![rect(72.0, 78.0, 0)]),
+            with_rects(vec![rect(72.0, 40.0, 2)]),
+        ];
+
+        let merged = merge_adjacent_highlights(highlights, 4.0);
+        assert_eq!(merged.len(), 2);
+        let joined = &merged[0].sentence_rects;
+        assert_eq!(joined.len(), 1);
+        assert_eq!((joined[0].x, joined[0].width), (72.0, 160.0));
+        assert_eq!(merged[0].exact_rects.len(), 1);
+        assert_eq!(merged[1].sentence_rects[0].line, 2);
+    }
+
+    #[test]
+    fn glossary_page_highlights_are_capped_best_first() {
+        // Twelve "term" entries on page 2, scores rising down the page
+        let mut scored: Vec<(f32, PageHighlight)> =
+            (0..12).map(|i| (0.85 + i as f32 * 0.01, highlight(2, i as f32))).collect();
+        scored.push((0.9, highlight(5, 100.0)));
+
+        let capped = cap_highlights_per_page(scored, 3);
+
+        let page_two: Vec<f32> = capped
+            .iter()
+            .filter(|h| h.page.get() == 2)
+            .map(|h| h.sentence_rects[0].x)
             .collect();
+        // x doubles as the entry index, so the best three are 11, 10, 9
+        assert_eq!(page_two, vec![11.0, 10.0, 9.0]);
+        assert_eq!(capped.iter().filter(|h| h.page.get() == 5).count(), 1);
+    }
+
+    #[test]
+    fn boosted_heading_outranks_body_chunk() {
+        let mut heading = point(5.0, "4.1 Convolutional Layers", 0.62);
+        heading.payload.insert("kind".to_string(), "heading".into());
+        let body = point(5.0, "Each filter slides across the input and sums the products.", 0.7);
+
+        let top = |boost: f32| {
+            let points = vec![body.clone(), heading.clone()];
+            results_from_points(points, "convolutional layers", boost)[0].text.clone()
+        };
+
+        assert_eq!(top(1.0), "Each filter slides across the input and sums the products.");
+        assert_eq!(top(SearchConfig::default().heading_boost), "4.1 Convolutional Layers");
+    }
+
+    #[test]
+    fn caption_hits_are_labeled_as_captions() {
+        let mut caption = point(3.0, "Bar chart of validation loss per epoch", 0.8);
+        caption.payload.insert("kind".to_string(), ChunkKind::Caption.as_str().into());
+        let results = results_from_points(vec![caption], "validation loss chart", 1.0);
+        assert_eq!(results[0].kind.as_deref(), Some("caption"));
 
-        for (entry_start, entry_end, _score) in snapped_matches {
-
-            let pdf_char_indices: Vec<usize> = char_entries[entry_start..entry_end]
-                .iter()
-                .map(|(pdf_idx, _)| *pdf_idx)
-                .collect();
-
-            match extract_char_bboxes(&text_page, &pdf_char_indices) {
-                Ok(rects) if !rects.is_empty() => highlights.push(PageHighlight {
-                    page: search_result.page as usize,
-                    rects,
-                }),
-                Ok(_) => continue,
-                Err(e) => eprintln!(
-                    "Failed to extract bounding boxes for page {}: {:?}",
-                    search_result.page, e
-                ),
+        let highlight = page_highlight(&results[0], Vec::new(), Vec::new(), 612.0, 792.0);
+        let value = serde_json::to_value(&highlight).unwrap();
+        assert_eq!(value["kind"], "caption");
+        assert_eq!(value["page"], 3);
+        assert_eq!(value["sentence_rects"], serde_json::json!([]));
+    }
+
+    #[test]
+    fn sentence_rects_are_also_sent_as_rects() {
+        // Clients written before exact_rects existed read `rects`
+        let mut snapped = highlight(2, 40.0);
+        snapped.exact_rects[0].x = 55.0;
+        let value = serde_json::to_value(&snapped).unwrap();
+        assert_eq!(value["rects"], value["sentence_rects"]);
+        assert_eq!(value["rects"][0]["x"], 40.0);
+        assert_eq!(value["exact_rects"][0]["x"], 55.0);
+    }
+
+    #[test]
+    fn equal_scores_are_ordered_by_page_then_text() {
+        let points = vec![
+            point(9.0, "Dropout randomly zeroes activations.", 0.5),
+            point(4.0, "Weight decay shrinks parameters.", 0.8),
+            point(4.0, "Batch norm rescales activations.", 0.8),
+            point(2.0, "Early stopping watches validation loss.", 0.8),
+        ];
+
+        let order = |points: Vec<ScoredPoint>| -> Vec<(u16, String)> {
+            results_from_points(points, "regularization", 1.0)
+                .into_iter()
+                .map(|r| (r.page.get(), r.text))
+                .collect()
+        };
+
+        let expected = vec![
+            (2, "Early stopping watches validation loss.".to_string()),
+            (4, "Batch norm rescales activations.".to_string()),
+            (4, "Weight decay shrinks parameters.".to_string()),
+            (9, "Dropout randomly zeroes activations.".to_string()),
+        ];
+        assert_eq!(order(points.clone()), expected);
+
+        // Same points, different arrival order from Qdrant
+        let mut reversed = points;
+        reversed.reverse();
+        assert_eq!(order(reversed), expected);
+    }
+
+    #[test]
+    fn merge_covers_all_queries_and_dedups_shared_chunks() {
+        let first = vec![
+            result(1, "Neural networks learn representations.", "neural networks"),
+            result(3, "Backpropagation computes gradients.", "neural networks"),
+        ];
+        let second = vec![
+            result(3, "Backpropagation computes gradients.", "gradients"),
+            result(7, "The chain rule underpins gradient computation.", "gradients"),
+        ];
+
+        let merged = merge_search_results(vec![first, second]);
+
+        assert_eq!(merged.len(), 3);
+        assert!(merged.iter().any(|r| r.query == "neural networks"));
+        assert!(merged.iter().any(|r| r.query == "gradients"));
+        // The shared chunk is attributed to the query that found it first
+        let shared: Vec<_> = merged.iter().filter(|r| r.page.get() == 3).collect();
+        assert_eq!(shared.len(), 1);
+        assert_eq!(shared[0].query, "neural networks");
+    }
+
+    // One A4 page (595x842pt) with a single line of Helvetica text
+    fn a4_pdf(line: &str) -> Vec<u8> {
+        crate::pdf::tests::a4_pdf_with_text(&[(72, 700, line)])
+    }
+
+    fn page_spans(bytes: &[u8]) -> TextSpans<'_> {
+        TextSpans::new(bytes, HighlightConfig::default().line_tolerance)
+    }
+
+    #[test]
+    #[ignore = "needs a pdfium library; run with PDFIUM_PATH set"]
+    fn highlights_carry_page_media_box_size() {
+        let bytes = a4_pdf("Gradient descent follows the slope of the loss.");
+        let results = vec![result(1, "Gradient descent follows the slope of the loss.", "slope")];
+        let options = SearchOptions { origin: CoordinateOrigin::TopLeft, ..SearchOptions::default() };
+
+        let highlights =
+            compute_highlights(&bytes, &results, &HighlightConfig::default(), &options)
+                .unwrap()
+                .highlights;
+
+        assert!(!highlights.is_empty());
+        assert_eq!(highlights[0].page_width, 595.0);
+        assert_eq!(highlights[0].page_height, 842.0);
+        // The line's baseline sits 700pt up, so its top edge is well within the top fifth
+        assert!(highlights[0].sentence_rects.iter().all(|r| r.y > 100.0 && r.y < 142.0), "{:?}", highlights[0].sentence_rects);
+    }
+
+    /// A page whose font maps every code it shows to U+FFFD in its ToUnicode
+    /// CMap. pdf_oxide ignores those entries and decodes from the standard
+    /// encoding; pdfium reports the replacement character.
+    fn undecodable_pdf(line: &str) -> Vec<u8> {
+        let mut pdf = crate::test_pdf::TestPdf::new();
+        pdf.text_page(&[(72, 700, line)]);
+        let mut doc = pdf.finish(lopdf::Dictionary::new());
+
+        let mut codes: Vec<u8> = line.bytes().collect();
+        codes.sort_unstable();
+        codes.dedup();
+        let entries: String = codes.iter().map(|code| format!("<{:02X}> <FFFD>\n", code)).collect();
+        let cmap = format!(
+            "/CIDInit /ProcSet findresource begin\n12 dict begin\nbegincmap\n\
+             1 begincodespacerange\n<00> <FF>\nendcodespacerange\n\
+             {} beginbfchar\n{}endbfchar\nendcmap\nend\nend\n",
+            codes.len(),
+            entries
+        );
+        let cmap_id = doc.add_object(lopdf::Stream::new(lopdf::Dictionary::new(), cmap.into_bytes()));
+        for object in doc.objects.values_mut() {
+            if let Ok(font) = object.as_dict_mut()
+                && font.get(b"Type").and_then(|t| t.as_name()).is_ok_and(|t| t == b"Font")
+            {
+                font.set("ToUnicode", cmap_id);
             }
         }
+        crate::test_pdf::to_bytes(doc)
     }
 
-    Ok(highlights)
-}
\ No newline at end of file
+    #[test]
+    #[ignore = "needs a pdfium library; run with PDFIUM_PATH set"]
+    fn undecodable_pdfium_text_falls_back_to_pdf_oxide() {
+        let line = "Gradient descent follows the slope of the loss.";
+        let bytes = undecodable_pdf(line);
+        let results = vec![result(1, line, "slope")];
+
+        let highlights =
+            compute_highlights(&bytes, &results, &HighlightConfig::default(), &SearchOptions::default())
+                .unwrap()
+                .highlights;
+
+        assert_eq!(highlights.len(), 1);
+        assert_eq!(highlights[0].page.get(), 1);
+        assert!(!highlights[0].sentence_rects.is_empty());
+    }
+
+    #[test]
+    #[ignore = "needs a pdfium library; run with PDFIUM_PATH set"]
+    fn pdfium_draws_a_chunk_across_the_break_once() {
+        let first = "Momentum keeps each update moving in";
+        let second = "a consistent direction across steps.";
+        let bytes = crate::pdf::tests::a4_pdf_pages(&[
+            &[(72, 700, "Optimizers differ in how they step."), (72, 60, first)],
+            &[(72, 780, second), (72, 700, "Unrelated closing remarks about regularization.")],
+        ]);
+        let text = format!("{} {}", first, second);
+        // The same chunk hit twice, e.g. by two of a request's queries
+        let results = vec![result(1, &text, "momentum"), result(1, &text, "direction")];
+        let config = HighlightConfig { merge_gap: None, ..HighlightConfig::default() };
+        let options = SearchOptions { origin: CoordinateOrigin::TopLeft, ..SearchOptions::default() };
+
+        let highlights = compute_highlights(&bytes, &results, &config, &options).unwrap().highlights;
+        let pages: Vec<u16> = highlights.iter().map(|h| h.page.get()).collect();
+        assert_eq!(pages, [1, 2]);
+        // The foot of page 1 and the top of page 2
+        assert!(highlights[0].exact_rects.iter().all(|r| r.y > 700.0), "{:?}", highlights[0].exact_rects);
+        assert!(highlights[1].exact_rects.iter().all(|r| r.y < 142.0), "{:?}", highlights[1].exact_rects);
+    }
+}
@@ -0,0 +1,164 @@
+// src/handlers/selection.rs
+
+use anyhow::Result;
+use std::ops::Range;
+use axum::{extract::State, Json};
+
+use crate::errors::{AppError, ErrorCode};
+use crate::handlers::search::{highlights_for_queries, SearchOptions};
+use crate::pdf::{extract_char_bboxes, get_pdfium, join_break_artifacts, LineTolerance};
+use crate::types::{AppState, CharBbox, SelectionRequest, SelectionResponse};
+
+/// `POST /api/search-selection` — find passages similar to a text selection.
+///
+/// `start`/`end` are char offsets into pdfium's text for `page`, as the
+/// frontend sees it. The selected text is used as the query, and its rects are
+/// returned so the UI can keep the selection drawn.
+pub async fn search_selection(
+    State(state): State<AppState>,
+    Json(req): Json<SelectionRequest>,
+) -> Result<Json<SelectionResponse>, AppError> {
+    // Deserializing doesn't check this, and page 0 would quietly read page 1
+    if req.page.get() == 0 {
+        return Err(AppError::bad_request("Pages are numbered from 1"));
+    }
+    let bytes = state
+        .bytes_map
+        .read()
+        .await
+        .get(&req.id)
+        .cloned()
+        .ok_or_else(|| AppError::not_found(format!("No bytes found for id: {}", req.id)))?;
+
+    let selection = read_selection(&bytes, &req, state.config.highlight.line_tolerance)?;
+    let options = SearchOptions { origin: req.origin, ..SearchOptions::default() };
+    let found = highlights_for_queries(&state, &req.id, &[selection.text.as_str()], &options).await?;
+
+    Ok(Json(SelectionResponse {
+        page: req.page,
+        text: selection.text,
+        rects: selection.rects,
+        page_width: selection.page_width,
+        page_height: selection.page_height,
+        highlights: found.highlights,
+        warnings: found.warnings,
+    }))
+}
+
+struct Selection {
+    text: String,
+    rects: Vec<CharBbox>,
+    page_width: f32,
+    page_height: f32,
+}
+
+// pdfium's document isn't held across an await, so this stays synchronous
+fn read_selection(
+    bytes: &[u8],
+    req: &SelectionRequest,
+    tolerance: LineTolerance,
+) -> Result<Selection, AppError> {
+    let pdfium = get_pdfium().map_err(|e| AppError::new(ErrorCode::Unavailable, e))?;
+    let doc = pdfium
+        .load_pdf_from_byte_slice(bytes, None)
+        .map_err(|e| anyhow::anyhow!("PDFium load failed: {:?}", e))?;
+    let page = doc
+        .pages()
+        .get(req.page.index())
+        .map_err(|_| AppError::bad_request(format!("No page {} in document", req.page)))?;
+    let text_page = page
+        .text()
+        .map_err(|e| anyhow::anyhow!("Failed to get text for page {}: {:?}", req.page, e))?;
+
+    let chars = text_page.chars();
+    let range = selection_range(req.start, req.end, chars.len())?;
+    let page_chars = chars
+        .iter()
+        .enumerate()
+        .filter_map(|(i, c)| c.unicode_char().map(|ch| (i, ch)));
+    let text = selection_text(page_chars, range.clone())?;
+
+    let indices: Vec<usize> = range.collect();
+    let mut rects = extract_char_bboxes(&text_page, &indices, tolerance)?;
+    let page_height = page.height().value;
+    req.origin.apply(&mut rects, page_height);
+    Ok(Selection { text, rects, page_width: page.width().value, page_height })
+}
+
+/// `start..end` cut to the page's `char_count` chars, or a 400 when nothing
+/// of it is on the page.
+fn selection_range(start: usize, end: usize, char_count: usize) -> Result<Range<usize>, AppError> {
+    if start >= end {
+        return Err(AppError::bad_request("Selection is empty: start must be before end"));
+    }
+    if start >= char_count {
+        return Err(AppError::bad_request(format!(
+            "Selection starts at char {}, past the page's {} chars",
+            start, char_count
+        )));
+    }
+    Ok(start..end.min(char_count))
+}
+
+/// The text at char offsets `range`, with line-break joins smoothed over so
+/// it reads like a stored chunk.
+fn selection_text<I>(page_chars: I, range: Range<usize>) -> Result<String, AppError>
+where
+    I: IntoIterator<Item = (usize, char)>,
+{
+    let selected = page_chars.into_iter().filter(|(i, _)| range.contains(i));
+    let text: String = join_break_artifacts(selected).into_iter().map(|(_, c)| c).collect();
+    if text.trim().is_empty() {
+        return Err(AppError::bad_request("Selection contains no text"));
+    }
+    Ok(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page_chars(text: &str) -> Vec<(usize, char)> {
+        text.chars().enumerate().collect()
+    }
+
+    #[test]
+    fn selection_becomes_query_text() {
+        let page = "Results.\r\nBatch normalization stabilizes\r\ntraining of deep networks. Next";
+        let start = page.find("Batch").unwrap();
+        let end = page.find(" Next").unwrap();
+
+        let text = selection_text(page_chars(page), start..end).ok();
+        assert_eq!(text.as_deref(), Some("Batch normalization stabilizes training of deep networks."));
+    }
+
+    #[test]
+    fn empty_or_blank_selection_is_rejected() {
+        assert!(selection_range(5, 5, 15).is_err());
+        assert!(selection_range(9, 4, 15).is_err());
+        assert!(selection_text(page_chars("Deep   networks"), 4..7).is_err());
+    }
+
+    #[test]
+    fn selection_is_cut_to_the_page() {
+        assert_eq!(selection_range(4, 9000, 15).ok(), Some(4..15));
+        assert_eq!(selection_range(0, 15, 15).ok(), Some(0..15));
+        // Nothing of it on the page
+        let status = selection_range(15, 9000, 15).err().map(|e| axum::response::IntoResponse::into_response(e).status());
+        assert_eq!(status, Some(axum::http::StatusCode::BAD_REQUEST));
+    }
+
+    #[tokio::test]
+    async fn page_zero_is_rejected() {
+        let state = AppState::for_tests();
+        state.bytes_map.write().await.insert("doc".to_string(), vec![]);
+        let req: SelectionRequest =
+            serde_json::from_value(serde_json::json!({ "id": "doc", "page": 0, "start": 0, "end": 5 })).unwrap();
+
+        let status = search_selection(State(state), Json(req))
+            .await
+            .err()
+            .map(|e| axum::response::IntoResponse::into_response(e).status());
+        assert_eq!(status, Some(axum::http::StatusCode::BAD_REQUEST));
+    }
+}
@@ -0,0 +1,126 @@
+// src/handlers/suggest.rs
+
+use axum::{
+    extract::{Query, State},
+    Json,
+};
+use std::collections::HashMap;
+use vb::{embed, Chunk};
+
+use crate::errors::AppError;
+use crate::types::{AppState, IdTermsMap, SuggestQuery, SuggestResponse, Suggestion};
+
+const DEFAULT_SUGGESTIONS: usize = 10;
+// Shorter words are rarely worth completing to
+const MIN_TERM_CHARS: usize = 3;
+
+/// `GET /api/suggest?id=&prefix=` — the document's most frequent words that
+/// start with `prefix` (case-insensitive), for completing a query as it's typed.
+pub async fn suggest(
+    State(state): State<AppState>,
+    Query(params): Query<SuggestQuery>,
+) -> Result<Json<SuggestResponse>, AppError> {
+    let prefix = params.prefix.trim().to_lowercase();
+    if prefix.is_empty() {
+        return Err(AppError::bad_request("Missing prefix"));
+    }
+    if !state.id_map.read().await.contains_key(&params.id) {
+        return Err(AppError::not_found(format!("No document found for id: {}", params.id)));
+    }
+
+    // Empty until the first page is indexed
+    let suggestions = match state.terms_map.read().await.get(&params.id) {
+        Some(terms) => top_terms(terms, &prefix, params.limit.unwrap_or(DEFAULT_SUGGESTIONS)),
+        None => Vec::new(),
+    };
+    Ok(Json(SuggestResponse { id: params.id, suggestions }))
+}
+
+/// Word counts for a page's chunks, lowercased, without stop words or words
+/// under [`MIN_TERM_CHARS`].
+pub(crate) fn count_terms(chunks: &[Chunk]) -> HashMap<String, u32> {
+    let mut terms = HashMap::new();
+    for chunk in chunks {
+        for word in chunk.content.split(|c: char| !c.is_alphanumeric()) {
+            let word = word.to_lowercase();
+            if word.chars().count() < MIN_TERM_CHARS
+                || !word.chars().any(char::is_alphabetic)
+                || embed::DEFAULT_STOP_WORDS.contains(&word.as_str())
+            {
+                continue;
+            }
+            *terms.entry(word).or_insert(0) += 1;
+        }
+    }
+    terms
+}
+
+/// Fold a committed page's counts into the document's totals.
+pub(crate) async fn add_terms(terms: &IdTermsMap, id: &str, page_terms: HashMap<String, u32>) {
+    let mut terms = terms.write().await;
+    let totals = terms.entry(id.to_string()).or_default();
+    for (term, count) in page_terms {
+        *totals.entry(term).or_insert(0) += count;
+    }
+}
+
+// Most frequent first; ties in alphabetical order so results are stable
+fn top_terms(terms: &HashMap<String, u32>, prefix: &str, limit: usize) -> Vec<Suggestion> {
+    let mut matches: Vec<(&String, u32)> = terms
+        .iter()
+        .filter(|(term, _)| term.starts_with(prefix))
+        .map(|(term, &count)| (term, count))
+        .collect();
+    matches.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+    matches
+        .into_iter()
+        .take(limit)
+        .map(|(term, count)| Suggestion { term: term.clone(), count })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use vb::{ChunkKind, PageNumber};
+
+    fn body(page: u16, content: &str) -> Chunk {
+        Chunk { content: content.to_string(), page: PageNumber::new(page), kind: ChunkKind::Body, parent: None }
+    }
+
+    #[tokio::test]
+    async fn prefix_returns_indexed_terms_by_frequency() {
+        let state = AppState::for_tests();
+        state.id_map.write().await.insert("doc".to_string(), "notes.pdf_1".to_string());
+        // What process_file records as each page is committed
+        let pages = [
+            vec![body(1, "Gradient descent follows the gradient."), body(1, "Gradients vanish in deep nets.")],
+            vec![body(2, "Granular gradient checks; grad-CAM for saliency.")],
+        ];
+        for chunks in &pages {
+            add_terms(&state.terms_map, "doc", count_terms(chunks)).await;
+        }
+
+        let query = |prefix: &str, limit: Option<usize>| SuggestQuery { id: "doc".to_string(), prefix: prefix.to_string(), limit };
+        let found = suggest(State(state.clone()), Query(query(" GRA", None))).await.ok().unwrap().0;
+        let terms: Vec<(&str, u32)> = found.suggestions.iter().map(|s| (s.term.as_str(), s.count)).collect();
+        assert_eq!(terms, [("gradient", 3), ("grad", 1), ("gradients", 1), ("granular", 1)]);
+
+        let top = suggest(State(state.clone()), Query(query("gra", Some(1)))).await.ok().unwrap().0;
+        assert_eq!(top.suggestions, [Suggestion { term: "gradient".to_string(), count: 3 }]);
+
+        // Stop words and short words are never suggested
+        assert!(suggest(State(state.clone()), Query(query("th", None))).await.ok().unwrap().0.suggestions.is_empty());
+        assert!(suggest(State(state.clone()), Query(query("in", None))).await.ok().unwrap().0.suggestions.is_empty());
+    }
+
+    #[tokio::test]
+    async fn unknown_document_is_not_found() {
+        let query = SuggestQuery { id: "missing".to_string(), prefix: "gra".to_string(), limit: None };
+        let status = suggest(State(AppState::for_tests()), Query(query))
+            .await
+            .err()
+            .map(|e| axum::response::IntoResponse::into_response(e).status());
+        assert_eq!(status, Some(axum::http::StatusCode::NOT_FOUND));
+    }
+}
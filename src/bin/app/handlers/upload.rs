@@ -1,87 +1,667 @@
 // src/handlers/upload.rs
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use axum::{
     body::Bytes,
-    extract::{Multipart, State},
-    http::StatusCode,
+    extract::{multipart::MultipartError, Multipart, Query, State},
+    http::{HeaderMap, StatusCode},
     response::IntoResponse,
     Json,
 };
-use std::sync::Arc;
-use std::time::Instant;
-use qdrant_client::Qdrant;
+use std::collections::HashMap;
+use std::future::Future;
+use std::time::{Duration, Instant};
 use uuid::Uuid;
-use vb::{chunk, embed, qdrant};
+use vb::{chunk, embed, layers, page, qdrant};
+use vb::chunk::ChunkConfig;
+use vb::extract::PageCountMismatch;
+use vb::{Chunk, Embeddings, PageNumber};
 
-use crate::errors::AppError;
-use crate::types::{AppState, UploadResponse};
+use crate::errors::{AppError, ErrorCode};
+use crate::handlers::embed::with_embed_permit;
+use crate::handlers::suggest::{add_terms, count_terms};
+use crate::metrics::metrics;
+use crate::pdf;
+use crate::types::{AppState, UploadQuery, UploadResponse};
 
+// How long a retried upload with the same Idempotency-Key gets the original id back
+const IDEMPOTENCY_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// `POST /upload` — a `pdf` field starts a background indexing job.
+///
+/// `?lang=de` indexes with that language's embedding model; searches of the
+/// document then embed queries with the same model. `?ttl=3600` deletes the
+/// document an hour after upload, and `?chunk_size=` overrides the chunk size.
+/// Each can also be sent as a form field, before or after `pdf`, and then
+/// takes precedence over the query string.
+///
+/// With an `Idempotency-Key` header, repeating the request while the first job
+/// is processing or done returns the first id instead of indexing again.
 pub async fn handle_upload(
     State(state): State<AppState>,
-    mut multipart: Multipart,
+    Query(params): Query<UploadQuery>,
+    headers: HeaderMap,
+    multipart: Multipart,
 ) -> Result<impl IntoResponse, AppError> {
-    while let Some(field) = multipart.next_field().await? {
-        if field.name() != Some("pdf") {
+    let idempotency_key = headers
+        .get("idempotency-key")
+        .and_then(|v| v.to_str().ok())
+        .map(str::trim)
+        .filter(|k| !k.is_empty())
+        .map(str::to_string);
+
+    let (pdf, options) = read_upload_form(multipart, params).await?;
+    if let Some(lang) = &options.lang {
+        embed::model_dir(&state.config.embed, Some(lang)).map_err(|e| AppError::bad_request(e.to_string()))?;
+    }
+    let (filename, data) = pdf.ok_or_else(|| AppError::bad_request("No PDF field found in multipart body"))?;
+
+    let id = Uuid::new_v4().to_string();
+    if let Some(key) = &idempotency_key {
+        // Held across the check and the insert so two retries can't both start a job
+        let mut keys = state.idempotency_map.write().await;
+        if let Some(existing) = live_key(&mut keys, key, Instant::now()) {
+            let failed = state.id_map.read().await.get(&existing).map(String::as_str) == Some("failed");
+            if !failed {
+                return Ok((StatusCode::OK, Json(UploadResponse { id: existing })));
+            }
+        }
+        keys.insert(key.clone(), (id.clone(), Instant::now()));
+    }
+
+    start_indexing(&state, id.clone(), filename, data.to_vec(), &options).await;
+    Ok((StatusCode::OK, Json(UploadResponse { id })))
+}
+
+// Read every field, whatever the order: the `pdf` file and any option fields,
+// which override `params`. Unknown fields are skipped.
+async fn read_upload_form(
+    mut multipart: Multipart,
+    mut params: UploadQuery,
+) -> Result<(Option<(String, Bytes)>, UploadQuery), AppError> {
+    let mut pdf = None;
+    // A malformed body is the client's fault, so none of these are 500s
+    while let Some(field) = multipart.next_field().await.map_err(multipart_error)? {
+        let name = field.name().unwrap_or_default().to_string();
+        // Only the first file is indexed
+        if name == "pdf" && pdf.is_none() {
+            let filename = field
+                .file_name()
+                .ok_or_else(|| AppError::bad_request("Missing filename"))?
+                .to_string();
+            pdf = Some((filename, field.bytes().await.map_err(multipart_error)?));
+            continue;
+        }
+        if !matches!(name.as_str(), "lang" | "ttl" | "chunk_size") {
             continue;
         }
+        let value = field.text().await.map_err(multipart_error)?.trim().to_string();
+        let invalid = || AppError::bad_request(format!("Invalid {} field: {:?}", name, value));
+        match name.as_str() {
+            "lang" => params.lang = Some(value.clone()),
+            "ttl" => params.ttl = Some(value.parse().map_err(|_| invalid())?),
+            _ => params.chunk_size = Some(value.parse().map_err(|_| invalid())?),
+        }
+    }
+    check_chunk_size(params.chunk_size)?;
+    Ok((pdf, params))
+}
 
-        let filename = field.file_name().ok_or_else(|| anyhow::anyhow!("Missing filename"))?.to_string();
-        let data = field.bytes().await?;
+/// A zero `chunk_size`, from the query string or a form field, would split
+/// the text into one chunk per grapheme.
+pub(crate) fn check_chunk_size(chunk_size: Option<usize>) -> Result<(), AppError> {
+    match chunk_size {
+        Some(0) => Err(AppError::bad_request("Invalid chunk_size: must be at least 1")),
+        _ => Ok(()),
+    }
+}
 
-        println!("Received file: {} ({} bytes)", filename, data.len());
+/// Record a received file under `id` and index it in the background.
+pub(crate) async fn start_indexing(
+    state: &AppState,
+    id: String,
+    filename: String,
+    data: Vec<u8>,
+    options: &UploadQuery,
+) {
+    println!("Received file: {} ({} bytes)", filename, data.len());
+    metrics().uploads.inc();
 
-        let id = Uuid::new_v4().to_string();
+    {
+        let mut map = state.id_map.write().await;
+        map.insert(id.clone(), "processing".to_string());
+    }
+    {
+        let mut map = state.name_map.write().await;
+        map.insert(id.clone(), filename.clone());
+    }
+    {
+        let mut map = state.bytes_map.write().await;
+        map.insert(id.clone(), data.clone());
+    }
+    {
+        let mut map = state.uploaded_map.write().await;
+        map.insert(id.clone(), qdrant::unix_now());
+    }
+    if let Some(ttl) = options.ttl {
+        let expires = qdrant::unix_now().saturating_add(i64::try_from(ttl).unwrap_or(i64::MAX));
+        state.expiry_map.write().await.insert(id.clone(), expires);
+    }
+    if let Some(lang) = &options.lang {
+        state.lang_map.write().await.insert(id.clone(), lang.to_ascii_lowercase());
+    }
 
-        {
+    let unique_filename = qdrant::unique_filename(&filename);
+    let mut config = state.config.chunking.clone();
+    if let Some(chunk_size) = options.chunk_size {
+        config.max_chars = chunk_size;
+    }
+    let job = tokio::spawn(index_upload(state.clone(), id.clone(), unique_filename, data, config));
+    let mut jobs = state.index_jobs.write().await;
+    jobs.retain(|_, job| !job.is_finished());
+    jobs.insert(id, job);
+}
+
+/// Wait up to `grace` for background indexing to finish. Jobs still running
+/// after that are aborted and marked failed; their ids are returned.
+pub(crate) async fn drain_indexing(state: &AppState, grace: Duration) -> Vec<String> {
+    let jobs: Vec<_> = state.index_jobs.write().await.drain().collect();
+    let deadline = tokio::time::Instant::now() + grace;
+    let mut abandoned = Vec::new();
+    for (id, mut job) in jobs {
+        if tokio::time::timeout_at(deadline, &mut job).await.is_err() {
+            job.abort();
+            state.id_map.write().await.insert(id.clone(), "failed".to_string());
+            abandoned.push(id);
+        }
+    }
+    abandoned
+}
+
+// Drop expired keys, then return the id still held by `key`, if any
+fn live_key(keys: &mut HashMap<String, (String, Instant)>, key: &str, now: Instant) -> Option<String> {
+    keys.retain(|_, (_, seen)| now.duration_since(*seen) < IDEMPOTENCY_TTL);
+    keys.get(key).map(|(id, _)| id.clone())
+}
+
+pub(crate) fn multipart_error(e: MultipartError) -> AppError {
+    // Running past the body limit surfaces here too, and isn't a malformed body
+    if e.status() == StatusCode::PAYLOAD_TOO_LARGE {
+        return AppError::new(ErrorCode::PayloadTooLarge, anyhow::anyhow!(e.body_text()));
+    }
+    AppError::bad_request(format!("Malformed multipart body: {}", e.body_text()))
+}
+
+/// What `process_file` did with a document that loaded successfully.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ProcessOutcome {
+    Indexed,
+    /// No page had any text, so nothing was sent to Qdrant.
+    Empty,
+}
+
+// Background half of an upload: index the file and record the result in `state`
+async fn index_upload(state: AppState, id: String, unique_filename: String, data: Vec<u8>, config: ChunkConfig) {
+    let start = Instant::now();
+    let result = process_file(
+        &state,
+        &id,
+        &unique_filename,
+        data.into(),
+        &config,
+        None,
+    )
+    .await;
+    match result {
+        Ok(ProcessOutcome::Indexed) => {
+            println!("Processing done: {:?}", start.elapsed());
+            metrics().processing_seconds.observe(start.elapsed().as_secs_f64());
             let mut map = state.id_map.write().await;
-            map.insert(id.clone(), "processing".to_string());
+            let mut set = state.ready_set.write().await;
+            set.insert(id.clone());
+            map.insert(id, unique_filename);
         }
-        {
-            let mut map = state.bytes_map.write().await;
-            map.insert(id.clone(), data.to_vec());
+        Ok(ProcessOutcome::Empty) => {
+            println!("No text found in {}, nothing to index", id);
+            state.id_map.write().await.insert(id, "empty".to_string());
         }
-
-        let data_clone = data.to_vec();
-        let filename_clone = filename.clone();
-        let id_clone = id.clone();
-        let id_map_clone = state.id_map.clone();
-        let ready_set_clone = state.ready_set.clone();
-        let qdrant = state.qdrant.clone();
-
-        tokio::spawn(async move {
-            let start = Instant::now();
-            match process_file(&filename_clone, data_clone.into(), qdrant).await {
-                Ok(unique_filename) => {
-                    println!("Processing done: {:?}", start.elapsed());
-                    let mut map = id_map_clone.write().await;
-                    let mut set = ready_set_clone.write().await;
-                    set.insert(id_clone.clone());
-                    map.insert(id_clone, unique_filename);
-                }
-                Err(e) => {
-                    eprintln!("Processing failed: {:?}", e);
-                    let mut map = id_map_clone.write().await;
-                    let mut set = ready_set_clone.write().await;
-                    map.insert(id_clone, "failed".to_string());
-                    set.insert("failed".to_string());
-                }
+        Err(e) => {
+            eprintln!("Processing failed: {:?}", e);
+            metrics().errors.with_label_values(&["upload"]).inc();
+            let indexed_through = state.progress_map.read().await.get(&id).copied();
+            let mut map = state.id_map.write().await;
+            let mut set = state.ready_set.write().await;
+            if indexed_through.is_some() {
+                // Earlier pages are already in Qdrant, keep them searchable
+                map.insert(id.clone(), unique_filename);
+                set.insert(id);
+            } else {
+                map.insert(id, "failed".to_string());
+                set.insert("failed".to_string());
             }
-        });
+        }
+    }
+}
 
-        return Ok((StatusCode::OK, Json(UploadResponse { id })));
+/// Index a PDF under `unique_filename` one page at a time.
+///
+/// Each page's vectors are committed before the next page starts, so a failure
+/// part-way through leaves earlier pages searchable. The last committed page is
+/// recorded in `state.progress_map`; pass it back as `resume_after` to continue
+/// from there. Chunks are stamped with the id's time in `state.uploaded_map`.
+/// Documents with no extractable text are reported as `ProcessOutcome::Empty`
+/// before anything is embedded, and ones over `config.upload.max_pages` fail.
+pub async fn process_file(
+    state: &AppState,
+    id: &str,
+    unique_filename: &str,
+    pdf_data: Bytes,
+    config: &ChunkConfig,
+    resume_after: Option<u32>,
+) -> Result<ProcessOutcome> {
+    let mut doc = chunk::load_document(chunk::PdfSource::Bytes(pdf_data.to_vec()))?;
+    let page_count = chunk::page_numbers(&doc).len();
+    let max_pages = state.config.upload.max_pages;
+    if page_count > max_pages {
+        anyhow::bail!("Document has {} pages, over the limit of {}", page_count, max_pages);
+    }
+    check_pdfium_pages(pdf::page_count(&pdf_data), page_count)?;
+    if let Some(labels) = page::page_labels(&doc) {
+        state.page_label_map.write().await.insert(id.to_string(), labels);
+    }
+    layers::strip_excluded_layers(&mut doc, &config.layers)?;
+    if resume_after.is_none() && !chunk::has_text(&doc) {
+        return Ok(ProcessOutcome::Empty);
     }
 
-    Err(AppError::from(anyhow::anyhow!("No PDF field found in multipart body")))
-}
+    let mut extras = pdf::extract_page_extras(&pdf_data, state.config.upload.tables).unwrap_or_else(|e| {
+        eprintln!("PDFium extraction failed, indexing body text only: {:?}", e);
+        HashMap::new()
+    });
+    // Figure alt text lives in the structure tree, not the page text
+    for caption in chunk::caption_chunks(&doc) {
+        extras.entry(caption.page).or_default().push(caption);
+    }
+    let pages = pages_to_index(chunk::page_numbers(&doc), resume_after);
 
-async fn process_file(filename: &str, pdf_data: Bytes, client: Arc<Qdrant>) -> Result<String> {
-    let chunks = chunk::extract_and_chunk(chunk::PdfSource::Bytes(pdf_data.to_vec()))?;
-    let embedded_chunks = embed::get_embeddings(chunks)?;
-    let unique_filename =
-        qdrant::store_embeddings(&client, "embedded_pdfs", filename, embedded_chunks).await?;
+    let uploaded_at = state.uploaded_map.read().await.get(id).copied().unwrap_or_else(qdrant::unix_now);
+    let lang = state.lang_map.read().await.get(id).cloned();
+    let lang = lang.as_deref();
+    // Chunks are sized in the tokens of the model that embeds them
+    let config = &ChunkConfig { lang: lang.map(str::to_string), ..config.clone() };
+    let doc = &doc;
+    let extras = &extras;
+    let client = &state.qdrant;
+    // A language's first document creates its model's collection
+    let collection = &state.config.collection(lang)?;
+    qdrant::ensure_collection(client, collection, state.config.search.metric, state.config.search.precision).await?;
+    let progress = &state.progress_map;
+    let terms = &state.terms_map;
+    let precision = state.config.search.precision;
+    index_pages_in_order(&pages, |page_num| async move {
+        let mut chunks = chunk::chunk_page(doc, page_num, config)?;
+        // Headings, tables, form values, annotations and captions are embedded as their own chunks
+        if let Some(page_extras) = extras.get(&PageNumber::new(page_num as u16)) {
+            chunks.extend(page_extras.iter().cloned());
+        }
+        if !chunks.is_empty() {
+            let page_terms = count_terms(&chunks);
+            let embedded_chunks = embed_chunks(state, chunks, lang.map(str::to_string)).await?;
+            let _timer = metrics().qdrant_seconds.with_label_values(&["upsert"]).start_timer();
+            qdrant::store_embeddings_as(
+                client,
+                collection,
+                unique_filename,
+                uploaded_at,
+                embedded_chunks,
+                precision,
+            )
+            .await?;
+            add_terms(terms, id, page_terms).await;
+        }
+        progress.write().await.insert(id.to_string(), page_num);
+        Ok(())
+    })
+    .await?;
 
     println!("File processed successfully!");
 
-    Ok(unique_filename)
+    Ok(ProcessOutcome::Indexed)
+}
+
+// Highlights find a result's page in pdfium by the number lopdf gives it
+// here, so a document the two count differently would highlight the wrong
+// pages. Without pdfium there are no highlights to get wrong.
+fn check_pdfium_pages(pdfium: Result<usize>, page_tree: usize) -> Result<()> {
+    match pdfium {
+        Ok(pages) => PageCountMismatch::check("pdfium", pages, page_tree).map(drop),
+        Err(e) => {
+            eprintln!("PDFium couldn't count pages, indexing anyway: {:?}", e);
+            Ok(())
+        }
+    }
+}
+
+// Embedding is CPU-bound, so batches run on the blocking pool, at most
+// `config.resources.embed_jobs` at a time
+async fn embed_chunks(state: &AppState, chunks: Vec<Chunk>, lang: Option<String>) -> Result<Embeddings> {
+    with_embed_permit(state, move || {
+        let _timer = metrics().embedding_seconds.start_timer();
+        embed::get_embeddings_in(chunks, lang.as_deref())
+    })
+    .await
+}
+
+// The pages still to index: all of them, or those after the last committed one
+fn pages_to_index(pages: Vec<u32>, resume_after: Option<u32>) -> Vec<u32> {
+    pages.into_iter().filter(|&page| resume_after.is_none_or(|last| page > last)).collect()
+}
+
+// Stops at the first page that fails; everything before it has been committed
+async fn index_pages_in_order<F, Fut>(pages: &[u32], mut index_page: F) -> Result<()>
+where
+    F: FnMut(u32) -> Fut,
+    Fut: Future<Output = Result<()>>,
+{
+    for &page_num in pages {
+        index_page(page_num)
+            .await
+            .with_context(|| format!("Failed to index page {}", page_num))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::config::AppConfig;
+    use std::sync::{Arc, Mutex};
+
+    use crate::pdf::tests::a4_pdf_pages;
+
+    use crate::handlers::ready::is_ready;
+
+    #[test]
+    fn pages_pdfium_counts_differently_fail_the_upload() {
+        let err = check_pdfium_pages(Ok(5), 3).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<PageCountMismatch>(),
+            Some(&PageCountMismatch { reader: "pdfium", reported: 5, page_tree: 3 })
+        );
+        assert!(check_pdfium_pages(Ok(3), 3).is_ok());
+        // No pdfium, no highlights to misplace
+        assert!(check_pdfium_pages(Err(anyhow::anyhow!("PDFium unavailable")), 3).is_ok());
+    }
+
+    #[test]
+    #[ignore = "needs a pdfium library; run with PDFIUM_PATH set"]
+    fn pdfium_counts_the_pages_lopdf_indexes() {
+        let bytes = a4_pdf_pages(&[&[(72, 760, "Adam")], &[(72, 760, "momentum")]]);
+        let doc = chunk::load_document(chunk::PdfSource::Bytes(bytes.clone())).unwrap();
+        let page_tree = chunk::page_numbers(&doc).len();
+        assert_eq!(page_tree, 2);
+        assert!(check_pdfium_pages(pdf::page_count(&bytes), page_tree).is_ok());
+    }
+
+    #[tokio::test]
+    async fn shutdown_waits_for_indexing_in_progress() {
+        let state = AppState::for_tests();
+        let slow = {
+            let state = state.clone();
+            tokio::spawn(async move {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                state.id_map.write().await.insert("slow".to_string(), "ready".to_string());
+            })
+        };
+        let stuck = tokio::spawn(std::future::pending::<()>());
+        state.id_map.write().await.insert("stuck".to_string(), "processing".to_string());
+        state.index_jobs.write().await.extend([("slow".to_string(), slow), ("stuck".to_string(), stuck)]);
+
+        let abandoned = drain_indexing(&state, Duration::from_millis(200)).await;
+
+        assert_eq!(abandoned, vec!["stuck".to_string()]);
+        let statuses = state.id_map.read().await;
+        assert_eq!(statuses.get("slow").map(String::as_str), Some("ready"));
+        assert_eq!(statuses.get("stuck").map(String::as_str), Some("failed"));
+        assert!(state.index_jobs.read().await.is_empty());
+    }
+
+    // One blank A4 page with an empty content stream
+    pub(crate) fn blank_pdf() -> Vec<u8> {
+        blank_pages_pdf(1)
+    }
+
+    fn blank_pages_pdf(pages: usize) -> Vec<u8> {
+        a4_pdf_pages(&vec![&[][..]; pages])
+    }
+
+    async fn upload(state: &AppState, body: Vec<u8>, key: Option<&str>) -> axum::response::Response {
+        use axum::extract::FromRequest;
+
+        let mut req = axum::http::Request::post("/upload")
+            .header("content-type", "multipart/form-data; boundary=XBOUNDARY");
+        if let Some(key) = key {
+            req = req.header("idempotency-key", key);
+        }
+        let req = req.body(axum::body::Body::from(body)).unwrap();
+        let headers = req.headers().clone();
+        let multipart = Multipart::from_request(req, state).await.unwrap();
+        match handle_upload(State(state.clone()), Query(UploadQuery::default()), headers, multipart).await {
+            Ok(resp) => resp.into_response(),
+            Err(e) => e.into_response(),
+        }
+    }
+
+    async fn post_multipart(state: &AppState, body: &'static str) -> StatusCode {
+        upload(state, body.as_bytes().to_vec(), None).await.status()
+    }
+
+    fn pdf_body(pdf: Vec<u8>) -> Vec<u8> {
+        let mut body = b"--XBOUNDARY\r\n\
+            Content-Disposition: form-data; name=\"pdf\"; filename=\"blank.pdf\"\r\n\
+            Content-Type: application/pdf\r\n\r\n"
+            .to_vec();
+        body.extend(pdf);
+        body.extend(b"\r\n--XBOUNDARY--\r\n");
+        body
+    }
+
+    async fn upload_id(resp: axum::response::Response) -> String {
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+        let value: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        value["id"].as_str().unwrap().to_string()
+    }
+
+    #[tokio::test]
+    async fn retried_upload_with_same_key_reuses_id() {
+        let state = AppState::for_tests();
+
+        let first = upload_id(upload(&state, pdf_body(blank_pdf()), Some("retry-1")).await).await;
+        let second = upload_id(upload(&state, pdf_body(blank_pdf()), Some("retry-1")).await).await;
+        assert_eq!(first, second);
+        assert_eq!(state.id_map.read().await.len(), 1);
+
+        // A different key, or none, is a new job
+        let other = upload_id(upload(&state, pdf_body(blank_pdf()), Some("retry-2")).await).await;
+        assert_ne!(other, first);
+        assert_eq!(state.id_map.read().await.len(), 2);
+    }
+
+    #[test]
+    fn idempotency_keys_expire() {
+        let start = Instant::now();
+        let mut keys = HashMap::new();
+        keys.insert("k".to_string(), ("id-1".to_string(), start));
+
+        assert_eq!(live_key(&mut keys, "k", start + Duration::from_secs(60)).as_deref(), Some("id-1"));
+        assert_eq!(live_key(&mut keys, "k", start + IDEMPOTENCY_TTL), None);
+        assert!(keys.is_empty());
+    }
+
+    // A text field of a multipart body
+    fn form_field(name: &str, value: &str) -> Vec<u8> {
+        format!("--XBOUNDARY\r\nContent-Disposition: form-data; name=\"{}\"\r\n\r\n{}\r\n", name, value).into_bytes()
+    }
+
+    async fn read_form(state: &AppState, body: Vec<u8>, params: UploadQuery) -> Result<UploadQuery, AppError> {
+        use axum::extract::FromRequest;
+
+        let req = axum::http::Request::post("/upload")
+            .header("content-type", "multipart/form-data; boundary=XBOUNDARY")
+            .body(axum::body::Body::from(body))
+            .unwrap();
+        let multipart = Multipart::from_request(req, state).await.unwrap();
+        read_upload_form(multipart, params).await.map(|(_, options)| options)
+    }
+
+    #[tokio::test]
+    async fn option_fields_work_before_or_after_the_pdf() {
+        let state = AppState::for_tests();
+        let options = [form_field("ttl", "3600"), form_field("chunk_size", "300"), form_field("note", "ignored")].concat();
+
+        let before = [options.clone(), pdf_body(blank_pdf())].concat();
+        let first = upload_id(upload(&state, before.clone(), None).await).await;
+        assert_eq!(read_form(&state, before, UploadQuery::default()).await.ok().and_then(|o| o.chunk_size), Some(300));
+
+        // pdf_body closes the body, so drop the closing boundary to add fields after the file
+        let mut after = pdf_body(blank_pdf());
+        after.truncate(after.len() - b"--XBOUNDARY--\r\n".len());
+        after.extend(options);
+        after.extend(b"--XBOUNDARY--\r\n");
+        let second = upload_id(upload(&state, after.clone(), None).await).await;
+        assert_eq!(read_form(&state, after, UploadQuery::default()).await.ok().and_then(|o| o.chunk_size), Some(300));
+
+        let expiry = state.expiry_map.read().await;
+        assert!(expiry.contains_key(&first) && expiry.contains_key(&second));
+        drop(expiry);
+
+        let bad_ttl = [form_field("ttl", "soon"), pdf_body(blank_pdf())].concat();
+        assert_eq!(upload(&state, bad_ttl, None).await.status(), StatusCode::BAD_REQUEST);
+        let options_only = [form_field("ttl", "60"), b"--XBOUNDARY--\r\n".to_vec()].concat();
+        assert_eq!(upload(&state, options_only, None).await.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn zero_chunk_size_is_rejected_from_either_source() {
+        let state = AppState::for_tests();
+        let status = |result: Result<UploadQuery, AppError>| result.err().map(|e| e.into_response().status());
+
+        let field = [form_field("chunk_size", "0"), pdf_body(blank_pdf())].concat();
+        assert_eq!(status(read_form(&state, field, UploadQuery::default()).await), Some(StatusCode::BAD_REQUEST));
+
+        let query = UploadQuery { chunk_size: Some(0), ..UploadQuery::default() };
+        assert_eq!(status(read_form(&state, pdf_body(blank_pdf()), query).await), Some(StatusCode::BAD_REQUEST));
+    }
+
+    #[tokio::test]
+    async fn malformed_multipart_is_a_400() {
+        let state = AppState::for_tests();
+
+        let no_filename = "--XBOUNDARY\r\n\
+            Content-Disposition: form-data; name=\"pdf\"\r\n\r\n\
+            %PDF-1.5\r\n\
+            --XBOUNDARY--\r\n";
+        assert_eq!(post_multipart(&state, no_filename).await, StatusCode::BAD_REQUEST);
+
+        // Body ends before the closing boundary
+        let truncated = "--XBOUNDARY\r\n\
+            Content-Disposition: form-data; name=\"pdf\"; filename=\"a.pdf\"\r\n\r\n\
+            %PDF-1.5";
+        assert_eq!(post_multipart(&state, truncated).await, StatusCode::BAD_REQUEST);
+
+        // Over axum's default 2 MB body limit
+        let oversized = pdf_body(vec![b'x'; 3 * 1024 * 1024]);
+        assert_eq!(upload(&state, oversized, None).await.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        assert!(state.id_map.read().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn blank_pdf_is_marked_empty() {
+        let state = AppState::for_tests();
+        let id = "blank".to_string();
+
+        index_upload(state.clone(), id.clone(), "blank.pdf".to_string(), blank_pdf(), ChunkConfig::default()).await;
+
+        assert_eq!(state.id_map.read().await.get(&id).map(String::as_str), Some("empty"));
+        assert!(!state.ready_set.read().await.contains(&id));
+
+        let params = [("id".to_string(), id)].into_iter().collect();
+        let resp = is_ready(State(state), axum::extract::Query(params)).await;
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+        let value: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(value["status"], "empty");
+        assert_eq!(value["ready"], false);
+    }
+
+    #[tokio::test]
+    async fn document_over_the_page_limit_is_rejected() {
+        let mut config = AppConfig::default();
+        config.upload.max_pages = 2;
+        let state = AppState { config: Arc::new(config), ..AppState::for_tests() };
+        let pdf = blank_pages_pdf(3);
+
+        let err = process_file(&state, "big", "big.pdf", pdf.clone().into(), &state.config.chunking, None)
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Document has 3 pages, over the limit of 2");
+
+        index_upload(state.clone(), "big".to_string(), "big.pdf".to_string(), pdf, state.config.chunking.clone()).await;
+        assert_eq!(state.id_map.read().await.get("big").map(String::as_str), Some("failed"));
+    }
+
+    #[tokio::test]
+    async fn failure_mid_document_keeps_earlier_pages() {
+        let collection: Mutex<Vec<u32>> = Mutex::new(Vec::new());
+
+        let result = index_pages_in_order(&[1, 2, 3, 4], |page_num| {
+            let collection = &collection;
+            async move {
+                if page_num == 3 {
+                    anyhow::bail!("embedding blew up");
+                }
+                collection.lock().unwrap().push(page_num);
+                Ok(())
+            }
+        })
+        .await;
+
+        let err = result.unwrap_err();
+        assert!(err.to_string().contains("page 3"));
+        assert_eq!(*collection.lock().unwrap(), vec![1, 2]);
+    }
+
+    #[tokio::test]
+    async fn resume_embeds_only_the_pages_after_the_last_committed_one() {
+        let all_pages = vec![1, 2, 3, 4, 5];
+        let embedded: Mutex<Vec<u32>> = Mutex::new(Vec::new());
+        let progress: Mutex<Option<u32>> = Mutex::new(None);
+        // Commits like process_file: embed, then record the page as done
+        let run = |pages: Vec<u32>, fail_at: Option<u32>| {
+            let (embedded, progress) = (&embedded, &progress);
+            async move {
+                index_pages_in_order(&pages, |page_num| async move {
+                    if Some(page_num) == fail_at {
+                        anyhow::bail!("connection reset");
+                    }
+                    embedded.lock().unwrap().push(page_num);
+                    *progress.lock().unwrap() = Some(page_num);
+                    Ok(())
+                })
+                .await
+            }
+        };
+
+        // Interrupted on page 4
+        assert!(run(pages_to_index(all_pages.clone(), None), Some(4)).await.is_err());
+        let resume_after = *progress.lock().unwrap();
+        assert_eq!(resume_after, Some(3));
+
+        embedded.lock().unwrap().clear();
+        run(pages_to_index(all_pages.clone(), resume_after), None).await.unwrap();
+        // Page 3 was committed, so it isn't embedded twice; page 4 isn't skipped
+        assert_eq!(*embedded.lock().unwrap(), vec![4, 5]);
+        assert!(pages_to_index(all_pages, Some(5)).is_empty());
+    }
 }
\ No newline at end of file
@@ -1,9 +1,15 @@
 // src/main.rs
 
+mod config;
 mod errors;
+mod expiry;
 mod handlers;
+mod metrics;
 mod pdf;
 mod types;
+#[cfg(test)]
+#[path = "../../test_pdf.rs"]
+mod test_pdf;
 
 use std::{collections::HashSet, net::SocketAddr};
 use std::sync::Arc;
@@ -14,44 +20,138 @@ use axum::{
     Router,
     extract::DefaultBodyLimit,
     response::{Html},
-    routing::{get, post},
+    routing::{get, patch, post, put},
     http::StatusCode,
 };
 use qdrant_client::Qdrant;
-use tokio::sync::RwLock;
+use tokio::sync::{RwLock, Semaphore};
 use tower_http::services::ServeDir;
-use vb::qdrant;
+use vb::{embed, qdrant};
+use vb::cache::TtlCache;
 
-use types::{AppState, IdToBytesMap, IdToFilenameMap, IdReadyMap};
-use handlers::{upload::handle_upload, search::search_with_bboxes, ready::is_ready};
+use config::{AppConfig, SearchConfig};
+use types::{AppState, IdToBytesMap, IdToFilenameMap, IdReadyMap, IdProgressMap, IdUploadedMap, IdempotencyMap, IdLabelMap, IdLangMap, PartialUploadMap, IndexJobMap, IdExpiryMap, IdTermsMap, IdPageLabelsMap};
+use handlers::{upload::{handle_upload, drain_indexing}, resumable::{init_upload, upload_part, complete_upload}, search::search_with_bboxes, library::search_library, ready::is_ready, reindex::reindex_document, document::{serve_pdf, rename_document, document_stats, document_text}, live_search::live_search, metrics::serve_metrics, health::health, selection::search_selection, compare::compare_documents, export::export_document, import::import_document, admin::cache_stats, suggest::suggest, embed::embed_texts};
 
-#[tokio::main]
-async fn main() {
+/// Embed a few sample sentences and make sure their vectors suit the collection's
+/// metric. Dot on unnormalized vectors ranks subtly wrong, and nothing else would notice.
+fn check_metric(config: &SearchConfig) {
+    let samples = [
+        "Gradient descent follows the slope of the loss.",
+        "The invoice is due at the end of the month.",
+    ];
+    let vectors = match embed::embed_batch(&samples, None) {
+        Ok(vectors) => vectors,
+        Err(e) => {
+            eprintln!("Skipping the vector metric check, the model didn't load: {}", e);
+            return;
+        }
+    };
+    if let Err(e) = qdrant::check_normalization(config.metric, &vectors) {
+        if config.strict_metric_check {
+            panic!("Vector metric check failed: {}", e);
+        }
+        eprintln!("**********\nWARNING: {}\n**********", e);
+    }
+}
+
+fn main() {
+    let config = AppConfig::from_env();
+    let budget = config.resources;
+    budget.install_global_rayon().expect("Global rayon pool already started");
+    tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(budget.runtime_threads)
+        .max_blocking_threads(budget.blocking_threads())
+        .enable_all()
+        .build()
+        .expect("Failed to build the async runtime")
+        .block_on(run(config));
+}
+
+async fn run(config: AppConfig) {
     let id_map: IdToFilenameMap = Arc::new(RwLock::new(HashMap::new()));
+    let name_map: IdToFilenameMap = Arc::new(RwLock::new(HashMap::new()));
     let bytes_map: IdToBytesMap = Arc::new(RwLock::new(HashMap::new()));
     let ready_set: IdReadyMap = Arc::new(RwLock::new(HashSet::new()));
+    let progress_map: IdProgressMap = Arc::new(RwLock::new(HashMap::new()));
+    let uploaded_map: IdUploadedMap = Arc::new(RwLock::new(HashMap::new()));
+    let idempotency_map: IdempotencyMap = Arc::new(RwLock::new(HashMap::new()));
+    let label_map: IdLabelMap = Arc::new(RwLock::new(HashMap::new()));
+    let lang_map: IdLangMap = Arc::new(RwLock::new(HashMap::new()));
+    let partial_uploads: PartialUploadMap = Arc::new(RwLock::new(HashMap::new()));
+    let index_jobs: IndexJobMap = Arc::new(RwLock::new(HashMap::new()));
+    let expiry_map: IdExpiryMap = Arc::new(RwLock::new(HashMap::new()));
+    let terms_map: IdTermsMap = Arc::new(RwLock::new(HashMap::new()));
+    let page_label_map: IdPageLabelsMap = Arc::new(RwLock::new(HashMap::new()));
 
     let qdrant_client = Qdrant::from_url("http://localhost:6334")
         .build()
         .expect("Failed to connect to Qdrant");
 
     qdrant::delete_all_collections(&qdrant_client).await;
-    let _ = qdrant::init_collection(&qdrant_client, "embedded_pdfs").await;
+    let collection = config.collection(None).expect("Cannot read the embedding model");
+    if let Err(e) = qdrant::ensure_collection(&qdrant_client, &collection, config.search.metric, config.search.precision).await {
+        eprintln!("Failed to create collection {}: {:?}", collection, e);
+    }
+    embed::configure(config.embed.clone()).expect("Embedding model configured twice");
+    check_metric(&config.search);
 
     let state = AppState {
         id_map,
+        name_map,
         bytes_map,
         qdrant: Arc::new(qdrant_client),
         ready_set,
+        progress_map,
+        uploaded_map,
+        idempotency_map,
+        label_map,
+        lang_map,
+        partial_uploads,
+        index_jobs,
+        expiry_map,
+        terms_map,
+        page_label_map,
+        search_cache: Arc::new(RwLock::new(TtlCache::new(config.search.cache_size, config.search.cache_ttl))),
+        embed_permits: Arc::new(Semaphore::new(config.resources.embed_jobs)),
+        config: Arc::new(config),
     };
+    let shutdown_state = state.clone();
+    expiry::spawn_sweeper(state.clone());
 
-    let app = Router::new()
+    let mut app = Router::new()
         .route("/", get(index))
         .route("/upload", post(handle_upload))
+        .route("/upload/init", post(init_upload))
+        .route("/upload/:id/part/:n", put(upload_part))
+        .route("/upload/:id/complete", post(complete_upload))
         .route("/api/search", get(search_with_bboxes))
+        .route("/api/search-all", get(search_library))
+        .route("/api/suggest", get(suggest))
+        .route("/api/search-selection", post(search_selection))
+        .route("/api/compare", post(compare_documents))
+        .route("/api/export/:id", get(export_document))
+        .route("/api/import", post(import_document))
         .route("/api/ready", get(is_ready))
+        .route("/api/reindex/:id", post(reindex_document))
+        .route("/api/pdf/:id", get(serve_pdf))
+        .route("/api/document/:id", patch(rename_document))
+        .route("/api/document/:id/stats", get(document_stats))
+        .route("/api/document/:id/text", get(document_text))
+        .route("/ws/search", get(live_search))
+        .route("/metrics", get(serve_metrics))
+        .route("/health", get(health))
+        .route("/admin/cache-stats", get(cache_stats));
+    if state.config.serve_embeddings {
+        if state.config.embed_endpoint.token.is_some() {
+            app = app.route(embed::EMBED_ENDPOINT, post(embed_texts));
+        } else {
+            eprintln!("SERVE_EMBEDDINGS is set without EMBED_TOKEN; not serving {}", embed::EMBED_ENDPOINT);
+        }
+    }
+    let app = app
         .nest_service("/static", ServeDir::new("static"))
-        .layer(DefaultBodyLimit::max(100 * 1024 * 1024))
+        .layer(DefaultBodyLimit::max(state.config.upload.max_bytes))
         .with_state(state);
 
     let addr = SocketAddr::from(([127, 0, 0, 1], 3000));
@@ -62,6 +162,12 @@ async fn main() {
         .with_graceful_shutdown(shutdown_signal())
         .await
         .unwrap();
+
+    let grace = shutdown_state.config.upload.shutdown_grace;
+    let abandoned = drain_indexing(&shutdown_state, grace).await;
+    if !abandoned.is_empty() {
+        eprintln!("Stopped before indexing finished for: {}", abandoned.join(", "));
+    }
 }
 
 async fn shutdown_signal() {
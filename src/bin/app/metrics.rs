@@ -0,0 +1,87 @@
+// src/metrics.rs
+
+use once_cell::sync::Lazy;
+use prometheus::{
+    Encoder, Histogram, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, Opts, Registry,
+    TextEncoder,
+};
+
+/// Process-wide Prometheus counters and histograms, scraped via `/metrics`.
+pub struct Metrics {
+    registry: Registry,
+    pub uploads: IntCounter,
+    pub searches: IntCounter,
+    /// Searches over `SLOW_SEARCH_MS`, each also logged with its stage timings.
+    pub slow_searches: IntCounter,
+    /// Failed requests, labelled by `endpoint`.
+    pub errors: IntCounterVec,
+    /// Time from upload to the last page being indexed.
+    pub processing_seconds: Histogram,
+    pub embedding_seconds: Histogram,
+    /// Qdrant round trips, labelled by `op` (query, upsert, delete).
+    pub qdrant_seconds: HistogramVec,
+}
+
+static METRICS: Lazy<Metrics> = Lazy::new(Metrics::new);
+
+pub fn metrics() -> &'static Metrics {
+    &METRICS
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new();
+
+        let uploads = IntCounter::new("pdf_uploads_total", "PDFs received for indexing").unwrap();
+        let searches = IntCounter::new("pdf_searches_total", "Search requests handled").unwrap();
+        let slow_searches = IntCounter::new("pdf_slow_searches_total", "Searches slower than the slow-search threshold").unwrap();
+        let errors = IntCounterVec::new(
+            Opts::new("pdf_request_errors_total", "Requests that ended in an error"),
+            &["endpoint"],
+        )
+        .unwrap();
+        let processing_seconds = Histogram::with_opts(
+            HistogramOpts::new("pdf_processing_seconds", "Time to index an uploaded PDF")
+                .buckets(vec![0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0]),
+        )
+        .unwrap();
+        let embedding_seconds = Histogram::with_opts(HistogramOpts::new(
+            "pdf_embedding_seconds",
+            "Time spent embedding one batch of chunks",
+        ))
+        .unwrap();
+        let qdrant_seconds = HistogramVec::new(
+            HistogramOpts::new("pdf_qdrant_seconds", "Qdrant call latency"),
+            &["op"],
+        )
+        .unwrap();
+
+        registry.register(Box::new(uploads.clone())).unwrap();
+        registry.register(Box::new(searches.clone())).unwrap();
+        registry.register(Box::new(slow_searches.clone())).unwrap();
+        registry.register(Box::new(errors.clone())).unwrap();
+        registry.register(Box::new(processing_seconds.clone())).unwrap();
+        registry.register(Box::new(embedding_seconds.clone())).unwrap();
+        registry.register(Box::new(qdrant_seconds.clone())).unwrap();
+
+        Metrics {
+            registry,
+            uploads,
+            searches,
+            slow_searches,
+            errors,
+            processing_seconds,
+            embedding_seconds,
+            qdrant_seconds,
+        }
+    }
+
+    /// Everything registered, in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let mut buf = Vec::new();
+        if let Err(e) = TextEncoder::new().encode(&self.registry.gather(), &mut buf) {
+            eprintln!("Failed to encode metrics: {:?}", e);
+        }
+        String::from_utf8(buf).unwrap_or_default()
+    }
+}
@@ -1,19 +1,85 @@
 // src/pdf.rs
 
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::path::{Path, PathBuf};
 use std::sync::OnceLock;
 use anyhow::Result;
 use pdfium_render::prelude::*;
+use serde::{Deserialize, Serialize};
+use vb::{Chunk, ChunkKind, PageNumber};
 use crate::types::CharBbox;
 
-static PDFIUM: OnceLock<Pdfium> = OnceLock::new();
+static PDFIUM: OnceLock<Result<Pdfium, PdfiumUnavailable>> = OnceLock::new();
 
-pub fn get_pdfium() -> &'static Pdfium {
-    PDFIUM.get_or_init(|| {
-        Pdfium::new(
-            Pdfium::bind_to_library(Pdfium::pdfium_platform_library_name_at_path("./"))
-                .or_else(|_| Pdfium::bind_to_system_library())
-                .expect("Failed to bind to pdfium library"),
-        )
+// Checked after `PDFIUM_PATH`, before falling back to the system library
+const PDFIUM_DIRS: &[&str] = &["./", "./lib", "/usr/lib", "/usr/local/lib"];
+
+/// No pdfium library could be bound; lists every path that was tried.
+#[derive(Debug, Clone)]
+pub struct PdfiumUnavailable {
+    attempts: Vec<String>,
+}
+
+impl fmt::Display for PdfiumUnavailable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "pdfium library not available; tried:\n  {}", self.attempts.join("\n  "))
+    }
+}
+
+impl std::error::Error for PdfiumUnavailable {}
+
+#[cfg(test)]
+impl PdfiumUnavailable {
+    pub fn for_tests(attempt: &str) -> Self {
+        PdfiumUnavailable { attempts: vec![attempt.to_string()] }
+    }
+}
+
+/// The shared pdfium instance. Binding is attempted once; if it failed, every
+/// call returns the same error so callers can report it instead of panicking.
+pub fn get_pdfium() -> Result<&'static Pdfium, PdfiumUnavailable> {
+    PDFIUM
+        .get_or_init(|| {
+            let candidates = pdfium_candidates(env::var("PDFIUM_PATH").ok().as_deref());
+            bind_pdfium(&candidates)
+                .map(Pdfium::new)
+                .map_err(|attempts| PdfiumUnavailable { attempts })
+        })
+        .as_ref()
+        .map_err(Clone::clone)
+}
+
+/// Library files to try, in order. `PDFIUM_PATH` may name the library file
+/// itself or the directory holding it.
+fn pdfium_candidates(env_path: Option<&str>) -> Vec<PathBuf> {
+    let mut candidates = Vec::new();
+    if let Some(path) = env_path.map(str::trim).filter(|p| !p.is_empty()) {
+        let path = Path::new(path);
+        if path.is_dir() {
+            candidates.push(Pdfium::pdfium_platform_library_name_at_path(path));
+        } else {
+            candidates.push(path.to_path_buf());
+        }
+    }
+    candidates.extend(PDFIUM_DIRS.iter().map(Pdfium::pdfium_platform_library_name_at_path));
+    candidates
+}
+
+// Try each candidate then the system library; on failure, every attempt and why it failed
+fn bind_pdfium(candidates: &[PathBuf]) -> Result<Box<dyn PdfiumLibraryBindings>, Vec<String>> {
+    let mut attempts = Vec::new();
+    for path in candidates {
+        match Pdfium::bind_to_library(path) {
+            Ok(bindings) => return Ok(bindings),
+            Err(e) => attempts.push(format!("{} ({:?})", path.display(), e)),
+        }
+    }
+    Pdfium::bind_to_system_library().map_err(|e| {
+        let name = Pdfium::pdfium_platform_library_name();
+        attempts.push(format!("system library {} ({:?})", name.to_string_lossy(), e));
+        attempts
     })
 }
 
@@ -30,13 +96,121 @@ pub fn expand_ligatures(pdf_idx: usize, ch: char) -> Vec<(usize, char)> {
     }
 }
 
+/// Smooth over the joins text extraction leaves at line and paragraph breaks.
+///
+/// Runs of whitespace (`\r\n`, blank lines between paragraphs) become one
+/// space, and a hyphen directly before a line break is dropped along with the
+/// break so "para-\ngraph" reads "paragraph". Applied to both the page text and
+/// the stored chunk text, so a needle that straddles a break still matches as
+/// one contiguous span. Each entry keeps the pdf index of the char it came from.
+pub fn join_break_artifacts<I>(entries: I) -> Vec<(usize, char)>
+where
+    I: IntoIterator<Item = (usize, char)>,
+{
+    let mut joined: Vec<(usize, char)> = Vec::new();
+    let mut gap: Option<(usize, bool)> = None; // (first index, saw a line break)
+
+    for (idx, ch) in entries {
+        if ch.is_whitespace() {
+            let line_break = matches!(ch, '\n' | '\r');
+            gap = Some(match gap {
+                Some((first, seen)) => (first, seen || line_break),
+                None => (idx, line_break),
+            });
+            continue;
+        }
+        if let Some((first, line_break)) = gap.take() {
+            if line_break && joined.last().is_some_and(|&(_, c)| c == '-') {
+                joined.pop();
+            } else if !joined.is_empty() {
+                joined.push((first, ' '));
+            }
+        }
+        joined.push((idx, ch));
+    }
+    joined
+}
+
+/// How far apart (vertically) two glyphs can be and still count as one line.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LineTolerance {
+    /// Fixed distance in PDF points.
+    Fixed(f32),
+    /// Fraction of the smaller of the two glyph heights.
+    HeightRatio(f32),
+}
+
+impl Default for LineTolerance {
+    fn default() -> Self {
+        LineTolerance::Fixed(2.0)
+    }
+}
+
+impl LineTolerance {
+    fn same_line(self, a: &CharBbox, b: &CharBbox) -> bool {
+        let tolerance = match self {
+            LineTolerance::Fixed(points) => points,
+            LineTolerance::HeightRatio(ratio) => ratio * a.height.min(b.height),
+        };
+        (a.y - b.y).abs() < tolerance
+    }
+}
+
+/// Where the returned rects measure `y` from.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CoordinateOrigin {
+    /// PDF user space: `y` is the rect's bottom edge, measured up from the page bottom.
+    #[default]
+    BottomLeft,
+    /// Screen space, as web viewers draw: `y` is the rect's top edge, measured down from the page top.
+    TopLeft,
+}
+
+impl CoordinateOrigin {
+    /// Convert rects from PDF space into this origin; `page_height` is in points.
+    pub fn apply(self, rects: &mut [CharBbox], page_height: f32) {
+        if self == CoordinateOrigin::TopLeft {
+            for rect in rects {
+                rect.y = page_height - (rect.y + rect.height);
+            }
+        }
+    }
+}
+
+/// What the returned rects are measured in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CoordinateUnits {
+    /// PDF points, as pdfium reports them.
+    #[default]
+    Points,
+    /// Fractions of the page width and height (0.0–1.0), for drawing at any zoom.
+    Normalized,
+}
+
+impl CoordinateUnits {
+    /// Convert rects from points into these units; the page size is in points.
+    pub fn apply(self, rects: &mut [CharBbox], page_width: f32, page_height: f32) {
+        if self != CoordinateUnits::Normalized || page_width <= 0.0 || page_height <= 0.0 {
+            return;
+        }
+        for rect in rects {
+            rect.x /= page_width;
+            rect.width /= page_width;
+            rect.y /= page_height;
+            rect.height /= page_height;
+        }
+    }
+}
+
 pub fn extract_char_bboxes(
     text_page: &PdfPageText,
     pdf_char_indices: &[usize],
+    tolerance: LineTolerance,
 ) -> Result<Vec<CharBbox>> {
     let chars = text_page.chars();
-    let mut result: Vec<CharBbox> = Vec::new();
-    let mut current: Option<CharBbox> = None;
+    let mut boxes: Vec<Option<CharBbox>> = Vec::with_capacity(pdf_char_indices.len());
 
     for &idx in pdf_char_indices {
         let ch = match chars.get(idx) {
@@ -45,77 +219,1219 @@ pub fn extract_char_bboxes(
         };
 
         if ch.unicode_char().map_or(false, |c| c.is_whitespace()) {
+            boxes.push(None);
+            continue;
+        }
+
+        boxes.push(Some(glyph_box(&ch)?));
+    }
+
+    let mut rects = merge_char_boxes(boxes, tolerance);
+    let page_glyphs = chars
+        .iter()
+        .filter(|c| c.unicode_char().is_some_and(|c| !c.is_whitespace()))
+        .filter_map(|c| glyph_box(&c).ok());
+    assign_lines(&mut rects, &line_bands(page_glyphs, tolerance));
+    Ok(rects)
+}
+
+fn glyph_box(ch: &PdfPageTextChar) -> Result<CharBbox> {
+    let bounds = ch.loose_bounds()?;
+    Ok(CharBbox {
+        x: bounds.left().value,
+        y: bounds.bottom().value,
+        width: (bounds.right() - bounds.left()).value,
+        height: (bounds.top() - bounds.bottom()).value,
+        line: 0,
+    })
+}
+
+/// The page's text lines from top to bottom, each represented by its topmost glyph.
+pub fn line_bands<I>(glyphs: I, tolerance: LineTolerance) -> Vec<CharBbox>
+where
+    I: IntoIterator<Item = CharBbox>,
+{
+    let mut glyphs: Vec<CharBbox> = glyphs.into_iter().collect();
+    glyphs.sort_by(|a, b| b.y.total_cmp(&a.y));
+
+    let mut bands: Vec<CharBbox> = Vec::new();
+    for glyph in glyphs {
+        if !bands.last().is_some_and(|band| tolerance.same_line(band, &glyph)) {
+            bands.push(glyph);
+        }
+    }
+    bands
+}
+
+/// Set each rect's `line` to the index of the nearest band from [`line_bands`].
+pub fn assign_lines(rects: &mut [CharBbox], bands: &[CharBbox]) {
+    for rect in rects {
+        rect.line = bands
+            .iter()
+            .enumerate()
+            .min_by(|(_, a), (_, b)| (a.y - rect.y).abs().total_cmp(&(b.y - rect.y).abs()))
+            .map_or(0, |(i, _)| i);
+    }
+}
+
+/// pdf_oxide's text runs for one document, for pages where pdfium loads but
+/// finds no text. pdf_oxide only reads from a path, so the bytes go to a temp
+/// file on first use, and each page is read once however many hits need it.
+pub struct TextSpans<'a> {
+    bytes: &'a [u8],
+    tolerance: LineTolerance,
+    file: Option<(PathBuf, pdf_oxide::PdfDocument)>,
+    pages: HashMap<PageNumber, Vec<(String, CharBbox)>>,
+}
+
+impl<'a> TextSpans<'a> {
+    pub fn new(bytes: &'a [u8], tolerance: LineTolerance) -> Self {
+        TextSpans { bytes, tolerance, file: None, pages: HashMap::new() }
+    }
+
+    /// Runs on `page`, each with its box in PDF points from the bottom-left
+    /// and its line from the top. Boxes cover whole runs, not glyphs.
+    pub fn page(&mut self, page: PageNumber) -> Result<Vec<(String, CharBbox)>> {
+        if let Some(spans) = self.pages.get(&page) {
+            return Ok(spans.clone());
+        }
+        let spans = self.document()?.extract_spans(page.index() as usize)?;
+        let mut spans: Vec<(String, CharBbox)> = spans
+            .into_iter()
+            .filter(|span| !span.text.trim().is_empty())
+            .map(|span| {
+                let rect = CharBbox { x: span.bbox.x, y: span.bbox.y, width: span.bbox.width, height: span.bbox.height, line: 0 };
+                (span.text, rect)
+            })
+            .collect();
+        let bands = line_bands(spans.iter().map(|(_, rect)| rect.clone()), self.tolerance);
+        for (_, rect) in &mut spans {
+            assign_lines(std::slice::from_mut(rect), &bands);
+        }
+        self.pages.insert(page, spans.clone());
+        Ok(spans)
+    }
+
+    fn document(&mut self) -> Result<&mut pdf_oxide::PdfDocument> {
+        let file = match self.file.take() {
+            Some(file) => file,
+            None => {
+                let path = env::temp_dir().join(format!("vb-spans-{}.pdf", uuid::Uuid::new_v4()));
+                std::fs::write(&path, self.bytes)?;
+                match pdf_oxide::PdfDocument::open(&path) {
+                    Ok(doc) => (path, doc),
+                    Err(e) => {
+                        let _ = std::fs::remove_file(&path);
+                        return Err(e.into());
+                    }
+                }
+            }
+        };
+        Ok(&mut self.file.insert(file).1)
+    }
+}
+
+impl Drop for TextSpans<'_> {
+    fn drop(&mut self) {
+        if let Some((path, doc)) = self.file.take() {
+            // Closed first, so the file can go on every platform
+            drop(doc);
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+/// Grow each rect by `padding` points on every side, clamped to the page.
+///
+/// Runs after line merging so padding never changes which glyphs count as one
+/// line. Each side is also capped at half the gap to the nearest rect beside
+/// it (above or below, or left or right on the same line), so stacked lines
+/// and neighbouring words stay separate rects instead of overlapping. NaN or
+/// negative padding leaves the rects as they are.
+pub fn pad_rects(rects: &mut [CharBbox], padding: f32, page_width: f32, page_height: f32) {
+    if padding.is_nan() || padding <= 0.0 {
+        return;
+    }
+    let original = rects.to_vec();
+    let horizontal_extent = |r: &CharBbox| (r.x, r.x + r.width);
+    let vertical_extent = |r: &CharBbox| (r.y, r.y + r.height);
+    for (i, rect) in rects.iter_mut().enumerate() {
+        let horizontal = capped_padding(padding, &original, i, horizontal_extent, vertical_extent);
+        let vertical = capped_padding(padding, &original, i, vertical_extent, horizontal_extent);
+
+        let left = (rect.x - horizontal).max(0.0);
+        let right = (rect.x + rect.width + horizontal).min(page_width);
+        let bottom = (rect.y - vertical).max(0.0);
+        let top = (rect.y + rect.height + vertical).min(page_height);
+        *rect = CharBbox { x: left, y: bottom, width: right - left, height: top - bottom, line: rect.line };
+    }
+}
+
+// `padding`, or less: half the gap along `along` to any other rect sharing
+// some of `rects[i]`'s extent `across`
+fn capped_padding(
+    padding: f32,
+    rects: &[CharBbox],
+    i: usize,
+    along: fn(&CharBbox) -> (f32, f32),
+    across: fn(&CharBbox) -> (f32, f32),
+) -> f32 {
+    let ((start, end), (low, high)) = (along(&rects[i]), across(&rects[i]));
+    rects
+        .iter()
+        .enumerate()
+        .filter(|&(j, other)| {
+            let (other_low, other_high) = across(other);
+            j != i && other_low < high && low < other_high
+        })
+        .filter_map(|(_, other)| {
+            let (other_start, other_end) = along(other);
+            let gap = (other_start - end).max(start - other_end);
+            (gap >= 0.0).then_some(gap / 2.0)
+        })
+        .fold(padding, f32::min)
+}
+
+/// Merge per-glyph boxes into one rect per run on the same line.
+/// `None` entries are whitespace and always end the current run.
+pub fn merge_char_boxes<I>(boxes: I, tolerance: LineTolerance) -> Vec<CharBbox>
+where
+    I: IntoIterator<Item = Option<CharBbox>>,
+{
+    let mut result: Vec<CharBbox> = Vec::new();
+    let mut current: Option<CharBbox> = None;
+
+    for b in boxes {
+        let Some(b) = b else {
             if let Some(r) = current.take() {
                 result.push(r);
             }
             continue;
-        }
-
-        let bounds = ch.loose_bounds()?;
-        let x = bounds.left().value;
-        let y = bounds.bottom().value;
-        let width = (bounds.right() - bounds.left()).value;
-        let height = (bounds.top() - bounds.bottom()).value;
+        };
 
         if let Some(ref mut cur) = current {
-            if (cur.y - y).abs() < 2.0 {
+            if tolerance.same_line(cur, &b) {
                 // Same line: extend the rect rightward
-                cur.width = (x + width) - cur.x;
-                cur.height = cur.height.max(height);
+                cur.width = (b.x + b.width) - cur.x;
+                cur.height = cur.height.max(b.height);
                 continue;
             }
             result.push(current.take().unwrap());
         }
-        current = Some(CharBbox { x, y, width, height });
+        current = Some(b);
     }
 
     if let Some(r) = current {
         result.push(r);
     }
 
-    Ok(result)
+    result
+}
+
+// A line must be set this much larger than the body text to count as a heading
+const HEADING_SIZE_RATIO: f32 = 1.2;
+const MAX_HEADING_CHARS: usize = 120;
+
+/// How many pages pdfium finds in `bytes`.
+pub fn page_count(bytes: &[u8]) -> Result<usize> {
+    let doc = get_pdfium()?
+        .load_pdf_from_byte_slice(bytes, None)
+        .map_err(|e| anyhow::anyhow!("PDFium load failed: {:?}", e))?;
+    Ok(doc.pages().len() as usize)
+}
+
+/// Extra chunks per page for text pdfium can see but lopdf's `extract_text`
+/// misses: headings (found from font sizes), tables (rebuilt from cell
+/// positions), filled-in form field values and annotation comments.
+pub fn extract_page_extras(bytes: &[u8], tables: TableFormat) -> Result<HashMap<PageNumber, Vec<Chunk>>> {
+    let doc = get_pdfium()?
+        .load_pdf_from_byte_slice(bytes, None)
+        .map_err(|e| anyhow::anyhow!("PDFium load failed: {:?}", e))?;
+    let mut extras = HashMap::new();
+
+    for (index, page) in doc.pages().iter().enumerate() {
+        let page_number = PageNumber::from_index(index as u16);
+        let mut chunks = Vec::new();
+
+        let text_page = page.text().ok();
+        if let Some(text_page) = &text_page {
+            chunks.extend(detect_headings(&text_lines(text_page)).into_iter().map(|heading| {
+                Chunk { content: heading, page: page_number, kind: ChunkKind::Heading, parent: None }
+            }));
+            if tables != TableFormat::Off {
+                let runs = text_runs(text_page, LineTolerance::default());
+                chunks.extend(table_chunks(page_number, detect_tables(&runs), tables));
+            }
+        }
+        chunks.extend(form_field_chunks(page_number, page_form_fields(&page)));
+        chunks.extend(annotation_chunks(page_number, page_annotations(&page, text_page.as_ref())));
+
+        if !chunks.is_empty() {
+            extras.insert(page_number, chunks);
+        }
+    }
+
+    Ok(extras)
+}
+
+/// How tables found on a page are turned into chunks.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum TableFormat {
+    /// Leave tables to the body text.
+    Off,
+    /// One chunk per row, each cell labelled with its column's header:
+    /// `Region: North | Q1: 120 | Q2: 135`.
+    #[default]
+    Rows,
+    /// One chunk per table, a line per row with cells joined by ` | `.
+    Delimited,
+}
+
+impl TableFormat {
+    /// Parse `off`, `rows` or `delimited` (any case).
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "off" | "none" => Some(TableFormat::Off),
+            "rows" => Some(TableFormat::Rows),
+            "delimited" => Some(TableFormat::Delimited),
+            _ => None,
+        }
+    }
+}
+
+// Glyphs further apart than this many glyph heights start a new cell
+const CELL_GAP_RATIO: f32 = 1.5;
+const CELL_SEPARATOR: &str = " | ";
+
+// The page's text as runs of glyphs that sit together on a line, each with its
+// box and line; a wide gap (a table column) ends a run
+fn text_runs(text_page: &PdfPageText, tolerance: LineTolerance) -> Vec<(String, CharBbox)> {
+    let chars = text_page.chars();
+    let glyphs = chars.iter().map(|ch| {
+        let c = ch.unicode_char();
+        // Only glyphs that can start or extend a run need a box
+        let glyph = c.filter(|c| !c.is_whitespace()).and_then(|_| glyph_box(&ch).ok());
+        (c, glyph)
+    });
+    runs_from_glyphs(glyphs, tolerance)
+}
+
+// `text_runs` over (char, box) pairs in page order; a glyph without a box is skipped
+fn runs_from_glyphs<I>(glyphs: I, tolerance: LineTolerance) -> Vec<(String, CharBbox)>
+where
+    I: IntoIterator<Item = (Option<char>, Option<CharBbox>)>,
+{
+    let mut runs: Vec<(String, CharBbox)> = Vec::new();
+    let mut current: Option<(String, CharBbox)> = None;
+    for (c, glyph) in glyphs {
+        let c = match c {
+            Some(c) if !c.is_whitespace() => c,
+            Some(c) if c != '\n' && c != '\r' => {
+                if let Some((text, _)) = &mut current {
+                    text.push(' ');
+                }
+                continue;
+            }
+            _ => {
+                runs.extend(current.take());
+                continue;
+            }
+        };
+        let Some(glyph) = glyph else { continue };
+        match &mut current {
+            Some((text, run)) if !starts_cell(run, &glyph) => {
+                text.push(c);
+                let right = (run.x + run.width).max(glyph.x + glyph.width);
+                let top = (run.y + run.height).max(glyph.y + glyph.height);
+                run.x = run.x.min(glyph.x);
+                run.y = run.y.min(glyph.y);
+                run.width = right - run.x;
+                run.height = top - run.y;
+            }
+            _ => {
+                runs.extend(current.take());
+                current = Some((c.to_string(), glyph));
+            }
+        }
+    }
+    runs.extend(current);
+
+    for (text, _) in &mut runs {
+        *text = text.trim().to_string();
+    }
+    let bands = line_bands(runs.iter().map(|(_, rect)| rect.clone()), tolerance);
+    for (_, rect) in &mut runs {
+        assign_lines(std::slice::from_mut(rect), &bands);
+    }
+    runs
+}
+
+// Whether `glyph` is too far right of `run`, or off its line, to continue it
+fn starts_cell(run: &CharBbox, glyph: &CharBbox) -> bool {
+    let height = run.height.min(glyph.height).max(1.0);
+    let gap = glyph.x - (run.x + run.width);
+    gap > CELL_GAP_RATIO * height || gap < -height || (glyph.y - run.y).abs() > height / 2.0
+}
+
+// A header and at least two rows under it
+const MIN_TABLE_ROWS: usize = 3;
+// Header cells are short labels, not running text
+const MAX_HEADER_WORDS: usize = 4;
+
+/// Tables among a page's text runs, each as rows of cell texts from top to
+/// bottom. A table is a header row of short labels followed by two or more
+/// lines with the same number of cells (at least two), each cell lined up with
+/// its header by left edge, right edge or centre. Prose set in columns and
+/// `Label: value` forms don't have such a header, so they stay body text.
+pub fn detect_tables(runs: &[(String, CharBbox)]) -> Vec<Vec<Vec<String>>> {
+    let mut lines: Vec<Vec<&(String, CharBbox)>> = Vec::new();
+    for run in runs {
+        let line = run.1.line;
+        if lines.len() <= line {
+            lines.resize(line + 1, Vec::new());
+        }
+        lines[line].push(run);
+    }
+    for line in &mut lines {
+        line.sort_by(|a, b| a.1.x.total_cmp(&b.1.x));
+    }
+
+    let mut tables = Vec::new();
+    let mut table: Vec<&Vec<&(String, CharBbox)>> = Vec::new();
+    for line in &lines {
+        if table.first().is_some_and(|header| columns_align(header, line)) {
+            table.push(line);
+            continue;
+        }
+        if table.len() >= MIN_TABLE_ROWS {
+            tables.push(cell_texts(&table));
+        }
+        table.clear();
+        if is_header(line) {
+            table.push(line);
+        }
+    }
+    if table.len() >= MIN_TABLE_ROWS {
+        tables.push(cell_texts(&table));
+    }
+    tables
+}
+
+fn is_header(line: &[&(String, CharBbox)]) -> bool {
+    line.len() >= 2
+        && line.iter().all(|(text, _)| {
+            text.chars().any(char::is_alphabetic)
+                && text.split_whitespace().count() <= MAX_HEADER_WORDS
+                && !text.ends_with([':', '.', ',', ';'])
+        })
+}
+
+// Whether each of `row`'s cells sits under the matching header cell
+fn columns_align(header: &[&(String, CharBbox)], row: &[&(String, CharBbox)]) -> bool {
+    header.len() == row.len()
+        && header.iter().zip(row).all(|((_, a), (_, b))| {
+            let tolerance = a.height.min(b.height).max(1.0);
+            let center = |r: &CharBbox| r.x + r.width / 2.0;
+            (a.x - b.x).abs() <= tolerance
+                || (a.x + a.width - b.x - b.width).abs() <= tolerance
+                || (center(a) - center(b)).abs() <= tolerance
+        })
+}
+
+fn cell_texts(rows: &[&Vec<&(String, CharBbox)>]) -> Vec<Vec<String>> {
+    rows.iter().map(|row| row.iter().map(|(text, _)| text.clone()).collect()).collect()
+}
+
+/// Chunks for the tables on `page`. The first row is taken as the header.
+/// Backslashes, `|` and `:` inside cells are escaped with a backslash, so
+/// [`table_cell_text`] can tell them from the separators.
+pub fn table_chunks(page: PageNumber, tables: Vec<Vec<Vec<String>>>, format: TableFormat) -> Vec<Chunk> {
+    let chunk = |content: String| Chunk { content, page, kind: ChunkKind::Table, parent: None };
+    match format {
+        TableFormat::Off => Vec::new(),
+        TableFormat::Rows => tables
+            .into_iter()
+            .flat_map(|rows| {
+                let header = rows[0].clone();
+                rows.into_iter().skip(1).map(move |row| {
+                    let cells: Vec<String> = header
+                        .iter()
+                        .zip(&row)
+                        .map(|(name, value)| format!("{}: {}", escape_cell(name), escape_cell(value)))
+                        .collect();
+                    cells.join(CELL_SEPARATOR)
+                })
+            })
+            .map(chunk)
+            .collect(),
+        TableFormat::Delimited => tables
+            .into_iter()
+            .map(|rows| {
+                rows.iter()
+                    .map(|row| row.iter().map(|cell| escape_cell(cell)).collect::<Vec<_>>().join(CELL_SEPARATOR))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            })
+            .map(chunk)
+            .collect(),
+    }
+}
+
+fn escape_cell(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '\\' | '|' | ':') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// The cell values of a table chunk as they read on the page, without
+/// header labels, separators or escapes, for matching against the page text.
+pub fn table_cell_text(content: &str) -> String {
+    let mut values = Vec::new();
+    for row in content.lines() {
+        let mut cell = String::new();
+        let mut chars = row.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => cell.extend(chars.next()),
+                '|' => values.push(std::mem::take(&mut cell)),
+                // What came before is the header label
+                ':' => cell.clear(),
+                _ => cell.push(c),
+            }
+        }
+        values.push(cell);
+    }
+    values.iter().map(|value| value.trim()).filter(|value| !value.is_empty()).collect::<Vec<_>>().join(" ")
+}
+
+// (line text, average glyph size) for each line on the page
+fn text_lines(text_page: &PdfPageText) -> Vec<(String, f32)> {
+    let mut lines: Vec<(String, f32)> = Vec::new();
+    let mut line = String::new();
+    let mut sizes: Vec<f32> = Vec::new();
+    for ch in text_page.chars().iter() {
+        match ch.unicode_char() {
+            Some('\n' | '\r') | None => {
+                if !sizes.is_empty() {
+                    let avg = sizes.iter().sum::<f32>() / sizes.len() as f32;
+                    lines.push((std::mem::take(&mut line), avg));
+                }
+                line.clear();
+                sizes.clear();
+            }
+            Some(c) => {
+                line.push(c);
+                if !c.is_whitespace() {
+                    sizes.push(ch.scaled_font_size().value);
+                }
+            }
+        }
+    }
+    if !sizes.is_empty() {
+        let avg = sizes.iter().sum::<f32>() / sizes.len() as f32;
+        lines.push((line, avg));
+    }
+    lines
+}
+
+// (field name, value) for every filled-in field whose value is text.
+// Checkboxes only carry on/off, so they are left out.
+fn page_form_fields(page: &PdfPage) -> Vec<(String, String)> {
+    page.annotations()
+        .iter()
+        .filter_map(|annotation| {
+            let field = annotation.as_form_field()?;
+            let value = match field {
+                PdfFormField::Text(f) => f.value(),
+                PdfFormField::ComboBox(f) => f.value(),
+                PdfFormField::ListBox(f) => f.value(),
+                PdfFormField::RadioButton(f) if f.is_checked().unwrap_or(false) => f.group_value(),
+                _ => None,
+            }?;
+            Some((field.name().unwrap_or_default(), value))
+        })
+        .collect()
+}
+
+/// One chunk per filled-in field, as "name: value" so a query on either matches.
+pub fn form_field_chunks(page: PageNumber, fields: Vec<(String, String)>) -> Vec<Chunk> {
+    fields
+        .into_iter()
+        .filter(|(_, value)| !value.trim().is_empty())
+        .map(|(name, value)| {
+            let content = if name.is_empty() {
+                value.trim().to_string()
+            } else {
+                format!("{}: {}", name, value.trim())
+            };
+            Chunk { content, page, kind: ChunkKind::FormField(name), parent: None }
+        })
+        .collect()
+}
+
+/// Text pulled from one annotation: the reviewer's comment and, for
+/// highlight-style markup, the page text it covers.
+pub struct AnnotationText {
+    pub comment: Option<String>,
+    pub marked: Option<String>,
+}
+
+fn page_annotations(page: &PdfPage, text_page: Option<&PdfPageText>) -> Vec<AnnotationText> {
+    page.annotations()
+        .iter()
+        .filter_map(|annotation| {
+            let marked = match annotation.annotation_type() {
+                PdfPageAnnotationType::Highlight
+                | PdfPageAnnotationType::Underline
+                | PdfPageAnnotationType::Squiggly
+                | PdfPageAnnotationType::Strikeout => {
+                    text_page.and_then(|text| text.for_annotation(&annotation).ok())
+                }
+                PdfPageAnnotationType::Text | PdfPageAnnotationType::FreeText => None,
+                // Popups repeat their parent's comment; links and widgets aren't commentary
+                _ => return None,
+            };
+            Some(AnnotationText { comment: annotation.contents(), marked })
+        })
+        .collect()
+}
+
+/// One chunk per annotation with any text. A comment is kept together with
+/// the passage it marks so a query on either finds both.
+pub fn annotation_chunks(page: PageNumber, annotations: Vec<AnnotationText>) -> Vec<Chunk> {
+    let non_empty = |text: Option<String>| {
+        text.map(|t| t.trim().to_string()).filter(|t| !t.is_empty())
+    };
+
+    annotations
+        .into_iter()
+        .filter_map(|annotation| {
+            let content = match (non_empty(annotation.comment), non_empty(annotation.marked)) {
+                (Some(comment), Some(marked)) => format!("{}\n\"{}\"", comment, marked),
+                (Some(text), None) | (None, Some(text)) => text,
+                (None, None) => return None,
+            };
+            Some(Chunk { content, page, kind: ChunkKind::Annotation, parent: None })
+        })
+        .collect()
+}
+
+/// Lines set noticeably larger than the page's body text.
+/// Body size is the median glyph size, so a few large lines can't skew it.
+pub fn detect_headings(lines: &[(String, f32)]) -> Vec<String> {
+    let mut weighted: Vec<(f32, usize)> = lines
+        .iter()
+        .map(|(text, size)| (*size, text.chars().filter(|c| !c.is_whitespace()).count()))
+        .filter(|&(_, count)| count > 0)
+        .collect();
+    weighted.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    let total: usize = weighted.iter().map(|&(_, count)| count).sum();
+    let mut seen = 0;
+    let Some(body_size) = weighted.iter().find_map(|&(size, count)| {
+        seen += count;
+        (seen * 2 >= total).then_some(size)
+    }) else {
+        return vec![];
+    };
+    if body_size <= 0.0 {
+        return vec![];
+    }
+
+    lines
+        .iter()
+        .filter(|(text, size)| {
+            let text = text.trim();
+            *size >= body_size * HEADING_SIZE_RATIO
+                && text.chars().count() <= MAX_HEADING_CHARS
+                && text.chars().any(char::is_alphabetic)
+        })
+        .map(|(text, _)| text.trim().to_string())
+        .collect()
 }
 
+/// Widen `start..end` to the enclosing sentence, plus `context_sentences`
+/// more on each side. 0 snaps to exactly the enclosing sentence.
 pub fn snap_to_sentence_boundaries(
     char_entries: &[(usize, char)],
     start: usize,
     end: usize,
+    context_sentences: usize,
 ) -> (usize, usize) {
     let chars: Vec<char> = char_entries.iter().map(|(_, c)| *c).collect();
     let len = chars.len();
 
-    let is_sentence_end = |c: char| matches!(c, '.' | '!' | '?');
-    let is_whitespace = |c: char| matches!(c, ' ' | '\t' | '\r' | '\n');
-
-    let new_start = if start == 0 {
-        0
-    } else {
-        let mut i = start.saturating_sub(1);
-        loop {
-            if is_sentence_end(chars[i]) {
-                let mut j = i + 1;
-                while j < len && (is_whitespace(chars[j]) || is_sentence_end(chars[j])) {
-                    j += 1;
-                }
-                break j;
-            }
-            if i == 0 {
-                break 0;
-            }
+    let mut new_start = sentence_start(&chars, start);
+    let mut new_end = sentence_end(&chars, end);
+    for _ in 0..context_sentences {
+        // Step back over the previous sentence's terminator before searching again
+        let mut i = new_start;
+        while i > 0 && (is_whitespace(chars[i - 1]) || is_sentence_end(chars[i - 1]) || is_closer(chars[i - 1])) {
             i -= 1;
         }
-    };
+        new_start = sentence_start(&chars, i);
 
-    let new_end = {
-        let mut i = end;
-        while i < len && !is_sentence_end(chars[i]) {
-            i += 1;
-        }
-        while i + 1 < len && matches!(chars[i + 1], '"' | '\'' | ')') {
+        let mut i = new_end;
+        while i < len && (is_whitespace(chars[i]) || is_sentence_end(chars[i])) {
             i += 1;
         }
-        (i + 1).min(len)
-    };
+        new_end = sentence_end(&chars, i);
+    }
 
     (new_start, new_end)
-}
\ No newline at end of file
+}
+
+fn is_sentence_end(c: char) -> bool {
+    matches!(c, '.' | '!' | '?')
+}
+
+// Quotes and brackets that close a sentence after its terminator
+fn is_closer(c: char) -> bool {
+    matches!(c, '"' | '\'' | ')' | ']' | '\u{201D}' | '\u{2019}')
+}
+
+fn is_whitespace(c: char) -> bool {
+    matches!(c, ' ' | '\t' | '\r' | '\n')
+}
+
+// First char after the last terminator (and its trailing space) before `start`
+fn sentence_start(chars: &[char], start: usize) -> usize {
+    if start == 0 {
+        return 0;
+    }
+    let len = chars.len();
+    let mut i = start.saturating_sub(1);
+    loop {
+        if is_sentence_end(chars[i]) {
+            let mut j = i + 1;
+            // Its closing quotes and brackets belong to it, not to the next sentence
+            while j < len && (is_sentence_end(chars[j]) || is_closer(chars[j])) {
+                j += 1;
+            }
+            while j < len && (is_whitespace(chars[j]) || is_sentence_end(chars[j])) {
+                j += 1;
+            }
+            return j;
+        }
+        if i == 0 {
+            return 0;
+        }
+        i -= 1;
+    }
+}
+
+// One past the first run of terminators at or after `end` ("...", "?!"),
+// keeping the closing quotes and brackets that follow it
+fn sentence_end(chars: &[char], end: usize) -> usize {
+    let len = chars.len();
+    let mut i = end;
+    while i < len && !is_sentence_end(chars[i]) {
+        i += 1;
+    }
+    while i + 1 < len && (is_sentence_end(chars[i + 1]) || is_closer(chars[i + 1])) {
+        i += 1;
+    }
+    (i + 1).min(len)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::test_pdf::{to_bytes, TestPdf};
+
+    // One A4 page of 12pt Helvetica, each (x, y, text) drawn as its own text object
+    pub(crate) fn a4_pdf_with_text(texts: &[(i64, i64, &str)]) -> Vec<u8> {
+        a4_pdf_pages(&[texts])
+    }
+
+    /// Like [`a4_pdf_with_text`], one page per entry.
+    pub(crate) fn a4_pdf_pages(pages: &[&[(i64, i64, &str)]]) -> Vec<u8> {
+        let mut pdf = TestPdf::new();
+        for lines in pages {
+            pdf.text_page(lines);
+        }
+        to_bytes(pdf.finish(lopdf::Dictionary::new()))
+    }
+
+    #[test]
+    fn table_rows_become_labelled_chunks() {
+        let mut cells = vec![(72, 760, "Quarterly revenue by region")];
+        for (y, row) in [(720, ["Region", "Q1", "Q2"]), (704, ["North", "120", "135"]), (688, ["South", "98", "101"])] {
+            cells.extend([(72, y, row[0]), (200, y, row[1]), (300, y, row[2])]);
+        }
+        cells.push((72, 640, "Both regions grew in the second quarter."));
+        let bytes = a4_pdf_with_text(&cells);
+
+        let runs = TextSpans::new(&bytes, LineTolerance::default()).page(PageNumber::new(1)).unwrap();
+        let tables = detect_tables(&runs);
+        assert_eq!(tables, [[["Region", "Q1", "Q2"], ["North", "120", "135"], ["South", "98", "101"]]]);
+
+        let page = PageNumber::new(1);
+        let rows = table_chunks(page, tables.clone(), TableFormat::Rows);
+        let contents: Vec<&str> = rows.iter().map(|chunk| chunk.content.as_str()).collect();
+        assert_eq!(contents, ["Region: North | Q1: 120 | Q2: 135", "Region: South | Q1: 98 | Q2: 101"]);
+        assert!(rows.iter().all(|chunk| chunk.kind == ChunkKind::Table && chunk.page == page));
+        assert_eq!(table_cell_text(contents[0]), "North 120 135");
+
+        let whole = table_chunks(page, tables, TableFormat::Delimited);
+        assert_eq!(whole.len(), 1);
+        assert_eq!(whole[0].content, "Region | Q1 | Q2\nNorth | 120 | 135\nSouth | 98 | 101");
+    }
+
+    // pdfium's chars for lines of (x, text) cells: a 6pt box per glyph, cells
+    // on a line joined by a boxless space and a break after each line
+    fn page_glyphs(lines: &[(f32, &[(f32, &str)])]) -> Vec<(Option<char>, Option<CharBbox>)> {
+        let mut glyphs = Vec::new();
+        for &(y, cells) in lines {
+            for (i, &(x, text)) in cells.iter().enumerate() {
+                if i > 0 {
+                    glyphs.push((Some(' '), None));
+                }
+                for (j, c) in text.chars().enumerate() {
+                    let rect = CharBbox { x: x + j as f32 * 6.0, y, width: 6.0, height: 10.0, line: 0 };
+                    glyphs.push((Some(c), (!c.is_whitespace()).then_some(rect)));
+                }
+            }
+            glyphs.push((Some('\n'), None));
+        }
+        glyphs
+    }
+
+    fn tables_in(lines: &[(f32, &[(f32, &str)])]) -> Vec<Vec<Vec<String>>> {
+        detect_tables(&runs_from_glyphs(page_glyphs(lines), LineTolerance::default()))
+    }
+
+    #[test]
+    fn only_headed_aligned_grids_are_tables() {
+        // Numbers are right-aligned under their headers
+        let table = tables_in(&[
+            (760.0, &[(72.0, "Quarterly revenue by region")]),
+            (720.0, &[(72.0, "Region"), (200.0, "Q1"), (300.0, "Q2")]),
+            (704.0, &[(72.0, "North"), (200.0, "120"), (300.0, "135")]),
+            (688.0, &[(72.0, "South"), (206.0, "98"), (300.0, "101")]),
+            (640.0, &[(72.0, "Both regions grew in the second quarter.")]),
+        ]);
+        assert_eq!(table, [[["Region", "Q1", "Q2"], ["North", "120", "135"], ["South", "98", "101"]]]);
+
+        let two_column_prose = tables_in(&[
+            (720.0, &[(72.0, "Gradient descent updates every"), (320.0, "Momentum keeps a running average")]),
+            (706.0, &[(72.0, "weight against the slope of the"), (320.0, "of past gradients, which damps")]),
+            (692.0, &[(72.0, "loss, scaled by the learning rate."), (320.0, "oscillation across narrow valleys.")]),
+        ]);
+        assert!(two_column_prose.is_empty(), "{:?}", two_column_prose);
+
+        let form = tables_in(&[
+            (720.0, &[(72.0, "Policy number:"), (240.0, "AX-2291-B")]),
+            (704.0, &[(72.0, "Claimant:"), (240.0, "Dana Whitfield")]),
+            (688.0, &[(72.0, "Filed:"), (240.0, "2024-03-01")]),
+        ]);
+        assert!(form.is_empty(), "{:?}", form);
+
+        let header_and_one_row = tables_in(&[
+            (720.0, &[(72.0, "Region"), (200.0, "Q1")]),
+            (704.0, &[(72.0, "North"), (200.0, "120")]),
+        ]);
+        assert!(header_and_one_row.is_empty());
+
+        // A row whose cells drift off the header's columns ends the table
+        let drifting = tables_in(&[
+            (720.0, &[(72.0, "Region"), (200.0, "Q1")]),
+            (704.0, &[(72.0, "North"), (200.0, "120")]),
+            (688.0, &[(140.0, "South"), (260.0, "98")]),
+        ]);
+        assert!(drifting.is_empty());
+    }
+
+    #[test]
+    fn separators_inside_cells_survive_the_chunk() {
+        let tables = vec![vec![
+            vec!["Ratio: A|B".to_string(), "Note".to_string()],
+            vec!["3:1".to_string(), "see C:\\data | raw".to_string()],
+        ]];
+        let page = PageNumber::new(1);
+
+        let rows = table_chunks(page, tables.clone(), TableFormat::Rows);
+        assert_eq!(rows[0].content, "Ratio\\: A\\|B: 3\\:1 | Note: see C\\:\\\\data \\| raw");
+        assert_eq!(table_cell_text(&rows[0].content), "3:1 see C:\\data | raw");
+
+        let whole = table_chunks(page, tables, TableFormat::Delimited);
+        assert_eq!(table_cell_text(&whole[0].content), "Ratio: A|B Note 3:1 see C:\\data | raw");
+    }
+
+    #[test]
+    #[ignore = "needs a pdfium library; run with PDFIUM_PATH set"]
+    fn pdfium_text_runs_find_the_table() {
+        let mut cells = vec![(72, 760, "Quarterly revenue by region")];
+        for (y, row) in [(720, ["Region", "Q1", "Q2"]), (704, ["North", "120", "135"]), (688, ["South", "98", "101"])] {
+            cells.extend([(72, y, row[0]), (200, y, row[1]), (300, y, row[2])]);
+        }
+        let bytes = a4_pdf_with_text(&cells);
+        let doc = get_pdfium().unwrap().load_pdf_from_byte_slice(&bytes, None).unwrap();
+        let page = doc.pages().get(0).unwrap();
+
+        let runs = text_runs(&page.text().unwrap(), LineTolerance::default());
+        assert_eq!(detect_tables(&runs), [[["Region", "Q1", "Q2"], ["North", "120", "135"], ["South", "98", "101"]]]);
+    }
+
+    #[test]
+    fn pdfium_path_env_is_tried_first() {
+        let file = "/opt/pdfium/libpdfium.so";
+        let candidates = pdfium_candidates(Some(file));
+        assert_eq!(candidates[0], PathBuf::from(file));
+        assert_eq!(candidates[1], Pdfium::pdfium_platform_library_name_at_path("./"));
+
+        // A directory gets the platform library name appended
+        let dir = env::temp_dir();
+        let candidates = pdfium_candidates(dir.to_str());
+        assert_eq!(candidates[0], Pdfium::pdfium_platform_library_name_at_path(&dir));
+
+        assert_eq!(pdfium_candidates(Some("  ")).len(), PDFIUM_DIRS.len());
+    }
+
+    #[test]
+    #[ignore = "needs a pdfium library; run with PDFIUM_PATH set"]
+    fn binds_from_pdfium_path() {
+        let path = env::var("PDFIUM_PATH").expect("PDFIUM_PATH not set");
+        let bindings = bind_pdfium(&pdfium_candidates(Some(&path)));
+        assert!(bindings.is_ok(), "{:?}", bindings.err());
+    }
+
+    fn glyph(x: f32, y: f32, height: f32) -> Option<CharBbox> {
+        Some(CharBbox { x, y, width: 1.0, height, line: 0 })
+    }
+
+    #[test]
+    fn height_ratio_keeps_tight_small_font_lines_apart() {
+        // 1.5pt footnote text with lines only 1.8pt apart
+        let boxes = vec![
+            glyph(10.0, 100.0, 1.5),
+            glyph(11.0, 100.0, 1.5),
+            glyph(10.0, 98.2, 1.5),
+            glyph(11.0, 98.2, 1.5),
+        ];
+
+        let fixed = merge_char_boxes(boxes.clone(), LineTolerance::Fixed(2.0));
+        assert_eq!(fixed.len(), 1, "fixed 2pt tolerance merges both lines");
+
+        let derived = merge_char_boxes(boxes, LineTolerance::HeightRatio(0.5));
+        assert_eq!(derived.len(), 2);
+        assert_eq!(derived[0].y, 100.0);
+        assert_eq!(derived[1].y, 98.2);
+    }
+
+    #[test]
+    fn top_left_origin_flips_against_page_height() {
+        // A 10pt glyph sitting 700pt up a US Letter page (792pt tall)
+        let rect = CharBbox { x: 72.0, y: 700.0, width: 6.0, height: 10.0, line: 0 };
+
+        let mut bottom_left = vec![rect.clone()];
+        CoordinateOrigin::BottomLeft.apply(&mut bottom_left, 792.0);
+        assert_eq!(bottom_left[0].y, 700.0);
+
+        let mut top_left = vec![rect];
+        CoordinateOrigin::TopLeft.apply(&mut top_left, 792.0);
+        // Top edge is at 710pt from the bottom, so 82pt from the top
+        assert_eq!(top_left[0].y, 82.0);
+        assert_eq!(top_left[0].x, 72.0);
+        assert_eq!(top_left[0].height, 10.0);
+    }
+
+    #[test]
+    fn normalized_rects_are_page_fractions() {
+        let (width, height) = (612.0, 792.0);
+        let original = vec![
+            CharBbox { x: 72.0, y: 700.0, width: 100.0, height: 10.0, line: 0 },
+            CharBbox { x: 0.0, y: 0.0, width: 612.0, height: 792.0, line: 1 },
+        ];
+
+        let mut rects = original.clone();
+        CoordinateUnits::Normalized.apply(&mut rects, width, height);
+        for rect in &rects {
+            for value in [rect.x, rect.y, rect.x + rect.width, rect.y + rect.height] {
+                assert!((0.0..=1.0).contains(&value), "{:?}", rect);
+            }
+        }
+
+        // Scaling back by the page size returns the point rects
+        for (rect, point) in rects.iter().zip(&original) {
+            assert!((rect.x * width - point.x).abs() < 1e-3);
+            assert!((rect.y * height - point.y).abs() < 1e-3);
+            assert!((rect.width * width - point.width).abs() < 1e-3);
+            assert!((rect.height * height - point.height).abs() < 1e-3);
+        }
+
+        let mut points = original.clone();
+        CoordinateUnits::Points.apply(&mut points, width, height);
+        assert_eq!(points[0].x, 72.0);
+    }
+
+    #[test]
+    fn padding_grows_rects_but_stays_on_page() {
+        let mut rects = vec![
+            CharBbox { x: 72.0, y: 700.0, width: 100.0, height: 10.0, line: 0 },
+            // Touches the left and bottom page edges
+            CharBbox { x: 1.0, y: 0.5, width: 20.0, height: 10.0, line: 0 },
+        ];
+        pad_rects(&mut rects, 2.0, 612.0, 792.0);
+
+        assert_eq!((rects[0].x, rects[0].y), (70.0, 698.0));
+        assert_eq!((rects[0].width, rects[0].height), (104.0, 14.0));
+        assert_eq!((rects[1].x, rects[1].y), (0.0, 0.0));
+        assert_eq!((rects[1].width, rects[1].height), (23.0, 12.5));
+    }
+
+    #[test]
+    fn padding_keeps_tight_lines_apart() {
+        // Two lines 1pt apart; 3pt of padding would make them overlap
+        let mut rects = vec![
+            CharBbox { x: 72.0, y: 711.0, width: 100.0, height: 10.0, line: 0 },
+            CharBbox { x: 72.0, y: 700.0, width: 100.0, height: 10.0, line: 0 },
+        ];
+        pad_rects(&mut rects, 3.0, 612.0, 792.0);
+
+        assert_eq!(rects[0].x, 69.0, "nothing beside it on the line, so the full padding");
+        assert!(rects[1].y + rects[1].height <= rects[0].y, "{:?}", rects);
+    }
+
+    #[test]
+    fn padding_keeps_neighbouring_words_apart() {
+        // Two words 2pt apart on one line; 3pt of padding would make them overlap
+        let mut rects = vec![
+            CharBbox { x: 72.0, y: 700.0, width: 40.0, height: 10.0, line: 0 },
+            CharBbox { x: 114.0, y: 700.0, width: 40.0, height: 10.0, line: 0 },
+        ];
+        pad_rects(&mut rects, 3.0, 612.0, 792.0);
+
+        assert_eq!(rects[0].x + rects[0].width, 113.0);
+        assert_eq!(rects[1].x, 113.0);
+        // Both sides grow by the capped amount, like the top and bottom
+        assert_eq!(rects[0].x, 71.0);
+        assert_eq!(rects[1].height, 16.0, "vertical padding is unaffected");
+
+        let before = format!("{:?}", rects);
+        pad_rects(&mut rects, f32::NAN, 612.0, 792.0);
+        pad_rects(&mut rects, -1.0, 612.0, 792.0);
+        assert_eq!(format!("{:?}", rects), before);
+    }
+
+    #[test]
+    fn large_font_lines_are_headings() {
+        let lines = vec![
+            ("3.2 Gradient Descent".to_string(), 16.0),
+            ("Gradient descent updates each parameter against".to_string(), 10.0),
+            ("the slope of the loss, scaled by a learning rate.".to_string(), 10.0),
+            ("12".to_string(), 16.0),
+            ("Choosing the rate well matters in practice.".to_string(), 10.5),
+        ];
+
+        assert_eq!(detect_headings(&lines), vec!["3.2 Gradient Descent".to_string()]);
+    }
+
+    #[test]
+    fn filled_form_fields_become_searchable_chunks() {
+        let page = PageNumber::new(2);
+        let fields = vec![
+            ("Policy number".to_string(), "AX-2291-B".to_string()),
+            ("Claimant".to_string(), "Dana Whitfield".to_string()),
+            ("Notes".to_string(), "   ".to_string()),
+        ];
+
+        let chunks = form_field_chunks(page, fields);
+
+        assert_eq!(chunks.len(), 2, "blank fields are skipped");
+        let policy = chunks
+            .iter()
+            .find(|c| vb::fuzzy::keyword_score(&c.content, "AX-2291-B") == 1.0)
+            .expect("field value should match a keyword query");
+        assert_eq!(policy.page, page);
+        assert_eq!(policy.kind, ChunkKind::FormField("Policy number".to_string()));
+        assert!(vb::fuzzy::keyword_score(&chunks[1].content, "claimant whitfield") > 0.8);
+    }
+
+    #[test]
+    #[ignore = "needs a pdfium library; run with PDFIUM_PATH set"]
+    fn pdfium_reads_filled_form_fields() {
+        use lopdf::{dictionary, Object};
+
+        let mut pdf = TestPdf::new();
+        let page = pdf.text_page(&[(72, 760, "Insurance claim")]);
+        let field = |name: &str, value: &str, y: i64| {
+            dictionary! {
+                "Type" => "Annot",
+                "Subtype" => "Widget",
+                "FT" => "Tx",
+                "T" => Object::string_literal(name),
+                "V" => Object::string_literal(value),
+                "Rect" => vec![72.into(), y.into(), 300.into(), (y + 20).into()],
+                "P" => page,
+            }
+        };
+        let fields: Vec<Object> = vec![
+            pdf.doc.add_object(field("Policy number", "AX-2291-B", 700)).into(),
+            pdf.doc.add_object(field("Notes", "", 660)).into(),
+        ];
+        pdf.doc.get_dictionary_mut(page).unwrap().set("Annots", fields.clone());
+        let bytes = to_bytes(pdf.finish(dictionary! { "AcroForm" => dictionary! { "Fields" => fields } }));
+
+        let extras = extract_page_extras(&bytes, TableFormat::Off).unwrap();
+        let fields: Vec<(&ChunkKind, &str)> = extras[&PageNumber::new(1)]
+            .iter()
+            .filter(|c| matches!(c.kind, ChunkKind::FormField(_)))
+            .map(|c| (&c.kind, c.content.as_str()))
+            .collect();
+        assert_eq!(fields, [(&ChunkKind::FormField("Policy number".to_string()), "Policy number: AX-2291-B")]);
+    }
+
+    #[test]
+    fn annotation_comments_are_indexed_on_their_page() {
+        let page = PageNumber::new(6);
+        let annotations = vec![
+            AnnotationText {
+                comment: Some("Check this against the 2019 audit".to_string()),
+                marked: Some("Revenue was restated for the prior year.".to_string()),
+            },
+            AnnotationText { comment: Some("Typo in table header".to_string()), marked: None },
+            AnnotationText { comment: Some("  ".to_string()), marked: None },
+        ];
+
+        let chunks = annotation_chunks(page, annotations);
+
+        assert_eq!(chunks.len(), 2, "annotations without text are skipped");
+        assert!(chunks.iter().all(|c| c.page == page && c.kind == ChunkKind::Annotation));
+        // The reviewer's note is searchable, and so is the passage it marks
+        assert_eq!(vb::fuzzy::keyword_score(&chunks[0].content, "2019 audit"), 1.0);
+        assert_eq!(vb::fuzzy::keyword_score(&chunks[0].content, "revenue was restated"), 1.0);
+        assert_eq!(chunks[1].content, "Typo in table header");
+    }
+
+    #[test]
+    #[ignore = "needs a pdfium library; run with PDFIUM_PATH set"]
+    fn pdfium_reads_annotation_comments_and_marked_text() {
+        use lopdf::{dictionary, Object};
+
+        let marked = "Revenue was restated for the prior year.";
+        let mut pdf = TestPdf::new();
+        let page = pdf.text_page(&[(72, 700, marked), (72, 600, "Costs were flat.")]);
+        let note = dictionary! {
+            "Type" => "Annot",
+            "Subtype" => "Text",
+            "Rect" => vec![20.into(), 600.into(), 40.into(), 620.into()],
+            "Contents" => Object::string_literal("Typo in table header"),
+        };
+        // Covers the first line: upper-left, upper-right, lower-left, lower-right
+        let (left, right, bottom, top) = (70, 300, 696, 712);
+        let highlight = dictionary! {
+            "Type" => "Annot",
+            "Subtype" => "Highlight",
+            "Rect" => vec![left.into(), bottom.into(), right.into(), top.into()],
+            "QuadPoints" => vec![
+                left.into(), top.into(), right.into(), top.into(),
+                left.into(), bottom.into(), right.into(), bottom.into(),
+            ],
+            "Contents" => Object::string_literal("Check this against the 2019 audit"),
+        };
+        let annots: Vec<Object> = vec![pdf.doc.add_object(note).into(), pdf.doc.add_object(highlight).into()];
+        pdf.doc.get_dictionary_mut(page).unwrap().set("Annots", annots);
+        let bytes = to_bytes(pdf.finish(lopdf::Dictionary::new()));
+
+        let extras = extract_page_extras(&bytes, TableFormat::Off).unwrap();
+        let annotations: Vec<&str> = extras[&PageNumber::new(1)]
+            .iter()
+            .filter(|c| c.kind == ChunkKind::Annotation)
+            .map(|c| c.content.as_str())
+            .collect();
+        assert_eq!(annotations.len(), 2, "{:?}", annotations);
+        assert!(annotations.contains(&"Typo in table header"));
+        // The highlight's comment travels with the passage it covers
+        let comment = annotations.iter().find(|c| c.starts_with("Check this against the 2019 audit")).unwrap();
+        assert!(comment.contains("Revenue was restated"), "{:?}", comment);
+        assert!(!comment.contains("Costs were flat"), "{:?}", comment);
+    }
+
+    #[test]
+    fn needle_straddling_paragraph_break_matches_as_one_span() {
+        // pdfium's view of the page: two lines, a blank line, then the next paragraph
+        let page_text = "Weights are updated after every batch.\r\n\r\nThe learning rate controls\r\nthe step size.";
+        let entries = join_break_artifacts(page_text.chars().enumerate());
+        // The stored chunk kept lopdf's join, including a hyphenated wrap
+        let needle: Vec<char> = join_break_artifacts("every batch.\n\nThe learn-\ning rate".chars().enumerate())
+            .into_iter()
+            .map(|(_, c)| c)
+            .collect();
+
+        let matches = vb::fuzzy::fuzzy_search_with_config(&entries, &needle, &Default::default());
+        assert_eq!(matches.len(), 1);
+        let (start, end, _) = matches[0];
+
+        // One glyph box per page char: line 0 up to the first break, line 1 after it
+        let break_at = page_text.find('\r').unwrap();
+        let boxes = entries[start..end].iter().map(|&(idx, c)| {
+            let line = if idx < break_at { 0.0 } else { 20.0 };
+            (!c.is_whitespace()).then_some(CharBbox { x: idx as f32, y: line, width: 1.0, height: 10.0, line: 0 })
+        });
+        let rects = merge_char_boxes(boxes, LineTolerance::default());
+
+        assert!(rects.len() > 1);
+        assert!(rects.iter().any(|r| r.y == 0.0) && rects.iter().any(|r| r.y == 20.0));
+    }
+
+    #[test]
+    fn whitespace_breaks_runs() {
+        let boxes = vec![glyph(0.0, 0.0, 10.0), None, glyph(5.0, 0.0, 10.0)];
+        assert_eq!(merge_char_boxes(boxes, LineTolerance::default()).len(), 2);
+    }
+
+    #[test]
+    fn rects_are_numbered_by_line_from_the_top() {
+        // Three lines 14pt apart with a little baseline jitter
+        let page: Vec<CharBbox> = [700.0, 700.4, 699.8, 686.0, 686.3, 672.0, 671.7]
+            .into_iter()
+            .enumerate()
+            .filter_map(|(i, y)| glyph(i as f32 * 6.0, y, 10.0))
+            .collect();
+        let bands = line_bands(page, LineTolerance::default());
+        assert_eq!(bands.len(), 3, "{:?}", bands);
+
+        let mut rects: Vec<CharBbox> = [686.3, 700.4, 671.7]
+            .into_iter()
+            .filter_map(|y| glyph(72.0, y, 10.0))
+            .collect();
+        assign_lines(&mut rects, &bands);
+        let lines: Vec<usize> = rects.iter().map(|r| r.line).collect();
+        assert_eq!(lines, vec![1, 0, 2]);
+    }
+
+    #[test]
+    fn context_sentences_widen_the_snap_on_each_side() {
+        let page = "One. Two is here! Three asks why? Four has the hit. Five... Six.";
+        let entries: Vec<(usize, char)> = page.chars().enumerate().collect();
+        let hit = page.find("the hit").unwrap();
+        let snapped = |context| {
+            let (start, end) = snap_to_sentence_boundaries(&entries, hit, hit + 7, context);
+            page[start..end].to_string()
+        };
+
+        assert_eq!(snapped(0), "Four has the hit.");
+        assert_eq!(snapped(1), "Three asks why? Four has the hit. Five...");
+        assert_eq!(snapped(2), "Two is here! Three asks why? Four has the hit. Five... Six.");
+        // Running out of sentences stops at the page edges
+        assert_eq!(snapped(5), page);
+
+        // Terminator runs and the quotes or brackets after them stay with their sentence
+        let page = "He asked \"why?!\" Then [it stopped.] Done.";
+        let entries: Vec<(usize, char)> = page.chars().enumerate().collect();
+        let snap = |needle: &str| {
+            let hit = page.find(needle).unwrap();
+            let (start, end) = snap_to_sentence_boundaries(&entries, hit, hit + needle.len(), 0);
+            page[start..end].to_string()
+        };
+        assert_eq!(snap("asked"), "He asked \"why?!\"");
+        assert_eq!(snap("Then"), "Then [it stopped.]");
+        assert_eq!(snap("Done"), "Done.");
+    }
+}
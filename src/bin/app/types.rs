@@ -1,31 +1,224 @@
 // src/types.rs
 
+use std::collections::BTreeMap;
 use std::collections::HashMap;
 use std::collections::HashSet;
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use std::time::Instant;
+use tokio::sync::{RwLock, Semaphore};
+use tokio::task::JoinHandle;
 use qdrant_client::Qdrant;
 use serde::{Deserialize, Serialize};
+use vb::cache::TtlCache;
+use vb::PageNumber;
+
+use crate::config::AppConfig;
+use crate::pdf::{CoordinateOrigin, CoordinateUnits};
 
 // --- Type aliases for shared state maps ---
 pub type IdToFilenameMap = Arc<RwLock<HashMap<String, String>>>;
 pub type IdToBytesMap = Arc<RwLock<HashMap<String, Vec<u8>>>>;
 pub type IdReadyMap = Arc<RwLock<HashSet<String>>>;
+// Last page (1-indexed) whose vectors are committed to Qdrant
+pub type IdProgressMap = Arc<RwLock<HashMap<String, u32>>>;
+// Upload time (Unix seconds), kept so a reindex stores the original timestamp
+pub type IdUploadedMap = Arc<RwLock<HashMap<String, i64>>>;
+// User-chosen display name; the Qdrant filename key never changes on rename
+pub type IdLabelMap = Arc<RwLock<HashMap<String, String>>>;
+// Language whose embedding model indexed the document; absent means the default model
+pub type IdLangMap = Arc<RwLock<HashMap<String, String>>>;
+// Idempotency-Key header -> (upload id, when the key was first seen)
+pub type IdempotencyMap = Arc<RwLock<HashMap<String, (String, Instant)>>>;
+// Resumable uploads still receiving parts
+pub type PartialUploadMap = Arc<RwLock<HashMap<String, PartialUpload>>>;
+// Unix seconds after which the sweeper deletes the document
+pub type IdExpiryMap = Arc<RwLock<HashMap<String, i64>>>;
+// Printed page labels (`iv`, `12`) by page index, for PDFs that define them
+pub type IdPageLabelsMap = Arc<RwLock<HashMap<String, Vec<String>>>>;
+// Word -> occurrences across a document's indexed chunks, for /api/suggest
+pub type IdTermsMap = Arc<RwLock<HashMap<String, HashMap<String, u32>>>>;
+// Background indexing tasks, awaited on shutdown so documents aren't left half-indexed
+pub type IndexJobMap = Arc<RwLock<HashMap<String, JoinHandle<()>>>>;
+// (document id, normalized queries and options) -> the response they produced
+pub type SearchCache = Arc<RwLock<TtlCache<(String, String), HighlightResponse>>>;
+
+/// A resumable upload between `/upload/init` and `/upload/:id/complete`.
+pub struct PartialUpload {
+    pub filename: String,
+    pub lang: Option<String>,
+    pub ttl: Option<u64>,
+    pub chunk_size: Option<usize>,
+    /// Part number -> bytes; a re-sent part replaces the earlier copy.
+    pub parts: BTreeMap<u32, Vec<u8>>,
+    pub started: Instant,
+}
 
 // --- App state shared across handlers ---
 #[derive(Clone)]
 pub struct AppState {
     pub id_map: IdToFilenameMap,
+    pub name_map: IdToFilenameMap,
     pub bytes_map: IdToBytesMap,
     pub qdrant: Arc<Qdrant>,
     pub ready_set: IdReadyMap,
+    pub progress_map: IdProgressMap,
+    pub uploaded_map: IdUploadedMap,
+    pub idempotency_map: IdempotencyMap,
+    pub label_map: IdLabelMap,
+    pub lang_map: IdLangMap,
+    pub partial_uploads: PartialUploadMap,
+    pub index_jobs: IndexJobMap,
+    pub expiry_map: IdExpiryMap,
+    pub terms_map: IdTermsMap,
+    pub page_label_map: IdPageLabelsMap,
+    pub search_cache: SearchCache,
+    /// One permit per embedding batch allowed to run at once.
+    pub embed_permits: Arc<Semaphore>,
+    pub config: Arc<AppConfig>,
+}
+
+#[cfg(test)]
+impl AppState {
+    /// Empty state; the Qdrant client connects lazily, so tests that never
+    /// reach Qdrant don't need a server.
+    pub fn for_tests() -> Self {
+        AppState {
+            id_map: Arc::new(RwLock::new(HashMap::new())),
+            name_map: Arc::new(RwLock::new(HashMap::new())),
+            bytes_map: Arc::new(RwLock::new(HashMap::new())),
+            qdrant: Arc::new(Qdrant::from_url("http://localhost:6334").build().unwrap()),
+            ready_set: Arc::new(RwLock::new(HashSet::new())),
+            progress_map: Arc::new(RwLock::new(HashMap::new())),
+            uploaded_map: Arc::new(RwLock::new(HashMap::new())),
+            idempotency_map: Arc::new(RwLock::new(HashMap::new())),
+            label_map: Arc::new(RwLock::new(HashMap::new())),
+            lang_map: Arc::new(RwLock::new(HashMap::new())),
+            partial_uploads: Arc::new(RwLock::new(HashMap::new())),
+            index_jobs: Arc::new(RwLock::new(HashMap::new())),
+            expiry_map: Arc::new(RwLock::new(HashMap::new())),
+            terms_map: Arc::new(RwLock::new(HashMap::new())),
+            page_label_map: Arc::new(RwLock::new(HashMap::new())),
+            search_cache: Arc::new(RwLock::new(TtlCache::new(16, std::time::Duration::from_secs(60)))),
+            embed_permits: Arc::new(Semaphore::new(1)),
+            config: Arc::new(AppConfig::default()),
+        }
+    }
 }
 
 // --- Request types ---
 #[derive(Deserialize)]
 pub struct SearchWithBboxQuery {
     pub id: String,
+    // Repeat `q` to run several sub-queries and merge their highlights
+    #[serde(default)]
+    pub q: Vec<String>,
+    /// Cut returned chunk text to about this many characters; highlights use the full text.
+    pub max_text_len: Option<usize>,
+    /// Only search documents uploaded at or after this Unix timestamp.
+    pub uploaded_after: Option<i64>,
+    /// Only search documents uploaded at or before this Unix timestamp.
+    pub uploaded_before: Option<i64>,
+    /// Return the chunks around each hit on its page as `before`/`after`.
+    #[serde(default)]
+    pub neighbors: bool,
+    /// `bottom-left` (PDF space, the default) or `top-left` for screen-style rects.
+    #[serde(default)]
+    pub origin: CoordinateOrigin,
+    /// `points` (the default) or `normalized` for fractions of the page size.
+    #[serde(default)]
+    pub units: CoordinateUnits,
+    /// Grow each rect by this many points (not negative) on every side, kept
+    /// inside the page and short of the rects beside it.
+    pub padding: Option<f32>,
+    /// Repeat to rank only these `point_id`s from an earlier response.
+    #[serde(default)]
+    pub within_ids: Vec<String>,
+    /// Must be the document's upload language, whose model embeds the query.
+    pub lang: Option<String>,
+    /// MMR lambda in [0, 1]: lower returns more varied passages, 1 is plain top-k.
+    pub diversity: Option<f32>,
+    /// Match the query and chunk text without folding case, so "US" isn't "us".
+    #[serde(default)]
+    pub case_sensitive: bool,
+    /// Minimum similarity for a hit to be returned at all (retrieval recall).
+    pub retrieve_score: Option<f32>,
+    /// Minimum fuzzy score, 0–1, for a hit's text to be highlighted (highlight precision).
+    pub highlight_score: Option<f32>,
+}
+
+#[derive(Deserialize, Default)]
+pub struct UploadQuery {
+    /// Index with the embedding model registered for this language.
+    pub lang: Option<String>,
+    /// Delete the document (vectors and file) this many seconds after upload.
+    pub ttl: Option<u64>,
+    /// Characters per chunk, instead of the configured size.
+    pub chunk_size: Option<usize>,
+}
+
+#[derive(Deserialize)]
+pub struct SuggestQuery {
+    pub id: String,
+    pub prefix: String,
+    /// Most suggestions returned; defaults to 10.
+    pub limit: Option<usize>,
+}
+
+#[derive(Deserialize)]
+pub struct LibrarySearchQuery {
     pub q: String,
+    /// Multiply this document's scores by the configured boost, without
+    /// excluding the others.
+    pub boost_id: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct LiveSearchQuery {
+    pub id: String,
+}
+
+#[derive(Deserialize)]
+pub struct ReindexQuery {
+    pub chunk_size: Option<usize>,
+    // Continue after the last committed page instead of starting over
+    #[serde(default)]
+    pub resume: bool,
+}
+
+#[derive(Deserialize)]
+pub struct SelectionRequest {
+    pub id: String,
+    pub page: PageNumber,
+    // Char offsets into pdfium's page text, end exclusive
+    pub start: usize,
+    pub end: usize,
+    #[serde(default)]
+    pub origin: CoordinateOrigin,
+}
+
+#[derive(Deserialize)]
+pub struct InitUploadRequest {
+    pub filename: String,
+}
+
+#[derive(Deserialize)]
+pub struct CompareRequest {
+    pub a: String,
+    pub b: String,
+    // Minimum cosine similarity for a pair to be returned, whatever the collection's metric
+    pub threshold: Option<f32>,
+}
+
+#[derive(Deserialize)]
+pub struct RenameRequest {
+    pub label: String,
+}
+
+#[derive(Deserialize)]
+pub struct HealthQuery {
+    // Also load the embedding model and embed a probe string
+    #[serde(default)]
+    pub deep: bool,
 }
 
 // --- Response types ---
@@ -35,21 +228,195 @@ pub struct UploadResponse {
 }
 
 #[derive(Serialize)]
-pub struct SearchResult {
-    pub page: i64,
+pub struct ImportResponse {
+    pub id: String,
+    /// Points upserted into Qdrant.
+    pub imported: usize,
+}
+
+#[derive(Serialize)]
+pub struct UploadPartsResponse {
+    pub id: String,
+    /// Part numbers received so far, ascending.
+    pub received: Vec<u32>,
+}
+
+#[derive(Serialize)]
+pub struct SelectionResponse {
+    pub page: PageNumber,
+    /// The selected text, as used for the query.
     pub text: String,
+    /// Where the selection sits on `page`.
+    pub rects: Vec<CharBbox>,
+    pub page_width: f32,
+    pub page_height: f32,
+    pub highlights: Vec<PageHighlight>,
+    pub warnings: Vec<PageWarning>,
 }
 
 #[derive(Serialize)]
+pub struct Passage {
+    pub page: PageNumber,
+    pub text: String,
+}
+
+/// A passage of document `a` and its closest match in document `b`.
+#[derive(Serialize)]
+pub struct PassagePair {
+    pub score: f32,
+    pub a: Passage,
+    pub b: Passage,
+}
+
+#[derive(Serialize)]
+pub struct DocumentResponse {
+    pub id: String,
+    pub label: String,
+    /// Name the file was uploaded under.
+    pub filename: String,
+}
+
+/// What extraction made of a document, for spotting garbled or missing text.
+#[derive(Serialize)]
+pub struct DocumentText {
+    pub id: String,
+    /// Text of each page as it was chunked, first page first.
+    pub pages: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct DocumentStats {
+    pub id: String,
+    /// Same values as `/api/ready`'s `status`.
+    pub status: &'static str,
+    /// Chunks stored in Qdrant; 0 until the document has been indexed.
+    pub chunks: u64,
+}
+
+/// Highlights for a search, plus a warning for each page that couldn't be highlighted.
+#[derive(Serialize, Default, Clone)]
+pub struct HighlightResponse {
+    pub highlights: Vec<PageHighlight>,
+    pub warnings: Vec<PageWarning>,
+    /// Units of every rect in `highlights`.
+    pub units: CoordinateUnits,
+}
+
+#[derive(Serialize)]
+pub struct SuggestResponse {
+    pub id: String,
+    /// Most frequent first.
+    pub suggestions: Vec<Suggestion>,
+}
+
+#[derive(Serialize)]
+pub struct LibrarySearchResponse {
+    /// Best first, after boosting.
+    pub results: Vec<LibraryHit>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct LibraryHit {
+    /// The document the chunk belongs to.
+    pub id: String,
+    pub page: PageNumber,
+    pub text: String,
+    pub score: f32,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Suggestion {
+    pub term: String,
+    /// Occurrences in the document's indexed chunks.
+    pub count: u32,
+}
+
+#[derive(Serialize, Clone)]
+pub struct PageWarning {
+    pub page: PageNumber,
+    pub message: String,
+}
+
+#[derive(Serialize)]
+pub struct ReindexResponse {
+    pub id: String,
+    pub unique_filename: String,
+}
+
+#[derive(Serialize)]
+pub struct SearchResult {
+    /// Qdrant id of the matched chunk.
+    pub point_id: Option<String>,
+    pub page: PageNumber,
+    pub text: String,
+    pub query: String,
+    pub score: f32,
+    /// Stored chunk kind (`body`, `heading`, `caption`, ...); older points have none.
+    pub kind: Option<String>,
+    /// Text of the parent chunk `text` was cut from, if it was stored with one.
+    pub context: Option<String>,
+    /// Position among the page's body chunks; headings and extras have none.
+    pub seq: Option<u32>,
+    pub before: Option<String>,
+    pub after: Option<String>,
+    /// `text`, HTML-escaped, with the query's best match wrapped in the configured markers.
+    pub html_snippet: Option<String>,
+}
+
+#[derive(Serialize, Clone, Debug)]
 pub struct CharBbox {
     pub x: f32,
     pub y: f32,
     pub width: f32,
     pub height: f32,
+    /// 0-based line on the page, counted from the top.
+    pub line: usize,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Clone)]
 pub struct PageHighlight {
-    pub page: usize,
-    pub rects: Vec<CharBbox>,
-}
\ No newline at end of file
+    /// Physical page, counted from the first page of the file.
+    pub page: PageNumber,
+    /// The number printed on that page (`xii`, `A-3`), when the PDF labels its pages.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub page_label: Option<String>,
+    /// Chunk this highlight came from; pass back as `within_ids` to refine a search.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub point_id: Option<String>,
+    /// Just the fuzzy match, for scrolling to the hit.
+    pub exact_rects: Vec<CharBbox>,
+    /// The match widened to whole sentences, for shading. Also sent as
+    /// `rects`, the name it had before `exact_rects` was added.
+    #[serde(flatten, serialize_with = "sentence_rects_and_alias")]
+    pub sentence_rects: Vec<CharBbox>,
+    /// Page size in points, for scaling the rects to the rendered page.
+    pub page_width: f32,
+    pub page_height: f32,
+    pub query: String,
+    /// Kind of the matched chunk. Captions come from figure alt text, which isn't
+    /// on the page, so their rects are empty.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+    /// Surrounding passage for the matched chunk; the rects still cover only the chunk.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub context: Option<String>,
+    /// Body chunks stored just before/after the match on the same page, when requested.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub before: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub after: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub html_snippet: Option<String>,
+    /// Found only by the retry at a relaxed fuzzy threshold, so the rects may
+    /// be off; worth drawing more faintly.
+    pub low_confidence: bool,
+}
+
+fn sentence_rects_and_alias<S: serde::Serializer>(rects: &[CharBbox], serializer: S) -> Result<S::Ok, S::Error> {
+    use serde::ser::SerializeMap;
+
+    let mut map = serializer.serialize_map(Some(2))?;
+    map.serialize_entry("sentence_rects", rects)?;
+    map.serialize_entry("rects", rects)?;
+    map.end()
+}
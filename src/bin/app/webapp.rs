@@ -104,13 +104,13 @@ async fn handle_upload(
     State(state): State<AppState>,
     mut multipart: Multipart,
 ) -> Result<impl IntoResponse, StatusCode> {
-    while let Some(field) = multipart.next_field().await.unwrap() {
+    while let Some(field) = multipart.next_field().await.map_err(|e| e.status())? {
         let id_map = state.id_map.clone();
         let bytes_map = state.bytes_map.clone();
         let qdrant = state.qdrant.clone();
-        let name = field.name().unwrap().to_string();
-        let filename = field.file_name().unwrap().to_string();
-        let data = field.bytes().await.unwrap();
+        let name = field.name().ok_or(StatusCode::BAD_REQUEST)?.to_string();
+        let filename = field.file_name().ok_or(StatusCode::BAD_REQUEST)?.to_string();
+        let data = field.bytes().await.map_err(|e| e.status())?;
 
         if name == "pdf" {
             println!("Received file: {} ({} bytes)", filename, data.len());
@@ -163,7 +163,7 @@ async fn process_file(filename: &str, pdf_data: Bytes, client: Arc<Qdrant>) -> R
     let chunks = chunk::extract_and_chunk(chunk::PdfSource::Bytes(pdf_data.to_vec()))?;
     let embedded_chunks = embed::get_embeddings(chunks)?;
     let unique_filename =
-        qdrant::store_embeddings(&client, "embedded_pdfs", filename, embedded_chunks).await?;
+        qdrant::store_embeddings(&client, "embedded_pdfs", filename, embedded_chunks, qdrant::Precision::default()).await?;
 
     println!("File processed successfully!");
 
@@ -141,7 +141,7 @@ async fn process_file(file_path: &str) -> Result<(), Box<dyn std::error::Error>>
     let chunks = chunk::extract_and_chunk(chunk::PdfSource::Path(file_path.to_string()))?;
     let embedded_chunks = embed::get_embeddings(chunks)?;
     let client = qdrant::setup_qdrant().await?;
-    let response = qdrant::store_embeddings(&client, "repl", file_path, embedded_chunks).await?;
+    let response = qdrant::store_embeddings(&client, "repl", file_path, embedded_chunks, qdrant::Precision::default()).await?;
 
     println!("File processed successfully!");
     dbg!(response);
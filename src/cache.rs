@@ -0,0 +1,230 @@
+//! Bounded embedding cache keyed by a hash of the embedded text, so
+//! re-uploads and repeated queries skip the model, and a small expiring cache
+//! for whole search results.
+
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use crate::embed::fnv1a;
+
+/// Counters reported by `/admin/cache-stats`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub size: usize,
+    pub capacity: usize,
+}
+
+/// Least-recently-used map from text to embedding. Once `capacity` entries
+/// are held, each insert evicts the entry that was used longest ago. A
+/// capacity of 0 turns caching off. Entries keep their text, so two texts
+/// whose hashes collide never get each other's vector.
+#[derive(Debug, Default)]
+pub struct EmbeddingCache {
+    capacity: usize,
+    // text hash -> (text, embedding, tick of last use)
+    entries: HashMap<u64, (String, Vec<f32>, u64)>,
+    // tick of last use -> text hash, oldest first
+    recency: BTreeMap<u64, u64>,
+    tick: u64,
+    hits: u64,
+    misses: u64,
+}
+
+impl EmbeddingCache {
+    pub fn new(capacity: usize) -> Self {
+        EmbeddingCache { capacity, ..Default::default() }
+    }
+
+    /// The cached embedding for `text`, marking it as just used.
+    pub fn get(&mut self, text: &str) -> Option<Vec<f32>> {
+        let key = fnv1a(text.as_bytes());
+        let tick = self.next_tick();
+        let Some((_, vector, last_used)) = self.entries.get_mut(&key).filter(|(cached, _, _)| cached == text) else {
+            self.misses += 1;
+            return None;
+        };
+        self.recency.remove(last_used);
+        self.recency.insert(tick, key);
+        *last_used = tick;
+        self.hits += 1;
+        Some(vector.clone())
+    }
+
+    pub fn insert(&mut self, text: &str, vector: Vec<f32>) {
+        if self.capacity == 0 {
+            return;
+        }
+        let key = fnv1a(text.as_bytes());
+        let tick = self.next_tick();
+        // A colliding text takes the slot over
+        if let Some((_, _, last_used)) = self.entries.insert(key, (text.to_string(), vector, tick)) {
+            self.recency.remove(&last_used);
+        }
+        self.recency.insert(tick, key);
+
+        while self.entries.len() > self.capacity {
+            let Some((_, oldest)) = self.recency.pop_first() else { break };
+            self.entries.remove(&oldest);
+        }
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits,
+            misses: self.misses,
+            size: self.entries.len(),
+            capacity: self.capacity,
+        }
+    }
+
+    fn next_tick(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+}
+
+/// Least-recently-used map whose entries also expire `ttl` after they were
+/// inserted. A capacity of 0 turns caching off.
+#[derive(Debug)]
+pub struct TtlCache<K, V> {
+    capacity: usize,
+    ttl: Duration,
+    // key -> (value, inserted at, tick of last use)
+    entries: HashMap<K, (V, Instant, u64)>,
+    // tick of last use -> key, oldest first
+    recency: BTreeMap<u64, K>,
+    tick: u64,
+}
+
+impl<K: Eq + Hash + Clone, V: Clone> TtlCache<K, V> {
+    pub fn new(capacity: usize, ttl: Duration) -> Self {
+        TtlCache { capacity, ttl, entries: HashMap::new(), recency: BTreeMap::new(), tick: 0 }
+    }
+
+    /// The value for `key` if it's younger than the TTL at `now`, marking it
+    /// as just used. Expired entries are dropped on the way.
+    pub fn get(&mut self, key: &K, now: Instant) -> Option<V> {
+        let (_, inserted, _) = self.entries.get(key)?;
+        if now.saturating_duration_since(*inserted) >= self.ttl {
+            self.remove(key);
+            return None;
+        }
+        self.tick += 1;
+        let (value, _, last_used) = self.entries.get_mut(key)?;
+        self.recency.remove(last_used);
+        self.recency.insert(self.tick, key.clone());
+        *last_used = self.tick;
+        Some(value.clone())
+    }
+
+    pub fn insert(&mut self, key: K, value: V, now: Instant) {
+        if self.capacity == 0 {
+            return;
+        }
+        self.tick += 1;
+        if let Some((_, _, last_used)) = self.entries.insert(key.clone(), (value, now, self.tick)) {
+            self.recency.remove(&last_used);
+        }
+        self.recency.insert(self.tick, key);
+
+        while self.entries.len() > self.capacity {
+            let Some((_, oldest)) = self.recency.pop_first() else { break };
+            self.entries.remove(&oldest);
+        }
+    }
+
+    /// Drop every entry whose key fails `keep`.
+    pub fn retain(&mut self, mut keep: impl FnMut(&K) -> bool) {
+        let stale: Vec<K> = self.entries.keys().filter(|key| !keep(key)).cloned().collect();
+        for key in &stale {
+            self.remove(key);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn remove(&mut self, key: &K) {
+        if let Some((_, _, last_used)) = self.entries.remove(key) {
+            self.recency.remove(&last_used);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filling_past_capacity_evicts_least_recently_used() {
+        let mut cache = EmbeddingCache::new(3);
+        cache.insert("convolution", vec![1.0]);
+        cache.insert("pooling", vec![2.0]);
+        cache.insert("dropout", vec![3.0]);
+
+        // Touching the oldest entry keeps it; "pooling" is now least recent
+        assert_eq!(cache.get("convolution"), Some(vec![1.0]));
+        cache.insert("batch norm", vec![4.0]);
+        cache.insert("attention", vec![5.0]);
+
+        assert_eq!(cache.get("pooling"), None);
+        assert_eq!(cache.get("dropout"), None);
+        assert_eq!(cache.get("convolution"), Some(vec![1.0]));
+        assert_eq!(cache.get("attention"), Some(vec![5.0]));
+        assert_eq!(
+            cache.stats(),
+            CacheStats { hits: 3, misses: 2, size: 3, capacity: 3 }
+        );
+    }
+
+    #[test]
+    fn colliding_hash_is_a_miss() {
+        let mut cache = EmbeddingCache::new(3);
+        cache.insert("dropout", vec![1.0]);
+        // Stand in for another text whose hash collides with "momentum"
+        let key = fnv1a(b"momentum");
+        cache.entries.insert(key, ("a colliding text".to_string(), vec![9.0], 0));
+
+        assert_eq!(cache.get("momentum"), None);
+        cache.insert("momentum", vec![2.0]);
+        assert_eq!(cache.get("momentum"), Some(vec![2.0]));
+        assert_eq!(cache.get("a colliding text"), None);
+        assert_eq!(cache.get("dropout"), Some(vec![1.0]));
+    }
+
+    #[test]
+    fn zero_capacity_caches_nothing() {
+        let mut cache = EmbeddingCache::new(0);
+        cache.insert("gradient descent", vec![0.5]);
+        assert_eq!(cache.get("gradient descent"), None);
+        assert_eq!(cache.stats().size, 0);
+    }
+
+    #[test]
+    fn entries_expire_after_ttl_and_evict_past_capacity() {
+        let start = Instant::now();
+        let mut cache = TtlCache::new(2, Duration::from_secs(60));
+        cache.insert("gradient", 1, start);
+        cache.insert("descent", 2, start);
+        assert_eq!(cache.get(&"gradient", start + Duration::from_secs(59)), Some(1));
+
+        // "descent" is least recently used, so it makes room
+        cache.insert("momentum", 3, start);
+        assert_eq!(cache.get(&"descent", start), None);
+
+        assert_eq!(cache.get(&"gradient", start + Duration::from_secs(60)), None);
+        assert_eq!(cache.len(), 1);
+        cache.retain(|key| *key != "momentum");
+        assert!(cache.is_empty());
+    }
+}
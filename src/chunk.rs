@@ -1,15 +1,79 @@
-use crate::extract::Page;
+use crate::embed;
+use crate::page::PageNumber;
+use crate::extract::{clean_text, ControlChars, Page};
+use crate::layers::{self, LayerSelection};
 use anyhow::Result;
-use lopdf::Document;
+use lopdf::{Dictionary, Document, Object, ObjectId};
 use regex::Regex;
-use text_splitter::TextSplitter;
+use std::collections::HashMap;
+use std::ops::Range;
+use text_splitter::{ChunkConfig as SplitterConfig, ChunkSizer, TextSplitter};
 use unicode_segmentation::UnicodeSegmentation;
 use rayon::prelude::*;
 
 #[derive(Debug, Clone)]
 pub struct Chunk {
     pub content: String,
-    pub page: u16,
+    /// Always 1-indexed; `PageNumber::index` gives the pdfium position.
+    pub page: PageNumber,
+    pub kind: ChunkKind,
+    /// The larger span this chunk was cut from, when parent chunking is on.
+    pub parent: Option<ParentChunk>,
+}
+
+/// A wider span of page text returned as context when one of its small chunks matches.
+///
+/// Only the small chunks are embedded and highlighted; the parent rides along
+/// in their payload so a hit can show the surrounding passage.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParentChunk {
+    /// Unique within a document: `<page>-<index on page>`.
+    pub id: String,
+    pub content: String,
+}
+
+/// Where on the page a chunk's text came from.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum ChunkKind {
+    #[default]
+    Body,
+    /// Section heading; boosted at query time.
+    Heading,
+    /// Value of a filled-in form field, with the field's name.
+    FormField(String),
+    /// Comment or highlighted passage from a PDF annotation.
+    Annotation,
+    /// Alt text of a tagged figure, formula or table; not part of the page text.
+    Caption,
+    /// A row (or whole table) rebuilt from cell positions, so columns stay together.
+    Table,
+}
+
+impl ChunkKind {
+    /// Name stored in the `kind` payload field.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChunkKind::Body => "body",
+            ChunkKind::Heading => "heading",
+            ChunkKind::FormField(_) => "form_field",
+            ChunkKind::Annotation => "annotation",
+            ChunkKind::Caption => "caption",
+            ChunkKind::Table => "table",
+        }
+    }
+
+    /// Inverse of [`as_str`](Self::as_str); a form field takes its name from `field`.
+    pub fn parse(name: &str, field: Option<&str>) -> Option<Self> {
+        match name {
+            "body" => Some(ChunkKind::Body),
+            "heading" => Some(ChunkKind::Heading),
+            "form_field" => Some(ChunkKind::FormField(field?.to_string())),
+            "annotation" => Some(ChunkKind::Annotation),
+            "caption" => Some(ChunkKind::Caption),
+            "table" => Some(ChunkKind::Table),
+            _ => None,
+        }
+    }
 }
 
 pub enum PdfSource {
@@ -17,53 +81,349 @@ pub enum PdfSource {
     Bytes(Vec<u8>),
 }
 
+/// Where chunk boundaries may fall.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ChunkBoundary {
+    /// Fill each chunk up to the size budget wherever the splitter finds room.
+    #[default]
+    Size,
+    /// Keep paragraphs (blank-line separated) whole, packing consecutive ones
+    /// into a chunk while they fit; only oversized paragraphs are split.
+    Paragraph,
+}
+
+impl ChunkBoundary {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "size" => Some(ChunkBoundary::Size),
+            "paragraph" => Some(ChunkBoundary::Paragraph),
+            _ => None,
+        }
+    }
+}
+
+/// Tunables for `extract_and_chunk_with_config`.
+#[derive(Debug, Clone)]
+pub struct ChunkConfig {
+    /// Maximum characters per chunk handed to the text splitter.
+    pub max_chars: usize,
+    /// Hard cap on tokens per chunk so nothing gets truncated by the model.
+    pub max_tokens: usize,
+    /// When set, size chunks by model tokens instead of `max_chars`.
+    pub target_tokens: Option<Range<usize>>,
+    /// Chunks shorter than this (after trimming) are dropped before embedding.
+    pub min_chunk_chars: usize,
+    /// When set, pages are first cut into parents of this many characters and
+    /// each parent is chunked as usual, so hits can return the wider passage.
+    pub parent_chars: Option<usize>,
+    /// Which PDF layers (optional content groups) contribute text.
+    pub layers: LayerSelection,
+    pub boundary: ChunkBoundary,
+    /// Cleanup of form feeds and other control characters in page text.
+    pub control_chars: ControlChars,
+    /// Count tokens with this language's model; `None` uses the default model.
+    pub lang: Option<String>,
+}
+
+impl Default for ChunkConfig {
+    fn default() -> Self {
+        ChunkConfig {
+            max_chars: 500,
+            max_tokens: embed::MAX_TOKENS,
+            target_tokens: None,
+            min_chunk_chars: 20,
+            parent_chars: None,
+            layers: LayerSelection::default(),
+            boundary: ChunkBoundary::default(),
+            control_chars: ControlChars::default(),
+            lang: None,
+        }
+    }
+}
+
 use rayon::prelude::*;
 
 pub fn extract_and_chunk(pdf_source: PdfSource) -> Result<Vec<Chunk>> {
-    // Load the PDF
+    extract_and_chunk_with_config(pdf_source, &ChunkConfig::default())
+}
+
+pub fn extract_and_chunk_with_config(pdf_source: PdfSource, config: &ChunkConfig) -> Result<Vec<Chunk>> {
+    let mut doc = load_document(pdf_source)?;
+    layers::strip_excluded_layers(&mut doc, &config.layers)?;
+
+    // Surface a missing tokenizer here rather than as silently empty pages
+    embed::get_tokenizer_in(config.lang.as_deref())?;
+
+    // Parallel iteration over page numbers
+    let chunks: Vec<Chunk> = page_numbers(&doc)
+        .par_iter()
+        .flat_map(|&page_num| chunk_page(&doc, page_num, config).unwrap_or_default())
+        .collect();
+    Ok(chunks)
+}
+
+pub fn load_document(pdf_source: PdfSource) -> Result<Document> {
     let doc = match pdf_source {
         PdfSource::Path(path) => Document::load(path)?,
         PdfSource::Bytes(vec) => Document::load_mem(&vec)?,
     };
+    Ok(doc)
+}
 
-    let pages = doc.get_pages();
-    
-    // Collect page numbers into a Vec for parallel iteration
-    let page_numbers: Vec<u32> = pages.keys().copied().collect();
-    
-    let splitter = TextSplitter::new(500); // chunk size
+/// Page numbers (1-indexed) in document order.
+pub fn page_numbers(doc: &Document) -> Vec<u32> {
+    doc.get_pages().keys().copied().collect()
+}
 
-    // Parallel iteration over page numbers
-    let chunks: Vec<Chunk> = page_numbers
-        .par_iter()
-        .flat_map(|&page_num| {
-            // Extract text for this page
-            if let Ok(text) = doc.extract_text(&[page_num]) {
-                splitter
-                    .chunks(&text)
-                    .filter_map(|chunk| {
-                        if is_valid_chunk(chunk) {
-                            Some(Chunk {
-                                content: chunk.to_string(),
-                                page: page_num as u16,
-                            })
-                        } else {
-                            None
-                        }
-                    })
-                    .collect::<Vec<_>>()
+/// Whether any page has extractable text. Blank and zero-page PDFs don't.
+pub fn has_text(doc: &Document) -> bool {
+    page_numbers(doc)
+        .into_iter()
+        .any(|page| doc.extract_text(&[page]).is_ok_and(|text| !text.trim().is_empty()))
+}
+
+// Structure trees nest a handful of levels; anything deeper is malformed or cyclic
+const MAX_STRUCT_DEPTH: usize = 64;
+
+/// One caption chunk per structure element with alt text (`/Alt`), on the page
+/// the element is tagged with. Untagged PDFs have none, and elements past the
+/// last page a [`PageNumber`] can hold are skipped.
+pub fn caption_chunks(doc: &Document) -> Vec<Chunk> {
+    let pages: HashMap<ObjectId, u32> = doc.get_pages().into_iter().map(|(number, id)| (id, number)).collect();
+    let Some(root) = doc.catalog().ok().and_then(|catalog| catalog.get(b"StructTreeRoot").ok()) else {
+        return Vec::new();
+    };
+    let mut chunks = Vec::new();
+    collect_captions(doc, &pages, root, None, 0, &mut chunks);
+    chunks
+}
+
+// Depth-first over the element's kids; `page` is the nearest /Pg above
+fn collect_captions(
+    doc: &Document,
+    pages: &HashMap<ObjectId, u32>,
+    node: &Object,
+    page: Option<u32>,
+    depth: usize,
+    chunks: &mut Vec<Chunk>,
+) {
+    let Ok((_, node)) = doc.dereference(node) else { return };
+    if depth > MAX_STRUCT_DEPTH {
+        return;
+    }
+    match node {
+        Object::Array(kids) => {
+            for kid in kids {
+                collect_captions(doc, pages, kid, page, depth + 1, chunks);
+            }
+        }
+        Object::Dictionary(element) => {
+            let page = struct_page(pages, element).or(page);
+            let kids = element.get(b"K").ok();
+            let alt = element
+                .get(b"Alt")
+                .ok()
+                .and_then(|alt| doc.dereference(alt).ok())
+                .and_then(|(_, alt)| lopdf::decode_text_string(alt).ok())
+                .map(|alt| alt.trim().to_string())
+                .filter(|alt| !alt.is_empty());
+            // Figures often leave /Pg to their marked-content kids
+            let alt_page = page.or_else(|| kids.and_then(|kids| first_kid_page(doc, pages, kids, depth + 1)));
+            // Page numbers are stored as u16; a caption further in has nowhere to point
+            if let (Some(content), Some(Ok(number))) = (alt, alt_page.map(u16::try_from)) {
+                let page = PageNumber::new(number);
+                chunks.push(Chunk { content, page, kind: ChunkKind::Caption, parent: None });
+            }
+            if let Some(kids) = kids {
+                collect_captions(doc, pages, kids, page, depth + 1, chunks);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn struct_page(pages: &HashMap<ObjectId, u32>, element: &Dictionary) -> Option<u32> {
+    let id = element.get(b"Pg").ok()?.as_reference().ok()?;
+    pages.get(&id).copied()
+}
+
+fn first_kid_page(doc: &Document, pages: &HashMap<ObjectId, u32>, node: &Object, depth: usize) -> Option<u32> {
+    if depth > MAX_STRUCT_DEPTH {
+        return None;
+    }
+    match doc.dereference(node).ok()?.1 {
+        Object::Array(kids) => kids.iter().find_map(|kid| first_kid_page(doc, pages, kid, depth + 1)),
+        Object::Dictionary(element) => struct_page(pages, element).or_else(|| {
+            let kids = element.get(b"K").ok()?;
+            first_kid_page(doc, pages, kids, depth + 1)
+        }),
+        _ => None,
+    }
+}
+
+/// A page's text as it is chunked: lopdf's extraction with control
+/// characters cleaned up as `config` says.
+pub fn page_text(doc: &Document, page_num: u32, config: &ChunkConfig) -> Result<String> {
+    Ok(clean_text(&doc.extract_text(&[page_num])?, config.control_chars))
+}
+
+/// Extract and chunk a single page. `page_num` is 1-indexed.
+pub fn chunk_page(doc: &Document, page_num: u32, config: &ChunkConfig) -> Result<Vec<Chunk>> {
+    let text = page_text(doc, page_num, config)?;
+
+    // Shared with the token-limit guard so the tokenizer is only loaded once
+    let tokenizer = embed::get_tokenizer_in(config.lang.as_deref())?;
+    let count_tokens = |text: &str| embed::count_tokens(tokenizer, text);
+
+    let page = PageNumber::new(page_num as u16);
+    let chunk_text = |text: &str| match &config.target_tokens {
+        Some(target) => {
+            let splitter =
+                TextSplitter::new(SplitterConfig::new(target.clone()).with_sizer(tokenizer));
+            chunk_page_text(&splitter, text, page, config, &count_tokens)
+        }
+        None => {
+            let splitter = TextSplitter::new(config.max_chars);
+            chunk_page_text(&splitter, text, page, config, &count_tokens)
+        }
+    };
+
+    match config.parent_chars {
+        Some(parent_chars) => chunk_with_parents(&text, page, parent_chars, chunk_text),
+        None => chunk_text(&text),
+    }
+}
+
+// Cut the page into parents, chunk each one and link the small chunks back to it
+fn chunk_with_parents<F>(text: &str, page: PageNumber, parent_chars: usize, chunk_text: F) -> Result<Vec<Chunk>>
+where
+    F: Fn(&str) -> Result<Vec<Chunk>>,
+{
+    let mut chunks = Vec::new();
+    for (i, parent_text) in TextSplitter::new(parent_chars).chunks(text).enumerate() {
+        let parent = ParentChunk {
+            id: format!("{}-{}", page, i),
+            content: parent_text.trim().to_string(),
+        };
+        chunks.extend(chunk_text(parent_text)?.into_iter().map(|chunk| Chunk {
+            parent: Some(parent.clone()),
+            ..chunk
+        }));
+    }
+    Ok(chunks)
+}
+
+// Split one page of text and keep only the chunks worth embedding
+fn chunk_page_text<S, F>(
+    splitter: &TextSplitter<S>,
+    text: &str,
+    page: PageNumber,
+    config: &ChunkConfig,
+    count_tokens: &F,
+) -> Result<Vec<Chunk>>
+where
+    S: ChunkSizer,
+    F: Fn(&str) -> Result<usize>,
+{
+    let pieces: Vec<String> = match config.boundary {
+        ChunkBoundary::Size => splitter.chunks(text).map(String::from).collect(),
+        ChunkBoundary::Paragraph => paragraph_chunks(splitter, text),
+    };
+    let mut fitted = Vec::new();
+    for piece in &pieces {
+        fitted.extend(split_to_token_limit(piece, config.max_tokens, count_tokens)?);
+    }
+    let chunks = fitted
+        .into_iter()
+        .filter_map(|chunk| {
+            if is_valid_chunk(&chunk, config.min_chunk_chars) {
+                Some(Chunk {
+                    content: chunk,
+                    page,
+                    kind: ChunkKind::Body,
+                    parent: None,
+                })
             } else {
-                Vec::new()
+                None
             }
         })
         .collect();
     Ok(chunks)
 }
 
+// Pack whole paragraphs into chunks while they fit the splitter's budget;
+// a paragraph too big on its own is handed to the splitter.
+fn paragraph_chunks<S: ChunkSizer>(splitter: &TextSplitter<S>, text: &str) -> Vec<String> {
+    let fits = |s: &str| splitter.chunks(s).nth(1).is_none();
+    let paragraph_break = Regex::new(r"\n[^\S\n]*\n").unwrap();
+
+    let mut chunks = Vec::new();
+    let mut current = String::new();
+    for paragraph in paragraph_break.split(text).map(str::trim).filter(|p| !p.is_empty()) {
+        if !fits(paragraph) {
+            if !current.is_empty() {
+                chunks.push(std::mem::take(&mut current));
+            }
+            chunks.extend(splitter.chunks(paragraph).map(String::from));
+            continue;
+        }
+        if current.is_empty() {
+            current = paragraph.to_string();
+            continue;
+        }
+        let joined = format!("{}\n\n{}", current, paragraph);
+        if fits(&joined) {
+            current = joined;
+        } else {
+            chunks.push(std::mem::replace(&mut current, paragraph.to_string()));
+        }
+    }
+    if !current.is_empty() {
+        chunks.push(current);
+    }
+    chunks
+}
+
+/// Halve `text` at whitespace until every piece fits in `max_tokens`.
+///
+/// Character-based splitting can't see tokens, so a page of long or unusual
+/// words can still produce a chunk the model would truncate. A text the
+/// tokenizer can't encode is an error, not a fit.
+pub(crate) fn split_to_token_limit<F>(text: &str, max_tokens: usize, count_tokens: &F) -> Result<Vec<String>>
+where
+    F: Fn(&str) -> Result<usize>,
+{
+    let trimmed = text.trim();
+    if trimmed.chars().count() <= 1 || count_tokens(trimmed)? <= max_tokens {
+        return Ok(vec![trimmed.to_string()]);
+    }
+
+    // Prefer the whitespace closest to the middle, fall back to a raw char split
+    let mid = trimmed.len() / 2;
+    let split_at = trimmed
+        .char_indices()
+        .filter(|(_, c)| c.is_whitespace())
+        .map(|(i, _)| i)
+        .min_by_key(|&i| i.abs_diff(mid))
+        .unwrap_or_else(|| {
+            let mut i = mid;
+            while !trimmed.is_char_boundary(i) {
+                i += 1;
+            }
+            i
+        });
+
+    let (left, right) = trimmed.split_at(split_at);
+    let mut pieces = split_to_token_limit(left, max_tokens, count_tokens)?;
+    pieces.extend(split_to_token_limit(right, max_tokens, count_tokens)?);
+    pieces.retain(|p| !p.is_empty());
+    Ok(pieces)
+}
+
 // Optimized validation function
-fn is_valid_chunk(text: &str) -> bool {
+fn is_valid_chunk(text: &str, min_chars: usize) -> bool {
     let trimmed = text.trim();
-    if trimmed.len() < 50 { 
+    if trimmed.chars().count() < min_chars { 
         return false; 
     }
 
@@ -142,10 +502,12 @@ pub fn extract_and_chunk_(pdf_source: PdfSource) -> Result<Vec<Chunk>> {
             let chunk_texts: Vec<_> = splitter.chunks(&text).collect();
 
             for chunk in chunk_texts {
-                if is_valid_chunk(&chunk) {
+                if is_valid_chunk(&chunk, 50) {
                     chunks.push(Chunk {
                         content: chunk.to_string(),
-                        page: (page_num) as u16,
+                        page: PageNumber::new(page_num as u16),
+                        kind: ChunkKind::Body,
+                        parent: None,
                     });
                 }
             }
@@ -276,7 +638,9 @@ pub fn chunk_pages_with_splitter(pages: &[Page], max_chars: usize) -> Vec<Chunk>
         for chunk_str in chunk_texts {
             chunks.push(Chunk {
                 content: chunk_str.to_string(),
-                page: page.page_num + 1,
+                page: PageNumber::from_index(page.page_num),
+                kind: ChunkKind::Body,
+                parent: None,
             });
         }
     }
@@ -294,14 +658,16 @@ pub fn chunk_per_page(pages: &[Page]) -> Vec<Chunk> {
             }
             return_chunks.push(Chunk {
                 content: (chunk),
-                page: (page.page_num),
+                page: PageNumber::from_index(page.page_num),
+                kind: ChunkKind::Body,
+                parent: None,
             });
         }
     }
     return_chunks
 }
 
-pub fn remove_section_headers(text: &str) -> String {
+pub(crate) fn remove_section_headers(text: &str) -> String {
     let lines: Vec<&str> = text.lines().collect();
     let mut cleaned_lines = Vec::new();
 
@@ -384,7 +750,7 @@ fn split_into_sentences(text: &str) -> Vec<String> {
 }
 
 /// Clean PDF text for chunking / embeddings.
-pub fn clean_pdf_text_robust(text: &str, remove_headers: bool) -> String {
+pub(crate) fn clean_pdf_text_robust(text: &str, remove_headers: bool) -> String {
     let mut cleaned = text.to_string();
 
     // 1: Optional: remove section headers
@@ -518,7 +884,7 @@ fn is_garbage_sentence(s: &str) -> bool {
 }
 
 // Updated chunking function
-pub fn smart_chunk_text(text: &str, chunk_size: usize, remove_headers: bool) -> Vec<String> {
+pub(crate) fn smart_chunk_text(text: &str, chunk_size: usize, remove_headers: bool) -> Vec<String> {
     let cleaned = clean_pdf_text_robust(text, remove_headers);
     let sentences = split_into_sentences(&cleaned);
 
@@ -564,42 +930,254 @@ mod tests {
     use super::*;
 
     #[test]
-    fn chunks_textbook_like_pages() {
-        let pages = vec![
-            Page {
-                page_num: 1,
-                content: "Chapter 1: Introduction\n\n\
-                Machine learning is a field of computer science that \
-                gives computers the ability to learn without being \
-                explicitly programmed. This chapter introduces basic \
-                terminology and concepts used throughout the book."
-                    .repeat(20),
-            },
-            Page {
-                page_num: 2,
-                content: "Chapter 2: Linear Models\n\n\
-                Linear regression is one of the simplest supervised \
-                learning algorithms. Despite its simplicity, it forms \
-                the basis for more complex models."
-                    .repeat(20),
-            },
-        ];
+    fn chunks_pdf_oxide_pages_under_their_page_numbers() {
+        use crate::test_pdf::{to_bytes, TestPdf};
+
+        let mut pdf = TestPdf::new();
+        pdf.text_page(&[(72, 700, "Machine learning lets computers learn without being explicitly programmed.")]);
+        pdf.text_page(&[(72, 700, "Linear regression is one of the simplest supervised learning algorithms.")]);
+        let path = std::env::temp_dir().join(format!("vb-chunk-pages-{}.pdf", std::process::id()));
+        std::fs::write(&path, to_bytes(pdf.finish(Dictionary::new()))).unwrap();
+
+        let file = crate::extract::extract_text(path.to_str().unwrap());
+        std::fs::remove_file(&path).ok();
+        let mut pages: Vec<Page> = file
+            .unwrap()
+            .get_pages()
+            .iter()
+            .map(|p| Page { content: p.content.clone(), page_num: p.page_num })
+            .collect();
+        pages.sort_by_key(|p| p.page_num);
+        // pdf_oxide numbers pages from 0
+        assert_eq!(pages.iter().map(|p| p.page_num).collect::<Vec<_>>(), [0, 1]);
 
         let chunks = chunk_per_page(&pages);
+        let found: Vec<(u16, bool)> = chunks.iter().map(|c| (c.page.get(), c.content.contains("regression"))).collect();
+        assert_eq!(found, [(1, false), (2, true)]);
+    }
+
+    #[test]
+    fn smaller_chunk_config_produces_more_chunks() {
+        let text = "Linear regression is one of the simplest supervised learning \
+            algorithms. Despite its simplicity, it forms the basis for more \
+            complex models used throughout the rest of this book. "
+            .repeat(30);
+
+        let config = ChunkConfig::default();
+        let word_count = |s: &str| Ok(s.split_whitespace().count());
+        let default_splitter = TextSplitter::new(config.max_chars);
+        let small_splitter = TextSplitter::new(200);
+
+        let page = PageNumber::new(1);
+        let default_chunks = chunk_page_text(&default_splitter, &text, page, &config, &word_count).unwrap();
+        let small_chunks = chunk_page_text(&small_splitter, &text, page, &config, &word_count).unwrap();
+
+        assert!(!default_chunks.is_empty());
+        assert!(small_chunks.len() > default_chunks.len());
+        for chunk in &small_chunks {
+            assert!(chunk.content.chars().count() <= 200);
+        }
+    }
 
+    #[test]
+    fn oversized_page_chunks_stay_under_token_limit() {
+        let tokenizer = embed::get_tokenizer().unwrap();
+        let count_tokens = |s: &str| embed::count_tokens(tokenizer, s);
+
+        // Spreadsheet-style export: one huge run of text with no sentence breaks
+        let text = (0..600)
+            .map(|i| format!("quarterly revenue forecast region{} adjusted", i))
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        // A character budget this large would hand the model thousands of tokens
+        let splitter = TextSplitter::new(100_000);
+        let config = ChunkConfig { max_tokens: 128, ..ChunkConfig::default() };
+        let chunks =
+            chunk_page_text(&splitter, &text, PageNumber::new(1), &config, &count_tokens).unwrap();
+
+        assert!(!chunks.is_empty());
         for chunk in &chunks {
-            print!("chunk start");
-            println!("{:?}", chunk.content);
-            println!("");
+            assert!(count_tokens(&chunk.content).unwrap() <= config.max_tokens);
         }
+    }
+
+    #[test]
+    fn token_sized_chunks_stay_within_target_band() {
+        let tokenizer = embed::get_tokenizer().unwrap();
+        let count_tokens = |s: &str| embed::count_tokens(tokenizer, s);
+        let tokens_without_specials = |s: &str| tokenizer.encode(s, false).unwrap().len();
+
+        let text = "Gradient descent iteratively updates model parameters in the \
+            direction that most reduces the loss. Choosing the learning rate \
+            carefully matters, because steps that are too large diverge. "
+            .repeat(40);
+
+        let target = 64..128;
+        let splitter =
+            TextSplitter::new(SplitterConfig::new(target.clone()).with_sizer(tokenizer));
+        let config = ChunkConfig::default();
+        let chunks =
+            chunk_page_text(&splitter, &text, PageNumber::new(1), &config, &count_tokens).unwrap();
+
+        assert!(chunks.len() > 1);
+        for (i, chunk) in chunks.iter().enumerate() {
+            let tokens = tokens_without_specials(&chunk.content);
+            assert!(tokens <= target.end, "chunk {} has {} tokens", i, tokens);
+            if i + 1 < chunks.len() {
+                assert!(tokens >= target.start, "chunk {} has {} tokens", i, tokens);
+            }
+        }
+    }
+
+    #[test]
+    fn tokenizer_failure_fails_the_page() {
+        let config = ChunkConfig::default();
+        let splitter = TextSplitter::new(config.max_chars);
+        let broken = |_: &str| Err(anyhow::anyhow!("tokenizer failed"));
+        let text = "Regularization penalizes large weights so the model generalizes better.";
+
+        // Not counted as zero tokens, which would let any chunk through
+        assert!(chunk_page_text(&splitter, text, PageNumber::new(1), &config, &broken).is_err());
+    }
+
+    #[test]
+    fn page_number_only_page_produces_no_chunk() {
+        let config = ChunkConfig::default();
+        let word_count = |s: &str| Ok(s.split_whitespace().count());
+        let splitter = TextSplitter::new(config.max_chars);
+
+        let blank =
+            chunk_page_text(&splitter, "  42\n ", PageNumber::new(3), &config, &word_count).unwrap();
+        assert!(blank.is_empty());
+
+        // The next page keeps its own number even though page 3 was skipped
+        let text = "Regularization penalizes large weights so the model generalizes \
+            better to examples it has not seen during training.";
+        let chunks = chunk_page_text(&splitter, text, PageNumber::new(4), &config, &word_count).unwrap();
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(chunks[0].page, PageNumber::new(4));
+    }
+
+    #[test]
+    fn small_chunks_link_to_enclosing_parent() {
+        let config = ChunkConfig::default();
+        let word_count = |s: &str| Ok(s.split_whitespace().count());
+        let small = TextSplitter::new(120);
+        let page = PageNumber::new(2);
+
+        let text = "Backpropagation applies the chain rule layer by layer to compute \
+            the gradient of the loss with respect to every weight in the network. "
+            .repeat(12);
+        let chunks = chunk_with_parents(&text, page, 600, |parent| {
+            chunk_page_text(&small, parent, page, &config, &word_count)
+        })
+        .unwrap();
 
-        // sanity checks
         assert!(!chunks.is_empty());
+        let parent_ids: std::collections::HashSet<_> =
+            chunks.iter().map(|c| c.parent.as_ref().unwrap().id.as_str()).collect();
+        assert!(parent_ids.len() > 1);
+        assert!(parent_ids.contains("2-0"));
 
-        // every chunk should be associated with a valid page
         for chunk in &chunks {
-            assert!(chunk.page == 1 || chunk.page == 2);
-            assert!(!chunk.content.is_empty());
+            let parent = chunk.parent.as_ref().unwrap();
+            assert!(parent.content.len() > chunk.content.len());
+            assert!(parent.content.contains(&chunk.content));
         }
     }
+
+    #[test]
+    fn paragraph_boundary_keeps_paragraphs_whole() {
+        let config = ChunkConfig { boundary: ChunkBoundary::Paragraph, ..ChunkConfig::default() };
+        let word_count = |s: &str| Ok(s.split_whitespace().count());
+        let splitter = TextSplitter::new(200);
+
+        let first = "Dropout randomly zeroes activations during training so that \
+            neurons cannot rely on one another and must each learn useful features.";
+        let second = "Weight decay adds a penalty on the norm of the weights, which \
+            keeps the model from fitting noise in a small training set.";
+        let long = "Batch normalization rescales each layer's inputs using statistics \
+            of the current mini-batch. "
+            .repeat(4);
+        let text = format!("{}\n\n{}\n \n{}", first, second, long.trim());
+
+        let chunks = chunk_page_text(&splitter, &text, PageNumber::new(1), &config, &word_count).unwrap();
+        assert_eq!(chunks[0].content, first);
+        assert_eq!(chunks[1].content, second);
+        assert!(chunks.len() > 3, "{:?}", chunks);
+        for chunk in &chunks[2..] {
+            assert!(long.contains(&chunk.content), "{}", chunk.content);
+        }
+    }
+
+    #[test]
+    fn form_feeds_never_reach_chunk_text() {
+        let config = ChunkConfig { boundary: ChunkBoundary::Paragraph, ..ChunkConfig::default() };
+        let word_count = |s: &str| Ok(s.split_whitespace().count());
+        let splitter = TextSplitter::new(120);
+
+        let first = "Dropout randomly zeroes activations during training so that neurons cannot rely on one another.";
+        let second = "Weight decay adds a penalty on the norm of the weights to keep the model from fitting noise.";
+        // A page break and a stray bell, as some extractors leave them between
+        // paragraphs; the page break still separates them
+        let raw = format!("{}\n\u{000C}\u{0007}{}", first, second);
+
+        let text = clean_text(&raw, config.control_chars);
+        assert_eq!(text.chars().count(), raw.chars().count());
+        let chunks = chunk_page_text(&splitter, &text, PageNumber::new(1), &config, &word_count).unwrap();
+        let contents: Vec<&str> = chunks.iter().map(|c| c.content.as_str()).collect();
+        assert_eq!(contents, [first, second]);
+    }
+
+    #[test]
+    fn figure_alt_text_becomes_a_caption_chunk() {
+        use crate::test_pdf::TestPdf;
+        use lopdf::dictionary;
+
+        let mut pdf = TestPdf::new();
+        let page_ids: Vec<ObjectId> = (0..2).map(|_| pdf.page_with(vec![], Dictionary::new())).collect();
+
+        // Document > [P on page 1, Figure whose page is only on its marked-content kid, Table on page 1]
+        let paragraph = dictionary! { "Type" => "StructElem", "S" => "P", "Pg" => page_ids[0], "K" => 0 };
+        let figure = dictionary! {
+            "Type" => "StructElem",
+            "S" => "Figure",
+            "Alt" => Object::string_literal("Bar chart of validation loss per epoch"),
+            "K" => dictionary! { "Type" => "MCR", "Pg" => page_ids[1], "MCID" => 0 },
+        };
+        let table = dictionary! {
+            "Type" => "StructElem",
+            "S" => "Table",
+            "Alt" => Object::string_literal("Learning rates tried per optimizer"),
+            "Pg" => page_ids[0],
+            "K" => 1,
+        };
+        let kids = vec![
+            pdf.doc.add_object(paragraph).into(),
+            pdf.doc.add_object(figure).into(),
+            pdf.doc.add_object(table).into(),
+        ];
+        let document = dictionary! { "Type" => "StructElem", "S" => "Document", "K" => kids };
+        let document_id = pdf.doc.add_object(document);
+        let root = dictionary! { "Type" => "StructTreeRoot", "K" => document_id };
+        let root_id = pdf.doc.add_object(root);
+        let mut doc = pdf.finish(dictionary! { "StructTreeRoot" => root_id });
+
+        let captions = caption_chunks(&doc);
+        let found: Vec<(&str, u16)> = captions.iter().map(|c| (c.content.as_str(), c.page.get())).collect();
+        assert_eq!(found, [("Bar chart of validation loss per epoch", 2), ("Learning rates tried per optimizer", 1)]);
+        assert!(captions.iter().all(|c| c.kind.as_str() == "caption"));
+
+        // A page number past u16 is skipped instead of wrapping onto an early page
+        let far: HashMap<ObjectId, u32> = HashMap::from([(page_ids[0], 1), (page_ids[1], 65_537)]);
+        let mut chunks = Vec::new();
+        collect_captions(&doc, &far, &Object::Reference(document_id), None, 0, &mut chunks);
+        let pages: Vec<u16> = chunks.iter().map(|c| c.page.get()).collect();
+        assert_eq!(pages, [1]);
+
+        // An untagged document has nothing to add
+        doc.catalog_mut().unwrap().remove(b"StructTreeRoot");
+        assert!(caption_chunks(&doc).is_empty());
+    }
 }
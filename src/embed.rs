@@ -4,9 +4,16 @@ use fastembed::{
     TokenizerFiles, UserDefinedEmbeddingModel,
 };
 use once_cell::sync::OnceCell;
+use ort::environment::GlobalThreadPoolOptions;
+use ort::session::{Session, builder::GraphOptimizationLevel};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fs;
-use std::sync::{Arc, RwLock};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use tokenizers::Tokenizer;
 
+use crate::cache::{CacheStats, EmbeddingCache};
 use crate::chunk::Chunk;
 
 pub struct Embeddings {
@@ -14,70 +21,723 @@ pub struct Embeddings {
     pub embedded: Vec<Vec<f32>>,
 }
 
-static MODEL_CELL: OnceCell<Arc<RwLock<TextEmbedding>>> = OnceCell::new();
-
-fn initialize_model() -> Result<TextEmbedding, Error> {
-    let model_dir = "model";
-
-    let onnx_file = fs::read(format!("{}/model_qint8_arm64.onnx", model_dir))?;
-    let tokenizer_file = fs::read(format!("{}/tokenizer.json", model_dir))?;
-    let config_file = fs::read(format!("{}/config.json", model_dir))?;
-    let special_tokens = fs::read(format!("{}/special_tokens_map.json", model_dir))?;
-    let tokenizer_config = fs::read(format!("{}/tokenizer_config.json", model_dir))?;
-
-    let model_data = UserDefinedEmbeddingModel {
-        onnx_file,
-        tokenizer_files: TokenizerFiles {
-            tokenizer_file,
-            config_file,
-            special_tokens_map_file: special_tokens,
-            tokenizer_config_file: tokenizer_config,
-        },
-        output_key: None,
-        pooling: None,
-        quantization: QuantizationMode::None,
-    };
+/// Path of the server endpoint that embeds for other processes; see [`embed_remote`].
+pub const EMBED_ENDPOINT: &str = "/internal/embed";
 
-    TextEmbedding::try_new_from_user_defined(model_data, InitOptionsUserDefined::default())
+/// Body of a [`EMBED_ENDPOINT`] request.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct EmbedRequest {
+    pub texts: Vec<String>,
+    /// Embed as search queries (stop words, query prefix) rather than as chunks.
+    #[serde(default)]
+    pub query: bool,
+    /// Use the model registered for this language.
+    #[serde(default)]
+    pub lang: Option<String>,
 }
 
-pub fn get_embeddings(original: Vec<Chunk>) -> Result<Embeddings, Error> {
-    // Initialize model on first call
-    let model = MODEL_CELL.get_or_try_init(|| {
-        let result = initialize_model();
-        result.map(|m| Arc::new(RwLock::new(m)))
-    })?;
+/// Body of a [`EMBED_ENDPOINT`] response, one vector per text in order.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EmbedResponse {
+    pub vectors: Vec<Vec<f32>>,
+}
+
+/// Longest input (in tokens) the model sees; fastembed silently truncates the rest.
+pub const MAX_TOKENS: usize = 512;
+
+// Models and tokenizers by directory; the default model and each language
+// model load on first use. The map lock is only held to find a directory's
+// cell, so loading one model doesn't stall embedding with another. Cells are
+// leaked, one per configured model directory.
+static MODELS_CELL: OnceCell<Mutex<HashMap<PathBuf, &'static OnceCell<LoadedModel>>>> = OnceCell::new();
+static TOKENIZERS_CELL: OnceCell<Mutex<HashMap<PathBuf, &'static OnceCell<Tokenizer>>>> = OnceCell::new();
+static CONFIG_CELL: OnceCell<EmbedConfig> = OnceCell::new();
+static CACHE_CELL: OnceCell<Mutex<EmbeddingCache>> = OnceCell::new();
+static FINGERPRINTS_CELL: OnceCell<Mutex<HashMap<PathBuf, String>>> = OnceCell::new();
+static RUNTIME_CELL: OnceCell<()> = OnceCell::new();
+
+const EMBED_BATCH_SIZE: usize = 32;
+const DEFAULT_CACHE_ENTRIES: usize = 10_000;
+
+/// Directory of the model used when no language is given.
+pub const DEFAULT_MODEL_DIR: &str = "model";
+
+/// How the embedding model is loaded; see [`configure`].
+#[derive(Debug, Clone)]
+pub struct EmbedConfig {
+    /// Directory of the model used when no language is given.
+    pub model_dir: PathBuf,
+    /// Quantization of the ONNX graph. `None` detects it from the file.
+    pub quantization: Option<QuantizationMode>,
+    /// Directory for the ORT-optimized graph. When set, later starts load the
+    /// already-optimized model instead of the original.
+    pub session_cache: Option<PathBuf>,
+    /// Most embeddings kept in memory by text hash; 0 disables the cache.
+    pub cache_entries: usize,
+    /// Words dropped from queries before embedding. Empty leaves queries as typed.
+    pub query_stop_words: Vec<String>,
+    /// Language code (lowercase) -> model directory laid out like [`DEFAULT_MODEL_DIR`].
+    /// Language models must produce vectors of the collection's dimension.
+    pub language_models: HashMap<String, PathBuf>,
+    /// Prepended to every query before embedding, for models trained with an
+    /// instruction (e5's `"query: "`). Empty embeds queries as typed.
+    pub query_prefix: String,
+    /// Prepended to every chunk before embedding (e5's `"passage: "`).
+    pub document_prefix: String,
+    /// Threads in the one intra-op pool every model's ORT session shares.
+    /// `None` leaves each session a pool as wide as the machine.
+    pub intra_threads: Option<usize>,
+}
+
+impl Default for EmbedConfig {
+    fn default() -> Self {
+        EmbedConfig {
+            model_dir: PathBuf::from(DEFAULT_MODEL_DIR),
+            quantization: None,
+            session_cache: None,
+            cache_entries: DEFAULT_CACHE_ENTRIES,
+            query_stop_words: Vec::new(),
+            language_models: HashMap::new(),
+            query_prefix: String::new(),
+            document_prefix: String::new(),
+            intra_threads: None,
+        }
+    }
+}
+
+/// Parse `de=models/de,fr=models/fr` into a language -> model directory map.
+pub fn parse_language_models(value: &str) -> HashMap<String, PathBuf> {
+    value
+        .split(',')
+        .filter_map(|entry| {
+            let (lang, dir) = entry.split_once('=')?;
+            let (lang, dir) = (lang.trim(), dir.trim());
+            (!lang.is_empty() && !dir.is_empty()).then(|| (lang.to_ascii_lowercase(), PathBuf::from(dir)))
+        })
+        .collect()
+}
+
+/// The model directory for `lang`, or the default model for `None`.
+/// A language without a registered model is an error rather than a silent fallback.
+pub fn model_dir(config: &EmbedConfig, lang: Option<&str>) -> Result<PathBuf, Error> {
+    match lang {
+        None => Ok(config.model_dir.clone()),
+        Some(lang) => config
+            .language_models
+            .get(&lang.trim().to_ascii_lowercase())
+            .cloned()
+            .ok_or_else(|| Error::msg(format!("No embedding model registered for language '{}'", lang))),
+    }
+}
 
-    // Prepare text data
-    let contents: Vec<&str> = original
+/// Built-in list for `QUERY_STOP_WORDS=default`.
+pub const DEFAULT_STOP_WORDS: &[&str] = &[
+    "a", "about", "an", "and", "are", "as", "at", "be", "by", "document", "for", "from", "in",
+    "is", "it", "of", "on", "or", "that", "the", "this", "to", "was", "what", "where", "which",
+    "with",
+];
+
+// Queries of this many words or fewer are embedded as typed
+const MIN_WORDS_TO_FILTER: usize = 3;
+
+/// Drop stop words from `query` when it is longer than a few words.
+///
+/// Short queries are left alone, since a stop word may be most of what the
+/// user meant, and a query made only of stop words is kept as typed.
+pub fn strip_stop_words(query: &str, stop_words: &[String]) -> String {
+    let words: Vec<&str> = query.split_whitespace().collect();
+    if stop_words.is_empty() || words.len() <= MIN_WORDS_TO_FILTER {
+        return query.to_string();
+    }
+    let kept: Vec<&str> = words
         .iter()
-        .map(|chunk| chunk.content.as_str())
+        .copied()
+        .filter(|word| {
+            let bare = word.trim_matches(|c: char| !c.is_alphanumeric());
+            !stop_words.iter().any(|stop| stop.eq_ignore_ascii_case(bare))
+        })
         .collect();
+    if kept.is_empty() {
+        query.to_string()
+    } else {
+        kept.join(" ")
+    }
+}
+
+/// Parse `none`, `static` or `dynamic` (any case).
+pub fn parse_quantization(mode: &str) -> Option<QuantizationMode> {
+    match mode.trim().to_ascii_lowercase().as_str() {
+        "none" => Some(QuantizationMode::None),
+        "static" => Some(QuantizationMode::Static),
+        "dynamic" => Some(QuantizationMode::Dynamic),
+        _ => None,
+    }
+}
+
+/// Set the model config. Only takes effect before the first embedding call.
+pub fn configure(config: EmbedConfig) -> Result<(), Error> {
+    CONFIG_CELL
+        .set(config)
+        .map_err(|_| Error::msg("Embedding model is already configured"))
+}
+
+#[derive(Clone)]
+struct LoadedModel {
+    model: Arc<RwLock<TextEmbedding>>,
+    quantization: QuantizationMode,
+}
+
+impl LoadedModel {
+    // Dynamically quantized graphs rescale per batch, so everything goes in one batch
+    fn batch_size(&self) -> Option<usize> {
+        match self.quantization {
+            QuantizationMode::Dynamic => None,
+            _ => Some(EMBED_BATCH_SIZE),
+        }
+    }
+}
+
+// `dir`'s cell in `cells`, created empty on first use
+fn dir_cell<T>(cells: &'static OnceCell<Mutex<HashMap<PathBuf, &'static OnceCell<T>>>>, dir: PathBuf) -> &'static OnceCell<T> {
+    let mut cells = cells.get_or_init(Default::default).lock().unwrap();
+    cells.entry(dir).or_insert_with(|| Box::leak(Box::new(OnceCell::new())))
+}
+
+fn get_model(lang: Option<&str>) -> Result<LoadedModel, Error> {
+    let config = CONFIG_CELL.get_or_init(EmbedConfig::default);
+    let dir = model_dir(config, lang)?;
+    init_runtime(config)?;
+    // Only callers of this same model wait while it loads
+    let loaded = dir_cell(&MODELS_CELL, dir.clone()).get_or_try_init(|| {
+        let (model, quantization) = initialize_model(config, &dir)?;
+        Ok::<_, Error>(LoadedModel { model: Arc::new(RwLock::new(model)), quantization })
+    })?;
+    Ok(loaded.clone())
+}
+
+// ORT reads its environment once, before the first session, so the shared
+// pool has to be in place before any model (or cached graph) is built
+fn init_runtime(config: &EmbedConfig) -> Result<(), Error> {
+    RUNTIME_CELL.get_or_try_init(|| {
+        if let Some(threads) = config.intra_threads {
+            let pool = GlobalThreadPoolOptions::default().with_intra_threads(threads.max(1))?;
+            ort::init().with_global_thread_pool(pool).commit()?;
+        }
+        Ok::<_, Error>(())
+    })?;
+    Ok(())
+}
+
+/// Guess the quantization from the ops in an ONNX graph.
+///
+/// `DynamicQuantizeLinear` only appears in dynamically quantized graphs;
+/// `QuantizeLinear`/`QLinear*` without it means static quantization. Op
+/// types are stored as plain strings, so a byte search is enough.
+pub fn detect_quantization(onnx: &[u8]) -> QuantizationMode {
+    let contains = |op: &[u8]| onnx.windows(op.len()).any(|w| w == op);
+    if contains(b"DynamicQuantizeLinear") {
+        QuantizationMode::Dynamic
+    } else if contains(b"QuantizeLinear") || contains(b"QLinear") {
+        QuantizationMode::Static
+    } else {
+        QuantizationMode::None
+    }
+}
+
+// Stable across builds, unlike DefaultHasher, so cache names survive upgrades
+pub(crate) fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &b| (hash ^ b as u64).wrapping_mul(0x100000001b3))
+}
+
+/// Cache file for the optimized form of `onnx`. Named after a hash of the
+/// original graph, so a changed model never picks up a stale cache.
+fn cached_graph_path(dir: &Path, onnx: &[u8]) -> PathBuf {
+    dir.join(format!("model-{:016x}.onnx", fnv1a(onnx)))
+}
+
+// Let ORT optimize the graph once and write the result next to `path`, then move it in
+fn write_optimized_graph(onnx: &[u8], path: &Path) -> Result<(), Error> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let partial = path.with_extension("onnx.partial");
+    Session::builder()?
+        .with_optimization_level(GraphOptimizationLevel::Level3)?
+        .with_optimized_model_path(&partial)?
+        .commit_from_memory(onnx)?;
+    fs::rename(&partial, path)?;
+    Ok(())
+}
+
+/// The graph bytes to hand fastembed, and the cache file they came from (if any).
+fn load_graph(onnx: Vec<u8>, cache_dir: Option<&Path>) -> (Vec<u8>, Option<PathBuf>) {
+    let Some(dir) = cache_dir else {
+        return (onnx, None);
+    };
+    let path = cached_graph_path(dir, &onnx);
+    if !path.exists()
+        && let Err(e) = write_optimized_graph(&onnx, &path)
+    {
+        eprintln!("Could not cache optimized model at {}: {:?}", path.display(), e);
+        return (onnx, None);
+    }
+    match fs::read(&path) {
+        Ok(optimized) => (optimized, Some(path)),
+        Err(_) => (onnx, None),
+    }
+}
+
+/// What a model directory must hold; [`model_fingerprint`] hashes all of them.
+pub const MODEL_FILES: [&str; 5] = [
+    "model_qint8_arm64.onnx",
+    "tokenizer.json",
+    "config.json",
+    "special_tokens_map.json",
+    "tokenizer_config.json",
+];
+
+/// A hash of the model files in `model_dir`, as 16 hex digits. The same
+/// files give the same fingerprint wherever they are; any change to them gives
+/// a new one. Each directory is read once per process.
+pub fn model_fingerprint(model_dir: &Path) -> Result<String, Error> {
+    let mut fingerprints = FINGERPRINTS_CELL.get_or_init(Default::default).lock().unwrap();
+    if let Some(fingerprint) = fingerprints.get(model_dir) {
+        return Ok(fingerprint.clone());
+    }
+    let mut hashes = Vec::with_capacity(MODEL_FILES.len() * 8);
+    for file in MODEL_FILES {
+        let bytes = fs::read(model_dir.join(file))
+            .map_err(|e| Error::msg(format!("Cannot read {}: {}", model_dir.join(file).display(), e)))?;
+        hashes.extend_from_slice(&fnv1a(&bytes).to_le_bytes());
+    }
+    let fingerprint = format!("{:016x}", fnv1a(&hashes));
+    fingerprints.insert(model_dir.to_path_buf(), fingerprint.clone());
+    Ok(fingerprint)
+}
+
+fn initialize_model(config: &EmbedConfig, model_dir: &Path) -> Result<(TextEmbedding, QuantizationMode), Error> {
+    let onnx_file = fs::read(model_dir.join("model_qint8_arm64.onnx"))?;
+    let tokenizer_file = fs::read(model_dir.join("tokenizer.json"))?;
+    let config_file = fs::read(model_dir.join("config.json"))?;
+    let special_tokens = fs::read(model_dir.join("special_tokens_map.json"))?;
+    let tokenizer_config = fs::read(model_dir.join("tokenizer_config.json"))?;
+
+    let detected = detect_quantization(&onnx_file);
+    let quantization = config.quantization.unwrap_or(detected);
+    if quantization != detected {
+        eprintln!(
+            "Embedding model configured as {:?} quantization but the graph looks {:?}",
+            quantization, detected
+        );
+    }
+
+    let tokenizer_files = TokenizerFiles {
+        tokenizer_file,
+        config_file,
+        special_tokens_map_file: special_tokens,
+        tokenizer_config_file: tokenizer_config,
+    };
+    let build = |onnx_file: Vec<u8>| {
+        let model_data = UserDefinedEmbeddingModel {
+            onnx_file,
+            tokenizer_files: tokenizer_files.clone(),
+            output_key: None,
+            pooling: None,
+            quantization,
+        };
+        TextEmbedding::try_new_from_user_defined(model_data, InitOptionsUserDefined::default())
+    };
+
+    let (graph, cache_file) = load_graph(onnx_file.clone(), config.session_cache.as_deref());
+    let model = match (build(graph), cache_file) {
+        (Ok(model), _) => model,
+        // A cache written by a different ORT build may not load; start over from the original
+        (Err(e), Some(cache_file)) => {
+            eprintln!("Ignoring cached model {}: {:?}", cache_file.display(), e);
+            let _ = fs::remove_file(&cache_file);
+            build(onnx_file)?
+        }
+        (Err(e), None) => return Err(e),
+    };
+    Ok((model, quantization))
+}
+
+fn initialize_tokenizer(model_dir: &Path) -> Result<Tokenizer, Error> {
+    let mut tokenizer = Tokenizer::from_file(model_dir.join("tokenizer.json")).map_err(Error::msg)?;
+
+    // We want the real length of the input, not what survives truncation
+    tokenizer.with_truncation(None).map_err(Error::msg)?;
+    tokenizer.with_padding(None);
+
+    Ok(tokenizer)
+}
+
+pub fn get_tokenizer() -> Result<&'static Tokenizer, Error> {
+    get_tokenizer_in(None)
+}
+
+/// The tokenizer of the model registered for `lang`, so chunks are sized in
+/// the tokens of the model that embeds them.
+pub fn get_tokenizer_in(lang: Option<&str>) -> Result<&'static Tokenizer, Error> {
+    let config = CONFIG_CELL.get_or_init(EmbedConfig::default);
+    let dir = model_dir(config, lang)?;
+    dir_cell(&TOKENIZERS_CELL, dir.clone()).get_or_try_init(|| initialize_tokenizer(&dir))
+}
+
+/// Number of tokens the model would see for `text`, including special tokens.
+pub fn count_tokens(tokenizer: &Tokenizer, text: &str) -> Result<usize, Error> {
+    tokenizer
+        .encode(text, true)
+        .map(|encoding| encoding.len())
+        .map_err(|e| anyhow::anyhow!("Failed to tokenize text: {}", e))
+}
+
+fn cache() -> &'static Mutex<EmbeddingCache> {
+    CACHE_CELL.get_or_init(|| {
+        let config = CONFIG_CELL.get_or_init(EmbedConfig::default);
+        Mutex::new(EmbeddingCache::new(config.cache_entries))
+    })
+}
+
+/// Hits, misses and size of the embedding cache.
+pub fn cache_stats() -> CacheStats {
+    cache().lock().unwrap().stats()
+}
+
+// Each model's vectors are cached under their own keys
+fn cache_key(text: &str, lang: Option<&str>) -> String {
+    match lang {
+        None => text.to_string(),
+        Some(lang) => format!("{}\u{1}{}", lang.to_ascii_lowercase(), text),
+    }
+}
+
+// What the model is given for a query: stop words dropped, then the query prefix
+fn query_text(config: &EmbedConfig, query: &str) -> String {
+    format!("{}{}", config.query_prefix, strip_stop_words(query, &config.query_stop_words))
+}
+
+fn document_text(config: &EmbedConfig, content: &str) -> String {
+    format!("{}{}", config.document_prefix, content)
+}
+
+pub fn get_embeddings(original: Vec<Chunk>) -> Result<Embeddings, Error> {
+    get_embeddings_in(original, None)
+}
+
+/// [`get_embeddings`] with the model registered for `lang`.
+pub fn get_embeddings_in(original: Vec<Chunk>, lang: Option<&str>) -> Result<Embeddings, Error> {
+    let contents: Vec<&str> = original.iter().map(|chunk| chunk.content.as_str()).collect();
+    let embedded = embed_documents_in(&contents, lang)?;
+    Ok(Embeddings { original, embedded })
+}
+
+/// Embed chunk texts with the document prefix, through the cache.
+pub fn embed_documents_in(contents: &[&str], lang: Option<&str>) -> Result<Vec<Vec<f32>>, Error> {
+    let config = CONFIG_CELL.get_or_init(EmbedConfig::default);
+    let texts: Vec<String> = contents.iter().map(|content| document_text(config, content)).collect();
+    embed_cached(&texts, lang)
+}
+
+/// Embed search queries the way [`embed_query_in`] does, in one batch.
+pub fn embed_queries_in(queries: &[&str], lang: Option<&str>) -> Result<Vec<Vec<f32>>, Error> {
+    let config = CONFIG_CELL.get_or_init(EmbedConfig::default);
+    let texts: Vec<String> = queries.iter().map(|query| query_text(config, query)).collect();
+    embed_cached(&texts, lang)
+}
+
+// Texts already seen keep their cached vector; only the rest go to the model
+fn embed_cached(texts: &[String], lang: Option<&str>) -> Result<Vec<Vec<f32>>, Error> {
+    let mut embedded: Vec<Option<Vec<f32>>> = {
+        let mut cache = cache().lock().unwrap();
+        texts.iter().map(|text| cache.get(&cache_key(text, lang))).collect()
+    };
+    let missing: Vec<usize> = (0..texts.len()).filter(|&i| embedded[i].is_none()).collect();
+
+    if !missing.is_empty() {
+        let contents: Vec<&str> = missing.iter().map(|&i| texts[i].as_str()).collect();
+        let fresh = embed_batch_in(&contents, None, lang)?;
+        if fresh.len() != missing.len() {
+            return Err(Error::msg(format!(
+                "Model returned {} embeddings for {} texts",
+                fresh.len(),
+                missing.len()
+            )));
+        }
+
+        let mut cache = cache().lock().unwrap();
+        for (&i, vector) in missing.iter().zip(fresh) {
+            cache.insert(&cache_key(&texts[i], lang), vector.clone());
+            embedded[i] = Some(vector);
+        }
+    }
+
+    embedded
+        .into_iter()
+        .enumerate()
+        .map(|(i, vector)| vector.ok_or_else(|| Error::msg(format!("No embedding for text {}", i))))
+        .collect()
+}
+
+/// Embed `texts` in order, bypassing the cache.
+///
+/// `batch_size` of `None` uses the model's own batching: one batch for
+/// dynamically quantized graphs, fixed-size batches otherwise.
+pub fn embed_batch(texts: &[&str], batch_size: Option<usize>) -> Result<Vec<Vec<f32>>, Error> {
+    embed_batch_in(texts, batch_size, None)
+}
+
+/// [`embed_batch`] with the model registered for `lang`.
+pub fn embed_batch_in(texts: &[&str], batch_size: Option<usize>, lang: Option<&str>) -> Result<Vec<Vec<f32>>, Error> {
+    // Initialize model on first call
+    let loaded = get_model(lang)?;
 
     // Generate embeddings (needs write lock for &mut self)
-    let mut model_guard = model.write().unwrap();
-    let embedded = model_guard.embed(contents, Some(32 as usize))?;
+    let mut model_guard = loaded.model.write().unwrap();
+    let embedded = model_guard.embed(texts, batch_size.or(loaded.batch_size()))?;
     drop(model_guard); // Explicit drop for clarity
 
-    Ok(Embeddings { original, embedded })
+    Ok(embedded)
 }
 
 pub fn embed_query(query: &str) -> Result<Vec<f32>, Error> {
-    let model = MODEL_CELL.get_or_try_init(|| {
-        let result = initialize_model();
-        result.map(|m| Arc::new(RwLock::new(m)))
-    })?;
+    embed_query_in(query, None)
+}
 
-    // Generate embedding for the single query
-    let mut model_guard = model.write().unwrap();
-    let embedded = model_guard.embed(vec![query], None)?;
-    drop(model_guard);
+/// [`embed_query`] with the model registered for `lang`, so a query meets a
+/// document embedded by the same model.
+pub fn embed_query_in(query: &str, lang: Option<&str>) -> Result<Vec<f32>, Error> {
+    embed_queries_in(&[query], lang)?
+        .pop()
+        .ok_or_else(|| Error::msg("Model returned no embedding for the query"))
+}
 
-    // Return the first (and only) embedding
-    Ok(embedded.into_iter().next().unwrap())
+/// Embed `request` with this process's models.
+pub fn embed_request(request: &EmbedRequest) -> Result<Vec<Vec<f32>>, Error> {
+    let lang = request.lang.as_deref();
+    let texts: Vec<&str> = request.texts.iter().map(String::as_str).collect();
+    if request.query {
+        embed_queries_in(&texts, lang)
+    } else {
+        embed_documents_in(&texts, lang)
+    }
+}
+
+/// Embed `request` on the server at `base_url` (e.g. `http://localhost:3000`)
+/// instead of loading a model here, so a CLI running next to the server reuses
+/// its warm model. The server must have [`EMBED_ENDPOINT`] enabled; `token`
+/// is the shared token it was started with.
+pub async fn embed_remote(base_url: &str, token: Option<&str>, request: &EmbedRequest) -> Result<Vec<Vec<f32>>, Error> {
+    let url = format!("{}{}", base_url.trim_end_matches('/'), EMBED_ENDPOINT);
+    let mut post = reqwest::Client::new().post(&url).json(request);
+    if let Some(token) = token {
+        post = post.bearer_auth(token);
+    }
+    let response = post.send().await?.error_for_status()?;
+    let vectors = response.json::<EmbedResponse>().await?.vectors;
+    if vectors.len() != request.texts.len() {
+        return Err(Error::msg(format!(
+            "{} returned {} vectors for {} texts",
+            url,
+            vectors.len(),
+            request.texts.len()
+        )));
+    }
+    Ok(vectors)
+}
+
+/// Load the model if needed and embed a probe string, so a broken model or
+/// runtime shows up before the first real request. Skips the cache so the
+/// model really runs every time.
+pub fn warmup() -> Result<(), Error> {
+    embed_batch(&["warmup"], None).map(|_| ())
 }
 
 pub fn get_dim() -> usize {
     let model_info = EmbeddingModel::get_model_info(&EmbeddingModel::AllMiniLML6V2);
     model_info.expect("Model info should always exist").dim
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_bundled_model_as_dynamic() {
+        let quantized = fs::read("model/model_qint8_arm64.onnx").unwrap();
+        assert_eq!(detect_quantization(&quantized), QuantizationMode::Dynamic);
+
+        assert_eq!(detect_quantization(b"\x08MatMul\x12\x03Add"), QuantizationMode::None);
+        assert_eq!(detect_quantization(b"\x0eQuantizeLinear"), QuantizationMode::Static);
+    }
+
+    #[test]
+    fn parses_quantization_names() {
+        assert_eq!(parse_quantization(" Static "), Some(QuantizationMode::Static));
+        assert_eq!(parse_quantization("dynamic"), Some(QuantizationMode::Dynamic));
+        assert_eq!(parse_quantization("int8"), None);
+    }
+
+    #[test]
+    fn lang_routes_to_its_registered_model() {
+        let config = EmbedConfig {
+            language_models: parse_language_models("de=model-de, FR = model-fr"),
+            ..EmbedConfig::default()
+        };
+
+        assert_eq!(model_dir(&config, Some("de")).unwrap(), PathBuf::from("model-de"));
+        assert_eq!(model_dir(&config, Some("fr")).unwrap(), PathBuf::from("model-fr"));
+        assert_eq!(model_dir(&config, Some("DE")).unwrap(), PathBuf::from("model-de"));
+        assert_eq!(model_dir(&config, None).unwrap(), PathBuf::from(DEFAULT_MODEL_DIR));
+        assert!(model_dir(&config, Some("es")).is_err());
+
+        // The same text embedded by two models mustn't share a cache entry
+        assert_ne!(cache_key("Gradientenabstieg", Some("de")), cache_key("Gradientenabstieg", None));
+    }
+
+    #[test]
+    fn loading_one_model_does_not_block_another() {
+        static CELLS: OnceCell<Mutex<HashMap<PathBuf, &'static OnceCell<u32>>>> = OnceCell::new();
+        let (started, wait_started) = std::sync::mpsc::channel();
+        let (finish, wait_finish) = std::sync::mpsc::channel::<()>();
+        let slow = std::thread::spawn(move || {
+            *dir_cell(&CELLS, PathBuf::from("model-de")).get_or_init(|| {
+                started.send(()).unwrap();
+                wait_finish.recv().unwrap();
+                1
+            })
+        });
+        wait_started.recv().unwrap();
+
+        // "model-de" is still loading; the default model loads meanwhile and is kept
+        assert_eq!(dir_cell(&CELLS, PathBuf::from("model")).get_or_init(|| 2), &2);
+        assert_eq!(dir_cell(&CELLS, PathBuf::from("model")).get(), Some(&2));
+        finish.send(()).unwrap();
+        assert_eq!(slow.join().unwrap(), 1);
+
+        // Tokenizers are looked up per language like models
+        assert!(get_tokenizer_in(Some("xx")).is_err());
+        assert!(get_tokenizer_in(None).is_ok());
+    }
+
+    #[test]
+    fn cache_file_changes_with_model_bytes() {
+        let dir = Path::new("/tmp/vb-cache");
+        assert_eq!(cached_graph_path(dir, b"graph"), cached_graph_path(dir, b"graph"));
+        assert_ne!(cached_graph_path(dir, b"graph"), cached_graph_path(dir, b"graph v2"));
+    }
+
+    #[test]
+    #[ignore = "needs the onnxruntime library"]
+    fn second_init_reuses_optimized_graph() {
+        let dir = std::env::temp_dir().join(format!("vb-session-cache-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let config = EmbedConfig { session_cache: Some(dir.clone()), ..EmbedConfig::default() };
+
+        let started = std::time::Instant::now();
+        initialize_model(&config, Path::new(DEFAULT_MODEL_DIR)).unwrap();
+        let cold = started.elapsed();
+
+        let onnx = fs::read("model/model_qint8_arm64.onnx").unwrap();
+        assert!(cached_graph_path(&dir, &onnx).exists());
+
+        let started = std::time::Instant::now();
+        initialize_model(&config, Path::new(DEFAULT_MODEL_DIR)).unwrap();
+        assert!(started.elapsed() < cold, "warm {:?} vs cold {:?}", started.elapsed(), cold);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    fn stop_words() -> Vec<String> {
+        DEFAULT_STOP_WORDS.iter().map(|w| w.to_string()).collect()
+    }
+
+    #[test]
+    fn stop_words_are_dropped_only_from_longer_queries() {
+        let words = stop_words();
+        assert_eq!(strip_stop_words("the document about the dropout", &words), "dropout");
+        assert_eq!(strip_stop_words("What is the learning rate?", &words), "learning rate?");
+        // Short and all-stop-word queries are embedded as typed
+        assert_eq!(strip_stop_words("the dropout", &words), "the dropout");
+        assert_eq!(strip_stop_words("what is this about", &words), "what is this about");
+        // Opt-in: no list, no filtering
+        assert_eq!(strip_stop_words("the document about the dropout", &[]), "the document about the dropout");
+    }
+
+    #[test]
+    #[ignore = "needs the onnxruntime library"]
+    fn stop_word_filtering_moves_query_embedding() {
+        let cosine = |a: &[f32], b: &[f32]| {
+            let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+            let norm = |v: &[f32]| v.iter().map(|x| x * x).sum::<f32>().sqrt();
+            dot / (norm(a) * norm(b))
+        };
+        let raw = "the document about the regularization of the network";
+        let filtered = strip_stop_words(raw, &stop_words());
+        assert_eq!(filtered, "regularization network");
+
+        let embedded = embed_batch(&[raw, filtered.as_str(), "regularization"], None).unwrap();
+        assert!(cosine(&embedded[0], &embedded[1]) < 0.95);
+        // Filtering moves the query toward its content words
+        assert!(cosine(&embedded[1], &embedded[2]) > cosine(&embedded[0], &embedded[2]));
+    }
+
+    #[test]
+    fn prefixes_mark_queries_and_documents_apart() {
+        let config = EmbedConfig {
+            query_prefix: "query: ".to_string(),
+            document_prefix: "passage: ".to_string(),
+            query_stop_words: stop_words(),
+            ..EmbedConfig::default()
+        };
+        assert_eq!(query_text(&config, "the document about the dropout"), "query: dropout");
+        assert_eq!(document_text(&config, "Dropout zeroes activations."), "passage: Dropout zeroes activations.");
+        // Unset, both are embedded as they are
+        let plain = EmbedConfig::default();
+        assert_eq!(query_text(&plain, "dropout rate"), "dropout rate");
+        assert_eq!(document_text(&plain, "Dropout zeroes activations."), "Dropout zeroes activations.");
+    }
+
+    #[test]
+    #[ignore = "needs the onnxruntime library"]
+    fn prefixes_change_the_vectors() {
+        let config = EmbedConfig { query_prefix: "query: ".to_string(), ..EmbedConfig::default() };
+        let prefixed = query_text(&config, "learning rate warmup");
+        let embedded = embed_batch(&["learning rate warmup", prefixed.as_str()], None).unwrap();
+        assert_ne!(embedded[0], embedded[1]);
+    }
+
+    #[test]
+    #[ignore = "needs the onnxruntime library"]
+    fn batch_of_one_matches_query_embedding() {
+        let batch = embed_batch(&["stochastic gradient descent"], None).unwrap();
+        assert_eq!(batch.len(), 1);
+        assert_eq!(batch[0], embed_query("stochastic gradient descent").unwrap());
+    }
+
+    #[test]
+    #[ignore = "needs the onnxruntime library"]
+    fn batched_queries_match_one_at_a_time() {
+        let queries = ["adam optimizer", "dropout"];
+        let request = EmbedRequest { texts: queries.map(str::to_string).to_vec(), query: true, lang: None };
+        let batched = embed_request(&request).unwrap();
+        assert_eq!(batched, [embed_query(queries[0]).unwrap(), embed_query(queries[1]).unwrap()]);
+    }
+
+    #[test]
+    #[ignore = "needs the onnxruntime library"]
+    fn every_quantization_mode_produces_embeddings() {
+        let texts = vec!["gradient descent"; EMBED_BATCH_SIZE + 1];
+        for mode in [QuantizationMode::None, QuantizationMode::Static, QuantizationMode::Dynamic] {
+            let (model, quantization) =
+                initialize_model(&EmbedConfig { quantization: Some(mode), ..EmbedConfig::default() }, Path::new(DEFAULT_MODEL_DIR))
+                    .unwrap();
+            assert_eq!(quantization, mode);
+
+            let loaded = LoadedModel { model: Arc::new(RwLock::new(model)), quantization };
+            let embedded = loaded.model.write().unwrap().embed(texts.clone(), loaded.batch_size()).unwrap();
+            assert_eq!(embedded.len(), texts.len());
+            assert_eq!(embedded[0].len(), get_dim());
+        }
+    }
+}
@@ -1,3 +1,6 @@
+use std::fmt;
+
+use anyhow::Result;
 use pdf_oxide::PdfDocument;
 use rayon::prelude::*;
 use serde::Deserialize;
@@ -52,10 +55,114 @@ pub fn extract_pdf_file(pdf_path: &str) -> File {
     File { pages }
 }
 
-pub fn extract_text(file: &str) -> File {
-    let page_count = PdfDocument::open(file).unwrap().page_count().unwrap();
+/// A PDF library and the page tree disagree on how many pages the file has.
+///
+/// pdf_oxide takes the count from the root `/Pages` dictionary, while stored
+/// page numbers come from walking the tree, so a wrong `/Count` would shift
+/// every page after the bad one. Highlighting looks pages up in pdfium by
+/// those numbers, so its count is checked the same way.
+#[derive(Debug, PartialEq, Eq)]
+pub struct PageCountMismatch {
+    /// The library whose count disagrees, e.g. `"pdf_oxide"`.
+    pub reader: &'static str,
+    pub reported: usize,
+    pub page_tree: usize,
+}
+
+impl PageCountMismatch {
+    /// `page_tree` if `reader` counted as many pages, otherwise the mismatch.
+    pub fn check(reader: &'static str, reported: usize, page_tree: usize) -> Result<usize> {
+        if reported != page_tree {
+            return Err(PageCountMismatch { reader, reported, page_tree }.into());
+        }
+        Ok(page_tree)
+    }
+}
+
+impl fmt::Display for PageCountMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "page count mismatch: {} reports {} pages but the page tree has {}",
+            self.reader, self.reported, self.page_tree
+        )
+    }
+}
+
+impl std::error::Error for PageCountMismatch {}
+
+/// What extraction does with control characters (form feeds, stray C0/C1
+/// codes) other than tab, newline and carriage return.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ControlChars {
+    /// Page and line breaks (form feed, vertical tab, separators) become a
+    /// newline and anything else a space, so every char keeps its offset.
+    #[default]
+    Normalize,
+    /// Drop them; offsets are carried through [`clean_control_chars`].
+    Strip,
+    /// Leave the text as extracted.
+    Keep,
+}
+
+impl ControlChars {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "normalize" => Some(ControlChars::Normalize),
+            "strip" => Some(ControlChars::Strip),
+            "keep" => Some(ControlChars::Keep),
+            _ => None,
+        }
+    }
+}
+
+/// Apply `mode` to `(offset, char)` entries, keeping each surviving char's
+/// offset so a match in the cleaned text still maps back to the original.
+pub fn clean_control_chars<I>(entries: I, mode: ControlChars) -> Vec<(usize, char)>
+where
+    I: IntoIterator<Item = (usize, char)>,
+{
+    entries
+        .into_iter()
+        .filter_map(|(idx, ch)| {
+            if mode == ControlChars::Keep || !ch.is_control() || matches!(ch, '\t' | '\n' | '\r') {
+                return Some((idx, ch));
+            }
+            match mode {
+                ControlChars::Strip => None,
+                _ if matches!(ch, '\u{000B}' | '\u{000C}' | '\u{001C}'..='\u{001E}' | '\u{0085}') => Some((idx, '\n')),
+                _ => Some((idx, ' ')),
+            }
+        })
+        .collect()
+}
+
+/// [`clean_control_chars`] over a whole string.
+pub fn clean_text(text: &str, mode: ControlChars) -> String {
+    clean_control_chars(text.chars().enumerate(), mode)
+        .into_iter()
+        .map(|(_, ch)| ch)
+        .collect()
+}
+
+/// pdf_oxide's page count, checked against the page tree as lopdf walks it.
+pub fn verified_page_count(file: &str) -> Result<usize> {
+    let pdf_oxide = PdfDocument::open(file)?.page_count()?;
+    let page_tree = lopdf::Document::load(file)?.get_pages().len();
+    PageCountMismatch::check("pdf_oxide", pdf_oxide, page_tree)
+}
+
+/// Extract every page's text. Fails with [`PageCountMismatch`] rather than
+/// returning pages whose numbers wouldn't line up with the document.
+///
+/// Pages are read in parallel on whichever rayon pool this is called from:
+/// the global one, sized by
+/// [`ResourceBudget::install_global_rayon`](crate::resources::ResourceBudget::install_global_rayon),
+/// unless the caller installs a pool of its own.
+pub fn extract_text(file: &str) -> Result<File> {
+    let page_count = verified_page_count(file)?;
 
-    // Calculate optimal chunk size based on available threads
+    // Calculate optimal chunk size based on the current pool's threads
     let num_threads = rayon::current_num_threads();
     let chunk_size = (page_count / num_threads).max(1);
 
@@ -70,7 +177,7 @@ pub fn extract_text(file: &str) -> File {
                 .iter()
                 .filter_map(|&page_num| {
                     doc.extract_text(page_num).ok().map(|text| Page {
-                        content: text,
+                        content: clean_text(&text, ControlChars::default()),
                         page_num: page_num as u16,
                     })
                 })
@@ -78,7 +185,7 @@ pub fn extract_text(file: &str) -> File {
         })
         .collect();
 
-    File { pages: pages }
+    Ok(File { pages })
 }
 
 impl File {
@@ -86,3 +193,104 @@ impl File {
         &self.pages
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_pdf::{to_bytes, TestPdf};
+    use lopdf::{Dictionary, Document};
+
+    // A PDF with one line of distinct text on each page, saved to a temp file
+    fn write_multi_page_pdf(path: &std::path::Path, pages: usize) {
+        let mut pdf = TestPdf::new();
+        for i in 0..pages {
+            pdf.text_page(&[(72, 700, &format!("Section {} covers optimizers", i))]);
+        }
+        std::fs::write(path, to_bytes(pdf.finish(Dictionary::new()))).unwrap();
+    }
+
+    #[test]
+    fn thread_count_does_not_change_extracted_pages() {
+        let path = std::env::temp_dir().join(format!("vb-extract-{}.pdf", std::process::id()));
+        write_multi_page_pdf(&path, 7);
+        let file = path.to_str().unwrap();
+
+        let pages = |extracted: File| -> Vec<(u16, String)> {
+            extracted.pages.into_iter().map(|p| (p.page_num, p.content)).collect()
+        };
+        let on_pool = |threads: usize| {
+            let pool = rayon::ThreadPoolBuilder::new().num_threads(threads).build().unwrap();
+            pages(pool.install(|| extract_text(file)).unwrap())
+        };
+        let single = on_pool(1);
+        let parallel = on_pool(4);
+        std::fs::remove_file(&path).ok();
+
+        assert_eq!(single.len(), 7);
+        assert!(single[3].1.contains("Section 3"), "{:?}", single[3]);
+        assert_eq!(single, parallel);
+    }
+
+    #[test]
+    fn extraction_stays_within_the_rayon_budget() {
+        let path = std::env::temp_dir().join(format!("vb-extract-budget-{}.pdf", std::process::id()));
+        write_multi_page_pdf(&path, 12);
+        let file = path.to_str().unwrap();
+
+        let budget = crate::resources::ResourceBudget { rayon_threads: 3, ..crate::resources::ResourceBudget::from_cpus(16) };
+        let pool = budget.rayon_pool().unwrap();
+        assert_eq!(pool.current_num_threads(), 3);
+
+        // Parallel work started inside the pool, as extraction's is, only runs on its workers
+        let (pages, workers) = pool.install(|| {
+            let extracted = extract_text(file).unwrap();
+            let workers: Vec<Option<usize>> = extracted
+                .pages
+                .par_iter()
+                .map(|_| rayon::current_thread_index())
+                .collect();
+            (extracted.pages.len(), workers)
+        });
+        std::fs::remove_file(&path).ok();
+
+        assert_eq!(pages, 12);
+        assert!(workers.iter().all(|w| w.is_some_and(|i| i < 3)), "{:?}", workers);
+    }
+
+    #[test]
+    fn form_feeds_are_cleaned_without_shifting_offsets() {
+        let text = "Gradient descent\u{000C}Chapter 2\u{0007} momentum\tterms\r\n";
+        let normalized = clean_control_chars(text.chars().enumerate(), ControlChars::Normalize);
+        let cleaned: String = normalized.iter().map(|&(_, ch)| ch).collect();
+        assert_eq!(cleaned, "Gradient descent\nChapter 2  momentum\tterms\r\n");
+        assert!(normalized.iter().enumerate().all(|(i, &(idx, _))| i == idx));
+
+        // Stripped chars are gone, and what follows still points at its original position
+        let stripped = clean_control_chars(text.chars().enumerate(), ControlChars::Strip);
+        let chapter = stripped.iter().position(|&(_, ch)| ch == 'C').unwrap();
+        assert_eq!(chapter, 16);
+        assert_eq!(stripped[chapter].0, text.chars().position(|ch| ch == 'C').unwrap());
+        assert_eq!(clean_text(text, ControlChars::Strip), "Gradient descentChapter 2 momentum\tterms\r\n");
+        assert_eq!(clean_text(text, ControlChars::Keep), text);
+    }
+
+    #[test]
+    fn wrong_page_count_is_reported_instead_of_misnumbering_pages() {
+        let path = std::env::temp_dir().join(format!("vb-extract-count-{}.pdf", std::process::id()));
+        write_multi_page_pdf(&path, 3);
+        // The tree has 3 pages but /Count claims 5; pdf_oxide believes /Count
+        let mut doc = Document::load(&path).unwrap();
+        let pages_id = doc.catalog().unwrap().get(b"Pages").unwrap().as_reference().unwrap();
+        doc.get_dictionary_mut(pages_id).unwrap().set("Count", 5);
+        doc.save(&path).unwrap();
+
+        let result = extract_text(path.to_str().unwrap());
+        std::fs::remove_file(&path).ok();
+
+        let err = result.err().expect("mismatched counts should fail");
+        assert_eq!(
+            err.downcast_ref::<PageCountMismatch>(),
+            Some(&PageCountMismatch { reader: "pdf_oxide", reported: 5, page_tree: 3 })
+        );
+    }
+}
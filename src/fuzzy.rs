@@ -1,28 +1,111 @@
+use std::time::{Duration, Instant};
+
+/// Tunables for `fuzzy_search_with_config`.
+#[derive(Debug, Clone)]
+pub struct FuzzyConfig {
+    /// Minimum jaro_winkler score for a window to count as a match.
+    pub threshold: f32,
+    /// Window sizes scored per candidate before deciding whether to keep expanding.
+    pub probe_windows: usize,
+    /// Abandon a candidate whose best probe score is below `threshold - abandon_margin`.
+    pub abandon_margin: f32,
+    /// Winkler boost per matching leading char; keep `prefix_scale * max_prefix <= 1`.
+    pub prefix_scale: f32,
+    /// Leading chars considered for the Winkler boost.
+    pub max_prefix: usize,
+    /// Most candidate starts scored when the anchor finds nothing and we fall
+    /// back to sampling the whole haystack; the samples are spread evenly
+    /// across it.
+    pub max_sparse_candidates: usize,
+    /// Stop the sparse fallback once this many matches are found.
+    pub sparse_match_limit: usize,
+    /// Most windows scored with jaro_winkler in one search; once spent, the
+    /// matches found so far are returned.
+    pub max_windows: Option<usize>,
+    /// Wall-clock budget for one search, with the same best-effort cut-off.
+    pub time_budget: Option<Duration>,
+    /// Compare chars as they are instead of lowercasing both sides, so "US"
+    /// and "us" are different words.
+    pub case_sensitive: bool,
+    /// Match accented letters to their plain form on both sides, so "resume"
+    /// finds "résumé". Off by default since some languages need accents kept.
+    pub fold_diacritics: bool,
+    /// Before scoring windows, also try an exact match with every run of
+    /// whitespace on either side read as one space, so "a  b\nc" is an exact
+    /// hit for "a b c".
+    pub collapse_whitespace: bool,
+}
+
+impl Default for FuzzyConfig {
+    fn default() -> Self {
+        FuzzyConfig {
+            threshold: 0.85,
+            probe_windows: 3,
+            abandon_margin: 0.1,
+            prefix_scale: 0.1,
+            max_prefix: 4,
+            max_sparse_candidates: 300,
+            sparse_match_limit: 10,
+            max_windows: None,
+            time_budget: None,
+            case_sensitive: false,
+            fold_diacritics: false,
+            collapse_whitespace: true,
+        }
+    }
+}
+
 pub fn fuzzy_search(
     char_entries: &[(usize, char)],
     needle_chars: &[char],
     threshold: f32,
 ) -> Vec<(usize, usize, f32)> {
+    let config = FuzzyConfig {
+        threshold,
+        ..FuzzyConfig::default()
+    };
+    fuzzy_search_with_config(char_entries, needle_chars, &config)
+}
+
+pub fn fuzzy_search_with_config(
+    char_entries: &[(usize, char)],
+    needle_chars: &[char],
+    config: &FuzzyConfig,
+) -> Vec<(usize, usize, f32)> {
+    search_counting_windows(char_entries, needle_chars, config).0
+}
+
+// Returns the matches plus how many windows were scored with jaro_winkler
+fn search_counting_windows(
+    char_entries: &[(usize, char)],
+    needle_chars: &[char],
+    config: &FuzzyConfig,
+) -> (Vec<(usize, usize, f32)>, usize) {
+    let threshold = config.threshold;
+    let abandon_below = threshold - config.abandon_margin;
+    let mut windows_scored = 0usize;
+    let deadline = config.time_budget.map(|budget| Instant::now() + budget);
+    let max_windows = config.max_windows.unwrap_or(usize::MAX);
+
     let needle_len = needle_chars.len();
     if needle_len == 0 || char_entries.is_empty() {
-        return vec![];
+        return (vec![], windows_scored);
     }
 
-    // Precompute lowercased haystack once
-    let haystack: Vec<char> = char_entries
-        .iter()
-        .map(|(_, ch)| ch.to_lowercase().next().unwrap_or(*ch))
-        .collect();
+    // Precompute the folded haystack once
+    let haystack: Vec<char> = char_entries.iter().map(|&(_, ch)| fold_char(ch, config)).collect();
     let haystack_len = haystack.len();
 
-    let needle_lower: Vec<char> = needle_chars
-        .iter()
-        .map(|ch| ch.to_lowercase().next().unwrap_or(*ch))
-        .collect();
+    let needle_lower: Vec<char> = needle_chars.iter().map(|&ch| fold_char(ch, config)).collect();
 
     // 1. Exact match
     if let Some(pos) = find_exact(&haystack, &needle_lower) {
-        return vec![(pos, pos + needle_len, 1.0)];
+        return (vec![(pos, pos + needle_len, 1.0)], windows_scored);
+    }
+    if config.collapse_whitespace
+        && let Some((start, end)) = find_exact_collapsed(&haystack, &needle_lower)
+    {
+        return (vec![(start, end, 1.0)], windows_scored);
     }
 
     let window_min = needle_len;
@@ -43,11 +126,15 @@ pub fn fuzzy_search(
     }
 
     // 3. Sparse fallback if anchor found nothing
-    if candidate_starts.is_empty() {
+    let sparse = candidate_starts.is_empty();
+    if sparse {
         let step = (needle_len / 4).max(1);
         for i in (0..haystack_len.saturating_sub(window_min - 1)).step_by(step) {
             candidate_starts.push(i);
         }
+        if candidate_starts.len() > config.max_sparse_candidates {
+            candidate_starts = evenly_spaced(candidate_starts, config.max_sparse_candidates);
+        }
     }
 
     // Score only the candidate windows
@@ -59,7 +146,14 @@ pub fn fuzzy_search(
 
     let mut last_match_end = 0usize;
 
-    for start in candidate_starts {
+    'candidates: for start in candidate_starts {
+        if sparse && matches.len() >= config.sparse_match_limit {
+            break;
+        }
+        // Out of budget: keep what was found rather than hold up the caller
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            break;
+        }
         if start < last_match_end {
             continue;
         }
@@ -69,14 +163,33 @@ pub fn fuzzy_search(
 
         let mut best_score = 0.0f32;
         let mut best_end = 0usize;
+        let mut best_probe = 0.0f32;
 
-        for window_size in window_min..=window_max {
+        for (probed, window_size) in (window_min..=window_max).enumerate() {
             let end = start + window_size;
             if end > haystack_len {
                 break;
             }
 
-            let score = jaro_winkler(&needle_lower, &haystack[start..end]);
+            // Hopeless candidate: the first few sizes are nowhere near the threshold
+            if probed == config.probe_windows && best_probe < abandon_below {
+                break;
+            }
+            if windows_scored >= max_windows {
+                if best_score >= threshold {
+                    matches.push((start, best_end, best_score));
+                }
+                break 'candidates;
+            }
+
+            let score = jaro_winkler_scaled(
+                &needle_lower,
+                &haystack[start..end],
+                config.prefix_scale,
+                config.max_prefix,
+            );
+            windows_scored += 1;
+            best_probe = best_probe.max(score);
             if score >= threshold && score > best_score {
                 best_score = score;
                 best_end = end;
@@ -92,24 +205,128 @@ pub fn fuzzy_search(
         }
     }
 
-    deduplicate_matches(matches)
+    (deduplicate_matches(matches), windows_scored)
+}
+
+// Keep `limit` of the sampled starts, spread evenly from the first to the last
+// so every part of the page gets looked at
+fn evenly_spaced(starts: Vec<usize>, limit: usize) -> Vec<usize> {
+    if limit == 0 {
+        return Vec::new();
+    }
+    if limit == 1 || starts.len() <= limit {
+        return starts.into_iter().take(limit).collect();
+    }
+    let last = starts.len() - 1;
+    (0..limit).map(|k| starts[k * last / (limit - 1)]).collect()
+}
+
+/// The plain letter an accented one transliterates to ('é' -> 'e'). Chars
+/// that transliterate to anything else ('æ' -> "ae", CJK) are kept as they
+/// are, so folded text stays index-aligned with the original.
+pub fn strip_diacritic(ch: char) -> char {
+    if ch.is_ascii() {
+        return ch;
+    }
+    let mut plain = unidecode::unidecode_char(ch).chars();
+    match (plain.next(), plain.next()) {
+        (Some(c), None) if c.is_ascii_alphanumeric() => c,
+        _ => ch,
+    }
+}
+
+// `ch` as `config` compares it: accents stripped and lowercased unless kept
+fn fold_char(ch: char, config: &FuzzyConfig) -> char {
+    let ch = if config.fold_diacritics { strip_diacritic(ch) } else { ch };
+    match config.case_sensitive {
+        true => ch,
+        false => ch.to_lowercase().next().unwrap_or(ch),
+    }
 }
 
 /// Naive exact subsequence search — O(n*m) but m is small and this exits early
-fn find_exact(haystack: &[char], needle: &[char]) -> Option<usize> {
+pub(crate) fn find_exact(haystack: &[char], needle: &[char]) -> Option<usize> {
     let n = needle.len();
     haystack.windows(n).position(|w| w == needle)
 }
 
+/// Exact search with each run of whitespace, in either input, read as a
+/// single space. Returns the match as a range of `haystack`, so it can be
+/// highlighted in the original text; the needle's edges are trimmed first.
+pub(crate) fn find_exact_collapsed(haystack: &[char], needle: &[char]) -> Option<(usize, usize)> {
+    let (collapsed_haystack, origins) = collapse_whitespace(haystack);
+    let (needle, _) = collapse_whitespace(needle);
+    let first = needle.iter().position(|&ch| ch != ' ')?;
+    let last = needle.iter().rposition(|&ch| ch != ' ')?;
+    let needle = &needle[first..=last];
+    let pos = find_exact(&collapsed_haystack, needle)?;
+    // The needle ends in a non-space, which stands for exactly one original char
+    Some((origins[pos], origins[pos + needle.len() - 1] + 1))
+}
+
+// `text` with whitespace runs as one ' ', plus each char's index in `text`
+fn collapse_whitespace(text: &[char]) -> (Vec<char>, Vec<usize>) {
+    let mut collapsed = Vec::with_capacity(text.len());
+    let mut origins = Vec::with_capacity(text.len());
+    for (i, &ch) in text.iter().enumerate() {
+        if !ch.is_whitespace() {
+            collapsed.push(ch);
+            origins.push(i);
+        } else if collapsed.last() != Some(&' ') {
+            collapsed.push(' ');
+            origins.push(i);
+        }
+    }
+    (collapsed, origins)
+}
+
+/// Plain keyword relevance used when no query embedding is available.
+/// A verbatim phrase hit scores 1.0, otherwise the fraction of query words present (max 0.9).
+pub fn keyword_score(text: &str, query: &str) -> f32 {
+    keyword_score_with(text, query, &FuzzyConfig::default())
+}
+
+/// [`keyword_score`] comparing chars the way `config` does; only its
+/// `case_sensitive` and `fold_diacritics` apply.
+pub fn keyword_score_with(text: &str, query: &str, config: &FuzzyConfig) -> f32 {
+    let haystack: Vec<char> = text.chars().map(|ch| fold_char(ch, config)).collect();
+    let query: String = query.trim().chars().map(|ch| fold_char(ch, config)).collect();
+    let phrase: Vec<char> = query.chars().collect();
+    if phrase.is_empty() || haystack.is_empty() {
+        return 0.0;
+    }
+
+    if find_exact(&haystack, &phrase).is_some() {
+        return 1.0;
+    }
+
+    let words: Vec<Vec<char>> = query
+        .split_whitespace()
+        .map(|w| w.chars().collect())
+        .collect();
+    let hits = words
+        .iter()
+        .filter(|w| find_exact(&haystack, w).is_some())
+        .count();
+
+    hits as f32 / words.len() as f32 * 0.9
+}
+
 pub fn jaro_winkler(a: &[char], b: &[char]) -> f32 {
+    jaro_winkler_scaled(a, b, 0.1, 4)
+}
+
+/// Jaro-Winkler with an explicit prefix boost, for text whose first few
+/// chars are unreliable (drop caps, bullet glyphs).
+pub fn jaro_winkler_scaled(a: &[char], b: &[char], prefix_scale: f32, max_prefix: usize) -> f32 {
     let jaro = jaro(a, b);
     let prefix = a
         .iter()
         .zip(b.iter())
-        .take(4)
+        .take(max_prefix)
         .take_while(|(x, y)| x == y)
         .count();
-    jaro + (prefix as f32 * 0.1 * (1.0 - jaro))
+    jaro + (prefix as f32 * prefix_scale * (1.0 - jaro))
 }
 
 fn jaro(a: &[char], b: &[char]) -> f32 {
@@ -165,7 +382,7 @@ fn jaro(a: &[char], b: &[char]) -> f32 {
     (m / a_len as f32 + m / b_len as f32 + (m - t) / m) / 3.0
 }
 
-pub fn deduplicate_matches(mut matches: Vec<(usize, usize, f32)>) -> Vec<(usize, usize, f32)> {
+pub(crate) fn deduplicate_matches(mut matches: Vec<(usize, usize, f32)>) -> Vec<(usize, usize, f32)> {
     matches.sort_by(|a, b| b.2.partial_cmp(&a.2).unwrap());
     let mut kept: Vec<(usize, usize, f32)> = Vec::new();
     'outer: for (start, end, score) in matches {
@@ -195,6 +412,34 @@ mod tests {
         s.chars().enumerate().collect()
     }
 
+    #[test]
+    fn case_sensitive_exact_search_tells_acronym_from_word() {
+        let haystack = char_entries("Sales in the US grew; most of us expected it.");
+        let acronym = haystack.iter().position(|&(_, c)| c == 'U').unwrap();
+        let word = acronym + "US grew; most of ".len();
+        let sensitive = FuzzyConfig { case_sensitive: true, ..FuzzyConfig::default() };
+
+        assert_eq!(fuzzy_search_with_config(&haystack, &chars("US"), &sensitive), [(acronym, acronym + 2, 1.0)]);
+        assert_eq!(fuzzy_search_with_config(&haystack, &chars("us"), &sensitive), [(word, word + 2, 1.0)]);
+        // The default folds case, so both needles land on the first occurrence
+        assert_eq!(fuzzy_search(&haystack, &chars("us"), 0.85), [(acronym, acronym + 2, 1.0)]);
+    }
+
+    #[test]
+    fn folded_diacritics_match_plain_needle() {
+        let haystack = char_entries("Attach your Résumé below.");
+        let start = "Attach your ".chars().count();
+        let folding = FuzzyConfig { fold_diacritics: true, ..FuzzyConfig::default() };
+
+        assert_eq!(fuzzy_search_with_config(&haystack, &chars("resume"), &folding), [(start, start + 6, 1.0)]);
+        assert!(fuzzy_search(&haystack, &chars("resume"), 0.85).iter().all(|&(_, _, score)| score < 1.0));
+        assert_eq!(strip_diacritic('æ'), 'æ');
+
+        // Keyword scoring folds the same way
+        assert_eq!(keyword_score_with("Attach your Résumé below.", "resume", &folding), 1.0);
+        assert_eq!(keyword_score("Attach your Résumé below.", "resume"), 0.0);
+    }
+
     #[test]
     fn fuzzy_search_hyphenated_word_matches_clean() {
         // PDF sometimes breaks "information" as "infor-\nmation"
@@ -252,6 +497,19 @@ mod tests {
         );
     }
 
+    #[test]
+    fn whitespace_runs_match_exactly_when_collapsed() {
+        let haystack = char_entries("see: words  without\nspaces here");
+        let needle = chars("words without spaces");
+        let start = 5;
+        let end = start + "words  without\nspaces".chars().count();
+        assert_eq!(find_exact_collapsed(&chars("see: words  without\nspaces here"), &needle), Some((start, end)));
+        assert_eq!(fuzzy_search(&haystack, &needle, 0.99), [(start, end, 1.0)]);
+
+        let literal = FuzzyConfig { collapse_whitespace: false, ..FuzzyConfig::default() };
+        assert!(fuzzy_search_with_config(&haystack, &needle, &literal).iter().all(|&(_, _, score)| score < 1.0));
+    }
+
     #[test]
     fn fuzzy_search_missing_space_after_extraction() {
         // PDF glyph spacing sometimes causes spaces to be dropped entirely
@@ -276,6 +534,136 @@ mod tests {
         );
     }
 
+    #[test]
+    fn early_abandon_keeps_results_and_scores_fewer_windows() {
+        let exhaustive = FuzzyConfig {
+            threshold: 0.75,
+            probe_windows: usize::MAX,
+            ..FuzzyConfig::default()
+        };
+        let abandoning = FuzzyConfig {
+            threshold: 0.75,
+            ..FuzzyConfig::default()
+        };
+
+        let fixtures = [
+            ("infor-\nmation", "information"),
+            ("the  quick   brown  fox", "quick brown"),
+            ("a self-\ncontained system", "self-contained"),
+            ("compre\u{00AD}hensive", "comprehensive"),
+            ("the  result.  The  value  is  correct", "result. The value"),
+            ("\u{201C}quoted text\u{201D}", "\"quoted text\""),
+            ("well\u{2014}known", "well-known"),
+        ];
+        for (haystack, needle) in fixtures {
+            let (full, _) =
+                search_counting_windows(&char_entries(haystack), &chars(needle), &exhaustive);
+            let (fast, _) =
+                search_counting_windows(&char_entries(haystack), &chars(needle), &abandoning);
+            assert_eq!(full, fast, "results changed for {:?}", needle);
+        }
+
+        // Lots of anchor hits that never turn into a real match
+        let haystack = char_entries(&"the qxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxx ".repeat(20));
+        let needle = chars("the quick brown fox jumps over");
+        let (full, full_windows) = search_counting_windows(&haystack, &needle, &exhaustive);
+        let (fast, fast_windows) = search_counting_windows(&haystack, &needle, &abandoning);
+        assert_eq!(full, fast);
+        assert!(fast_windows < full_windows);
+    }
+
+    #[test]
+    fn sparse_fallback_cap_still_finds_match() {
+        // ~20k chars with no trace of the needle's anchor ("gradient"); the
+        // near-match opens the page, where the first even sample lands
+        let filler = "lorem ipsum dolor sit amet consectetur adipiscing elit sed do ";
+        let mut text = String::from("gradeint descent converges slowly near saddle points ");
+        text.push_str(&filler.repeat(320));
+
+        let haystack = char_entries(&text);
+        let needle = chars("gradient descent converges slowly near saddle points");
+
+        let uncapped = FuzzyConfig {
+            max_sparse_candidates: usize::MAX,
+            ..FuzzyConfig::default()
+        };
+        let (full, full_windows) = search_counting_windows(&haystack, &needle, &uncapped);
+        let (capped, capped_windows) =
+            search_counting_windows(&haystack, &needle, &FuzzyConfig::default());
+
+        assert_eq!(capped.len(), 1);
+        assert_eq!(capped[0].0, 0);
+        assert_eq!(full, capped);
+        assert!(
+            capped_windows * 5 < full_windows,
+            "{} vs {} windows",
+            capped_windows,
+            full_windows
+        );
+    }
+
+    #[test]
+    fn sparse_samples_are_spread_over_the_whole_page() {
+        let starts: Vec<usize> = (0..1_000).map(|i| i * 13).collect();
+        assert_eq!(evenly_spaced(starts.clone(), 5), vec![0, 3237, 6487, 9737, 12987]);
+        assert_eq!(evenly_spaced(starts.clone(), 1), vec![0]);
+        assert!(evenly_spaced(starts.clone(), 0).is_empty());
+        assert_eq!(evenly_spaced(starts[..3].to_vec(), 5), vec![0, 13, 26]);
+    }
+
+    #[test]
+    fn budget_returns_best_effort_results_early() {
+        // The needle's anchor ("abstra") hits every 8 chars but never grows into a match
+        let haystack = char_entries(&"abstrax ".repeat(30_000));
+        let needle = chars("abstract gradient descent with momentum");
+
+        let window_capped = FuzzyConfig { max_windows: Some(500), ..FuzzyConfig::default() };
+        let (_, windows) = search_counting_windows(&haystack, &needle, &window_capped);
+        assert!(windows <= 500, "{} windows", windows);
+
+        let budget = Duration::from_millis(20);
+        let timed = FuzzyConfig { time_budget: Some(budget), ..FuzzyConfig::default() };
+        let started = Instant::now();
+        fuzzy_search_with_config(&haystack, &needle, &timed);
+        assert!(started.elapsed() < budget * 10, "took {:?}", started.elapsed());
+
+        // A generous budget doesn't change the result
+        let small = char_entries("the quick brown fox");
+        let roomy = FuzzyConfig { max_windows: Some(1_000), time_budget: Some(Duration::from_secs(5)), ..FuzzyConfig::default() };
+        assert_eq!(
+            fuzzy_search_with_config(&small, &chars("quick brwn"), &roomy),
+            fuzzy_search_with_config(&small, &chars("quick brwn"), &FuzzyConfig::default())
+        );
+    }
+
+    #[test]
+    fn keyword_score_ranks_phrase_over_partial() {
+        let phrase = keyword_score("The Gradient Descent update rule", "gradient descent");
+        let partial = keyword_score("descent into the valley", "gradient descent");
+        let miss = keyword_score("unrelated text", "gradient descent");
+        assert_eq!(phrase, 1.0);
+        assert!(partial > 0.0 && partial < phrase);
+        assert_eq!(miss, 0.0);
+    }
+
+    #[test]
+    fn lower_prefix_scale_stops_rewarding_shared_prefix() {
+        let needle = chars("the quick brown fox");
+        // Shares the prefix but has two letters swapped mid-sentence
+        let swapped = chars("the quikc brown fox");
+        // Exact apart from a bullet glyph picked up before the first word
+        let bulleted = chars("•the quick brown fox");
+
+        let winner = |scale: f32| {
+            let swapped_score = jaro_winkler_scaled(&needle, &swapped, scale, 4);
+            let bulleted_score = jaro_winkler_scaled(&needle, &bulleted, scale, 4);
+            if swapped_score > bulleted_score { "swapped" } else { "bulleted" }
+        };
+
+        assert_eq!(winner(FuzzyConfig::default().prefix_scale), "swapped");
+        assert_eq!(winner(0.0), "bulleted");
+    }
+
     #[test]
     fn fuzzy_search_em_dash_vs_hyphen() {
         // PDFs frequently encode em-dashes (—) where the source had a hyphen
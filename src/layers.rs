@@ -0,0 +1,255 @@
+//! Optional content groups (layers) and which of them contribute text.
+//!
+//! lopdf's `extract_text` reads every text operator on a page, so text on a
+//! layer the viewer hides by default would be indexed as if it were visible.
+//! [`strip_excluded_layers`] drops the text of unwanted layers from the page
+//! content before extraction.
+
+use std::collections::{HashMap, HashSet};
+
+use anyhow::Result;
+use lopdf::content::Operation;
+use lopdf::{Dictionary, Document, Object, ObjectId};
+
+/// Which layers' text is extracted. Text outside any layer is always kept.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum LayerSelection {
+    /// Layers shown in the document's default view; hidden layers are skipped.
+    #[default]
+    Visible,
+    /// Every layer, hidden or not.
+    All,
+    /// Only the layers with these names, whatever their default visibility.
+    Named(Vec<String>),
+}
+
+impl LayerSelection {
+    /// Parse `visible`, `all`, or a comma-separated list of layer names.
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim() {
+            "" => None,
+            v if v.eq_ignore_ascii_case("visible") => Some(LayerSelection::Visible),
+            v if v.eq_ignore_ascii_case("all") => Some(LayerSelection::All),
+            v => Some(LayerSelection::Named(
+                v.split(',').map(str::trim).filter(|n| !n.is_empty()).map(String::from).collect(),
+            )),
+        }
+    }
+}
+
+/// Remove text drawn inside excluded layers from every page's content.
+/// Returns the number of pages that changed.
+pub fn strip_excluded_layers(doc: &mut Document, selection: &LayerSelection) -> Result<usize> {
+    if *selection == LayerSelection::All {
+        return Ok(0);
+    }
+    let excluded = excluded_groups(doc, selection);
+    if excluded.is_empty() {
+        return Ok(0);
+    }
+
+    let mut changed = 0;
+    let pages: Vec<ObjectId> = doc.get_pages().into_values().collect();
+    for page_id in pages {
+        let hidden = hidden_property_names(doc, page_id, &excluded);
+        if hidden.is_empty() {
+            continue;
+        }
+        let mut content = doc.get_and_decode_page_content(page_id)?;
+        let before = content.operations.len();
+        content.operations = drop_hidden_text(content.operations, &hidden);
+        if content.operations.len() != before {
+            doc.change_page_content(page_id, content.encode()?)?;
+            changed += 1;
+        }
+    }
+    Ok(changed)
+}
+
+// The document's layers as (id, name)
+fn layer_groups(doc: &Document) -> Vec<(ObjectId, String)> {
+    let Some(properties) = oc_properties(doc) else {
+        return vec![];
+    };
+    let Ok(groups) = properties.get(b"OCGs").and_then(Object::as_array) else {
+        return vec![];
+    };
+    groups
+        .iter()
+        .filter_map(|group| {
+            let id = group.as_reference().ok()?;
+            let name = doc.get_dictionary(id).ok()?.get(b"Name").and_then(Object::as_str).ok()?;
+            Some((id, String::from_utf8_lossy(name).into_owned()))
+        })
+        .collect()
+}
+
+fn oc_properties(doc: &Document) -> Option<&Dictionary> {
+    let properties = doc.catalog().ok()?.get(b"OCProperties").ok()?;
+    doc.dereference(properties).ok()?.1.as_dict().ok()
+}
+
+// Layers switched off in the default view: in /OFF, or not in /ON when /BaseState is /OFF
+fn hidden_groups(doc: &Document) -> HashSet<ObjectId> {
+    let Some(default_config) = oc_properties(doc)
+        .and_then(|p| p.get(b"D").ok())
+        .and_then(|d| doc.dereference(d).ok()?.1.as_dict().ok())
+    else {
+        return HashSet::new();
+    };
+    let refs = |key: &[u8]| -> HashSet<ObjectId> {
+        default_config
+            .get(key)
+            .and_then(Object::as_array)
+            .map(|ids| ids.iter().filter_map(|id| id.as_reference().ok()).collect())
+            .unwrap_or_default()
+    };
+
+    let base_off = default_config.get(b"BaseState").and_then(Object::as_name).is_ok_and(|s| s == b"OFF");
+    if base_off {
+        let on = refs(b"ON");
+        layer_groups(doc).into_iter().map(|(id, _)| id).filter(|id| !on.contains(id)).collect()
+    } else {
+        refs(b"OFF")
+    }
+}
+
+fn excluded_groups(doc: &Document, selection: &LayerSelection) -> HashSet<ObjectId> {
+    match selection {
+        LayerSelection::All => HashSet::new(),
+        LayerSelection::Visible => hidden_groups(doc),
+        LayerSelection::Named(names) => layer_groups(doc)
+            .into_iter()
+            .filter(|(_, name)| !names.contains(name))
+            .map(|(id, _)| id)
+            .collect(),
+    }
+}
+
+// Names under the page's /Properties resources that mark content as excluded.
+// A membership dict (OCMD) counts as excluded when all of its groups are.
+fn hidden_property_names(doc: &Document, page_id: ObjectId, excluded: &HashSet<ObjectId>) -> HashSet<Vec<u8>> {
+    let Ok((own, inherited)) = doc.get_page_resources(page_id) else {
+        return HashSet::new();
+    };
+    let resources = own.into_iter().chain(inherited.into_iter().filter_map(|id| doc.get_dictionary(id).ok()));
+
+    let mut properties: HashMap<Vec<u8>, ObjectId> = HashMap::new();
+    for resource in resources {
+        let Some(dict) = resource
+            .get(b"Properties")
+            .ok()
+            .and_then(|p| doc.dereference(p).ok()?.1.as_dict().ok())
+        else {
+            continue;
+        };
+        for (name, value) in dict.iter() {
+            if let Ok(id) = value.as_reference() {
+                properties.entry(name.clone()).or_insert(id);
+            }
+        }
+    }
+
+    properties
+        .into_iter()
+        .filter(|(_, id)| {
+            if excluded.contains(id) {
+                return true;
+            }
+            let Ok(members) = doc.get_dictionary(*id).and_then(|d| d.get(b"OCGs")) else {
+                return false;
+            };
+            let members: Vec<ObjectId> = match members {
+                Object::Array(ids) => ids.iter().filter_map(|id| id.as_reference().ok()).collect(),
+                other => other.as_reference().into_iter().collect(),
+            };
+            !members.is_empty() && members.iter().all(|m| excluded.contains(m))
+        })
+        .map(|(name, _)| name)
+        .collect()
+}
+
+// Drop text-showing operators inside `/OC /<name> BDC ... EMC` sections for
+// hidden names. Everything else stays so font and position state carry on.
+fn drop_hidden_text(operations: Vec<Operation>, hidden: &HashSet<Vec<u8>>) -> Vec<Operation> {
+    let mut marked: Vec<bool> = Vec::new();
+    operations
+        .into_iter()
+        .filter(|op| {
+            match op.operator.as_str() {
+                "BDC" => {
+                    let is_hidden = op.operands.first().and_then(|tag| tag.as_name().ok()) == Some(b"OC")
+                        && op.operands.get(1).and_then(|p| p.as_name().ok()).is_some_and(|p| hidden.contains(p));
+                    marked.push(is_hidden);
+                }
+                "BMC" => marked.push(false),
+                "EMC" => {
+                    marked.pop();
+                }
+                "Tj" | "TJ" | "'" | "\"" => return !marked.contains(&true),
+                _ => {}
+            }
+            true
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_pdf::{show_text, TestPdf};
+    use lopdf::dictionary;
+
+    // One page with a visible paragraph and an answer key on a layer hidden by default
+    fn layered_pdf() -> Document {
+        let mut pdf = TestPdf::new();
+        let questions = pdf.doc.add_object(dictionary! { "Type" => "OCG", "Name" => Object::string_literal("Questions") });
+        let answers = pdf.doc.add_object(dictionary! { "Type" => "OCG", "Name" => Object::string_literal("Answers") });
+
+        let mut operations = vec![Operation::new("BDC", vec!["OC".into(), "MC0".into()])];
+        operations.extend(show_text(72, 700, "What does dropout regularize"));
+        operations.push(Operation::new("EMC", vec![]));
+        operations.push(Operation::new("BDC", vec!["OC".into(), "MC1".into()]));
+        operations.extend(show_text(72, 650, "Co-adaptation between neurons"));
+        operations.push(Operation::new("EMC", vec![]));
+        pdf.page_with(operations, dictionary! { "Properties" => dictionary! { "MC0" => questions, "MC1" => answers } });
+
+        pdf.finish(dictionary! {
+            "OCProperties" => dictionary! {
+                "OCGs" => vec![questions.into(), answers.into()],
+                "D" => dictionary! { "OFF" => vec![answers.into()] },
+            },
+        })
+    }
+
+    fn page_text(selection: &LayerSelection) -> String {
+        let mut doc = layered_pdf();
+        strip_excluded_layers(&mut doc, selection).unwrap();
+        doc.extract_text(&[1]).unwrap()
+    }
+
+    #[test]
+    fn hidden_layer_text_is_included_only_on_request() {
+        let visible = page_text(&LayerSelection::Visible);
+        assert!(visible.contains("dropout regularize"), "{}", visible);
+        assert!(!visible.contains("Co-adaptation"), "{}", visible);
+
+        let all = page_text(&LayerSelection::All);
+        assert!(all.contains("dropout regularize") && all.contains("Co-adaptation"), "{}", all);
+
+        let answers_only = page_text(&LayerSelection::Named(vec!["Answers".to_string()]));
+        assert!(answers_only.contains("Co-adaptation"), "{}", answers_only);
+        assert!(!answers_only.contains("dropout regularize"), "{}", answers_only);
+    }
+
+    #[test]
+    fn parses_layer_selection() {
+        assert_eq!(LayerSelection::parse("ALL"), Some(LayerSelection::All));
+        assert_eq!(LayerSelection::parse(" visible "), Some(LayerSelection::Visible));
+        assert_eq!(
+            LayerSelection::parse("Answers, Notes"),
+            Some(LayerSelection::Named(vec!["Answers".to_string(), "Notes".to_string()]))
+        );
+        assert_eq!(LayerSelection::parse(""), None);
+    }
+}
@@ -1,5 +1,72 @@
+//! Semantic search over PDF documents.
+//!
+//! The usual flow is: chunk a PDF with [`chunk::extract_and_chunk`], embed the
+//! chunks with [`embed::get_embeddings`], [`index`] them into a [`VectorStore`],
+//! then [`search`] with a query vector from [`embed::embed_query`].
+//!
+//! ```
+//! use vb::{Chunk, ChunkKind, Embeddings, InMemoryStore, PageNumber, index, search};
+//!
+//! let chunks = vec![
+//!     Chunk {
+//!         content: "Gradient descent minimises a loss.".to_string(),
+//!         page: PageNumber::new(1),
+//!         kind: ChunkKind::Body,
+//!         parent: None,
+//!     },
+//!     Chunk {
+//!         content: "Qdrant stores vectors.".to_string(),
+//!         page: PageNumber::new(2),
+//!         kind: ChunkKind::Body,
+//!         parent: None,
+//!     },
+//! ];
+//! // Normally produced by embed::get_embeddings
+//! let embeddings = Embeddings {
+//!     original: chunks,
+//!     embedded: vec![vec![1.0, 0.0], vec![0.0, 1.0]],
+//! };
+//!
+//! let mut store = InMemoryStore::new();
+//! index(&mut store, "notes.pdf", embeddings).unwrap();
+//!
+//! let results = search(&store, "notes.pdf", &[0.9, 0.1], 1).unwrap();
+//! assert_eq!(results[0].page, PageNumber::new(1));
+//! ```
+
+pub mod cache;
 pub mod chunk;
 pub mod embed;
 pub mod extract;
 pub mod fuzzy;
+pub mod layers;
+pub mod page;
 pub mod qdrant;
+pub mod resources;
+pub mod store;
+#[cfg(test)]
+mod test_pdf;
+
+pub use chunk::{Chunk, ChunkConfig, ChunkKind, PdfSource};
+pub use embed::Embeddings;
+pub use page::PageNumber;
+pub use store::{InMemoryStore, SearchResult, VectorStore};
+
+/// Store a document's embedded chunks so they can be searched.
+pub fn index<S: VectorStore>(
+    store: &mut S,
+    document: &str,
+    embeddings: Embeddings,
+) -> anyhow::Result<()> {
+    store.upsert(document, embeddings.original, embeddings.embedded)
+}
+
+/// Top `limit` chunks of `document` by similarity to `query`.
+pub fn search<S: VectorStore>(
+    store: &S,
+    document: &str,
+    query: &[f32],
+    limit: usize,
+) -> anyhow::Result<Vec<SearchResult>> {
+    store.search(document, query, limit)
+}
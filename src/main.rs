@@ -0,0 +1,158 @@
+// src/main.rs
+
+use clap::{Parser, Subcommand};
+use qdrant_client::Qdrant;
+use vb::embed::{self, EmbedRequest};
+use vb::qdrant;
+
+/// Maintenance commands for the Qdrant collections behind the search engine.
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+struct Cli {
+    #[arg(long, default_value = "http://localhost:6334")]
+    qdrant_url: String,
+
+    /// Embed on this server (started with SERVE_EMBEDDINGS=true) instead of
+    /// loading the model here, e.g. http://localhost:3000. Sends the server's
+    /// shared token from EMBED_TOKEN.
+    #[arg(long)]
+    embed_url: Option<String>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug, PartialEq)]
+enum Command {
+    /// Print the name of every collection
+    ListCollections,
+    /// Drop a collection and all of its points
+    DeleteCollection {
+        name: String,
+        /// Required; without it nothing is deleted
+        #[arg(long)]
+        yes: bool,
+    },
+    /// Print the embedding of each text as a JSON array, one per line
+    Embed {
+        #[arg(required = true)]
+        texts: Vec<String>,
+        /// Embed as search queries rather than document chunks
+        #[arg(long)]
+        query: bool,
+        /// Use the model registered for this language
+        #[arg(long)]
+        lang: Option<String>,
+    },
+}
+
+#[tokio::main]
+async fn main() {
+    let cli = Cli::parse();
+    if let Err(e) = run(cli).await {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    }
+}
+
+async fn run(cli: Cli) -> Result<(), Box<dyn std::error::Error>> {
+    let client = Qdrant::from_url(&cli.qdrant_url).build()?;
+
+    match cli.command {
+        Command::ListCollections => {
+            let names = qdrant::list_collections(&client).await?;
+            if names.is_empty() {
+                println!("No collections");
+            }
+            for name in names {
+                println!("{}", name);
+            }
+        }
+        Command::DeleteCollection { name, yes: false } => {
+            return Err(format!("Refusing to delete '{}' without --yes", name).into());
+        }
+        Command::DeleteCollection { name, yes: true } => {
+            qdrant::delete_collection(&client, &name).await?;
+            println!("Deleted collection: {}", name);
+        }
+        Command::Embed { texts, query, lang } => {
+            let request = EmbedRequest { texts, query, lang };
+            let token = std::env::var("EMBED_TOKEN").ok();
+            for vector in embed_texts(cli.embed_url.as_deref(), token.as_deref(), request).await? {
+                println!("{}", serde_json::to_string(&vector)?);
+            }
+        }
+    }
+    Ok(())
+}
+
+// The server's warm model when `embed_url` is set, otherwise one loaded here
+async fn embed_texts(embed_url: Option<&str>, token: Option<&str>, request: EmbedRequest) -> anyhow::Result<Vec<Vec<f32>>> {
+    match embed_url {
+        Some(url) => embed::embed_remote(url, token, &request).await,
+        None => tokio::task::spawn_blocking(move || embed::embed_request(&request)).await?,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_collection_subcommands() {
+        let cli = Cli::try_parse_from(["vb", "list-collections"]).unwrap();
+        assert_eq!(cli.command, Command::ListCollections);
+        assert_eq!(cli.qdrant_url, "http://localhost:6334");
+
+        let cli = Cli::try_parse_from(["vb", "delete-collection", "embedded_pdfs"]).unwrap();
+        assert_eq!(cli.command, Command::DeleteCollection { name: "embedded_pdfs".to_string(), yes: false });
+
+        let cli = Cli::try_parse_from(["vb", "--qdrant-url", "http://qdrant:6334", "delete-collection", "repl", "--yes"])
+            .unwrap();
+        assert_eq!(cli.command, Command::DeleteCollection { name: "repl".to_string(), yes: true });
+        assert_eq!(cli.qdrant_url, "http://qdrant:6334");
+
+        assert!(Cli::try_parse_from(["vb", "delete-collection"]).is_err());
+
+        let cli = Cli::try_parse_from(["vb", "--embed-url", "http://localhost:3000", "embed", "dropout", "--query"]).unwrap();
+        let expected = Command::Embed { texts: vec!["dropout".to_string()], query: true, lang: None };
+        assert_eq!(cli.command, expected);
+        assert_eq!(cli.embed_url.as_deref(), Some("http://localhost:3000"));
+    }
+
+    #[tokio::test]
+    async fn delete_without_yes_is_an_error() {
+        // Fails before any request, so nothing needs to listen on the port
+        let cli = Cli::try_parse_from(["vb", "--qdrant-url", "http://127.0.0.1:9", "delete-collection", "repl"]).unwrap();
+        let error = run(cli).await.unwrap_err();
+        assert_eq!(error.to_string(), "Refusing to delete 'repl' without --yes");
+    }
+
+    #[tokio::test]
+    async fn embed_uses_the_server_model_when_configured() {
+        use axum::{http::HeaderMap, routing::post, Json, Router};
+        use std::sync::{Arc, Mutex};
+        use vb::embed::EmbedResponse;
+
+        // Stands in for the app; no model is loaded in this process
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let seen = received.clone();
+        let server = Router::new().route(
+            embed::EMBED_ENDPOINT,
+            post(move |headers: HeaderMap, Json(request): Json<EmbedRequest>| async move {
+                let vectors = request.texts.iter().map(|t| vec![t.len() as f32, 1.0]).collect();
+                let auth = headers.get("authorization").and_then(|v| v.to_str().ok()).map(str::to_string);
+                seen.lock().unwrap().push((auth, request));
+                Json(EmbedResponse { vectors })
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, server).await.unwrap() });
+
+        let request = EmbedRequest { texts: vec!["dropout".to_string(), "weight decay".to_string()], query: true, lang: None };
+        let vectors = embed_texts(Some(&format!("{}/", url)), Some("s3cret"), request.clone()).await.unwrap();
+        assert_eq!(vectors, [vec![7.0, 1.0], vec![12.0, 1.0]]);
+        assert_eq!(*received.lock().unwrap(), [(Some("Bearer s3cret".to_string()), request)]);
+    }
+}
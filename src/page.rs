@@ -0,0 +1,225 @@
+use std::fmt;
+
+use lopdf::{Dictionary, Document, Object};
+use serde::{Deserialize, Serialize};
+
+/// A 1-indexed page number, as stored in chunks and Qdrant payloads.
+///
+/// lopdf numbers pages from 1 but pdfium indexes them from 0. Going through
+/// [`PageNumber::new`]/[`PageNumber::from_index`] on the way in and
+/// [`PageNumber::index`] on the way out keeps the two from drifting apart.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct PageNumber(u16);
+
+impl PageNumber {
+    /// Wrap a 1-indexed page number. Debug builds reject 0.
+    pub fn new(number: u16) -> Self {
+        debug_assert!(number >= 1, "page numbers are 1-indexed, got 0");
+        PageNumber(number)
+    }
+
+    /// Convert a 0-indexed position (pdfium, pdf_oxide) into a page number.
+    pub fn from_index(index: u16) -> Self {
+        PageNumber(index + 1)
+    }
+
+    /// The 1-indexed number shown to users.
+    pub fn get(self) -> u16 {
+        self.0
+    }
+
+    /// The page after this one; `None` past the last page a `u16` can number.
+    pub fn next(self) -> Option<Self> {
+        self.0.checked_add(1).map(PageNumber)
+    }
+
+    /// The 0-indexed position pdfium expects.
+    pub fn index(self) -> u16 {
+        self.0.saturating_sub(1)
+    }
+}
+
+impl fmt::Display for PageNumber {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+// Deeper /PageLabels trees are treated as malformed (or cyclic)
+const MAX_LABEL_TREE_DEPTH: usize = 32;
+// Higher /St values are clamped, since roman and letter labels grow with the number
+const MAX_LABEL_START: usize = 100_000;
+
+/// The printed label of every page, in page order, from the document's
+/// /PageLabels tree: front matter numbered `i`, `ii`, ... before the body
+/// restarts at `1`, say. `None` when the PDF defines no labels.
+///
+/// Pages before the first labelled range get their page number.
+pub fn page_labels(doc: &Document) -> Option<Vec<String>> {
+    let tree = doc.catalog().ok()?.get(b"PageLabels").ok()?;
+    let mut ranges = Vec::new();
+    collect_label_ranges(doc, tree, &mut ranges, 0);
+    if ranges.is_empty() {
+        return None;
+    }
+    ranges.sort_by_key(|(start, _)| *start);
+
+    let labels = (0..doc.get_pages().len())
+        .map(|index| match ranges.iter().rev().find(|(start, _)| *start <= index) {
+            Some((start, range)) => range.label(index - start),
+            None => (index + 1).to_string(),
+        })
+        .collect();
+    Some(labels)
+}
+
+// The number tree's entries as (first page index, range), from /Nums and any /Kids
+fn collect_label_ranges(doc: &Document, node: &Object, ranges: &mut Vec<(usize, LabelRange)>, depth: usize) {
+    if depth > MAX_LABEL_TREE_DEPTH {
+        return;
+    }
+    let Some(node) = doc.dereference(node).ok().and_then(|(_, node)| node.as_dict().ok()) else {
+        return;
+    };
+    if let Ok(nums) = node.get(b"Nums").and_then(Object::as_array) {
+        for entry in nums.chunks_exact(2) {
+            let start = entry[0].as_i64().ok().and_then(|start| usize::try_from(start).ok());
+            let range = doc.dereference(&entry[1]).ok().and_then(|(_, range)| range.as_dict().ok());
+            if let (Some(start), Some(range)) = (start, range) {
+                ranges.push((start, LabelRange::from_dict(range)));
+            }
+        }
+    }
+    if let Ok(kids) = node.get(b"Kids").and_then(Object::as_array) {
+        for kid in kids {
+            collect_label_ranges(doc, kid, ranges, depth + 1);
+        }
+    }
+}
+
+/// One /PageLabels range: pages from its start are `prefix` followed by a
+/// number in `style` counting up from `first`.
+struct LabelRange {
+    /// `D`, `R`, `r`, `A` or `a`; empty means the prefix alone.
+    style: Vec<u8>,
+    prefix: String,
+    first: usize,
+}
+
+impl LabelRange {
+    fn from_dict(dict: &Dictionary) -> Self {
+        LabelRange {
+            style: dict.get(b"S").and_then(Object::as_name).map(<[u8]>::to_vec).unwrap_or_default(),
+            prefix: dict.get(b"P").ok().and_then(|p| lopdf::decode_text_string(p).ok()).unwrap_or_default(),
+            first: dict
+                .get(b"St")
+                .and_then(Object::as_i64)
+                .ok()
+                .and_then(|first| usize::try_from(first).ok())
+                .filter(|&first| first >= 1)
+                .map_or(1, |first| first.min(MAX_LABEL_START)),
+        }
+    }
+
+    // Label of the page `offset` pages into the range
+    fn label(&self, offset: usize) -> String {
+        let n = self.first + offset;
+        let number = match self.style.as_slice() {
+            b"D" => n.to_string(),
+            b"R" => roman(n),
+            b"r" => roman(n).to_lowercase(),
+            b"A" => letters(n),
+            b"a" => letters(n).to_lowercase(),
+            _ => String::new(),
+        };
+        format!("{}{}", self.prefix, number)
+    }
+}
+
+fn roman(mut n: usize) -> String {
+    const NUMERALS: [(usize, &str); 13] = [
+        (1000, "M"), (900, "CM"), (500, "D"), (400, "CD"), (100, "C"), (90, "XC"),
+        (50, "L"), (40, "XL"), (10, "X"), (9, "IX"), (5, "V"), (4, "IV"), (1, "I"),
+    ];
+    let mut numeral = String::new();
+    for (value, symbol) in NUMERALS {
+        while n >= value {
+            numeral.push_str(symbol);
+            n -= value;
+        }
+    }
+    numeral
+}
+
+// A to Z, then AA to ZZ, AAA and so on, as PDF letter labels count
+fn letters(n: usize) -> String {
+    let letter = (b'A' + ((n - 1) % 26) as u8) as char;
+    letter.to_string().repeat((n - 1) / 26 + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_pdf::TestPdf;
+    use lopdf::dictionary;
+
+    // A document of `pages` empty pages with `labels` as its /PageLabels tree
+    fn labelled_doc(pages: usize, labels: Dictionary) -> Document {
+        let mut pdf = TestPdf::new();
+        for _ in 0..pages {
+            pdf.page_with(vec![], Dictionary::new());
+        }
+        pdf.finish(dictionary! { "PageLabels" => labels })
+    }
+
+    #[test]
+    fn next_page_stops_at_the_last_numberable_page() {
+        assert_eq!(PageNumber::new(1).next(), Some(PageNumber::new(2)));
+        assert_eq!(PageNumber::new(7).next().map(PageNumber::index), Some(7));
+        assert_eq!(PageNumber::new(u16::MAX).next(), None);
+    }
+
+    #[test]
+    fn front_matter_keeps_its_roman_numerals() {
+        let labels = dictionary! {
+            "Nums" => vec![
+                0.into(), dictionary! { "S" => "r" }.into(),
+                3.into(), dictionary! { "S" => "D" }.into(),
+                5.into(), dictionary! { "S" => "A", "P" => Object::string_literal("App. "), "St" => 26 }.into(),
+            ],
+        };
+        let doc = labelled_doc(7, labels);
+        assert_eq!(page_labels(&doc).unwrap(), ["i", "ii", "iii", "1", "2", "App. Z", "App. AA"]);
+
+        assert_eq!(page_labels(&labelled_doc(2, dictionary! {})), None);
+    }
+
+    #[test]
+    fn hostile_label_start_is_clamped() {
+        let labels = dictionary! {
+            "Nums" => vec![
+                0.into(), dictionary! { "S" => "R", "St" => i64::MAX }.into(),
+                1.into(), dictionary! { "S" => "A", "St" => i64::MAX }.into(),
+            ],
+        };
+        let labels = page_labels(&labelled_doc(2, labels)).unwrap();
+        // 100_000 is a hundred Ms, and the 100_000th letter label 3847 letters
+        assert_eq!(labels[0], "M".repeat(100));
+        assert_eq!(labels[1].len(), 3847);
+    }
+
+    #[test]
+    fn first_page_is_pdfium_index_zero() {
+        assert_eq!(PageNumber::new(1).index(), 0);
+        assert_eq!(PageNumber::from_index(0), PageNumber::new(1));
+        assert_eq!(PageNumber::from_index(PageNumber::new(7).index()).get(), 7);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "1-indexed")]
+    fn zero_is_rejected_in_debug_builds() {
+        PageNumber::new(0);
+    }
+}
@@ -1,14 +1,28 @@
 use qdrant_client::Qdrant;
 use qdrant_client::QdrantError;
+use qdrant_client::qdrant::DeletePointsBuilder;
 use qdrant_client::qdrant::Distance;
+use qdrant_client::qdrant::ScoredPoint;
+use qdrant_client::qdrant::ScrollPointsBuilder;
 use qdrant_client::qdrant::SearchPointsBuilder;
 use qdrant_client::qdrant::SearchResponse;
 use qdrant_client::qdrant::UpsertPointsBuilder;
-use qdrant_client::qdrant::{Condition, CreateCollectionBuilder, Filter, VectorParamsBuilder};
-use qdrant_client::qdrant::{PointStruct, Value};
+use qdrant_client::qdrant::{Condition, CountPointsBuilder, CreateCollectionBuilder, Datatype, Filter, Range, VectorParamsBuilder};
+use qdrant_client::qdrant::{PayloadIncludeSelector, PointId, PointStruct, Value};
+use qdrant_client::qdrant::with_payload_selector::SelectorOptions;
+use qdrant_client::qdrant::point_id::PointIdOptions;
+use qdrant_client::qdrant::vector_output::Vector;
+use qdrant_client::qdrant::RetrievedPoint;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
+use crate::chunk::{Chunk, ChunkKind, ParentChunk};
+use crate::page::PageNumber;
 use crate::embed;
+use crate::fuzzy;
 
 pub async fn setup_qdrant() -> Result<Qdrant, QdrantError> {
     let client = Qdrant::from_url("http://localhost:6334").build()?;
@@ -21,13 +35,202 @@ pub async fn setup_qdrant() -> Result<Qdrant, QdrantError> {
     Ok(client)
 }
 
-pub async fn init_collection(client: &Qdrant, collection_name: &str) -> Result<(), QdrantError> {
+/// How vectors are compared, both by the collection and when scoring results.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Metric {
+    #[default]
+    Dot,
+    Cosine,
+    /// Qdrant reports a distance, where lower is better.
+    Euclid,
+}
+
+impl Metric {
+    /// Parse `dot`, `cosine` or `euclid` (any case).
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "dot" => Some(Metric::Dot),
+            "cosine" => Some(Metric::Cosine),
+            "euclid" | "euclidean" => Some(Metric::Euclid),
+            _ => None,
+        }
+    }
+
+    pub fn distance(self) -> Distance {
+        match self {
+            Metric::Dot => Distance::Dot,
+            Metric::Cosine => Distance::Cosine,
+            Metric::Euclid => Distance::Euclid,
+        }
+    }
+
+    /// Turn a raw Qdrant score into a similarity where higher is better.
+    /// Euclid distances map to `1 / (1 + d)`, so the nearest point scores highest.
+    pub fn similarity(self, score: f32) -> f32 {
+        match self {
+            Metric::Dot | Metric::Cosine => score,
+            Metric::Euclid => 1.0 / (1.0 + score.max(0.0)),
+        }
+    }
+
+    /// Carry a threshold given as a cosine similarity over to this metric's
+    /// scale, so one setting means the same closeness under every metric.
+    /// Euclid assumes unit vectors, where `d² = 2 - 2·cos`.
+    pub fn threshold(self, cosine: f32) -> f32 {
+        match self {
+            Metric::Dot | Metric::Cosine => cosine,
+            Metric::Euclid => self.similarity((2.0 - 2.0 * cosine).max(0.0).sqrt()),
+        }
+    }
+
+    /// Similarity of two vectors on the same scale as [`Metric::similarity`].
+    pub fn compare(self, a: &[f32], b: &[f32]) -> f32 {
+        let dot = |x: &[f32], y: &[f32]| x.iter().zip(y).map(|(x, y)| x * y).sum::<f32>();
+        match self {
+            Metric::Dot => dot(a, b),
+            Metric::Cosine => {
+                let norms = dot(a, a).sqrt() * dot(b, b).sqrt();
+                if norms == 0.0 { 0.0 } else { dot(a, b) / norms }
+            }
+            Metric::Euclid => {
+                let distance = a.iter().zip(b).map(|(x, y)| (x - y) * (x - y)).sum::<f32>().sqrt();
+                self.similarity(distance)
+            }
+        }
+    }
+}
+
+/// Embeddings whose length is off from 1 while the collection ranks by Dot,
+/// so scores mix vector length into similarity.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NotNormalized {
+    /// Norm of the vector furthest from unit length.
+    pub norm: f32,
+}
+
+impl fmt::Display for NotNormalized {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "embeddings are not unit-norm (found a norm of {:.4}) but the collection uses Dot; \
+             ranking will favour long vectors. Normalize the model output or set VECTOR_METRIC=cosine",
+            self.norm
+        )
+    }
+}
+
+impl std::error::Error for NotNormalized {}
+
+// Float drift a normalized f32 embedding can show
+const NORM_TOLERANCE: f32 = 1e-3;
+
+/// Check sample embeddings suit `metric`: Dot only ranks like cosine on unit vectors.
+/// Other metrics accept any length.
+pub fn check_normalization(metric: Metric, vectors: &[Vec<f32>]) -> Result<(), NotNormalized> {
+    if metric != Metric::Dot {
+        return Ok(());
+    }
+    let worst = vectors
+        .iter()
+        .map(|v| v.iter().map(|x| x * x).sum::<f32>().sqrt())
+        .max_by(|a, b| (a - 1.0).abs().total_cmp(&(b - 1.0).abs()));
+    match worst {
+        Some(norm) if (norm - 1.0).abs() > NORM_TOLERANCE => Err(NotNormalized { norm }),
+        _ => Ok(()),
+    }
+}
+
+/// How the collection keeps vectors. Half precision halves vector memory for
+/// a small loss of ranking accuracy.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Precision {
+    #[default]
+    F32,
+    F16,
+}
+
+impl Precision {
+    /// Parse `f32` or `f16` (any case).
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "f32" | "float32" => Some(Precision::F32),
+            "f16" | "float16" => Some(Precision::F16),
+            _ => None,
+        }
+    }
+
+    pub fn datatype(self) -> Datatype {
+        match self {
+            Precision::F32 => Datatype::Float32,
+            Precision::F16 => Datatype::Float16,
+        }
+    }
+
+    /// Round `vector` to what the collection will keep, so what's sent is what's stored.
+    pub fn apply(self, vector: Vec<f32>) -> Vec<f32> {
+        match self {
+            Precision::F32 => vector,
+            Precision::F16 => vector.into_iter().map(|x| half::f16::from_f32(x).to_f32()).collect(),
+        }
+    }
+}
+
+/// Prefix of every collection the app creates.
+const COLLECTION_PREFIX: &str = "embedded_pdfs";
+
+/// Collection holding the vectors of one model: the one whose files have
+/// `fingerprint` (see [`crate::embed::model_fingerprint`]), producing
+/// `dim`-dimensional vectors, registered for `lang` (`None` for the default).
+///
+/// Vectors from different models don't compare, so each model gets its own
+/// collection and swapping a model's files never searches the old vectors.
+pub fn model_collection(fingerprint: &str, dim: usize, lang: Option<&str>) -> String {
+    let mut name = format!("{}_{}_{}", COLLECTION_PREFIX, name_part(fingerprint), dim);
+    if let Some(lang) = lang {
+        name.push('_');
+        name.push_str(&name_part(&lang.trim().to_ascii_lowercase()));
+    }
+    name
+}
+
+// ASCII letters and digits as they are, every other byte as `_` and two hex
+// digits, so distinct inputs ("a/b", "a_b") never share a name
+fn name_part(text: &str) -> String {
+    let mut part = String::with_capacity(text.len());
+    for b in text.bytes() {
+        if b.is_ascii_alphanumeric() {
+            part.push(b as char);
+        } else {
+            part.push_str(&format!("_{:02x}", b));
+        }
+    }
+    part
+}
+
+/// Create the collection unless it already exists.
+pub async fn ensure_collection(
+    client: &Qdrant,
+    collection_name: &str,
+    metric: Metric,
+    precision: Precision,
+) -> Result<(), QdrantError> {
+    if !client.collection_exists(collection_name).await? {
+        init_collection(client, collection_name, metric, precision).await?;
+    }
+    Ok(())
+}
+
+pub async fn init_collection(
+    client: &Qdrant,
+    collection_name: &str,
+    metric: Metric,
+    precision: Precision,
+) -> Result<(), QdrantError> {
     client
         .create_collection(
-            CreateCollectionBuilder::new(collection_name).vectors_config(VectorParamsBuilder::new(
-                embed::get_dim() as u64,
-                Distance::Dot,
-            )),
+            CreateCollectionBuilder::new(collection_name).vectors_config(
+                VectorParamsBuilder::new(embed::get_dim() as u64, metric.distance()).datatype(precision.datatype()),
+            ),
         )
         .await?;
     Ok(())
@@ -38,25 +241,197 @@ pub async fn store_embeddings(
     collection_name: &str,
     filename: &str,
     embeddings: embed::Embeddings,
+    precision: Precision,
 ) -> Result<String, QdrantError> {
+    let unique_filename = unique_filename(filename);
+    store_embeddings_as(client, collection_name, &unique_filename, unix_now(), embeddings, precision).await?;
+    Ok(unique_filename)
+}
+
+/// Per-upload name used as the `filename` payload, so re-uploads of the same file don't collide.
+pub fn unique_filename(filename: &str) -> String {
+    format!("{}_{}", filename, uuid::Uuid::new_v4())
+}
+
+/// Seconds since the Unix epoch, as stored in the `uploaded_at` payload.
+pub fn unix_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64)
+}
+
+/// Only match points uploaded inside this window (Unix seconds, inclusive).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct UploadedRange {
+    pub after: Option<i64>,
+    pub before: Option<i64>,
+}
+
+impl UploadedRange {
+    pub fn is_unbounded(&self) -> bool {
+        self.after.is_none() && self.before.is_none()
+    }
+}
+
+/// Points belonging to `filename`, optionally restricted to an upload window.
+pub fn document_filter(filename: &str, uploaded: UploadedRange) -> Filter {
+    let mut filter = library_filter(uploaded);
+    filter.must.insert(0, Condition::matches("filename", filename.to_string()));
+    filter
+}
+
+/// Points of every document, optionally restricted to an upload window.
+pub fn library_filter(uploaded: UploadedRange) -> Filter {
+    let mut conditions = vec![];
+    if !uploaded.is_unbounded() {
+        conditions.push(Condition::range(
+            "uploaded_at",
+            Range {
+                gte: uploaded.after.map(|t| t as f64),
+                lte: uploaded.before.map(|t| t as f64),
+                ..Default::default()
+            },
+        ));
+    }
+    Filter::must(conditions)
+}
+
+/// [`document_filter`], further limited to the given point ids when any are
+/// passed, so a follow-up search only ranks an earlier search's hits.
+pub fn scoped_filter(filename: &str, uploaded: UploadedRange, within_ids: &[String]) -> Filter {
+    let mut filter = document_filter(filename, uploaded);
+    if !within_ids.is_empty() {
+        filter.must.push(Condition::has_id(within_ids.iter().cloned()));
+    }
+    filter
+}
+
+/// Payload fields a search returns unless asked for others.
+pub const DEFAULT_PAYLOAD_FIELDS: &[&str] = &["text", "page"];
+
+/// What a search may match and what it returns for each hit.
+#[derive(Debug, Clone)]
+pub struct QueryOptions {
+    pub uploaded: UploadedRange,
+    /// Only rank these point ids; empty means every point of the document.
+    pub within_ids: Vec<String>,
+    /// Payload fields to return with each hit; anything else stays in Qdrant.
+    pub payload_fields: Vec<String>,
+    pub metric: Metric,
+    /// Embed the query with this language's model; `None` uses the default model.
+    pub lang: Option<String>,
+    /// Rerank with Maximal Marginal Relevance at this lambda: 1.0 is plain
+    /// relevance, lower values trade score for passages unlike those already picked.
+    pub diversity: Option<f32>,
+    /// Keyword scoring matches accented letters to their plain form.
+    pub fold_diacritics: bool,
+    /// Keyword scoring keeps letter case instead of folding it.
+    pub case_sensitive: bool,
+    /// Hits a vector search returns; raise it to rerank a wider pool yourself.
+    pub limit: usize,
+}
+
+impl Default for QueryOptions {
+    fn default() -> Self {
+        QueryOptions {
+            uploaded: UploadedRange::default(),
+            within_ids: vec![],
+            payload_fields: DEFAULT_PAYLOAD_FIELDS.iter().map(|f| f.to_string()).collect(),
+            metric: Metric::default(),
+            lang: None,
+            diversity: None,
+            fold_diacritics: false,
+            case_sensitive: false,
+            limit: RESULT_LIMIT,
+        }
+    }
+}
+
+fn payload_selector(fields: &[String]) -> SelectorOptions {
+    SelectorOptions::Include(PayloadIncludeSelector { fields: fields.to_vec() })
+}
+
+/// Drop every payload field not in `fields`.
+pub fn select_payload(payload: &mut HashMap<String, Value>, fields: &[String]) {
+    payload.retain(|key, _| fields.contains(key));
+}
+
+/// A point id as the string clients pass back in `within_ids`.
+pub fn point_id_string(id: &PointId) -> Option<String> {
+    match id.point_id_options.as_ref()? {
+        PointIdOptions::Uuid(uuid) => Some(uuid.clone()),
+        PointIdOptions::Num(num) => Some(num.to_string()),
+    }
+}
+
+/// Position of each body chunk among the body chunks of its page, in input
+/// order. Headings, form fields, annotations and captions aren't part of the running text
+/// and get `None`.
+fn page_sequence(chunks: &[Chunk]) -> Vec<Option<u32>> {
+    let mut next: HashMap<PageNumber, u32> = HashMap::new();
+    chunks
+        .iter()
+        .map(|chunk| {
+            (chunk.kind == ChunkKind::Body).then(|| {
+                let seq = next.entry(chunk.page).or_default();
+                *seq += 1;
+                *seq - 1
+            })
+        })
+        .collect()
+}
+
+fn chunk_payload(
+    unique_filename: &str,
+    uploaded_at: i64,
+    chunk: &Chunk,
+    seq: Option<u32>,
+) -> HashMap<String, Value> {
+    let mut payload = HashMap::new();
+    payload.insert("filename".to_string(), Value::from(unique_filename.to_string()));
+    payload.insert("text".to_string(), Value::from(chunk.content.clone()));
+    payload.insert("page".to_string(), Value::from(chunk.page.get() as f32));
+    payload.insert("kind".to_string(), Value::from(chunk.kind.as_str()));
+    payload.insert("uploaded_at".to_string(), Value::from(uploaded_at));
+    if let Some(seq) = seq {
+        payload.insert("seq".to_string(), Value::from(seq as i64));
+    }
+    if let ChunkKind::FormField(name) = &chunk.kind {
+        payload.insert("field".to_string(), Value::from(name.clone()));
+    }
+    if let Some(parent) = &chunk.parent {
+        payload.insert("parent_id".to_string(), Value::from(parent.id.clone()));
+        payload.insert("parent_text".to_string(), Value::from(parent.content.clone()));
+    }
+    payload
+}
+
+/// Append embeddings under an existing unique filename (e.g. one page at a time).
+/// `uploaded_at` (Unix seconds) should be the same for every page of one upload.
+/// A page's chunks must arrive in a single call for their `seq` numbers to line up.
+pub async fn store_embeddings_as(
+    client: &Qdrant,
+    collection_name: &str,
+    unique_filename: &str,
+    uploaded_at: i64,
+    embeddings: embed::Embeddings,
+    precision: Precision,
+) -> Result<(), QdrantError> {
     assert_eq!(
         embeddings.original.len(),
         embeddings.embedded.len(),
         "Original and embedded vectors must have the same length"
     );
 
-    let unique_filename = format!("{}_{}", filename, uuid::Uuid::new_v4());
-
+    let sequence = page_sequence(&embeddings.original);
     let points: Vec<PointStruct> = embeddings
         .original
         .into_iter()
         .zip(embeddings.embedded)
-        .map(|(chunk, embedding)| {
-            let mut payload = HashMap::new();
-            payload.insert("filename".to_string(), Value::from(unique_filename.clone()));
-            payload.insert("text".to_string(), Value::from(chunk.content.clone()));
-            payload.insert("page".to_string(), Value::from(chunk.page as f32));
-            PointStruct::new(uuid::Uuid::new_v4().to_string(), embedding, payload)
+        .zip(sequence)
+        .map(|((chunk, embedding), seq)| {
+            let payload = chunk_payload(unique_filename, uploaded_at, &chunk, seq);
+            PointStruct::new(uuid::Uuid::new_v4().to_string(), precision.apply(embedding), payload)
         })
         .collect();
 
@@ -64,7 +439,7 @@ pub async fn store_embeddings(
         .upsert_points(UpsertPointsBuilder::new(collection_name, points).wait(true))
         .await?;
     dbg!(response);
-    Ok(unique_filename)
+    Ok(())
 }
 
 pub async fn run_query(
@@ -72,29 +447,534 @@ pub async fn run_query(
     collection_name: &str,
     filename: &str,
     query: &str,
+    options: &QueryOptions,
 ) -> Result<SearchResponse, anyhow::Error> {
-    let emb_query = match embed::embed_query(query) {
-        Ok(embedding) => embedding,
+    let embed = async { embed::embed_query_in(query, options.lang.as_deref()) };
+    let (response, _) = run_query_with(client, collection_name, filename, query, options, embed).await?;
+    Ok(response)
+}
+
+/// Like [`run_query`], with the query embedded by `embed` (e.g. one that waits
+/// its turn for the model). Also returns how long `embed` took, so callers
+/// can time embedding apart from the search.
+pub async fn run_query_with<F>(
+    client: &Qdrant,
+    collection_name: &str,
+    filename: &str,
+    query: &str,
+    options: &QueryOptions,
+    embed: F,
+) -> Result<(SearchResponse, Duration), anyhow::Error>
+where
+    F: Future<Output = Result<Vec<f32>, anyhow::Error>>,
+{
+    let started = Instant::now();
+    let embedded = embed.await;
+    let embed_time = started.elapsed();
+    let response = vector_or_keyword(
+        embedded,
+        |emb_query| run_vector_query(client, collection_name, filename, emb_query, options),
+        || run_keyword_query(client, collection_name, filename, query, options),
+    )
+    .await?;
+    Ok((response, embed_time))
+}
+
+// Search with the embedded query, or by keyword when it couldn't be embedded
+async fn vector_or_keyword<V, K>(
+    embedded: Result<Vec<f32>, anyhow::Error>,
+    vector: impl FnOnce(Vec<f32>) -> V,
+    keyword: impl FnOnce() -> K,
+) -> Result<SearchResponse, anyhow::Error>
+where
+    V: Future<Output = Result<SearchResponse, anyhow::Error>>,
+    K: Future<Output = Result<SearchResponse, anyhow::Error>>,
+{
+    match embedded {
+        Ok(emb_query) => vector(emb_query).await,
         Err(e) => {
-            eprintln!("Failed to embed query: {}", e);
-            return Err(e);
+            eprintln!("Failed to embed query, falling back to keyword search: {}", e);
+            keyword().await
         }
-    };
+    }
+}
 
-    let filename_filter = Filter::must([Condition::matches("filename", filename.to_string())]);
+/// Like [`run_query`], for a query that is already embedded.
+pub async fn run_vector_query(
+    client: &Qdrant,
+    collection_name: &str,
+    filename: &str,
+    emb_query: Vec<f32>,
+    options: &QueryOptions,
+) -> Result<SearchResponse, anyhow::Error> {
+    let filter = scoped_filter(filename, options.uploaded, &options.within_ids);
+    search_filtered(client, collection_name, filter, emb_query, options).await
+}
 
-    let search_result = client
+/// Like [`run_vector_query`], across every document in the collection. Each
+/// hit's payload also carries its `filename`, to tell the documents apart.
+pub async fn run_library_query(
+    client: &Qdrant,
+    collection_name: &str,
+    emb_query: Vec<f32>,
+    options: &QueryOptions,
+) -> Result<SearchResponse, anyhow::Error> {
+    let mut options = options.clone();
+    if !options.payload_fields.iter().any(|f| f == "filename") {
+        options.payload_fields.push("filename".to_string());
+    }
+    search_filtered(client, collection_name, library_filter(options.uploaded), emb_query, &options).await
+}
+
+async fn search_filtered(
+    client: &Qdrant,
+    collection_name: &str,
+    filter: Filter,
+    emb_query: Vec<f32>,
+    options: &QueryOptions,
+) -> Result<SearchResponse, anyhow::Error> {
+    // MMR needs a wider pool to pick from, and the vectors to compare
+    let limit = if options.diversity.is_some() { options.limit * MMR_POOL_FACTOR } else { options.limit };
+
+    let mut search_result = client
         .search_points(
-            SearchPointsBuilder::new(collection_name, emb_query, 5)
-                .filter(filename_filter)
-                .with_payload(true)
+            SearchPointsBuilder::new(collection_name, emb_query, limit as u64)
+                .filter(filter)
+                .with_payload(payload_selector(&options.payload_fields))
+                .with_vectors(options.diversity.is_some())
                 .build(),
         )
         .await?;
 
+    // Callers rank by score descending, like the keyword fallback's scores
+    as_similarities(&mut search_result.result, options.metric);
+    if let Some(lambda) = options.diversity {
+        search_result.result = diversify(std::mem::take(&mut search_result.result), lambda, options.metric, options.limit);
+    }
     Ok(search_result)
 }
 
+/// Hits returned per query.
+pub const RESULT_LIMIT: usize = 5;
+// Candidates fetched per returned hit when reranking for diversity
+const MMR_POOL_FACTOR: usize = 4;
+
+// Keep the `limit` points MMR picks, without their vectors
+fn diversify(points: Vec<ScoredPoint>, lambda: f32, metric: Metric, limit: usize) -> Vec<ScoredPoint> {
+    let vectors: Vec<Vec<f32>> = points
+        .iter()
+        .map(|point| match point.vectors.as_ref().and_then(|v| v.get_vector()) {
+            Some(Vector::Dense(dense)) => dense.data,
+            _ => vec![],
+        })
+        .collect();
+    let relevance: Vec<f32> = points.iter().map(|point| point.score).collect();
+    let order = mmr_order(&relevance, &vectors, lambda, metric, limit);
+
+    let mut points: Vec<Option<ScoredPoint>> = points.into_iter().map(Some).collect();
+    order
+        .into_iter()
+        .filter_map(|i| points[i].take())
+        .map(|point| ScoredPoint { vectors: None, ..point })
+        .collect()
+}
+
+/// Maximal Marginal Relevance: pick up to `k` candidates, each time taking the
+/// one maximizing `lambda * relevance - (1 - lambda) * (closest similarity to
+/// anything already picked)`. Returns candidate indices in pick order.
+pub fn mmr_order(relevance: &[f32], vectors: &[Vec<f32>], lambda: f32, metric: Metric, k: usize) -> Vec<usize> {
+    let mut picked: Vec<usize> = Vec::with_capacity(k.min(relevance.len()));
+    while picked.len() < k {
+        let marginal = |i: usize| {
+            let redundancy = picked
+                .iter()
+                .map(|&j| metric.compare(&vectors[i], &vectors[j]))
+                .fold(None, |max: Option<f32>, s| Some(max.map_or(s, |m| m.max(s))))
+                .unwrap_or(0.0);
+            lambda * relevance[i] - (1.0 - lambda) * redundancy
+        };
+        let best = (0..relevance.len())
+            .filter(|i| !picked.contains(i))
+            .max_by(|&a, &b| marginal(a).total_cmp(&marginal(b)).then(b.cmp(&a)));
+        match best {
+            Some(i) => picked.push(i),
+            None => break,
+        }
+    }
+    picked
+}
+
+fn as_similarities(points: &mut [ScoredPoint], metric: Metric) {
+    for point in points {
+        point.score = metric.similarity(point.score);
+    }
+}
+
+/// Keyword scan over a document's stored chunk texts, shaped like a vector search response.
+pub async fn run_keyword_query(
+    client: &Qdrant,
+    collection_name: &str,
+    filename: &str,
+    query: &str,
+    options: &QueryOptions,
+) -> Result<SearchResponse, anyhow::Error> {
+    let filename_filter = scoped_filter(filename, options.uploaded, &options.within_ids);
+    // Scoring needs the text even when the caller didn't ask for it
+    let mut fetched = options.payload_fields.clone();
+    if !fetched.iter().any(|f| f == "text") {
+        fetched.push("text".to_string());
+    }
+
+    let mut scored: Vec<ScoredPoint> = Vec::new();
+    let mut offset = None;
+    loop {
+        let mut builder = ScrollPointsBuilder::new(collection_name)
+            .filter(filename_filter.clone())
+            .limit(256)
+            .with_payload(payload_selector(&fetched));
+        if let Some(offset) = offset {
+            builder = builder.offset(offset);
+        }
+        let page = client.scroll(builder).await?;
+
+        scored.extend(page.result.into_iter().filter_map(|point| keyword_hit(point, query, options)));
+
+        offset = page.next_page_offset;
+        if offset.is_none() {
+            break;
+        }
+    }
+
+    Ok(SearchResponse {
+        result: best_hits(scored),
+        ..Default::default()
+    })
+}
+
+// The RESULT_LIMIT best-scoring hits, best first, as a vector search returns them
+fn best_hits(mut scored: Vec<ScoredPoint>) -> Vec<ScoredPoint> {
+    scored.sort_by(|a, b| b.score.total_cmp(&a.score));
+    scored.truncate(RESULT_LIMIT);
+    scored
+}
+
+/// `point` scored against `query` the way [`run_keyword_query`] does, with
+/// only the requested payload fields; `None` if none of the query is in its text.
+pub fn keyword_hit(point: RetrievedPoint, query: &str, options: &QueryOptions) -> Option<ScoredPoint> {
+    let matching = fuzzy::FuzzyConfig {
+        fold_diacritics: options.fold_diacritics,
+        case_sensitive: options.case_sensitive,
+        ..fuzzy::FuzzyConfig::default()
+    };
+    let text = point.payload.get("text")?.as_str()?;
+    let score = fuzzy::keyword_score_with(text, query, &matching);
+    if score <= 0.0 {
+        return None;
+    }
+    let mut payload = point.payload;
+    select_payload(&mut payload, &options.payload_fields);
+    Some(ScoredPoint { id: point.id, payload, score, ..Default::default() })
+}
+
+pub async fn delete_by_filename(
+    client: &Qdrant,
+    collection_name: &str,
+    filename: &str,
+) -> Result<(), QdrantError> {
+    let filename_filter = Filter::must([Condition::matches("filename", filename.to_string())]);
+
+    client
+        .delete_points(
+            DeletePointsBuilder::new(collection_name)
+                .points(filename_filter)
+                .wait(true),
+        )
+        .await?;
+    Ok(())
+}
+
+/// Text of the body chunks stored right before and after `seq` on `page`.
+pub async fn page_neighbors(
+    client: &Qdrant,
+    collection_name: &str,
+    filename: &str,
+    page: PageNumber,
+    seq: u32,
+) -> Result<(Option<String>, Option<String>), QdrantError> {
+    let filter = Filter::must([
+        Condition::matches("filename", filename.to_string()),
+        // Pages are stored as floats, so match them with a closed range
+        Condition::range(
+            "page",
+            Range { gte: Some(page.get() as f64), lte: Some(page.get() as f64), ..Default::default() },
+        ),
+        Condition::range(
+            "seq",
+            Range {
+                gte: Some(seq.saturating_sub(1) as f64),
+                lte: Some(seq as f64 + 1.0),
+                ..Default::default()
+            },
+        ),
+    ]);
+    let found = client
+        .scroll(ScrollPointsBuilder::new(collection_name).filter(filter).limit(3).with_payload(true))
+        .await?;
+    let payloads: Vec<_> = found.result.into_iter().map(|point| point.payload).collect();
+    Ok(neighbor_texts(&payloads, seq))
+}
+
+// Pick the `seq - 1` and `seq + 1` texts out of the scrolled payloads
+fn neighbor_texts(payloads: &[HashMap<String, Value>], seq: u32) -> (Option<String>, Option<String>) {
+    let text_at = |wanted: u32| {
+        payloads
+            .iter()
+            .find(|payload| payload.get("seq").and_then(|v| v.as_integer()) == Some(wanted as i64))
+            .and_then(|payload| payload.get("text")?.as_str().cloned())
+    };
+    let before = seq.checked_sub(1).and_then(text_at);
+    (before, text_at(seq + 1))
+}
+
+/// Number of points stored for `filename`, counted exactly.
+pub async fn count_points(client: &Qdrant, collection_name: &str, filename: &str) -> Result<u64, QdrantError> {
+    let filter = Filter::must([Condition::matches("filename", filename.to_string())]);
+    let response = client
+        .count(CountPointsBuilder::new(collection_name).filter(filter).exact(true))
+        .await?;
+    Ok(response.result.map_or(0, |result| result.count))
+}
+
+/// A stored chunk with the vector it was indexed under.
+#[derive(Debug, Clone)]
+pub struct StoredChunk {
+    pub page: PageNumber,
+    pub text: String,
+    pub vector: Vec<f32>,
+}
+
+/// Every chunk stored for `filename`, vectors included.
+pub async fn document_chunks(
+    client: &Qdrant,
+    collection_name: &str,
+    filename: &str,
+) -> Result<Vec<StoredChunk>, QdrantError> {
+    let filter = Filter::must([Condition::matches("filename", filename.to_string())]);
+
+    let mut chunks = Vec::new();
+    let mut offset = None;
+    loop {
+        let mut builder = ScrollPointsBuilder::new(collection_name)
+            .filter(filter.clone())
+            .limit(256)
+            .with_payload(true)
+            .with_vectors(true);
+        if let Some(offset) = offset {
+            builder = builder.offset(offset);
+        }
+        let page = client.scroll(builder).await?;
+
+        chunks.extend(page.result.into_iter().filter_map(stored_chunk));
+
+        offset = page.next_page_offset;
+        if offset.is_none() {
+            break;
+        }
+    }
+    Ok(chunks)
+}
+
+// None for a point missing its text or vector, or whose page isn't a page number
+fn stored_chunk(point: RetrievedPoint) -> Option<StoredChunk> {
+    let text = point.payload.get("text")?.as_str()?.to_string();
+    let stored = point.payload.get("page")?.as_double()?;
+    let Some(page) = stored_page(stored) else {
+        eprintln!("Skipping stored chunk with unusable page {}: {:?}", stored, point.id);
+        return None;
+    };
+    let vector = match point.vectors?.get_vector()? {
+        Vector::Dense(dense) => dense.data,
+        _ => return None,
+    };
+    Some(StoredChunk { page, text, vector })
+}
+
+// A page payload as written at upload: a whole number from 1 to u16::MAX
+fn stored_page(page: f64) -> Option<PageNumber> {
+    if page.fract() != 0.0 || !(1.0..=u16::MAX as f64).contains(&page) {
+        return None;
+    }
+    Some(PageNumber::new(page as u16))
+}
+
+/// One stored point as the export endpoint writes it and the import endpoint reads it.
+///
+/// The optional fields are left out when unset, and exports written before
+/// they existed import as body text.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExportedPoint {
+    pub id: String,
+    pub text: String,
+    pub page: PageNumber,
+    pub vector: Vec<f32>,
+    /// Payload `kind`, e.g. `heading`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+    /// Form field name, for `form_field` chunks.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub field: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent_text: Option<String>,
+    /// Position among the body chunks of its page.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seq: Option<u32>,
+    /// Language of the document's model; the export endpoint fills it in.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lang: Option<String>,
+}
+
+impl ExportedPoint {
+    /// A body chunk with none of the optional fields.
+    pub fn new(id: String, text: String, page: PageNumber, vector: Vec<f32>) -> Self {
+        ExportedPoint { id, text, page, vector, kind: None, field: None, parent_id: None, parent_text: None, seq: None, lang: None }
+    }
+}
+
+/// One scroll page of an export; see [`export_page`].
+pub struct ExportPage {
+    pub points: Vec<ExportedPoint>,
+    /// Points missing their id, text, page or vector, left out of `points`.
+    pub skipped: usize,
+    /// The offset the next page starts at, `None` after the last.
+    pub next: Option<PointId>,
+}
+
+/// One scroll page of `filename`'s points, vectors included. Start with
+/// `offset: None`. Paging lets a large document be streamed out without
+/// holding every vector. A stored page number below 1 fails the page.
+pub async fn export_page(
+    client: &Qdrant,
+    collection_name: &str,
+    filename: &str,
+    offset: Option<PointId>,
+) -> Result<ExportPage, anyhow::Error> {
+    let filter = Filter::must([Condition::matches("filename", filename.to_string())]);
+    let mut builder = ScrollPointsBuilder::new(collection_name)
+        .filter(filter)
+        .limit(256)
+        .with_payload(true)
+        .with_vectors(true);
+    if let Some(offset) = offset {
+        builder = builder.offset(offset);
+    }
+    let page = client.scroll(builder).await?;
+    let mut points = Vec::with_capacity(page.result.len());
+    let mut skipped = 0;
+    for point in page.result {
+        match exported_point(point)? {
+            Some(point) => points.push(point),
+            None => skipped += 1,
+        }
+    }
+    Ok(ExportPage { points, skipped, next: page.next_page_offset })
+}
+
+// `None` when a field the export needs is missing
+fn exported_point(point: RetrievedPoint) -> Result<Option<ExportedPoint>, anyhow::Error> {
+    let Some(id) = point.id.as_ref().and_then(point_id_string) else { return Ok(None) };
+    let Some(text) = point.payload.get("text").and_then(|value| value.as_str()).cloned() else { return Ok(None) };
+    let Some(stored) = point.payload.get("page").and_then(|value| value.as_double()) else { return Ok(None) };
+    let Some(page) = stored_page(stored) else {
+        anyhow::bail!("Point {} has page {}, which isn't a page number", id, stored);
+    };
+    let vector = match point.vectors.as_ref().and_then(|vectors| vectors.get_vector()) {
+        Some(Vector::Dense(dense)) => dense.data,
+        _ => return Ok(None),
+    };
+    let string = |key: &str| point.payload.get(key).and_then(|value| value.as_str()).cloned();
+    let seq = point.payload.get("seq").and_then(|value| value.as_integer()).and_then(|seq| u32::try_from(seq).ok());
+    Ok(Some(ExportedPoint {
+        id,
+        text,
+        page,
+        vector,
+        kind: string("kind"),
+        field: string("field"),
+        parent_id: string("parent_id"),
+        parent_text: string("parent_text"),
+        seq,
+        lang: None,
+    }))
+}
+
+/// Upsert exported points under `unique_filename` without re-embedding them.
+/// See [`import_structs`].
+pub async fn import_points(
+    client: &Qdrant,
+    collection_name: &str,
+    unique_filename: &str,
+    uploaded_at: i64,
+    points: Vec<ExportedPoint>,
+    precision: Precision,
+) -> Result<(), QdrantError> {
+    let points = import_structs(unique_filename, uploaded_at, points, precision);
+    client
+        .upsert_points(UpsertPointsBuilder::new(collection_name, points).wait(true))
+        .await?;
+    Ok(())
+}
+
+/// The points [`import_points`] stores. Each gets a fresh id, so importing an
+/// export twice, or into the instance it came from, never overwrites stored
+/// points. Kinds, parents and positions are kept; vectors are rounded to
+/// `precision`.
+pub fn import_structs(
+    unique_filename: &str,
+    uploaded_at: i64,
+    points: Vec<ExportedPoint>,
+    precision: Precision,
+) -> Vec<PointStruct> {
+    points
+        .into_iter()
+        .map(|point| {
+            let kind = point
+                .kind
+                .as_deref()
+                .and_then(|kind| ChunkKind::parse(kind, point.field.as_deref()))
+                .unwrap_or_default();
+            let parent = point
+                .parent_id
+                .zip(point.parent_text)
+                .map(|(id, content)| ParentChunk { id, content });
+            let chunk = Chunk { content: point.text, page: point.page, kind, parent };
+            let payload = chunk_payload(unique_filename, uploaded_at, &chunk, point.seq);
+            PointStruct::new(uuid::Uuid::new_v4().to_string(), precision.apply(point.vector), payload)
+        })
+        .collect()
+}
+
+/// Inverse of [`point_id_string`]: digits are numeric ids, anything else a UUID.
+pub fn point_id_from_string(id: &str) -> PointId {
+    match id.parse::<u64>() {
+        Ok(num) => PointId::from(num),
+        Err(_) => PointId::from(id),
+    }
+}
+
+/// Names of every collection on the server.
+pub async fn list_collections(client: &Qdrant) -> Result<Vec<String>, QdrantError> {
+    let collections = client.list_collections().await?;
+    Ok(collections.collections.into_iter().map(|c| c.name).collect())
+}
+
+/// Drop one collection and all its points.
+pub async fn delete_collection(client: &Qdrant, collection_name: &str) -> Result<(), QdrantError> {
+    client.delete_collection(collection_name).await?;
+    Ok(())
+}
+
 pub async fn delete_all_collections(client: &Qdrant) -> Result<(), Box<dyn std::error::Error>> {
     // Get list of all collections
     let collections = client.list_collections().await?;
@@ -108,3 +988,377 @@ pub async fn delete_all_collections(client: &Qdrant) -> Result<(), Box<dyn std::
     println!("All collections deleted!");
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use qdrant_client::qdrant::condition::ConditionOneOf;
+
+    fn stored_at(filename: &str, uploaded_at: i64) -> HashMap<String, Value> {
+        let chunk = Chunk {
+            content: "Quarterly revenue grew by twelve percent.".to_string(),
+            page: PageNumber::new(1),
+            kind: ChunkKind::Body,
+            parent: None,
+        };
+        chunk_payload(filename, uploaded_at, &chunk, Some(0))
+    }
+
+    fn body(page: u16, content: &str) -> Chunk {
+        Chunk { content: content.to_string(), page: PageNumber::new(page), kind: ChunkKind::Body, parent: None }
+    }
+
+    #[test]
+    fn each_model_searches_only_its_own_collection() {
+        let minilm = model_collection("3f2a9c0d1e4b5a67", 384, None);
+        assert_eq!(minilm, "embedded_pdfs_3f2a9c0d1e4b5a67_384");
+        // Switching back finds the first model's vectors again
+        assert_eq!(model_collection("3f2a9c0d1e4b5a67", 384, None), minilm);
+        assert_ne!(model_collection("77aa9c0d1e4b5a67", 384, None), minilm);
+        assert_ne!(model_collection("3f2a9c0d1e4b5a67", 768, None), minilm);
+        assert_eq!(model_collection("3f2a9c0d1e4b5a67", 384, Some(" DE ")), "embedded_pdfs_3f2a9c0d1e4b5a67_384_de");
+    }
+
+    #[test]
+    fn collection_names_never_collide() {
+        let names = ["a/b", "a_b", "a_2fb", "a-b", "a.b"].map(|lang| model_collection("f", 3, Some(lang)));
+        let distinct: std::collections::HashSet<&String> = names.iter().collect();
+        assert_eq!(distinct.len(), names.len(), "{:?}", names);
+        assert!(names.iter().all(|name| name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')));
+    }
+
+    #[test]
+    fn middle_chunk_hit_gets_both_neighbors() {
+        let chunks = vec![
+            body(4, "Convolutions slide a small kernel over the image."),
+            Chunk { kind: ChunkKind::Heading, ..body(4, "4.2 Pooling") },
+            body(4, "Pooling then shrinks each feature map."),
+            body(4, "Stride controls how far the window moves."),
+            body(5, "Fully connected layers come last."),
+        ];
+        let sequence = page_sequence(&chunks);
+        assert_eq!(sequence, [Some(0), None, Some(1), Some(2), Some(0)]);
+
+        // What page_neighbors scrolls back for page 4 around seq 1
+        let payloads: Vec<_> = chunks
+            .iter()
+            .zip(&sequence)
+            .filter(|(chunk, _)| chunk.page == PageNumber::new(4))
+            .map(|(chunk, &seq)| chunk_payload("cnn.pdf_1", 0, chunk, seq))
+            .collect();
+
+        let (before, after) = neighbor_texts(&payloads, 1);
+        assert_eq!(before.as_deref(), Some("Convolutions slide a small kernel over the image."));
+        assert_eq!(after.as_deref(), Some("Stride controls how far the window moves."));
+
+        assert_eq!(neighbor_texts(&payloads, 0).0, None);
+    }
+
+    #[test]
+    fn euclid_distances_rank_nearest_first() {
+        // Distances as Qdrant reports them for a Euclid collection
+        let mut points: Vec<ScoredPoint> = [("far", 1.4), ("nearest", 0.1), ("middle", 0.6)]
+            .into_iter()
+            .map(|(text, score)| ScoredPoint {
+                payload: HashMap::from([("text".to_string(), Value::from(text))]),
+                score,
+                ..Default::default()
+            })
+            .collect();
+
+        as_similarities(&mut points, Metric::Euclid);
+        points.sort_by(|a, b| b.score.total_cmp(&a.score));
+
+        let order: Vec<&str> = points.iter().map(|p| p.payload["text"].as_str().unwrap().as_str()).collect();
+        assert_eq!(order, ["nearest", "middle", "far"]);
+
+        // Comparing vectors directly agrees with the collection's ordering
+        let query = [0.0, 0.0];
+        assert!(Metric::Euclid.compare(&query, &[0.1, 0.0]) > Metric::Euclid.compare(&query, &[0.6, 0.0]));
+        assert_eq!(Metric::Dot.similarity(0.42), 0.42);
+    }
+
+    #[test]
+    fn thresholds_mean_the_same_closeness_under_every_metric() {
+        // Unit vectors at cosine 0.8 to each other
+        let (a, b) = ([1.0, 0.0], [0.8, 0.6]);
+        for metric in [Metric::Dot, Metric::Cosine, Metric::Euclid] {
+            let score = metric.compare(&a, &b);
+            assert!(score >= metric.threshold(0.75), "{:?} dropped the pair", metric);
+            assert!(score < metric.threshold(0.85), "{:?} kept the pair", metric);
+        }
+        assert_eq!(Metric::Euclid.threshold(1.0), 1.0);
+        assert_eq!(Metric::Cosine.threshold(0.85), 0.85);
+    }
+
+    #[test]
+    fn mmr_spreads_picks_across_clusters() {
+        // Three near-duplicates of the best passage, then two distinct topics
+        let vectors = vec![
+            vec![1.0, 0.0, 0.0],
+            vec![0.99, 0.14, 0.0],
+            vec![0.98, 0.0, 0.2],
+            vec![0.0, 1.0, 0.0],
+            vec![0.0, 0.0, 1.0],
+        ];
+        let relevance = [0.95, 0.94, 0.93, 0.70, 0.65];
+        let clusters = |order: &[usize]| {
+            let mut topics: Vec<usize> = order.iter().map(|&i| i.saturating_sub(2)).collect();
+            topics.sort();
+            topics.dedup();
+            topics.len()
+        };
+
+        let top_k = mmr_order(&relevance, &vectors, 1.0, Metric::Dot, 3);
+        assert_eq!(top_k, [0, 1, 2]);
+        assert_eq!(clusters(&top_k), 1);
+
+        let diverse = mmr_order(&relevance, &vectors, 0.5, Metric::Dot, 3);
+        assert_eq!(diverse[0], 0);
+        assert_eq!(clusters(&diverse), 3);
+
+        assert_eq!(mmr_order(&relevance, &vectors, 0.5, Metric::Dot, 10).len(), 5);
+    }
+
+    #[test]
+    fn unnormalized_vectors_fail_the_dot_check() {
+        let unit = vec![0.6, 0.8];
+        let long = vec![3.0, 4.0];
+        assert_eq!(check_normalization(Metric::Dot, std::slice::from_ref(&unit)), Ok(()));
+        assert_eq!(check_normalization(Metric::Dot, &[unit, long.clone()]), Err(NotNormalized { norm: 5.0 }));
+        // Cosine normalizes on its own, so length doesn't matter
+        assert_eq!(check_normalization(Metric::Cosine, &[long]), Ok(()));
+    }
+
+    #[test]
+    fn imported_points_get_fresh_ids_and_keep_their_payload() {
+        let uuid = uuid::Uuid::new_v4().to_string();
+        let points = vec![
+            ExportedPoint {
+                parent_id: Some("5-0".to_string()),
+                parent_text: Some("Momentum smooths updates. It damps oscillation.".to_string()),
+                seq: Some(3),
+                ..ExportedPoint::new(uuid.clone(), "Momentum smooths updates.".to_string(), PageNumber::new(5), vec![0.6, 0.8])
+            },
+            ExportedPoint {
+                kind: Some("form_field".to_string()),
+                field: Some("author".to_string()),
+                ..ExportedPoint::new("42".to_string(), "Ada Lovelace".to_string(), PageNumber::new(1), vec![0.8, 0.6])
+            },
+        ];
+
+        let structs = import_structs("notes.pdf_7", 1_700_000_000, points.clone(), Precision::F32);
+        let ids: Vec<String> = structs.iter().filter_map(|p| p.id.as_ref().and_then(point_id_string)).collect();
+        assert_eq!(ids.len(), 2);
+        assert!(!ids.contains(&uuid) && !ids.contains(&"42".to_string()), "{:?}", ids);
+        let again = import_structs("notes.pdf_8", 1_700_000_000, points, Precision::F32);
+        assert_ne!(again[0].id, structs[0].id);
+
+        let body = &structs[0].payload;
+        assert_eq!(body["filename"].as_str().map(String::as_str), Some("notes.pdf_7"));
+        assert_eq!(body["page"].as_double(), Some(5.0));
+        assert_eq!(body["kind"].as_str().map(String::as_str), Some("body"));
+        assert_eq!(body["seq"].as_integer(), Some(3));
+        assert_eq!(body["parent_id"].as_str().map(String::as_str), Some("5-0"));
+        assert_eq!(body["parent_text"].as_str().map(String::as_str), Some("Momentum smooths updates. It damps oscillation."));
+
+        let field = &structs[1].payload;
+        assert_eq!(field["kind"].as_str().map(String::as_str), Some("form_field"));
+        assert_eq!(field["field"].as_str().map(String::as_str), Some("author"));
+        assert!(!field.contains_key("seq"));
+    }
+
+    #[test]
+    fn half_precision_keeps_the_top_result() {
+        // Deterministic unit vectors in the model's dimension
+        let vector = |seed: u32| -> Vec<f32> {
+            let raw: Vec<f32> = (0..384u32).map(|i| ((i * 31 + seed * 17) % 97) as f32 / 97.0 - 0.5).collect();
+            let norm = raw.iter().map(|x| x * x).sum::<f32>().sqrt();
+            raw.into_iter().map(|x| x / norm).collect()
+        };
+        let stored: Vec<Vec<f32>> = (1..=20).map(vector).collect();
+        // The query sits close to chunk 7
+        let query: Vec<f32> = stored[6].iter().zip(vector(99)).map(|(a, b)| a + 0.3 * b).collect();
+
+        let best = |vectors: &[Vec<f32>]| -> (usize, f32) {
+            vectors
+                .iter()
+                .map(|v| Metric::Dot.compare(&query, v))
+                .enumerate()
+                .max_by(|a, b| a.1.total_cmp(&b.1))
+                .unwrap()
+        };
+        let halved: Vec<Vec<f32>> = stored.iter().cloned().map(|v| Precision::F16.apply(v)).collect();
+
+        let (full_top, full_score) = best(&stored);
+        let (half_top, half_score) = best(&halved);
+        assert_eq!(full_top, 6);
+        assert_eq!(half_top, full_top);
+        assert!((full_score - half_score).abs() < 1e-3);
+        assert_eq!(Precision::F32.apply(stored[0].clone()), stored[0]);
+    }
+
+    #[test]
+    fn refined_search_only_sees_earlier_hits() {
+        let first_hits: Vec<String> = (0..5).map(|_| uuid::Uuid::new_v4().to_string()).collect();
+        let within = vec![first_hits[1].clone(), first_hits[3].clone()];
+
+        let filter = scoped_filter("notes.pdf_1", UploadedRange::default(), &within);
+        assert_eq!(filter.must.len(), 2, "filename match plus the id restriction");
+
+        // What Qdrant is allowed to rank on the second search
+        let allowed: Vec<String> = filter
+            .must
+            .iter()
+            .find_map(|condition| match &condition.condition_one_of {
+                Some(ConditionOneOf::HasId(has_id)) => Some(&has_id.has_id),
+                _ => None,
+            })
+            .unwrap()
+            .iter()
+            .filter_map(point_id_string)
+            .collect();
+        assert_eq!(allowed, within);
+        assert!(allowed.iter().all(|id| first_hits.contains(id)));
+
+        // No ids means no restriction
+        assert_eq!(scoped_filter("notes.pdf_1", UploadedRange::default(), &[]).must.len(), 1);
+    }
+
+    // The `uploaded_at` range condition of a built filter
+    fn uploaded_at_range(filter: &Filter) -> Option<Range> {
+        filter.must.iter().find_map(|condition| match &condition.condition_one_of {
+            Some(ConditionOneOf::Field(field)) if field.key == "uploaded_at" => field.range,
+            _ => None,
+        })
+    }
+
+    #[test]
+    fn upload_window_excludes_older_document() {
+        let last_week = 1_700_000_000;
+        let today = last_week + 7 * 24 * 3600;
+        let stored = [stored_at("old.pdf_1", last_week), stored_at("new.pdf_2", today)];
+
+        // Evaluate the range condition Qdrant gets against each stored payload
+        let kept = |window: UploadedRange| -> Vec<String> {
+            let range = uploaded_at_range(&scoped_filter("notes.pdf_1", window, &[])).unwrap();
+            stored
+                .iter()
+                .filter(|payload| {
+                    let uploaded_at = payload["uploaded_at"].as_integer().unwrap() as f64;
+                    range.gte.is_none_or(|gte| uploaded_at >= gte) && range.lte.is_none_or(|lte| uploaded_at <= lte)
+                })
+                .map(|payload| payload["filename"].as_str().unwrap().clone())
+                .collect()
+        };
+        assert_eq!(kept(UploadedRange { after: Some(today - 24 * 3600), before: None }), ["new.pdf_2"]);
+        assert_eq!(kept(UploadedRange { after: None, before: Some(today - 1) }), ["old.pdf_1"]);
+        // Both bounds are inclusive
+        assert_eq!(kept(UploadedRange { after: Some(last_week), before: Some(today) }), ["old.pdf_1", "new.pdf_2"]);
+
+        // No window, no range condition next to the filename match
+        let unbounded = scoped_filter("notes.pdf_1", UploadedRange::default(), &[]);
+        assert!(uploaded_at_range(&unbounded).is_none());
+        assert_eq!(unbounded.must.len(), 1);
+    }
+
+    #[test]
+    fn only_requested_payload_fields_are_kept() {
+        let mut payload: HashMap<String, Value> = HashMap::from([
+            ("text".to_string(), Value::from("Dropout regularizes deep networks.")),
+            ("page".to_string(), Value::from(3_i64)),
+            ("uploaded_at".to_string(), Value::from(1_700_000_000_i64)),
+        ]);
+        select_payload(&mut payload, &["text".to_string()]);
+
+        assert!(payload.contains_key("text"));
+        assert!(!payload.contains_key("page"));
+        assert_eq!(payload.len(), 1);
+
+        let SelectorOptions::Include(selector) = payload_selector(&["text".to_string()]) else {
+            panic!("expected an include selector");
+        };
+        assert_eq!(selector.fields, vec!["text".to_string()]);
+    }
+
+    fn retrieved(payload: HashMap<String, Value>, vector: Vec<f32>) -> RetrievedPoint {
+        use qdrant_client::qdrant::{vector_output, vectors_output, DenseVector, VectorOutput, VectorsOutput};
+
+        let vector = VectorOutput { vector: Some(vector_output::Vector::Dense(DenseVector { data: vector })), ..Default::default() };
+        RetrievedPoint {
+            id: Some(point_id_from_string("7")),
+            payload,
+            vectors: Some(VectorsOutput { vectors_options: Some(vectors_output::VectorsOptions::Vector(vector)) }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn export_skips_incomplete_points_and_rejects_page_zero() {
+        let exported = exported_point(retrieved(stored_at("notes.pdf_1", 0), vec![0.5, 0.25])).unwrap().unwrap();
+        assert_eq!((exported.id.as_str(), exported.page.get()), ("7", 1));
+        assert_eq!(exported.vector, [0.5, 0.25]);
+
+        let mut no_text = stored_at("notes.pdf_1", 0);
+        no_text.remove("text");
+        assert!(exported_point(retrieved(no_text, vec![0.5])).unwrap().is_none());
+
+        let mut page_zero = stored_at("notes.pdf_1", 0);
+        page_zero.insert("page".to_string(), Value::from(0.0));
+        assert!(exported_point(retrieved(page_zero, vec![0.5])).is_err());
+    }
+
+    #[test]
+    fn compared_chunks_skip_points_whose_page_is_not_a_page_number() {
+        let chunk = stored_chunk(retrieved(stored_at("notes.pdf_1", 0), vec![0.5, 0.25])).unwrap();
+        assert_eq!(chunk.page.get(), 1);
+        assert_eq!(chunk.vector, [0.5, 0.25]);
+
+        for page in [0.0, -3.0, 2.5, 65_536.0, 1e9] {
+            let mut payload = stored_at("notes.pdf_1", 0);
+            payload.insert("page".to_string(), Value::from(page));
+            assert!(stored_chunk(retrieved(payload, vec![0.5])).is_none(), "page {}", page);
+        }
+        let mut last_page = stored_at("notes.pdf_1", 0);
+        last_page.insert("page".to_string(), Value::from(65_535.0));
+        assert_eq!(stored_chunk(retrieved(last_page, vec![0.5])).unwrap().page.get(), u16::MAX);
+    }
+
+    #[tokio::test]
+    async fn keyword_hits_are_returned_when_the_query_cannot_be_embedded() {
+        let texts = [
+            "Dropout zeroes random activations.",
+            "Batch normalization rescales activations.",
+            "Quarterly revenue grew by twelve percent.",
+        ];
+        let stored: Vec<RetrievedPoint> = texts
+            .iter()
+            .map(|text| RetrievedPoint { payload: chunk_payload("notes.pdf_1", 0, &body(1, text), Some(0)), ..Default::default() })
+            .collect();
+        let options = QueryOptions::default();
+        let embed_failed = Err(anyhow::anyhow!("the embedding model isn't loaded"));
+
+        let response = vector_or_keyword(
+            embed_failed,
+            |_| async { panic!("nothing to run a vector search with") },
+            || async {
+                let scored = stored.into_iter().filter_map(|point| keyword_hit(point, "activations", &options)).collect();
+                Ok(SearchResponse { result: best_hits(scored), ..Default::default() })
+            },
+        )
+        .await
+        .unwrap();
+
+        let found: Vec<&str> = response.result.iter().map(|p| p.payload["text"].as_str().unwrap().as_str()).collect();
+        assert_eq!(found.len(), 2);
+        assert!(found.iter().all(|text| text.contains("activations")), "{:?}", found);
+    }
+
+    #[test]
+    fn keyword_search_keeps_as_many_hits_as_a_vector_search() {
+        let scored: Vec<ScoredPoint> = (0..RESULT_LIMIT * 2).map(|i| ScoredPoint { score: i as f32, ..Default::default() }).collect();
+        let best = best_hits(scored);
+        assert_eq!(best.len(), RESULT_LIMIT);
+        assert_eq!(best[0].score, (RESULT_LIMIT * 2 - 1) as f32);
+    }
+}
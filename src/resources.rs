@@ -0,0 +1,81 @@
+//! One CPU budget split between the async runtime, rayon and embedding, so
+//! concurrent uploads don't have all three oversubscribing the machine.
+
+use anyhow::Result;
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use std::thread::available_parallelism;
+
+// Blocking threads kept beyond embedding for file IO and the like
+const BLOCKING_HEADROOM: usize = 4;
+
+/// How much of the machine each part of the process may use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResourceBudget {
+    /// Cores the whole process should stay within.
+    pub cpus: usize,
+    /// Async runtime workers serving requests.
+    pub runtime_threads: usize,
+    /// Rayon workers for extraction and chunking.
+    pub rayon_threads: usize,
+    /// Embedding batches running at once, and the threads of the intra-op
+    /// pool their ORT sessions share, so batches split these cores between
+    /// them instead of each spreading over the whole machine.
+    pub embed_jobs: usize,
+}
+
+impl Default for ResourceBudget {
+    fn default() -> Self {
+        ResourceBudget::from_cpus(available_parallelism().map_or(1, |n| n.get()))
+    }
+}
+
+impl ResourceBudget {
+    /// Split `cpus`: a quarter to the runtime, half to rayon and a quarter as
+    /// concurrent embedding batches, with at least one of each.
+    pub fn from_cpus(cpus: usize) -> Self {
+        let cpus = cpus.max(1);
+        ResourceBudget {
+            cpus,
+            runtime_threads: (cpus / 4).max(1),
+            rayon_threads: (cpus / 2).max(1),
+            embed_jobs: (cpus / 4).max(1),
+        }
+    }
+
+    /// Ceiling for the runtime's blocking pool, where embedding batches run.
+    pub fn blocking_threads(&self) -> usize {
+        self.embed_jobs + BLOCKING_HEADROOM
+    }
+
+    /// A rayon pool of `rayon_threads` workers.
+    pub fn rayon_pool(&self) -> Result<ThreadPool> {
+        Ok(self.rayon_builder().build()?)
+    }
+
+    /// Size the global rayon pool, which extraction and chunking use unless
+    /// given a pool of their own. Fails if the global pool already started.
+    pub fn install_global_rayon(&self) -> Result<()> {
+        Ok(self.rayon_builder().build_global()?)
+    }
+
+    fn rayon_builder(&self) -> ThreadPoolBuilder {
+        ThreadPoolBuilder::new()
+            .num_threads(self.rayon_threads.max(1))
+            .thread_name(|i| format!("rayon-{}", i))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn budget_is_split_without_starving_anything() {
+        let budget = ResourceBudget::from_cpus(8);
+        assert_eq!((budget.runtime_threads, budget.rayon_threads, budget.embed_jobs), (2, 4, 2));
+        assert_eq!(budget.blocking_threads(), 6);
+
+        let tiny = ResourceBudget::from_cpus(1);
+        assert_eq!((tiny.runtime_threads, tiny.rayon_threads, tiny.embed_jobs), (1, 1, 1));
+    }
+}
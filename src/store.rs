@@ -0,0 +1,73 @@
+use anyhow::{Result, bail};
+use std::collections::HashMap;
+
+use crate::chunk::Chunk;
+use crate::page::PageNumber;
+
+/// A chunk returned from a similarity search.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchResult {
+    pub page: PageNumber,
+    pub text: String,
+    pub score: f32,
+    /// The parent chunk's text when `text` is a small chunk cut from one.
+    pub context: Option<String>,
+}
+
+/// Somewhere to keep chunk embeddings per document and query them back.
+pub trait VectorStore {
+    fn upsert(&mut self, document: &str, chunks: Vec<Chunk>, vectors: Vec<Vec<f32>>) -> Result<()>;
+
+    fn search(&self, document: &str, query: &[f32], limit: usize) -> Result<Vec<SearchResult>>;
+}
+
+/// Brute-force dot-product store (same metric as the Qdrant collections).
+/// Fine for tests and small documents.
+#[derive(Default)]
+pub struct InMemoryStore {
+    documents: HashMap<String, Vec<(Chunk, Vec<f32>)>>,
+}
+
+impl InMemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl VectorStore for InMemoryStore {
+    fn upsert(&mut self, document: &str, chunks: Vec<Chunk>, vectors: Vec<Vec<f32>>) -> Result<()> {
+        if chunks.len() != vectors.len() {
+            bail!(
+                "Got {} chunks but {} vectors for {}",
+                chunks.len(),
+                vectors.len(),
+                document
+            );
+        }
+        self.documents
+            .entry(document.to_string())
+            .or_default()
+            .extend(chunks.into_iter().zip(vectors));
+        Ok(())
+    }
+
+    fn search(&self, document: &str, query: &[f32], limit: usize) -> Result<Vec<SearchResult>> {
+        let Some(entries) = self.documents.get(document) else {
+            return Ok(vec![]);
+        };
+
+        let mut results: Vec<SearchResult> = entries
+            .iter()
+            .map(|(chunk, vector)| SearchResult {
+                page: chunk.page,
+                text: chunk.content.clone(),
+                score: vector.iter().zip(query).map(|(a, b)| a * b).sum(),
+                context: chunk.parent.as_ref().map(|parent| parent.content.clone()),
+            })
+            .collect();
+
+        results.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap());
+        results.truncate(limit);
+        Ok(results)
+    }
+}
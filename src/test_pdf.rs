@@ -0,0 +1,83 @@
+//! Small PDFs built with lopdf for tests.
+//!
+//! The app's tests include this file by path, so the library and the app
+//! draw their test pages the same way.
+
+use lopdf::content::{Content, Operation};
+use lopdf::{dictionary, Dictionary, Document, Object, ObjectId, Stream};
+
+/// Show `text` in 12pt Helvetica with its baseline starting at (x, y), as its
+/// own text object.
+pub fn show_text(x: i64, y: i64, text: &str) -> Vec<Operation> {
+    vec![
+        Operation::new("BT", vec![]),
+        Operation::new("Tf", vec!["F1".into(), 12.into()]),
+        // Absolute, since pdf_oxide carries Td offsets over from the previous BT
+        Operation::new("Tm", vec![1.into(), 0.into(), 0.into(), 1.into(), x.into(), y.into()]),
+        Operation::new("Tj", vec![Object::string_literal(text)]),
+        Operation::new("ET", vec![]),
+    ]
+}
+
+/// A4 pages under one `/Pages` node, each with Helvetica as font `F1`.
+pub struct TestPdf {
+    /// For objects the pages or catalog refer to, added before them.
+    pub doc: Document,
+    pages_id: ObjectId,
+    font_id: ObjectId,
+    kids: Vec<ObjectId>,
+}
+
+impl TestPdf {
+    pub fn new() -> Self {
+        let mut doc = Document::with_version("1.5");
+        let pages_id = doc.new_object_id();
+        let font_id = doc.add_object(dictionary! {
+            "Type" => "Font",
+            "Subtype" => "Type1",
+            "BaseFont" => "Helvetica",
+        });
+        TestPdf { doc, pages_id, font_id, kids: vec![] }
+    }
+
+    /// Add a page showing each (x, y, text).
+    pub fn text_page(&mut self, lines: &[(i64, i64, &str)]) -> ObjectId {
+        let operations = lines.iter().flat_map(|&(x, y, text)| show_text(x, y, text)).collect();
+        self.page_with(operations, dictionary! {})
+    }
+
+    /// Add a page drawing `operations`, with `resources` next to the font.
+    pub fn page_with(&mut self, operations: Vec<Operation>, mut resources: Dictionary) -> ObjectId {
+        let content_id = self.doc.add_object(Stream::new(dictionary! {}, Content { operations }.encode().unwrap()));
+        resources.set("Font", dictionary! { "F1" => self.font_id });
+        let page_id = self.doc.add_object(dictionary! {
+            "Type" => "Page",
+            "Parent" => self.pages_id,
+            "Contents" => content_id,
+            "Resources" => resources,
+            "MediaBox" => vec![0.into(), 0.into(), 595.into(), 842.into()],
+        });
+        self.kids.push(page_id);
+        page_id
+    }
+
+    /// Close the page tree, with `catalog` entries next to `/Pages` in the catalog.
+    pub fn finish(mut self, mut catalog: Dictionary) -> Document {
+        let kids: Vec<Object> = self.kids.iter().map(|&id| id.into()).collect();
+        self.doc.objects.insert(
+            self.pages_id,
+            Object::Dictionary(dictionary! { "Type" => "Pages", "Count" => kids.len() as i64, "Kids" => kids }),
+        );
+        catalog.set("Type", "Catalog");
+        catalog.set("Pages", self.pages_id);
+        let catalog_id = self.doc.add_object(catalog);
+        self.doc.trailer.set("Root", catalog_id);
+        self.doc
+    }
+}
+
+pub fn to_bytes(mut doc: Document) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    doc.save_to(&mut bytes).unwrap();
+    bytes
+}
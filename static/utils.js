@@ -116,18 +116,18 @@ export function mergeRectsOnSameLine(rects, tolerance = 3) {
 }
 
 /**
- * @param {object}  highlight            - { page, rects }
+ * @param {object}  highlight            - { page, sentence_rects }
  * @param {number}  index                - position in matchResults array
  * @param {number}  selectedHighlightIndex
  * @param {object}  pdfViewer
  */
 export function renderHighlight(highlight, index, selectedHighlightIndex, pdfViewer) {
     const pageView = pdfViewer.getPageView(highlight.page - 1);
-    if (!pageView || !highlight.rects || !highlight.rects.length) return;
+    if (!pageView || !highlight.sentence_rects || !highlight.sentence_rects.length) return;
 
     const isSelected    = index === selectedHighlightIndex;
     const viewport      = pageView.viewport;
-    const viewportRects = pdfiumRectsToViewport(highlight.rects, viewport);
+    const viewportRects = pdfiumRectsToViewport(highlight.sentence_rects, viewport);
     const cleanRects    = mergeRectsOnSameLine(viewportRects);
 
     for (const rect of cleanRects) {
@@ -166,7 +166,14 @@ export function scrollToMatch(index, activeHighlights, pdfViewer) {
     clearAllHighlights();
     renderHighlight(h, index, index, pdfViewer);
 
-    const allRects = h.rects;
+    // Captions (figure alt text) have no rects; just bring their page up
+    if (!h.sentence_rects.length) {
+        pdfViewer.scrollPageIntoView({ pageNumber: h.page });
+        return index;
+    }
+
+    // Scroll to the hit itself; the whole sentence is shaded
+    const allRects = h.exact_rects.length ? h.exact_rects : h.sentence_rects;
     const maxY     = Math.max(...allRects.map(r => r.y + r.height));
     const padding  = 50;
 
@@ -198,8 +205,15 @@ export async function getBackendResults(query, documentId, signal) {
     const response = await fetch(`/api/search?q=${encodeURIComponent(query)}&id=${documentId}`, {
         signal // 👈 fetch will throw AbortError if cancelled
     });
-    if (!response.ok) throw new Error('Search failed');
-    return response.json();
+    if (!response.ok) {
+        const body = await response.json().catch(() => null);
+        throw new Error(body?.error?.message || 'Search failed');
+    }
+    const { highlights, warnings } = await response.json();
+    for (const warning of warnings ?? []) {
+        console.warn(`No highlights for page ${warning.page}: ${warning.message}`);
+    }
+    return highlights;
 }
 
 // ── Poll Backend ────────────────────────────────────────────────────────────
@@ -207,17 +221,20 @@ export async function waitUntilReady(docId, intervalMs = 500, timeoutMs = 60000)
     const start = Date.now();
 
     while (Date.now() - start < timeoutMs) {
+        let data = null;
         try {
             const res = await fetch(`/api/ready?id=${docId}`);
             if (!res.ok) throw new Error(`HTTP ${res.status}`);
 
-            const data = await res.json();
-            if (data.ready) return;
+            data = await res.json();
         } catch (err) {
             console.error("Polling error:", err);
             // Optional: decide if you want to break instead
         }
 
+        if (data?.status === 'empty') throw new Error('This PDF has no searchable text');
+        if (data?.ready) return;
+
         await new Promise(r => setTimeout(r, intervalMs));
     }
 